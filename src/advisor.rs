@@ -0,0 +1,564 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    fmt,
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader},
+    path::Path,
+};
+
+use crate::{
+    AdviseConfig, DERIVING_RULES, PBarberError, SUPPORTED_JUSTIFIERS,
+    cp_lit_map::CPLitMap,
+    line_map::LineMap,
+    opb_model::OpbModel,
+    pol_eval::{PolToken, parse_pol},
+    proof_line::{Deletion, ProofLine},
+    split_debug_marker,
+    verify::{LineOrigin, reported_lines},
+};
+
+pub use explain::{Explanation, explain};
+pub use lint::{DuplicateId, LintFindings, MalformedLine};
+pub use minimize::{MinimizeOutcome, minimize};
+use rup::RupChecker;
+pub use rup::{NotRup, RupFindings};
+
+mod explain;
+mod lint;
+mod minimize;
+mod rup;
+
+/// Number of example lines to show for each kind of problem.
+const MAX_EXAMPLES: usize = 5;
+
+/// A constraint ID used where it doesn't exist, with the line number of the use.
+#[derive(Debug, Clone)]
+pub struct BadReference {
+    pub line_number: u64,
+    pub id: String,
+}
+
+/// A line VeriPB reported an error on, and where it came from in this proof.
+#[derive(Debug, Clone)]
+pub struct CheckerFailure {
+    /// Line number in the proof VeriPB checked
+    pub checked_line: u64,
+    pub origin: LineOrigin,
+    /// The line of this proof it came from, if any
+    pub line: Option<String>,
+}
+
+impl CheckerFailure {
+    fn original_line(&self) -> Option<u64> {
+        match self.origin {
+            LineOrigin::Original(line_number) | LineOrigin::WrittenFor(line_number) => {
+                Some(line_number)
+            }
+            LineOrigin::Generated => None,
+        }
+    }
+}
+
+/// What `advise` found in a proof.
+#[derive(Debug, Default)]
+pub struct AdviceReport {
+    pub total_lines: u64,
+    /// Assertion counts by justification name (`None` for assertions without a name)
+    pub assertions_by_name: BTreeMap<Option<String>, u64>,
+    pub undefined_ids: Vec<BadReference>,
+    pub used_after_delete: Vec<BadReference>,
+    /// Literals used by assertions, if no literal map was given
+    pub assertion_literals: BTreeSet<String>,
+    /// Literals used by assertions but missing from the literal map, if one was given
+    pub unmapped_literals: Option<BTreeSet<String>>,
+    /// Lines VeriPB reported errors on, if its output was given
+    pub checker_failures: Vec<CheckerFailure>,
+    /// Problems found by `--lint`, if it was given
+    pub lint: Option<LintFindings>,
+    /// Assertions checked by `--rup`, if it was given
+    pub rup: Option<RupFindings>,
+}
+
+impl AdviceReport {
+    /// Whether the justifier can justify assertions named `name`. None can if it wasn't
+    /// built, without the `justify` feature.
+    pub fn justifiable(name: &str) -> bool {
+        cfg!(feature = "justify") && SUPPORTED_JUSTIFIERS.contains(&name)
+    }
+
+    /// Record `id` if it doesn't refer to a live constraint. Numeric IDs count the formula and
+    /// derived constraints (`numbered` of them so far).
+    fn check_reference(
+        &mut self,
+        defined: &HashMap<String, u64>,
+        deleted: &HashSet<String>,
+        numbered: u64,
+        line_number: u64,
+        id: &str,
+    ) {
+        let bad_reference = BadReference {
+            line_number,
+            id: id.to_string(),
+        };
+        if deleted.contains(id) {
+            self.used_after_delete.push(bad_reference);
+        } else if !is_defined(defined, numbered, id) {
+            self.undefined_ids.push(bad_reference);
+        }
+    }
+
+    /// Assertions that PBarber can't justify yet, by name.
+    pub fn unsupported(&self) -> impl Iterator<Item = (&Option<String>, &u64)> {
+        self.assertions_by_name
+            .iter()
+            .filter(|(name, _)| !name.as_deref().is_some_and(Self::justifiable))
+    }
+
+    /// The report as `<key> <value>` lines, for `--porcelain` output.
+    pub fn porcelain(&self) -> PorcelainAdvice<'_> {
+        PorcelainAdvice(self)
+    }
+}
+
+pub struct PorcelainAdvice<'a>(&'a AdviceReport);
+
+impl fmt::Display for PorcelainAdvice<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let report = self.0;
+        writeln!(f, "total_lines {}", report.total_lines)?;
+        for (name, count) in &report.assertions_by_name {
+            let (name, supported) = match name {
+                Some(name) => (name.as_str(), AdviceReport::justifiable(name)),
+                None => ("<unnamed>", false),
+            };
+            writeln!(f, "assertions {name} {count} {supported}")?;
+        }
+        for bad_reference in &report.undefined_ids {
+            writeln!(
+                f,
+                "undefined_id {} {}",
+                bad_reference.line_number, bad_reference.id
+            )?;
+        }
+        for bad_reference in &report.used_after_delete {
+            writeln!(
+                f,
+                "used_after_delete {} {}",
+                bad_reference.line_number, bad_reference.id
+            )?;
+        }
+        if let Some(unmapped) = &report.unmapped_literals {
+            for lit in unmapped {
+                writeln!(f, "unmapped_literal {lit}")?;
+            }
+        }
+        if let Some(lint) = &report.lint {
+            lint.write_porcelain(f)?;
+        }
+        if let Some(rup) = &report.rup {
+            rup.write_porcelain(f)?;
+        }
+        for failure in &report.checker_failures {
+            match failure.origin {
+                LineOrigin::Original(line_number) => {
+                    writeln!(f, "checker_failure {} {line_number}", failure.checked_line)?
+                }
+                LineOrigin::WrittenFor(line_number) => writeln!(
+                    f,
+                    "checker_failure {} written_for {line_number}",
+                    failure.checked_line
+                )?,
+                LineOrigin::Generated => {
+                    writeln!(f, "checker_failure {} generated", failure.checked_line)?
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Scan a proof forwards and report its assertions and any broken references.
+pub fn advise<R: BufRead>(input: R, config: &AdviseConfig) -> Result<AdviceReport, PBarberError> {
+    let lit_map = match &config.lits_path {
+        Some(lits_path) => {
+            let lits_file = OpenOptions::new()
+                .read(true)
+                .open(lits_path)
+                .map_err(|err| PBarberError::reading("literal mapping", lits_path, err))?;
+            Some(CPLitMap::try_from_reader(lits_file)?)
+        }
+        None => None,
+    };
+    let mut rup_checker = match &config.opb_path {
+        Some(opb_path) if config.rup => {
+            let model = OpbModel::open(opb_path)
+                .map_err(|err| PBarberError::reading("OPB model", opb_path, err))?;
+            Some(RupChecker::new(&model))
+        }
+        _ => None,
+    };
+    let mut checker_failures = match &config.veripb_log {
+        Some(log_path) => checker_failures(log_path, config.line_map_path.as_deref())?,
+        None => Vec::new(),
+    };
+
+    let mut report = AdviceReport {
+        lint: config.lint.then(LintFindings::default),
+        ..Default::default()
+    };
+    let mut defined = HashMap::<String, u64>::new();
+    let mut deleted = HashSet::<String>::new();
+    let mut formula_constraints = 0;
+    let mut derived = 0;
+    for line in input.lines() {
+        let line = line?;
+        report.total_lines += 1;
+        let line_number = report.total_lines;
+        for failure in &mut checker_failures {
+            if failure.original_line() == Some(line_number) {
+                failure.line = Some(line.trim().to_string());
+            }
+        }
+        let line = split_debug_marker(&line).1;
+        if let Some(lint) = report.lint.as_mut() {
+            lint.check_syntax(line_number, line);
+        }
+        if let Some(rup_checker) = rup_checker.as_mut() {
+            rup_checker.see_line(line_number, line);
+        }
+        let mut tokens = line.split_whitespace();
+        let Some(first) = tokens.next() else {
+            continue;
+        };
+
+        let numbered = formula_constraints + derived;
+        match first {
+            "f" => {
+                formula_constraints = tokens
+                    .next()
+                    .and_then(|n| n.trim_end_matches(';').parse::<u64>().ok())
+                    .unwrap_or(0);
+                continue;
+            }
+            "del" => {
+                if tokens.next() == Some("id") {
+                    for id in tokens
+                        .map(|t| t.trim_end_matches(';'))
+                        .filter(|t| !t.is_empty())
+                    {
+                        report.check_reference(&defined, &deleted, numbered, line_number, id);
+                        deleted.insert(id.to_string());
+                    }
+                }
+                continue;
+            }
+            "conclusion" => {
+                let missing = line
+                    .split_once(':')
+                    .map(|(_, id)| id.trim().trim_end_matches(';').trim())
+                    .filter(|id| deleted.contains(*id) || !is_defined(&defined, numbered, id));
+                if let (Some(lint), Some(id)) = (report.lint.as_mut(), missing) {
+                    lint.missing_conclusions.push(BadReference {
+                        line_number,
+                        id: id.to_string(),
+                    });
+                }
+                continue;
+            }
+            _ => (),
+        }
+
+        let (label, rule) = if first.starts_with('@') {
+            (Some(first), tokens.next().unwrap_or_default())
+        } else {
+            (None, first)
+        };
+        if !DERIVING_RULES.contains(&rule) {
+            continue;
+        }
+        derived += 1;
+
+        if rule == "pol" || rule == "p" {
+            for token in parse_pol(tokens) {
+                if let PolToken::Constraint(id) = token {
+                    report.check_reference(&defined, &deleted, numbered, line_number, id);
+                }
+            }
+        } else if rule == "a" {
+            let mut parts = line.split(':');
+            let constraint = parts.next().unwrap_or_default();
+            let name = parts
+                .nth(1)
+                .map(|name| name.trim().trim_end_matches(';').trim().to_string())
+                .filter(|name| !name.is_empty());
+            *report.assertions_by_name.entry(name).or_insert(0) += 1;
+
+            for token in constraint.split_whitespace().skip(2) {
+                if token == ">=" {
+                    break;
+                }
+                let var = token.trim_start_matches('~');
+                if var.starts_with('x') {
+                    report.assertion_literals.insert(var.to_string());
+                }
+            }
+        }
+
+        if let Some(label) = label {
+            let live_definition = defined.get(label).filter(|_| !deleted.contains(label));
+            if let (Some(lint), Some(&first_line)) = (report.lint.as_mut(), live_definition) {
+                lint.duplicate_ids.push(DuplicateId {
+                    line_number,
+                    id: label.to_string(),
+                    first_line,
+                });
+            }
+            // A redefined ID is usable again
+            deleted.remove(label);
+            defined.insert(label.to_string(), line_number);
+        }
+    }
+
+    if let Some(lit_map) = lit_map {
+        let unmapped = report
+            .assertion_literals
+            .iter()
+            .filter(|lit| lit_map.get(lit).is_none())
+            .cloned()
+            .collect();
+        report.unmapped_literals = Some(unmapped);
+    }
+    report.checker_failures = checker_failures;
+    report.rup = rup_checker.map(RupChecker::finish);
+    Ok(report)
+}
+
+/// The lines VeriPB reported errors on in the log at `log_path`, mapped back through the line
+/// map at `line_map_path` if given.
+fn checker_failures(
+    log_path: &Path,
+    line_map_path: Option<&Path>,
+) -> Result<Vec<CheckerFailure>, PBarberError> {
+    let checked_lines = File::open(log_path)
+        .and_then(|file| reported_lines(BufReader::new(file)))
+        .map_err(|err| PBarberError::reading("VeriPB output", log_path, err))?;
+    let line_map = line_map_path.map(LineMap::from_path).transpose()?;
+    let mut failures: Vec<CheckerFailure> = Vec::new();
+    for checked_line in checked_lines {
+        if failures.iter().any(|f| f.checked_line == checked_line) {
+            continue;
+        }
+        let origin = match &line_map {
+            Some(line_map) => line_map
+                .origin(checked_line)
+                .cloned()
+                .unwrap_or(LineOrigin::Generated),
+            None => LineOrigin::Original(checked_line),
+        };
+        failures.push(CheckerFailure {
+            checked_line,
+            origin,
+            line: None,
+        });
+    }
+    Ok(failures)
+}
+
+/// Whether `id` is a label defined so far, or the number of one of the `numbered` formula and
+/// derived constraints.
+fn is_defined(defined: &HashMap<String, u64>, numbered: u64, id: &str) -> bool {
+    defined.contains_key(id) || id.parse::<u64>().is_ok_and(|n| (1..=numbered).contains(&n))
+}
+
+/// The constraint IDs `line` refers to, e.g. the antecedents of a derivation.
+pub(crate) fn references<'a>(line: &ProofLine<'a>) -> Vec<&'a str> {
+    match line {
+        ProofLine::Pol { terms, .. } => terms
+            .iter()
+            .filter_map(|token| match token {
+                PolToken::Constraint(id) => Some(*id),
+                _ => None,
+            })
+            .collect(),
+        ProofLine::Rup { hints, .. } => hints.clone(),
+        ProofLine::Del(Deletion::Ids(ids)) | ProofLine::Core { ids } => ids.clone(),
+        ProofLine::Ia { antecedent, .. } => antecedent.iter().copied().collect(),
+        ProofLine::Conclusion { id, .. } => id.iter().copied().collect(),
+        ProofLine::Assertion { antecedents, .. } => antecedents
+            .iter()
+            .flat_map(|antecedents| antecedents.split_whitespace())
+            .collect(),
+        ProofLine::Other { body, .. } => body
+            .split_whitespace()
+            .filter(|token| token.starts_with('@'))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn write_bad_references(
+    f: &mut fmt::Formatter<'_>,
+    heading: &str,
+    references: &[BadReference],
+) -> fmt::Result {
+    if references.is_empty() {
+        return Ok(());
+    }
+    writeln!(f, "{heading}: {}", references.len())?;
+    for reference in references.iter().take(MAX_EXAMPLES) {
+        writeln!(f, " ∟ line {}: `{}`", reference.line_number, reference.id)?;
+    }
+    if references.len() > MAX_EXAMPLES {
+        writeln!(f, " ∟ ...")?;
+    }
+    Ok(())
+}
+
+impl fmt::Display for AdviceReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Total lines: {}", self.total_lines)?;
+        let total_assertions: u64 = self.assertions_by_name.values().sum();
+        writeln!(f, "Assertion lines: {}", total_assertions)?;
+        for (name, count) in &self.assertions_by_name {
+            let (name, supported) = match name {
+                Some(name) => (name.as_str(), Self::justifiable(name)),
+                None => ("<unnamed>", false),
+            };
+            writeln!(
+                f,
+                " ∟ `{}`: {} ({})",
+                name,
+                count,
+                if supported {
+                    "can be justified"
+                } else {
+                    "no justifier"
+                }
+            )?;
+        }
+
+        write_bad_references(f, "Undefined IDs used", &self.undefined_ids)?;
+        write_bad_references(f, "IDs used after deletion", &self.used_after_delete)?;
+        if let Some(lint) = &self.lint {
+            write!(f, "{lint}")?;
+        }
+        if let Some(rup) = &self.rup {
+            write!(f, "{rup}")?;
+        }
+        if !self.checker_failures.is_empty() {
+            writeln!(f, "VeriPB errors: {}", self.checker_failures.len())?;
+            for failure in &self.checker_failures {
+                let origin = match (&failure.origin, &failure.line) {
+                    (LineOrigin::Original(n), Some(line)) => format!("line {n}: `{line}`"),
+                    (LineOrigin::WrittenFor(n), Some(line)) => {
+                        format!("written by PBarber for line {n}: `{line}`")
+                    }
+                    (LineOrigin::Original(n) | LineOrigin::WrittenFor(n), None) => {
+                        format!("line {n}, past the end of this proof")
+                    }
+                    (LineOrigin::Generated, _) => "written by PBarber".to_string(),
+                };
+                writeln!(f, " ∟ checked line {} is {origin}", failure.checked_line)?;
+            }
+        }
+
+        writeln!(f, "Summary:")?;
+        let unsupported: Vec<String> = self
+            .unsupported()
+            .map(|(name, count)| format!("`{}` ({count})", name.as_deref().unwrap_or("<unnamed>")))
+            .collect();
+        if unsupported.is_empty() {
+            writeln!(f, " ∟ All assertions have a justifier.")?;
+        } else {
+            writeln!(f, " ∟ Justifiers needed for: {}", unsupported.join(", "))?;
+        }
+        match &self.unmapped_literals {
+            Some(unmapped) if unmapped.is_empty() => {
+                writeln!(f, " ∟ All assertion literals are in the literal map.")?
+            }
+            Some(unmapped) => {
+                let examples: Vec<&str> = unmapped
+                    .iter()
+                    .take(MAX_EXAMPLES)
+                    .map(String::as_str)
+                    .collect();
+                writeln!(
+                    f,
+                    " ∟ Literal map entries needed for {} literals, e.g. {}",
+                    unmapped.len(),
+                    examples.join(", ")
+                )?
+            }
+            None => writeln!(
+                f,
+                " ∟ Assertions use {} literals (pass `--lits` to check them against a literal map).",
+                self.assertion_literals.len()
+            )?,
+        }
+        if self.undefined_ids.is_empty() && self.used_after_delete.is_empty() {
+            writeln!(f, " ∟ No broken constraint references.")?;
+        } else {
+            writeln!(
+                f,
+                " ∟ {} broken constraint references.",
+                self.undefined_ids.len() + self.used_after_delete.len()
+            )?;
+        }
+        match &self.lint {
+            Some(lint) if lint.is_empty() => writeln!(f, " ∟ No lint problems.")?,
+            Some(lint) => writeln!(f, " ∟ {} lint problems.", lint.len())?,
+            None => (),
+        }
+        match &self.rup {
+            Some(rup) if rup.not_rup.is_empty() => {
+                writeln!(f, " ∟ All {} checked assertions are RUP.", rup.checked)?
+            }
+            Some(rup) if rup.certain() => writeln!(
+                f,
+                " ∟ {} assertions will fail if checked as RUP.",
+                rup.not_rup.len()
+            )?,
+            Some(rup) => writeln!(
+                f,
+                " ∟ {} assertions may fail if checked as RUP (some constraints couldn't be worked out).",
+                rup.not_rup.len()
+            )?,
+            None => (),
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_undefined_and_deleted_references() {
+        let proof = "\
+pseudo-Boolean proof version 2.0
+f 2 ;
+@c1 pol 1 2 + ;
+del id @c1 ;
+@c2 pol @c1 @nowhere + ;
+@c3 a 1 x1 >= 1 : : IntLinear ;
+";
+        let report = advise(proof.as_bytes(), &AdviseConfig::default()).unwrap();
+
+        assert_eq!(report.total_lines, 6);
+        let ids = |references: &[BadReference]| {
+            references
+                .iter()
+                .map(|reference| (reference.line_number, reference.id.clone()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(ids(&report.used_after_delete), [(5, "@c1".to_string())]);
+        assert_eq!(ids(&report.undefined_ids), [(5, "@nowhere".to_string())]);
+        assert_eq!(
+            report
+                .assertions_by_name
+                .get(&Some("IntLinear".to_string())),
+            Some(&1)
+        );
+    }
+}
@@ -0,0 +1,292 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt,
+    io::BufRead,
+};
+
+use ustr::{Ustr, ustr};
+
+use crate::{PBarberError, proof_line::ProofLine, split_debug_marker};
+
+use super::references;
+
+/// A constraint a derivation uses.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Antecedent {
+    /// A derived constraint, by its index in [`Explanation::constraints`]
+    Derived(usize),
+    /// A constraint of the formula, by its number
+    Formula(u64),
+    /// An ID that wasn't defined where it was used
+    Unknown(String),
+}
+
+/// A line deriving a constraint.
+#[derive(Debug, Clone)]
+pub struct DerivedConstraint {
+    /// The label, or number if it has none
+    pub id: Ustr,
+    pub line_number: u64,
+    pub rule: Ustr,
+    pub antecedents: Vec<Antecedent>,
+}
+
+/// The derivation of a constraint, from `advise --explain`.
+#[derive(Debug)]
+pub struct Explanation {
+    /// The derived constraints of the proof, in proof order
+    pub constraints: Vec<DerivedConstraint>,
+    /// The explained constraint
+    pub root: usize,
+    /// Levels of antecedents to show below the root
+    pub max_depth: usize,
+}
+
+impl Explanation {
+    /// The derived constraints the root's derivation uses, including the root.
+    pub fn derivation(&self) -> Vec<usize> {
+        let mut seen = HashSet::new();
+        let mut queue = vec![self.root];
+        let mut derivation = Vec::new();
+        while let Some(index) = queue.pop() {
+            if !seen.insert(index) {
+                continue;
+            }
+            derivation.push(index);
+            for antecedent in &self.constraints[index].antecedents {
+                if let Antecedent::Derived(used) = antecedent {
+                    queue.push(*used);
+                }
+            }
+        }
+        derivation.sort_unstable();
+        derivation
+    }
+
+    /// The formula constraints the root's derivation uses.
+    pub fn formula_constraints(&self, derivation: &[usize]) -> Vec<u64> {
+        let mut used: Vec<u64> = derivation
+            .iter()
+            .flat_map(|&index| &self.constraints[index].antecedents)
+            .filter_map(|antecedent| match antecedent {
+                Antecedent::Formula(number) => Some(*number),
+                _ => None,
+            })
+            .collect();
+        used.sort_unstable();
+        used.dedup();
+        used
+    }
+
+    /// The tree as `<key> <value>` lines, for `--porcelain` output.
+    pub fn porcelain(&self) -> PorcelainExplanation<'_> {
+        PorcelainExplanation(self)
+    }
+
+    /// Visit the tree depth first, skipping the antecedents of constraints already visited
+    /// and of those at the depth limit.
+    fn walk<E>(
+        &self,
+        mut visit: impl FnMut(usize, &Antecedent, Visit) -> Result<(), E>,
+    ) -> Result<(), E> {
+        let mut shown = HashSet::new();
+        let mut stack = vec![(0, Antecedent::Derived(self.root))];
+        while let Some((depth, antecedent)) = stack.pop() {
+            let Antecedent::Derived(index) = antecedent else {
+                visit(depth, &antecedent, Visit::Leaf)?;
+                continue;
+            };
+            let antecedents = &self.constraints[index].antecedents;
+            if !shown.insert(index) {
+                visit(depth, &antecedent, Visit::Repeated)?;
+            } else if depth >= self.max_depth && !antecedents.is_empty() {
+                visit(depth, &antecedent, Visit::Cut(antecedents.len()))?;
+            } else {
+                visit(depth, &antecedent, Visit::Expanded)?;
+                stack.extend(antecedents.iter().rev().map(|a| (depth + 1, a.clone())));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// How [`Explanation::walk`] reached a constraint.
+#[derive(Debug, Clone, Copy)]
+enum Visit {
+    Expanded,
+    /// Already shown, with its antecedents
+    Repeated,
+    /// At the depth limit, with this many antecedents not shown
+    Cut(usize),
+    /// A formula constraint or unknown ID
+    Leaf,
+}
+
+pub struct PorcelainExplanation<'a>(&'a Explanation);
+
+impl fmt::Display for PorcelainExplanation<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let explanation = self.0;
+        let derivation = explanation.derivation();
+        writeln!(f, "derived_constraints {}", derivation.len())?;
+        writeln!(
+            f,
+            "formula_constraints {}",
+            explanation.formula_constraints(&derivation).len()
+        )?;
+        explanation.walk(|depth, antecedent, visit| {
+            let (id, rule, line_number) = match antecedent {
+                Antecedent::Derived(index) => {
+                    let constraint = &explanation.constraints[*index];
+                    (
+                        constraint.id.to_string(),
+                        constraint.rule.as_str(),
+                        constraint.line_number,
+                    )
+                }
+                Antecedent::Formula(number) => (number.to_string(), "formula", 0),
+                Antecedent::Unknown(id) => (id.clone(), "unknown", 0),
+            };
+            let visit = match visit {
+                Visit::Expanded | Visit::Leaf => "shown".to_string(),
+                Visit::Repeated => "repeated".to_string(),
+                Visit::Cut(antecedents) => format!("cut {antecedents}"),
+            };
+            writeln!(f, "node {depth} {id} {rule} {line_number} {visit}")
+        })
+    }
+}
+
+impl fmt::Display for Explanation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let derivation = self.derivation();
+        let mut by_rule = BTreeMap::<&str, u64>::new();
+        for &index in &derivation {
+            *by_rule
+                .entry(self.constraints[index].rule.as_str())
+                .or_insert(0) += 1;
+        }
+        let by_rule: Vec<String> = by_rule
+            .iter()
+            .map(|(rule, count)| format!("{count} {rule}"))
+            .collect();
+        writeln!(
+            f,
+            "Derived from {} constraints ({}) and {} formula constraints.",
+            derivation.len(),
+            by_rule.join(", "),
+            self.formula_constraints(&derivation).len()
+        )?;
+        self.walk(|depth, antecedent, visit| {
+            let indent = if depth == 0 {
+                String::new()
+            } else {
+                format!("{} ∟ ", "   ".repeat(depth - 1))
+            };
+            let node = match antecedent {
+                Antecedent::Derived(index) => {
+                    let constraint = &self.constraints[*index];
+                    format!(
+                        "{} ({}, line {})",
+                        constraint.id, constraint.rule, constraint.line_number
+                    )
+                }
+                Antecedent::Formula(number) => format!("{number} (formula)"),
+                Antecedent::Unknown(id) => format!("{id} (not defined where it's used)"),
+            };
+            match visit {
+                Visit::Expanded | Visit::Leaf => writeln!(f, "{indent}{node}"),
+                Visit::Repeated => writeln!(f, "{indent}{node}, shown above"),
+                Visit::Cut(antecedents) => {
+                    let plural = if antecedents == 1 { "" } else { "s" };
+                    writeln!(
+                        f,
+                        "{indent}{node}, {antecedents} antecedent{plural} not shown"
+                    )
+                }
+            }
+        })
+    }
+}
+
+/// What `id` refers to, after `derived` constraints have been derived.
+fn resolve(
+    id: &str,
+    formula_constraints: u64,
+    derived: usize,
+    labels: &HashMap<Ustr, usize>,
+) -> Antecedent {
+    let index = match id.parse::<u64>() {
+        Ok(number) if (1..=formula_constraints).contains(&number) => {
+            return Antecedent::Formula(number);
+        }
+        Ok(number) => number
+            .checked_sub(formula_constraints + 1)
+            .map(|index| index as usize)
+            .filter(|&index| index < derived),
+        Err(_) => labels.get(&ustr(id)).copied(),
+    };
+    index.map_or_else(|| Antecedent::Unknown(id.to_string()), Antecedent::Derived)
+}
+
+/// Read a proof forwards and find the derivation of the constraint `id` (its last definition,
+/// if the label is reused), to be shown `max_depth` levels deep.
+pub fn explain<R: BufRead>(
+    input: R,
+    id: &str,
+    max_depth: usize,
+) -> Result<Explanation, PBarberError> {
+    let mut constraints = Vec::<DerivedConstraint>::new();
+    let mut labels = HashMap::<Ustr, usize>::new();
+    let mut formula_constraints = 0;
+    for (i, line) in input.lines().enumerate() {
+        let line = line?;
+        let Ok(parsed) = ProofLine::parse(split_debug_marker(&line).1) else {
+            continue;
+        };
+        if let ProofLine::Formula {
+            constraints: Some(count),
+        } = parsed
+        {
+            formula_constraints = count;
+        }
+        if !parsed.derives() {
+            continue;
+        }
+
+        let antecedents = references(&parsed)
+            .into_iter()
+            .map(|id| resolve(id, formula_constraints, constraints.len(), &labels))
+            .collect();
+        let number = formula_constraints + constraints.len() as u64 + 1;
+        let label = parsed.label().map(ustr);
+        if let Some(label) = label {
+            labels.insert(label, constraints.len());
+        }
+        constraints.push(DerivedConstraint {
+            id: label.unwrap_or_else(|| ustr(&number.to_string())),
+            line_number: i as u64 + 1,
+            rule: ustr(parsed.rule()),
+            antecedents,
+        });
+    }
+
+    let root = match resolve(id, formula_constraints, constraints.len(), &labels) {
+        Antecedent::Derived(index) => index,
+        Antecedent::Formula(_) => {
+            return Err(PBarberError::InvalidConfig(format!(
+                "`{id}` is a formula constraint, so it has no derivation"
+            )));
+        }
+        Antecedent::Unknown(_) => {
+            return Err(PBarberError::InvalidConfig(format!(
+                "`{id}` isn't derived in the proof"
+            )));
+        }
+    };
+    Ok(Explanation {
+        constraints,
+        root,
+        max_depth,
+    })
+}
@@ -0,0 +1,225 @@
+use std::fmt;
+
+use crate::{
+    DERIVING_RULES, PBarberError,
+    pol_eval::Constraint,
+    proof_line::{PolToken, ProofLine},
+};
+
+use super::{BadReference, MAX_EXAMPLES, write_bad_references};
+
+/// VeriPB rules that aren't read into a [`ProofLine`] of their own.
+static ALLOWED_RULES: [&str; 13] = [
+    "dom",
+    "e",
+    "ea",
+    "eobj",
+    "obju",
+    "core",
+    "def_order",
+    "load_order",
+    "pre_order",
+    "strengthening_to_core",
+    "proofgoal",
+    "qed",
+    "end",
+];
+
+/// An ID defined again while its earlier definition is still live.
+#[derive(Debug, Clone)]
+pub struct DuplicateId {
+    pub line_number: u64,
+    pub id: String,
+    pub first_line: u64,
+}
+
+/// A line that VeriPB wouldn't be able to read.
+#[derive(Debug, Clone)]
+pub struct MalformedLine {
+    pub line_number: u64,
+    pub message: String,
+}
+
+/// What `advise --lint` found, besides the broken references every report has.
+#[derive(Debug, Default)]
+pub struct LintFindings {
+    pub duplicate_ids: Vec<DuplicateId>,
+    /// Conclusions whose constraint isn't defined, or was deleted
+    pub missing_conclusions: Vec<BadReference>,
+    pub malformed_lines: Vec<MalformedLine>,
+}
+
+impl LintFindings {
+    pub fn len(&self) -> usize {
+        self.duplicate_ids.len() + self.missing_conclusions.len() + self.malformed_lines.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Record `line` if its syntax is wrong.
+    pub(crate) fn check_syntax(&mut self, line_number: u64, line: &str) {
+        let message = match ProofLine::parse(line) {
+            Ok(parsed) => syntax_error(&parsed).map(str::to_string),
+            Err(PBarberError::MalformedLine { message, .. }) => Some(message),
+            Err(err) => Some(err.to_string()),
+        };
+        if let Some(message) = message {
+            self.malformed_lines.push(MalformedLine {
+                line_number,
+                message,
+            });
+        }
+    }
+
+    pub(crate) fn write_porcelain(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for duplicate in &self.duplicate_ids {
+            writeln!(
+                f,
+                "duplicate_id {} {} {}",
+                duplicate.line_number, duplicate.id, duplicate.first_line
+            )?;
+        }
+        for missing in &self.missing_conclusions {
+            writeln!(
+                f,
+                "missing_conclusion {} {}",
+                missing.line_number, missing.id
+            )?;
+        }
+        for malformed in &self.malformed_lines {
+            writeln!(
+                f,
+                "malformed_line {} {}",
+                malformed.line_number, malformed.message
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for LintFindings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.duplicate_ids.is_empty() {
+            writeln!(
+                f,
+                "IDs defined again while live: {}",
+                self.duplicate_ids.len()
+            )?;
+            for duplicate in self.duplicate_ids.iter().take(MAX_EXAMPLES) {
+                writeln!(
+                    f,
+                    " ∟ line {}: `{}` (first defined on line {})",
+                    duplicate.line_number, duplicate.id, duplicate.first_line
+                )?;
+            }
+            if self.duplicate_ids.len() > MAX_EXAMPLES {
+                writeln!(f, " ∟ ...")?;
+            }
+        }
+        write_bad_references(
+            f,
+            "Conclusions of missing constraints",
+            &self.missing_conclusions,
+        )?;
+        if !self.malformed_lines.is_empty() {
+            writeln!(f, "Malformed lines: {}", self.malformed_lines.len())?;
+            for malformed in self.malformed_lines.iter().take(MAX_EXAMPLES) {
+                writeln!(
+                    f,
+                    " ∟ line {}: {}",
+                    malformed.line_number, malformed.message
+                )?;
+            }
+            if self.malformed_lines.len() > MAX_EXAMPLES {
+                writeln!(f, " ∟ ...")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// What's wrong with a line that parses, if anything.
+fn syntax_error(line: &ProofLine) -> Option<&'static str> {
+    match line {
+        ProofLine::Pol { terms, .. } => pol_error(terms),
+        ProofLine::Assertion { constraint, .. }
+        | ProofLine::Rup { constraint, .. }
+        | ProofLine::Red { constraint, .. }
+        | ProofLine::Ia { constraint, .. } => Constraint::parse(constraint)
+            .is_none()
+            .then_some("the constraint can't be read"),
+        ProofLine::Other {
+            label: None, rule, ..
+        } => (!ALLOWED_RULES.contains(rule)).then_some("unknown rule"),
+        // Only derivations have IDs
+        ProofLine::Other {
+            label: Some(_),
+            rule,
+            ..
+        } => (!ALLOWED_RULES.contains(rule) || !DERIVING_RULES.contains(rule))
+            .then_some("unknown rule, or one that doesn't derive a constraint, after an ID"),
+        _ => None,
+    }
+}
+
+/// What's wrong with the shape of a pol derivation, if anything, without looking up the
+/// constraints it uses.
+fn pol_error(terms: &[PolToken]) -> Option<&'static str> {
+    #[derive(PartialEq)]
+    enum Item {
+        Constraint,
+        Scalar,
+        Literal,
+    }
+
+    /// Literals are also axioms, so they can be used as constraints
+    fn pop_constraint(stack: &mut Vec<Item>) -> bool {
+        matches!(stack.pop(), Some(Item::Constraint | Item::Literal))
+    }
+
+    let mut stack = Vec::new();
+    for term in terms {
+        let (operands_ok, message) = match term {
+            PolToken::Constraint(_) => {
+                stack.push(Item::Constraint);
+                continue;
+            }
+            PolToken::Scalar(_) => {
+                stack.push(Item::Scalar);
+                continue;
+            }
+            PolToken::Literal(_) => {
+                stack.push(Item::Literal);
+                continue;
+            }
+            PolToken::Add => (
+                pop_constraint(&mut stack) && pop_constraint(&mut stack),
+                "`+` needs two constraints",
+            ),
+            PolToken::Multiply => (
+                stack.pop() == Some(Item::Scalar) && pop_constraint(&mut stack),
+                "`*` needs a constraint and a scalar",
+            ),
+            PolToken::Divide => (
+                stack.pop() == Some(Item::Scalar) && pop_constraint(&mut stack),
+                "`d` needs a constraint and a scalar",
+            ),
+            PolToken::Saturate => (pop_constraint(&mut stack), "`s` needs a constraint"),
+            PolToken::Weaken => (
+                stack.pop() == Some(Item::Literal) && pop_constraint(&mut stack),
+                "`w` needs a constraint and a literal",
+            ),
+        };
+        if !operands_ok {
+            return Some(message);
+        }
+        stack.push(Item::Constraint);
+    }
+    match stack.len() {
+        0 => Some("the derivation is empty"),
+        1 => None,
+        _ => Some("the derivation leaves more than one constraint"),
+    }
+}
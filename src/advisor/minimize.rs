@@ -0,0 +1,253 @@
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::{self, BufRead, BufWriter, Write},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use tracing::info;
+
+use crate::{
+    PBarberError,
+    proof_line::{Deletion, ProofLine},
+    split_debug_marker,
+    trimmer::lit_def_literal,
+};
+
+use super::references;
+
+/// Written in place of a conclusion whose constraint was removed.
+const NO_CONCLUSION: &str = "conclusion NONE";
+
+/// The result of minimizing a failing proof.
+#[derive(Debug)]
+pub struct MinimizeOutcome {
+    pub original_lines: usize,
+    pub minimized_lines: usize,
+    /// Times the checker was run
+    pub checks: u64,
+}
+
+/// A line of the proof being minimized.
+struct MinLine {
+    text: String,
+    /// Earlier lines this one uses, so it goes when any of them does
+    uses: Vec<usize>,
+    /// Headers, the formula line and the output, conclusion and end lines are always kept
+    removable: bool,
+}
+
+struct Minimizer<'c> {
+    lines: Vec<MinLine>,
+    checker: &'c [String],
+    candidate_path: PathBuf,
+    checks: u64,
+}
+
+impl Minimizer<'_> {
+    /// Also remove every line that uses a removed line. Lines only use earlier lines, so one
+    /// pass in proof order is enough.
+    fn close(&self, removed: &mut [bool]) {
+        for (i, line) in self.lines.iter().enumerate() {
+            if line.removable && !removed[i] && line.uses.iter().any(|&used| removed[used]) {
+                removed[i] = true;
+            }
+        }
+    }
+
+    fn write_proof(&self, removed: &[bool], path: &Path) -> io::Result<usize> {
+        let mut out = BufWriter::new(File::create(path)?);
+        let mut written = 0;
+        for (line, _) in self
+            .lines
+            .iter()
+            .zip(removed)
+            .filter(|(_, removed)| !**removed)
+        {
+            if line.uses.iter().any(|&used| removed[used]) {
+                // Only a conclusion is kept when what it uses is removed
+                writeln!(out, "{NO_CONCLUSION}")?;
+            } else {
+                writeln!(out, "{}", line.text)?;
+            }
+            written += 1;
+        }
+        out.flush()?;
+        Ok(written)
+    }
+
+    /// Whether the proof without the `removed` lines still fails the checker.
+    fn fails(&mut self, removed: &[bool]) -> Result<bool, PBarberError> {
+        self.write_proof(removed, &self.candidate_path)?;
+        self.checks += 1;
+        let (program, args) = self.checker.split_first().ok_or_else(|| {
+            PBarberError::InvalidConfig("`--minimize` needs a checker command".to_string())
+        })?;
+        let status = Command::new(program)
+            .args(args)
+            .arg(&self.candidate_path)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .map_err(|e| {
+                PBarberError::VerificationFailed(format!("couldn't run `{program}`: {e}"))
+            })?;
+        Ok(!status.success())
+    }
+
+    /// Delta debugging: remove ever smaller chunks of the removable lines, keeping each
+    /// removal that leaves the proof failing.
+    fn minimize(&mut self) -> Result<Vec<bool>, PBarberError> {
+        let mut removed = vec![false; self.lines.len()];
+        let mut granularity = 2;
+        loop {
+            let candidates: Vec<usize> = (0..self.lines.len())
+                .filter(|&i| self.lines[i].removable && !removed[i])
+                .collect();
+            if candidates.is_empty() {
+                break;
+            }
+            let chunk_size = candidates.len().div_ceil(granularity);
+            let mut reduced = false;
+            for chunk in candidates.chunks(chunk_size) {
+                let mut attempt = removed.clone();
+                for &i in chunk {
+                    attempt[i] = true;
+                }
+                self.close(&mut attempt);
+                if self.fails(&attempt)? {
+                    removed = attempt;
+                    reduced = true;
+                    break;
+                }
+            }
+            if reduced {
+                let left = removed.iter().filter(|removed| !**removed).count();
+                info!("Minimizing: {left} lines left after {} checks", self.checks);
+                granularity = (granularity - 1).max(2);
+            } else if granularity >= candidates.len() {
+                break;
+            } else {
+                granularity = (granularity * 2).min(candidates.len());
+            }
+        }
+        Ok(removed)
+    }
+}
+
+/// Read the proof and work out which lines use which. Derived constraints have to be
+/// referred to by label, as removing lines renumbers the rest.
+fn read_lines<R: BufRead>(input: R) -> Result<Vec<MinLine>, PBarberError> {
+    let mut lines = Vec::new();
+    let mut formula_constraints = 0;
+    let mut labels = HashMap::<String, usize>::new();
+    let mut lit_defs = HashMap::<String, Vec<usize>>::new();
+    for (i, text) in input.lines().enumerate() {
+        let text = text?;
+        let content = split_debug_marker(&text).1;
+        let parsed = ProofLine::parse(content).ok();
+        let removable = !matches!(
+            parsed,
+            Some(
+                ProofLine::Header { .. }
+                    | ProofLine::Formula { .. }
+                    | ProofLine::Output { .. }
+                    | ProofLine::Conclusion { .. }
+                    | ProofLine::End
+            )
+        );
+        if let Some(ProofLine::Formula {
+            constraints: Some(constraints),
+        }) = parsed
+        {
+            formula_constraints = constraints;
+        }
+        match &parsed {
+            Some(ProofLine::Del(Deletion::Range(range))) if range.end > formula_constraints + 1 => {
+                return Err(derived_by_number(i + 1, &format!("{range:?}")));
+            }
+            _ => (),
+        }
+
+        let mut uses = Vec::new();
+        for id in parsed.as_ref().map(references).unwrap_or_default() {
+            if let Some(&used) = labels.get(id) {
+                uses.push(used);
+            } else if id.parse::<u64>().is_ok_and(|n| n > formula_constraints) {
+                return Err(derived_by_number(i + 1, id));
+            }
+        }
+        if !matches!(parsed, Some(ProofLine::Comment(_) | ProofLine::Blank)) {
+            for token in content.split_whitespace() {
+                uses.extend(
+                    lit_defs
+                        .get(token.trim_start_matches('~'))
+                        .into_iter()
+                        .flatten(),
+                );
+            }
+        }
+
+        if let Some(label) = parsed.as_ref().and_then(ProofLine::label) {
+            if let Some(lit) = lit_def_literal(label) {
+                lit_defs.entry(lit.to_string()).or_default().push(i);
+            }
+            labels.insert(label.to_string(), i);
+        }
+        uses.sort_unstable();
+        uses.dedup();
+        lines.push(MinLine {
+            text,
+            uses,
+            removable,
+        });
+    }
+    Ok(lines)
+}
+
+fn derived_by_number(line_number: usize, ids: &str) -> PBarberError {
+    PBarberError::InvalidConfig(format!(
+        "line {line_number} refers to derived constraints ({ids}) by number, which removing \
+         lines would change (trim it with `--implicit-ids` first)"
+    ))
+}
+
+/// Shrink a proof that fails `checker` (a command run with the path of a proof appended,
+/// failing if it exits non-zero) to a smaller one that still fails it, written to
+/// `output_path`. A line is only removed along with the lines that use it, so the smaller
+/// proof still refers only to constraints it derives.
+pub fn minimize<R: BufRead>(
+    input: R,
+    checker: &[String],
+    output_path: &Path,
+) -> Result<MinimizeOutcome, PBarberError> {
+    let mut candidate_path = output_path.as_os_str().to_owned();
+    candidate_path.push(".candidate.tmp");
+    let mut minimizer = Minimizer {
+        lines: read_lines(input)?,
+        checker,
+        candidate_path: candidate_path.into(),
+        checks: 0,
+    };
+    let original_lines = minimizer.lines.len();
+    let result = minimizer
+        .fails(&vec![false; original_lines])
+        .and_then(|fails| {
+            if fails {
+                minimizer.minimize()
+            } else {
+                Err(PBarberError::InvalidConfig(
+                    "the proof passes the checker, so there is no failure to minimize".to_string(),
+                ))
+            }
+        });
+    let _ = fs::remove_file(&minimizer.candidate_path);
+    let minimized_lines = minimizer.write_proof(&result?, output_path)?;
+    Ok(MinimizeOutcome {
+        original_lines,
+        minimized_lines,
+        checks: minimizer.checks,
+    })
+}
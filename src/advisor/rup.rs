@@ -0,0 +1,204 @@
+use std::{collections::HashMap, fmt};
+
+use crate::{
+    opb_model::OpbModel,
+    pol_eval::{Constraint, evaluate_pol},
+    proof_line::ProofLine,
+    propagator::Propagator,
+};
+
+use super::MAX_EXAMPLES;
+
+/// An assertion the checker can't verify by unit propagation.
+#[derive(Debug, Clone)]
+pub struct NotRup {
+    pub line_number: u64,
+    /// RUP with everything derived before it, but not from the hints it lists
+    pub from_hints: bool,
+}
+
+/// What `advise --rup` found.
+#[derive(Debug, Default)]
+pub struct RupFindings {
+    /// Assertions checked
+    pub checked: u64,
+    pub not_rup: Vec<NotRup>,
+    /// Constraints that couldn't be worked out (e.g. from an unknown rule) and were left out
+    /// of the propagation, so an assertion that isn't RUP without them might be with them
+    pub unreadable: u64,
+}
+
+impl RupFindings {
+    /// Whether the assertions found not to be RUP will certainly fail.
+    pub fn certain(&self) -> bool {
+        self.unreadable == 0
+    }
+
+    pub(crate) fn write_porcelain(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "rup_checked {}", self.checked)?;
+        writeln!(f, "rup_unreadable {}", self.unreadable)?;
+        for not_rup in &self.not_rup {
+            let key = if not_rup.from_hints {
+                "not_rup_from_hints"
+            } else {
+                "not_rup"
+            };
+            writeln!(f, "{key} {}", not_rup.line_number)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for RupFindings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Assertions that aren't RUP: {} of {}",
+            self.not_rup.len(),
+            self.checked
+        )?;
+        for not_rup in self.not_rup.iter().take(MAX_EXAMPLES) {
+            let hints = if not_rup.from_hints {
+                " (from its hints)"
+            } else {
+                ""
+            };
+            writeln!(f, " ∟ line {}{hints}", not_rup.line_number)?;
+        }
+        if self.not_rup.len() > MAX_EXAMPLES {
+            writeln!(f, " ∟ ...")?;
+        }
+        if !self.certain() {
+            writeln!(
+                f,
+                " ∟ {} constraints couldn't be worked out and were left out.",
+                self.unreadable
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Works out the constraint each line of a proof derives, and checks whether each assertion
+/// is RUP with respect to the formula and the constraints derived before it.
+pub(crate) struct RupChecker {
+    propagator: Propagator,
+    /// Formula then derived constraints, numbered from 1, or `None` if they couldn't be
+    /// worked out
+    constraints: Vec<Option<Constraint>>,
+    labels: HashMap<String, usize>,
+    findings: RupFindings,
+}
+
+impl RupChecker {
+    pub(crate) fn new(model: &OpbModel) -> Self {
+        let mut checker = Self {
+            propagator: Propagator::default(),
+            constraints: Vec::with_capacity(model.len()),
+            labels: HashMap::new(),
+            findings: RupFindings::default(),
+        };
+        for constraint in model.constraints() {
+            if let Some(parsed) = constraint.parsed() {
+                checker.propagator.add(parsed);
+            } else if let Some(halves) = equality_halves(constraint.text()) {
+                for half in &halves {
+                    checker.propagator.add(half);
+                }
+            } else {
+                checker.findings.unreadable += 1;
+            }
+            checker.constraints.push(constraint.parsed().cloned());
+        }
+        checker
+    }
+
+    fn lookup(&self, id: &str) -> Option<&Constraint> {
+        let index = match id.parse::<usize>() {
+            Ok(number) => number.checked_sub(1)?,
+            Err(_) => *self.labels.get(id)?,
+        };
+        self.constraints.get(index)?.as_ref()
+    }
+
+    /// Check the line if it's an assertion, and add the constraint it derives.
+    pub(crate) fn see_line(&mut self, line_number: u64, line: &str) {
+        let Ok(parsed) = ProofLine::parse(line) else {
+            return;
+        };
+        if !parsed.derives() {
+            return;
+        }
+        let constraint = match &parsed {
+            ProofLine::Pol { terms, .. } => evaluate_pol(terms, |id| self.lookup(id)),
+            ProofLine::Assertion {
+                constraint,
+                antecedents,
+                ..
+            } => {
+                let constraint = Constraint::parse(constraint);
+                if let Some(constraint) = &constraint {
+                    self.check(line_number, constraint, *antecedents);
+                }
+                constraint
+            }
+            ProofLine::Rup { constraint, .. }
+            | ProofLine::Red { constraint, .. }
+            | ProofLine::Ia { constraint, .. } => Constraint::parse(constraint),
+            _ => None,
+        };
+        match &constraint {
+            Some(constraint) => self.propagator.add(constraint),
+            None => self.findings.unreadable += 1,
+        }
+        if let Some(label) = parsed.label() {
+            self.labels
+                .insert(label.to_string(), self.constraints.len());
+        }
+        self.constraints.push(constraint);
+    }
+
+    /// Check an assertion against everything before it, then against its hints alone if it
+    /// lists some and they're all proof constraints.
+    fn check(&mut self, line_number: u64, constraint: &Constraint, antecedents: Option<&str>) {
+        self.findings.checked += 1;
+        if !self.propagator.is_rup(constraint) {
+            self.findings.not_rup.push(NotRup {
+                line_number,
+                from_hints: false,
+            });
+            return;
+        }
+        let hints: Option<Vec<&Constraint>> = antecedents
+            .unwrap_or_default()
+            .split_whitespace()
+            .map(|id| self.lookup(id))
+            .collect();
+        let Some(hints) = hints.filter(|hints| !hints.is_empty()) else {
+            return;
+        };
+        let mut propagator = Propagator::default();
+        for hint in hints {
+            propagator.add(hint);
+        }
+        if !propagator.is_rup(constraint) {
+            self.findings.not_rup.push(NotRup {
+                line_number,
+                from_hints: true,
+            });
+        }
+    }
+
+    pub(crate) fn finish(self) -> RupFindings {
+        self.findings
+    }
+}
+
+/// The `>=` and `<=` halves of an equality of the model.
+fn equality_halves(text: &str) -> Option<[Constraint; 2]> {
+    let (lhs, rhs) = text.split_once(" = ")?;
+    Some([
+        Constraint::parse(&format!("{lhs} >= {rhs}"))?,
+        Constraint::parse(&format!("{lhs} <= {rhs}"))?,
+    ])
+}
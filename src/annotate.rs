@@ -0,0 +1,203 @@
+use std::{
+    collections::BTreeSet,
+    fs::OpenOptions,
+    io::{BufRead, Write},
+};
+
+use flatzinc_serde::{Argument, Domain, Literal};
+use rangelist::IntervalIterator;
+use ustr::Ustr;
+
+use crate::{
+    AnnotateConfig, FORWARD_LIT_DEF_PREFIX, PBarberError, REVERSE_LIT_DEF_PREFIX,
+    cp_lit_map::{CPLitData, CPLitMap},
+    fzn_model::FznModel,
+    justifier::fzn_constraint,
+    split_debug_marker,
+};
+
+/// Copy a proof, adding comments that describe the FlatZinc constraint above each line that
+/// references one (`@f<n>`), and the CP meaning of the literal above each literal definition.
+/// Returns the number of lines annotated.
+pub fn annotate<R: BufRead, W: Write>(
+    input: R,
+    mut out: W,
+    config: &AnnotateConfig,
+) -> Result<u64, PBarberError> {
+    let annotator = Annotator::new(config)?;
+    let mut annotated = 0;
+    for line in input.lines() {
+        let line = line?;
+        let comments = annotator.comments(&line);
+        if !comments.is_empty() {
+            annotated += 1;
+        }
+        for comment in comments {
+            writeln!(out, "{comment}")?;
+        }
+        writeln!(out, "{line}")?;
+    }
+    out.flush()?;
+    Ok(annotated)
+}
+
+/// Describes the lines of a proof a line at a time, as [`annotate`] does.
+pub struct Annotator {
+    fzn: FznModel,
+    cp_lit_map: Option<CPLitMap>,
+}
+
+impl Annotator {
+    pub fn new(config: &AnnotateConfig) -> Result<Self, PBarberError> {
+        let fzn_file = OpenOptions::new().read(true).open(&config.fzn_path)?;
+        // Only the constraints the proof refers to are described
+        let fzn = FznModel::from_reader(fzn_file, true)?;
+        let cp_lit_map = match &config.lits_path {
+            Some(lits_path) => Some(CPLitMap::try_from_reader(
+                OpenOptions::new().read(true).open(lits_path)?,
+            )?),
+            None => None,
+        };
+        Ok(Self { fzn, cp_lit_map })
+    }
+
+    /// The comments to go above `line`, if any.
+    pub fn comments(&self, line: &str) -> Vec<String> {
+        let content = split_debug_marker(line).1;
+        let mut comments = Vec::new();
+
+        let label = content.split_whitespace().next().unwrap_or_default();
+        if let Some(meaning) = self
+            .cp_lit_map
+            .as_ref()
+            .and_then(|cp_lit_map| describe_lit_definition(cp_lit_map, label))
+        {
+            comments.push(format!("% {label}: {meaning}"));
+        }
+
+        let fzn_ids: BTreeSet<&str> = content
+            .split(|c: char| c.is_whitespace() || c == ':' || c == ';')
+            .filter(|token| is_fzn_id(token))
+            .collect();
+        for fzn_id in fzn_ids {
+            comments.extend(describe_constraint(&self.fzn, fzn_id));
+        }
+        comments
+    }
+}
+
+fn is_fzn_id(token: &str) -> bool {
+    token
+        .strip_prefix("@f")
+        .is_some_and(|n| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()))
+}
+
+/// What a literal definition (`@lf<var>` or `@lr<var>`) says about the CP variables.
+fn describe_lit_definition(cp_lit_map: &CPLitMap, label: &str) -> Option<String> {
+    let label = label.strip_prefix('@')?;
+    let (var, negated) = if let Some(var) = label.strip_prefix(FORWARD_LIT_DEF_PREFIX) {
+        (var, false)
+    } else {
+        (label.strip_prefix(REVERSE_LIT_DEF_PREFIX)?, true)
+    };
+    let meaning = match cp_lit_map.get(var)? {
+        CPLitData::Condition {
+            name,
+            operator,
+            value,
+            ..
+        } => {
+            let operator = if negated {
+                operator.negated()
+            } else {
+                operator
+            };
+            format!("[{name} {operator} {value}]")
+        }
+        CPLitData::Boolvar { name, .. } if negated => format!("¬{name}"),
+        CPLitData::Boolvar { name, .. } => name,
+    };
+    let lit = if negated {
+        format!("~{var}")
+    } else {
+        var.to_string()
+    };
+    Some(format!("{lit} means {meaning}"))
+}
+
+/// Comment lines giving the FlatZinc constraint `fzn_id` and the domains of its variables.
+fn describe_constraint(fzn: &FznModel, fzn_id: &str) -> Vec<String> {
+    let Ok(constraint) = fzn_constraint(fzn, fzn_id) else {
+        return vec![format!("% {fzn_id}: not in the FlatZinc model")];
+    };
+    let mut variables = BTreeSet::new();
+    let mut describe = |literal: &Literal<Ustr>| match literal {
+        Literal::Identifier(id) => match fzn.array(id) {
+            Some(array) => {
+                let contents: Vec<String> = array.contents.iter().map(literal_name).collect();
+                for literal in &array.contents {
+                    if let Literal::Identifier(id) = literal {
+                        variables.insert(*id);
+                    }
+                }
+                format!("{id} = [{}]", contents.join(", "))
+            }
+            None => {
+                variables.insert(*id);
+                id.to_string()
+            }
+        },
+        literal => literal_name(literal),
+    };
+    let args: Vec<String> = constraint
+        .args
+        .iter()
+        .map(|arg| match arg {
+            Argument::Array(literals) => {
+                let literals: Vec<String> = literals.iter().map(&mut describe).collect();
+                format!("[{}]", literals.join(", "))
+            }
+            Argument::Literal(literal) => describe(literal),
+        })
+        .collect();
+
+    let mut comments = vec![format!(
+        "% {fzn_id}: {}({})",
+        constraint.id,
+        args.join(", ")
+    )];
+    let domains: Vec<String> = variables
+        .iter()
+        .filter_map(|id| {
+            let domain = fzn.variable(id)?.domain.as_ref()?;
+            Some(format!("{id} in {}", domain_name(domain)))
+        })
+        .collect();
+    if !domains.is_empty() {
+        comments.push(format!("%   {}", domains.join(", ")));
+    }
+    comments
+}
+
+fn literal_name(literal: &Literal<Ustr>) -> String {
+    match literal {
+        Literal::Int(value) => value.to_string(),
+        Literal::Bool(value) => value.to_string(),
+        Literal::Identifier(id) => id.to_string(),
+        literal => format!("{literal:?}"),
+    }
+}
+
+fn domain_name(domain: &Domain) -> String {
+    let intervals: Vec<String> = match domain {
+        Domain::Int(ranges) => ranges
+            .intervals()
+            .map(|r| format!("{}..{}", r.start(), r.end()))
+            .collect(),
+        Domain::Float(ranges) => ranges
+            .intervals()
+            .map(|r| format!("{}..{}", r.start(), r.end()))
+            .collect(),
+    };
+    intervals.join(" ∪ ")
+}
@@ -0,0 +1,204 @@
+use std::{
+    fs::File,
+    io::{self, Seek, Write},
+    mem,
+};
+
+#[cfg(feature = "justify")]
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::{
+    io::{AsyncRead, AsyncWrite, AsyncWriteExt},
+    sync::mpsc,
+    task::{self, JoinHandle},
+};
+
+#[cfg(feature = "justify")]
+use crate::{JustifierConfig, justifier::Justifier};
+use crate::{PBarberError, ProofFileStats, TrimmerConfig, trimmer::Trimmer};
+
+/// Bytes of output collected before they're passed to the async writer.
+const CHUNK_BYTES: usize = 64 * 1024;
+/// Output chunks or input lines buffered between the blocking and async sides.
+const CHANNEL_CAPACITY: usize = 64;
+
+/// Everything produced by an [`AsyncTrimmer`] run besides the trimmed proof.
+pub struct AsyncTrimOutcome {
+    pub stats: Option<(ProofFileStats, ProofFileStats)>,
+    pub warnings: Vec<String>,
+    pub formula_core: Option<Vec<String>>,
+}
+
+/// Trims proofs read from an [`AsyncRead`] into an [`AsyncWrite`]. The proof is read in
+/// reverse, so it's spooled to a temporary file first. The trimming itself runs on tokio's
+/// blocking pool, so it doesn't hold up the runtime's threads.
+pub struct AsyncTrimmer {
+    config: TrimmerConfig,
+}
+
+impl AsyncTrimmer {
+    pub fn new(config: TrimmerConfig) -> Self {
+        Self { config }
+    }
+
+    pub async fn trim<I, O>(&self, input: I, out: O) -> Result<AsyncTrimOutcome, PBarberError>
+    where
+        I: AsyncRead + Unpin,
+        O: AsyncWrite + Unpin,
+    {
+        let spooled = spool(input).await?;
+        let config = self.config.clone();
+        let (sender, receiver) = mpsc::channel(CHANNEL_CAPACITY);
+        let trimming = task::spawn_blocking(move || {
+            let mut trimmer = Trimmer::try_with_config(spooled, ChunkSender::new(sender), config)?;
+            let stats = trimmer.trim()?;
+            Ok(AsyncTrimOutcome {
+                stats,
+                warnings: trimmer.warnings().map(str::to_string).collect(),
+                formula_core: trimmer.formula_core(),
+            })
+        });
+        let copied = copy_chunks(receiver, out).await;
+        let outcome = join(trimming, "trimmer").await;
+        // An output error stops the trimmer too, but it's the more useful one to report
+        copied?;
+        outcome
+    }
+}
+
+/// Justifies proofs read from an [`AsyncRead`] into an [`AsyncWrite`], with the justifying
+/// on tokio's blocking pool. A proof read forwards is streamed into the justifier as it
+/// arrives, otherwise it's spooled to a temporary file to be read in reverse.
+#[cfg(feature = "justify")]
+pub struct AsyncJustifier {
+    config: JustifierConfig,
+}
+
+#[cfg(feature = "justify")]
+impl AsyncJustifier {
+    pub fn new(config: JustifierConfig) -> Self {
+        Self { config }
+    }
+
+    pub async fn style<I, O>(
+        &self,
+        input: I,
+        out: O,
+    ) -> Result<Option<(ProofFileStats, ProofFileStats)>, PBarberError>
+    where
+        I: AsyncRead + Unpin,
+        O: AsyncWrite + Unpin,
+    {
+        let config = self.config.clone();
+        let (sender, receiver) = mpsc::channel(CHANNEL_CAPACITY);
+        let chunks = ChunkSender::new(sender);
+        if config.read_forwards {
+            let (line_sender, mut line_receiver) = mpsc::channel(CHANNEL_CAPACITY);
+            let styling = task::spawn_blocking(move || {
+                let lines = std::iter::from_fn(move || line_receiver.blocking_recv());
+                Justifier::try_from_lines(lines, chunks, config)?.style()
+            });
+            let ((), copied) =
+                tokio::join!(feed_lines(input, line_sender), copy_chunks(receiver, out));
+            let stats = join(styling, "justifier").await;
+            copied?;
+            stats
+        } else {
+            let spooled = spool(input).await?;
+            let styling = task::spawn_blocking(move || {
+                Justifier::try_with_config(spooled, chunks, config)?.style()
+            });
+            let copied = copy_chunks(receiver, out).await;
+            let stats = join(styling, "justifier").await;
+            copied?;
+            stats
+        }
+    }
+}
+
+/// Copy an async stream into an anonymous temporary file so it can be read in reverse.
+async fn spool<I: AsyncRead + Unpin>(mut input: I) -> io::Result<File> {
+    let mut spooled = tokio::fs::File::from_std(tempfile::tempfile()?);
+    tokio::io::copy(&mut input, &mut spooled).await?;
+    spooled.flush().await?;
+    let mut spooled = spooled.into_std().await;
+    spooled.rewind()?;
+    Ok(spooled)
+}
+
+/// Send the lines of `input` to the justifier until it runs out or the justifier stops.
+/// Read errors are passed on for the justifier to report.
+#[cfg(feature = "justify")]
+async fn feed_lines<I: AsyncRead + Unpin>(input: I, sender: mpsc::Sender<io::Result<String>>) {
+    let mut lines = BufReader::new(input).lines();
+    while let Some(line) = lines.next_line().await.transpose() {
+        let failed = line.is_err();
+        if sender.send(line).await.is_err() || failed {
+            return;
+        }
+    }
+}
+
+/// Write the chunks sent from the blocking side to `out`, until the sender is dropped.
+async fn copy_chunks<O: AsyncWrite + Unpin>(
+    mut receiver: mpsc::Receiver<Vec<u8>>,
+    mut out: O,
+) -> io::Result<()> {
+    while let Some(chunk) = receiver.recv().await {
+        out.write_all(&chunk).await?;
+    }
+    out.flush().await
+}
+
+async fn join<T>(
+    handle: JoinHandle<Result<T, PBarberError>>,
+    name: &str,
+) -> Result<T, PBarberError> {
+    handle
+        .await
+        .map_err(|_| PBarberError::Internal(format!("{name} task panicked")))?
+}
+
+/// Writer that passes what's written to it down a channel, in chunks.
+struct ChunkSender {
+    sender: mpsc::Sender<Vec<u8>>,
+    chunk: Vec<u8>,
+}
+
+impl ChunkSender {
+    fn new(sender: mpsc::Sender<Vec<u8>>) -> Self {
+        Self {
+            sender,
+            chunk: Vec::new(),
+        }
+    }
+
+    fn send(&mut self) -> io::Result<()> {
+        if self.chunk.is_empty() {
+            return Ok(());
+        }
+        let chunk = mem::take(&mut self.chunk);
+        self.sender
+            .blocking_send(chunk)
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "output stopped"))
+    }
+}
+
+impl Write for ChunkSender {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.chunk.extend_from_slice(buf);
+        if self.chunk.len() >= CHUNK_BYTES {
+            self.send()?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.send()
+    }
+}
+
+impl Drop for ChunkSender {
+    fn drop(&mut self) {
+        let _ = self.send();
+    }
+}
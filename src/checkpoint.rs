@@ -0,0 +1,96 @@
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Write},
+    path::Path,
+};
+
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "justify")]
+use crate::justifier::coverage::CoverageRecorder;
+use crate::{PBarberError, RecordedStats};
+
+/// How far a `style` run got, saved periodically so an interrupted run can carry on from there
+/// rather than starting again.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Checkpoint {
+    /// Lines of the input already styled
+    pub input_lines: u64,
+    /// Length of the output written for those lines
+    pub output_bytes: u64,
+    /// Lines of output written for them
+    #[serde(default)]
+    pub output_lines: u64,
+    pub read_forwards: bool,
+    /// Definition IDs (`@lf<var>` or `@lr<var>`) of the literals defined so far
+    pub defined_lits: Vec<String>,
+    /// Definition IDs of the Boolvar literals among `defined_lits`
+    #[serde(default)]
+    pub defined_boolvars: Vec<String>,
+    pub defined_bounds: Vec<String>,
+    /// Assertions read but not yet justified, by ID, with the number of the line each was read
+    /// from
+    pub pending_assertions: BTreeMap<String, (u64, String)>,
+    /// The input and output stats recorded so far, if stats are being recorded
+    #[serde(default)]
+    pub stats: Option<(RecordedStats, RecordedStats)>,
+    #[cfg(feature = "justify")]
+    #[serde(default)]
+    pub(crate) coverage: Option<CoverageRecorder>,
+}
+
+impl Checkpoint {
+    pub fn load(path: &Path) -> Result<Self, PBarberError> {
+        let file =
+            File::open(path).map_err(|err| PBarberError::reading("checkpoint", path, err))?;
+        serde_json::from_reader(BufReader::new(file)).map_err(|err| {
+            PBarberError::MalformedInput(format!("checkpoint `{}`: {err}", path.display()))
+        })
+    }
+
+    /// Write the checkpoint to a temporary file and rename it over `path`, so an interruption
+    /// never leaves a half-written checkpoint.
+    pub fn save(&self, path: &Path) -> Result<(), PBarberError> {
+        let temp_path = path.with_extension("tmp");
+        let mut out = BufWriter::new(File::create(&temp_path)?);
+        serde_json::to_writer(&mut out, self).map_err(io::Error::other)?;
+        out.flush()?;
+        fs::rename(&temp_path, path)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn saved_checkpoints_load_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("checkpoint.json");
+        let checkpoint = Checkpoint {
+            input_lines: 3,
+            output_bytes: 42,
+            read_forwards: true,
+            defined_lits: vec!["@lfx1".to_string()],
+            defined_bounds: Vec::new(),
+            pending_assertions: BTreeMap::from([(
+                "@c2".to_string(),
+                (2, "@c2 a 1 x1 >= 1 ;".to_string()),
+            )]),
+            ..Checkpoint::default()
+        };
+
+        checkpoint.save(&path).unwrap();
+        let loaded = Checkpoint::load(&path).unwrap();
+
+        assert_eq!(loaded.input_lines, 3);
+        assert_eq!(loaded.output_bytes, 42);
+        assert!(loaded.read_forwards);
+        assert_eq!(loaded.defined_lits, checkpoint.defined_lits);
+        assert_eq!(loaded.pending_assertions, checkpoint.pending_assertions);
+        // Only the checkpoint is left, not the temporary file it was written through
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+}
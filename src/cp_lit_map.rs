@@ -1,3 +1,4 @@
+use crate::PBarberError;
 use serde::Deserialize;
 use std::{
     collections::HashMap,
@@ -5,16 +6,18 @@ use std::{
     io::{BufReader, Read},
 };
 
-#[derive(Debug, Deserialize, Clone)]
+/// The kind of CP variable a literal is about.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
 #[serde(rename_all = "lowercase")]
-pub(crate) enum CPVarType {
+pub enum CPVarType {
     IntVar,
     BoolVar,
 }
 
-#[derive(Debug, Deserialize, Clone, Copy)]
+/// The comparison of a [`CPLitData::Condition`] literal.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
 #[serde(rename_all = "lowercase")]
-pub(crate) enum CPOperator {
+pub enum CPOperator {
     #[serde(alias = "<")]
     Less,
     #[serde(alias = ">=")]
@@ -26,7 +29,8 @@ pub(crate) enum CPOperator {
 }
 
 impl CPOperator {
-    pub(crate) fn negated(&self) -> Self {
+    /// The operator of the negated condition.
+    pub fn negated(&self) -> Self {
         match self {
             Self::Less => Self::GreaterEqual,
             Self::GreaterEqual => Self::Less,
@@ -48,38 +52,111 @@ impl fmt::Display for CPOperator {
     }
 }
 
-#[derive(Debug, Deserialize, Clone)]
+/// What a PB literal means in the CP model, as in the literal mapping JSON file.
+#[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
 #[serde(tag = "type", rename_all = "lowercase")]
-pub(crate) enum CPLitData {
-    #[serde(rename_all = "camelCase")]
+pub enum CPLitData {
+    /// `[name operator value]`, e.g. `[x >= 2]`
     Condition {
-        _cpvartype: CPVarType,
+        #[serde(rename = "cpvartype")]
+        var_type: CPVarType,
         name: String,
         operator: CPOperator,
         value: String,
     },
-    #[serde(rename_all = "camelCase")]
-    Boolvar { _cpvartype: CPVarType, name: String },
+    /// A Boolean CP variable, or `name=value` for a 0-1 integer variable
+    Boolvar {
+        #[serde(rename = "cpvartype")]
+        var_type: CPVarType,
+        name: String,
+    },
 }
 
-pub(crate) struct CPLitMap {
+/// The meaning of each PB variable in the CP model, by PB variable name. Read one from the
+/// literal mapping JSON file with [`CPLitMap::from_reader`], or build one up with
+/// [`CPLitMap::insert`] or by collecting `(name, data)` pairs.
+#[derive(Debug, Clone, Default)]
+pub struct CPLitMap {
     raw_map: HashMap<String, CPLitData>,
 }
 
 impl CPLitMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
     pub fn from_reader<R: Read>(reader: R) -> Self {
-        let buffered = BufReader::new(reader);
-        let raw_map =
-            serde_json::from_reader(buffered).expect("Failed to parse literal mapping data.");
-        Self { raw_map }
+        Self::try_from_reader(reader).expect("Failed to parse literal mapping data.")
     }
 
-    pub fn get(&self, pb_var: &String) -> Option<CPLitData> {
+    /// [`CPLitMap::from_reader`], failing with [`PBarberError::ParseError`] if the mapping
+    /// isn't valid JSON of the right shape.
+    pub fn try_from_reader<R: Read>(reader: R) -> Result<Self, PBarberError> {
+        let raw_map = serde_json::from_reader(BufReader::new(reader))
+            .map_err(|err| PBarberError::from_json("literal mapping JSON", err))?;
+        Ok(Self { raw_map })
+    }
+
+    pub fn get(&self, pb_var: &str) -> Option<CPLitData> {
         self.raw_map.get(pb_var).cloned()
     }
+
+    /// Set the meaning of `pb_var`, returning its previous meaning if it had one.
+    pub fn insert(&mut self, pb_var: impl Into<String>, data: CPLitData) -> Option<CPLitData> {
+        self.raw_map.insert(pb_var.into(), data)
+    }
+
+    pub fn len(&self) -> usize {
+        self.raw_map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.raw_map.is_empty()
+    }
+
+    /// The PB variable names and their meanings, in no particular order.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &CPLitData)> {
+        self.raw_map
+            .iter()
+            .map(|(name, data)| (name.as_str(), data))
+    }
+}
+
+impl<S: Into<String>> Extend<(S, CPLitData)> for CPLitMap {
+    fn extend<I: IntoIterator<Item = (S, CPLitData)>>(&mut self, iter: I) {
+        self.raw_map
+            .extend(iter.into_iter().map(|(name, data)| (name.into(), data)));
+    }
+}
+
+impl<S: Into<String>> FromIterator<(S, CPLitData)> for CPLitMap {
+    fn from_iter<I: IntoIterator<Item = (S, CPLitData)>>(iter: I) -> Self {
+        let mut map = Self::new();
+        map.extend(iter);
+        map
+    }
 }
 
 impl CPLitData {
+    /// The literal `[name operator value]` on the integer variable `name`.
+    pub fn condition(name: impl Into<String>, operator: CPOperator, value: impl ToString) -> Self {
+        CPLitData::Condition {
+            var_type: CPVarType::IntVar,
+            name: name.into(),
+            operator,
+            value: value.to_string(),
+        }
+    }
+
+    /// The Boolean variable `name`.
+    pub fn boolvar(name: impl Into<String>) -> Self {
+        CPLitData::Boolvar {
+            var_type: CPVarType::BoolVar,
+            name: name.into(),
+        }
+    }
+
+    /// The name of the CP variable the literal is about.
     pub fn get_name(&self) -> String {
         match self {
             CPLitData::Condition { name, .. } => name.clone(),
@@ -0,0 +1,67 @@
+use tracing::warn;
+
+use crate::{PBarberError, WarningKind};
+
+/// Where the messages about a run that aren't part of its output go, so library users can
+/// show, collect or suppress them. Install one on a justifier with
+/// [`Justifier::set_diagnostics`](crate::justifier::Justifier::set_diagnostics); by default
+/// they're logged, as [`LogDiagnostics`] does.
+pub trait Diagnostics: Send {
+    /// Report a warning. Returning an error stops the run with it, as `--deny-warnings` does.
+    fn warning(&mut self, kind: WarningKind, message: &str) -> Result<(), PBarberError>;
+
+    /// The justifier couldn't justify the assertion `id` with `justifier`, so it's kept as an
+    /// assertion. Returns the comment (without the `%`) to write above it in the proof, if
+    /// any.
+    fn failed_to_justify(&mut self, _id: &str, _justifier: &str, message: &str) -> Option<String> {
+        Some(format!(
+            "PBarber Justifier failed to justify the following: (error msg: {message})"
+        ))
+    }
+}
+
+/// Logs warnings through `tracing`, and comments on assertions that couldn't be justified.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct LogDiagnostics;
+
+impl Diagnostics for LogDiagnostics {
+    fn warning(&mut self, _kind: WarningKind, message: &str) -> Result<(), PBarberError> {
+        warn!("{message}");
+        Ok(())
+    }
+}
+
+/// Fails with [`PBarberError::DeniedWarning`] on any warning, as `--deny-warnings` does.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct DenyDiagnostics;
+
+impl Diagnostics for DenyDiagnostics {
+    fn warning(&mut self, kind: WarningKind, message: &str) -> Result<(), PBarberError> {
+        Err(PBarberError::DeniedWarning {
+            kind,
+            message: message.to_string(),
+        })
+    }
+}
+
+/// Drops every message, leaving no comments in the proof either.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SilentDiagnostics;
+
+impl Diagnostics for SilentDiagnostics {
+    fn warning(&mut self, _kind: WarningKind, _message: &str) -> Result<(), PBarberError> {
+        Ok(())
+    }
+
+    fn failed_to_justify(&mut self, _id: &str, _justifier: &str, _message: &str) -> Option<String> {
+        None
+    }
+}
+
+/// Collects the warnings, e.g. to show them once the run is done.
+impl Diagnostics for Vec<(WarningKind, String)> {
+    fn warning(&mut self, kind: WarningKind, message: &str) -> Result<(), PBarberError> {
+        self.push((kind, message.to_string()));
+        Ok(())
+    }
+}
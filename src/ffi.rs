@@ -0,0 +1,289 @@
+#[cfg(feature = "justify")]
+use std::sync::{Arc, Mutex};
+use std::{
+    cell::RefCell,
+    ffi::{CStr, CString, c_char, c_int},
+    fs::File,
+    io::BufWriter,
+    panic::{self, AssertUnwindSafe},
+    path::PathBuf,
+    ptr,
+};
+
+#[cfg(feature = "justify")]
+use crate::{JustifierConfig, WarningKind, diagnostics::Diagnostics, justifier::Justifier};
+use crate::{PBarberError, TrimmerConfig, trimmer::Trimmer};
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+    static LAST_WARNINGS: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// Trimming options, the same as the command line options of the same names. Zeroed
+/// options are the defaults.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct PBarberTrimOptions {
+    pub eager_deletion: bool,
+    pub lit_deletion: bool,
+    pub implicit_ids: bool,
+    pub faithful_deletions: bool,
+    pub reverse_output: bool,
+    pub parallel: bool,
+    /// Fail on warnings rather than recording them
+    pub deny_warnings: bool,
+    /// `--max-depth`, or 0 for no limit
+    pub max_depth: usize,
+    /// `--opb`, or null
+    pub opb_path: *const c_char,
+}
+
+/// Styling options, the same as the command line options of the same names. Zeroed options
+/// are the defaults.
+#[cfg(feature = "justify")]
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct PBarberStyleOptions {
+    pub read_forwards: bool,
+    pub strict: bool,
+    /// `--parallel-style`
+    pub parallel: bool,
+    pub lazy_fzn: bool,
+    /// `--max-line-cache`, or 0 for the default
+    pub max_line_cache: usize,
+}
+
+/// Trim the proof at `input_path` into `output_path`. `options` may be null for the defaults.
+/// Returns 0, or the exit code the command line tool gives for the error (see
+/// [`PBarberError::exit_code`]) with its message from [`pbarber_last_error`]. Warnings are
+/// given by [`pbarber_last_warnings`].
+///
+/// # Safety
+///
+/// The paths must be valid NUL-terminated strings, and `options` null or valid (with its
+/// `opb_path` null or a valid string).
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn pbarber_trim(
+    input_path: *const c_char,
+    output_path: *const c_char,
+    options: *const PBarberTrimOptions,
+) -> c_int {
+    run(|| {
+        let input_path = unsafe { path_arg(input_path, "input_path") }?;
+        let output_path = unsafe { path_arg(output_path, "output_path") }?;
+        let config = match unsafe { options.as_ref() } {
+            Some(options) => unsafe { trimmer_config(options) }?,
+            None => TrimmerConfig::default(),
+        };
+        let input = File::open(input_path)?;
+        let output = BufWriter::new(File::create(output_path)?);
+        let mut trimmer = Trimmer::try_with_config(input, output, config)?;
+        trimmer.trim()?;
+        Ok(trimmer.warnings().map(str::to_string).collect())
+    })
+}
+
+/// Justify the assertions in the proof at `input_path` into `output_path`, against the
+/// FlatZinc model (in JSON) at `fzn_path` and literal mapping at `lits_path`. `options` may
+/// be null for the defaults. Returns 0 or an exit code, as [`pbarber_trim`] does.
+///
+/// # Safety
+///
+/// The paths must be valid NUL-terminated strings, and `options` null or valid.
+#[cfg(feature = "justify")]
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn pbarber_style(
+    input_path: *const c_char,
+    output_path: *const c_char,
+    fzn_path: *const c_char,
+    lits_path: *const c_char,
+    options: *const PBarberStyleOptions,
+) -> c_int {
+    run(|| {
+        let input_path = unsafe { path_arg(input_path, "input_path") }?;
+        let output_path = unsafe { path_arg(output_path, "output_path") }?;
+        let fzn_path = unsafe { path_arg(fzn_path, "fzn_path") }?;
+        let lits_path = unsafe { path_arg(lits_path, "lits_path") }?;
+        let mut config = JustifierConfig::new(fzn_path, lits_path);
+        if let Some(options) = unsafe { options.as_ref() } {
+            config = config
+                .read_forwards(options.read_forwards)
+                .strict(options.strict)
+                .parallel(options.parallel)
+                .lazy_fzn(options.lazy_fzn);
+            if options.max_line_cache > 0 {
+                config = config.max_line_cache(options.max_line_cache);
+            }
+        }
+        let input = File::open(input_path)?;
+        let output = BufWriter::new(File::create(output_path)?);
+        let mut justifier = Justifier::try_with_config(input, output, config)?;
+        let warnings = SharedWarnings::default();
+        justifier.set_diagnostics(warnings.clone());
+        justifier.style()?;
+        Ok(warnings.take())
+    })
+}
+
+/// Collects the warnings of a styling run for the entry point, which keeps a clone of it.
+#[cfg(feature = "justify")]
+#[derive(Default, Clone)]
+struct SharedWarnings(Arc<Mutex<Vec<String>>>);
+
+#[cfg(feature = "justify")]
+impl SharedWarnings {
+    fn take(&self) -> Vec<String> {
+        std::mem::take(&mut self.0.lock().unwrap())
+    }
+}
+
+#[cfg(feature = "justify")]
+impl Diagnostics for SharedWarnings {
+    fn warning(&mut self, _kind: WarningKind, message: &str) -> Result<(), PBarberError> {
+        self.0.lock().unwrap().push(message.to_string());
+        Ok(())
+    }
+}
+
+/// The message of the last error on this thread, or null if there wasn't one. The string
+/// is valid until the next call into the library on this thread.
+#[unsafe(no_mangle)]
+pub extern "C" fn pbarber_last_error() -> *const c_char {
+    LAST_ERROR.with_borrow(|error| error.as_ref().map_or(ptr::null(), |error| error.as_ptr()))
+}
+
+/// The warnings of the last call into the library on this thread, one per line, or null if
+/// there weren't any. The string is valid until the next call into the library on this
+/// thread.
+#[unsafe(no_mangle)]
+pub extern "C" fn pbarber_last_warnings() -> *const c_char {
+    LAST_WARNINGS.with_borrow(|warnings| {
+        warnings
+            .as_ref()
+            .map_or(ptr::null(), |warnings| warnings.as_ptr())
+    })
+}
+
+/// Run an entry point, recording its error (or panic) as the last error, and the warnings it
+/// gives if it succeeds as the last warnings.
+fn run(entry_point: impl FnOnce() -> Result<Vec<String>, PBarberError>) -> c_int {
+    let result = panic::catch_unwind(AssertUnwindSafe(entry_point)).unwrap_or_else(|panic| {
+        let message = panic
+            .downcast_ref::<&str>()
+            .map(|message| message.to_string())
+            .or_else(|| panic.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "panicked".to_string());
+        Err(PBarberError::Internal(message))
+    });
+    let (code, error, warnings) = match result {
+        Ok(warnings) => (0, None, c_string(&warnings.join("\n"))),
+        Err(err) => (err.exit_code().into(), c_string(&err.to_string()), None),
+    };
+    LAST_ERROR.set(error);
+    LAST_WARNINGS.set(warnings);
+    code
+}
+
+/// `text` as a C string, or `None` if it's empty.
+fn c_string(text: &str) -> Option<CString> {
+    if text.is_empty() {
+        return None;
+    }
+    // C strings can't contain NULs
+    CString::new(text.replace('\0', " ")).ok()
+}
+
+/// # Safety
+///
+/// `path` must be null or a valid NUL-terminated string.
+unsafe fn path_arg(path: *const c_char, name: &str) -> Result<PathBuf, PBarberError> {
+    if path.is_null() {
+        return Err(PBarberError::Internal(format!("{name} is null")));
+    }
+    let path = unsafe { CStr::from_ptr(path) }
+        .to_str()
+        .map_err(|_| PBarberError::Internal(format!("{name} isn't valid UTF-8")))?;
+    Ok(PathBuf::from(path))
+}
+
+/// # Safety
+///
+/// `options.opb_path` must be null or a valid NUL-terminated string.
+unsafe fn trimmer_config(options: &PBarberTrimOptions) -> Result<TrimmerConfig, PBarberError> {
+    let opb_path = if options.opb_path.is_null() {
+        None
+    } else {
+        Some(unsafe { path_arg(options.opb_path, "opb_path") }?)
+    };
+    Ok(TrimmerConfig {
+        eager_deletion: options.eager_deletion,
+        lit_deletion: options.lit_deletion,
+        implicit_ids: options.implicit_ids,
+        faithful_deletions: options.faithful_deletions,
+        reverse_output: options.reverse_output,
+        parallel: options.parallel,
+        deny_warnings: options.deny_warnings,
+        max_depth: (options.max_depth > 0).then_some(options.max_depth),
+        opb_path,
+        ..TrimmerConfig::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    fn c_path(path: PathBuf) -> CString {
+        CString::new(path.into_os_string().into_string().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn trimming_gives_its_warnings() {
+        let dir = tempfile::tempdir().unwrap();
+        let input_path = dir.path().join("proof.pbp");
+        fs::write(
+            &input_path,
+            "\
+pseudo-Boolean proof version 2.0
+f 2 ;
+@c1 pol 1 2 + ;
+output NONE ;
+conclusion UNSAT : @c1 ;
+end pseudo-Boolean proof
+",
+        )
+        .unwrap();
+        let opb_path = dir.path().join("model.opb");
+        fs::write(&opb_path, "1 x1 >= 1 ;\n").unwrap();
+        let opb_path = c_path(opb_path);
+        let options = PBarberTrimOptions {
+            eager_deletion: false,
+            lit_deletion: false,
+            implicit_ids: false,
+            faithful_deletions: false,
+            reverse_output: false,
+            parallel: false,
+            deny_warnings: false,
+            max_depth: 0,
+            opb_path: opb_path.as_ptr(),
+        };
+
+        let code = unsafe {
+            pbarber_trim(
+                c_path(input_path).as_ptr(),
+                c_path(dir.path().join("trimmed.pbp")).as_ptr(),
+                &options,
+            )
+        };
+
+        assert_eq!(code, 0);
+        assert!(pbarber_last_error().is_null());
+        let warnings = unsafe { CStr::from_ptr(pbarber_last_warnings()) };
+        assert!(
+            warnings.to_str().unwrap().contains("the OPB model has 1"),
+            "{warnings:?}"
+        );
+    }
+}
@@ -0,0 +1,117 @@
+use std::{
+    collections::BTreeMap,
+    io::{BufReader, Read},
+    sync::OnceLock,
+};
+
+use flatzinc_serde::{Array, Constraint, FlatZinc, Variable};
+use serde::Deserialize;
+use serde_json::value::RawValue;
+use ustr::Ustr;
+
+use crate::PBarberError;
+
+/// The parts of a FlatZinc model the justifier looks up. Its constraints are either all
+/// parsed as it's read, or kept as JSON and each parsed the first time it's looked up.
+pub(crate) struct FznModel {
+    variables: BTreeMap<Ustr, Variable<Ustr>>,
+    arrays: BTreeMap<Ustr, Array<Ustr>>,
+    constraints: Constraints,
+}
+
+enum Constraints {
+    Parsed(Vec<Constraint<Ustr>>),
+    Lazy(Vec<LazyConstraint>),
+}
+
+/// The JSON of a constraint, with the constraint (or why it isn't one) once it's parsed.
+struct LazyConstraint {
+    json: Box<RawValue>,
+    parsed: OnceLock<Result<Constraint<Ustr>, String>>,
+}
+
+/// A FlatZinc model with its constraints left as JSON.
+#[derive(Deserialize)]
+struct LazyFlatZinc {
+    #[serde(default)]
+    variables: BTreeMap<Ustr, Variable<Ustr>>,
+    #[serde(default)]
+    arrays: BTreeMap<Ustr, Array<Ustr>>,
+    #[serde(default)]
+    constraints: Vec<Box<RawValue>>,
+}
+
+impl FznModel {
+    /// Read a model in JSON, leaving its constraints to be parsed when looked up if `lazy`.
+    pub(crate) fn from_reader(fzn: impl Read, lazy: bool) -> Result<Self, PBarberError> {
+        let reader = BufReader::new(fzn);
+        let to_err = |err| PBarberError::from_json("FlatZinc JSON", err);
+        if lazy {
+            let fzn: LazyFlatZinc = serde_json::from_reader(reader).map_err(to_err)?;
+            Ok(Self {
+                variables: fzn.variables,
+                arrays: fzn.arrays,
+                constraints: Constraints::Lazy(
+                    fzn.constraints
+                        .into_iter()
+                        .map(|json| LazyConstraint {
+                            json,
+                            parsed: OnceLock::new(),
+                        })
+                        .collect(),
+                ),
+            })
+        } else {
+            let fzn: FlatZinc<Ustr> = serde_json::from_reader(reader).map_err(to_err)?;
+            Ok(Self {
+                variables: fzn.variables,
+                arrays: fzn.arrays,
+                constraints: Constraints::Parsed(fzn.constraints),
+            })
+        }
+    }
+
+    /// The constraint at `index` in the model's list, if there is one. Fails with
+    /// [`PBarberError::JustificationError`] if it's left as JSON that isn't a constraint.
+    pub(crate) fn constraint(
+        &self,
+        index: usize,
+    ) -> Result<Option<&Constraint<Ustr>>, PBarberError> {
+        match &self.constraints {
+            Constraints::Parsed(constraints) => Ok(constraints.get(index)),
+            Constraints::Lazy(constraints) => {
+                let Some(constraint) = constraints.get(index) else {
+                    return Ok(None);
+                };
+                constraint
+                    .parsed
+                    .get_or_init(|| {
+                        serde_json::from_str(constraint.json.get()).map_err(|err| err.to_string())
+                    })
+                    .as_ref()
+                    .map(Some)
+                    .map_err(|err| {
+                        PBarberError::JustificationError(format!(
+                            "FlatZinc constraint {index} isn't valid ({err})"
+                        ))
+                    })
+            }
+        }
+    }
+
+    /// The number of constraints in the model, valid or not.
+    pub(crate) fn constraint_count(&self) -> usize {
+        match &self.constraints {
+            Constraints::Parsed(constraints) => constraints.len(),
+            Constraints::Lazy(constraints) => constraints.len(),
+        }
+    }
+
+    pub(crate) fn variable(&self, id: &Ustr) -> Option<&Variable<Ustr>> {
+        self.variables.get(id)
+    }
+
+    pub(crate) fn array(&self, id: &Ustr) -> Option<&Array<Ustr>> {
+        self.arrays.get(id)
+    }
+}
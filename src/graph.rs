@@ -0,0 +1,133 @@
+#[cfg(feature = "cli")]
+use clap::ValueEnum;
+use serde::Serialize;
+use std::collections::HashSet;
+use std::io::{self, Write};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(ValueEnum))]
+pub enum GraphFormat {
+    Dot,
+    Json,
+}
+
+impl GraphFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            GraphFormat::Dot => "graph.dot",
+            GraphFormat::Json => "graph.json",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GraphNode {
+    pub id: String,
+    pub rule: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GraphEdge {
+    pub from: String,
+    pub to: String,
+}
+
+/// Antecedent graph of the constraints kept by the trimmer. Edges point from an antecedent
+/// to the constraint derived from it.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ProofGraph {
+    pub nodes: Vec<GraphNode>,
+    pub edges: Vec<GraphEdge>,
+}
+
+impl ProofGraph {
+    pub fn add_node(&mut self, id: &str, rule: &str) {
+        self.nodes.push(GraphNode {
+            id: id.to_string(),
+            rule: rule.to_string(),
+        });
+    }
+
+    pub fn add_edge(&mut self, from: &str, to: &str) {
+        self.edges.push(GraphEdge {
+            from: from.to_string(),
+            to: to.to_string(),
+        });
+    }
+
+    /// Add a node for each ID that is only referenced, i.e. the formula constraints, as they
+    /// aren't derived in the proof.
+    pub fn add_referenced_nodes(&mut self) {
+        let mut seen: HashSet<String> = self.nodes.iter().map(|node| node.id.clone()).collect();
+        for edge in &self.edges {
+            if seen.insert(edge.from.clone()) {
+                self.nodes.push(GraphNode {
+                    id: edge.from.clone(),
+                    rule: "formula".to_string(),
+                });
+            }
+        }
+    }
+
+    pub fn write<W: Write>(&self, format: GraphFormat, out: W) -> io::Result<()> {
+        match format {
+            GraphFormat::Dot => self.write_dot(out),
+            GraphFormat::Json => self.write_json(out),
+        }
+    }
+
+    pub fn write_dot<W: Write>(&self, mut out: W) -> io::Result<()> {
+        writeln!(out, "digraph proof {{")?;
+        for node in &self.nodes {
+            writeln!(
+                out,
+                "  \"{}\" [label=\"{} ({})\"];",
+                escape_dot(&node.id),
+                escape_dot(&node.id),
+                escape_dot(&node.rule)
+            )?;
+        }
+        for edge in &self.edges {
+            writeln!(
+                out,
+                "  \"{}\" -> \"{}\";",
+                escape_dot(&edge.from),
+                escape_dot(&edge.to)
+            )?;
+        }
+        writeln!(out, "}}")
+    }
+
+    pub fn write_json<W: Write>(&self, out: W) -> io::Result<()> {
+        serde_json::to_writer_pretty(out, self).map_err(io::Error::other)
+    }
+}
+
+/// Escape `text` for a quoted DOT string.
+fn escape_dot(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dot_output_is_escaped_and_has_referenced_nodes() {
+        let mut graph = ProofGraph::default();
+        graph.add_node("@c\"1\\", "pol");
+        graph.add_edge("1", "@c\"1\\");
+        graph.add_referenced_nodes();
+        let mut out = Vec::new();
+        graph.write_dot(&mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            r#"digraph proof {
+  "@c\"1\\" [label="@c\"1\\ (pol)"];
+  "1" [label="1 (formula)"];
+  "1" -> "@c\"1\\";
+}
+"#
+        );
+    }
+}
@@ -0,0 +1,92 @@
+use std::{collections::BTreeMap, ops::Range};
+
+use ustr::{Ustr, UstrSet, existing_ustr, ustr};
+
+/// Set of constraint IDs or literals, interned so each distinct string is stored once no
+/// matter how many sets hold it, and hashed by pointer rather than by content.
+#[derive(Default, Clone)]
+pub(crate) struct IdSet {
+    ids: UstrSet,
+}
+
+impl IdSet {
+    pub(crate) fn contains(&self, id: &str) -> bool {
+        // Strings that were never interned can't be in any set
+        existing_ustr(id).is_some_and(|id| self.ids.contains(&id))
+    }
+
+    /// Returns whether the ID was newly inserted.
+    pub(crate) fn insert(&mut self, id: &str) -> bool {
+        self.ids.insert(ustr(id))
+    }
+
+    pub(crate) fn iter(&self) -> impl Iterator<Item = Ustr> + '_ {
+        self.ids.iter().copied()
+    }
+}
+
+/// Set of numeric constraint IDs made up of ranges, e.g. from `del range` lines. Ranges that
+/// overlap or touch are merged, and they're ordered by start, so looking up an ID is a search
+/// for the last range starting at or before it.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct RangeSet {
+    /// End of each range, by its start
+    ranges: BTreeMap<u64, u64>,
+}
+
+impl RangeSet {
+    pub(crate) fn contains(&self, id: u64) -> bool {
+        self.ranges
+            .range(..=id)
+            .next_back()
+            .is_some_and(|(_, &end)| id < end)
+    }
+
+    pub(crate) fn insert(&mut self, range: Range<u64>) {
+        if range.is_empty() {
+            return;
+        }
+        let (mut start, mut end) = (range.start, range.end);
+        if let Some((&before_start, &before_end)) = self.ranges.range(..=start).next_back()
+            && before_end >= start
+        {
+            start = before_start;
+        }
+        while let Some((&next_start, &next_end)) = self.ranges.range(start..=end).next() {
+            self.ranges.remove(&next_start);
+            end = end.max(next_end);
+        }
+        self.ranges.insert(start, end);
+    }
+}
+
+impl Extend<Range<u64>> for RangeSet {
+    fn extend<I: IntoIterator<Item = Range<u64>>>(&mut self, ranges: I) {
+        for range in ranges {
+            self.insert(range);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ranges_are_merged() {
+        let mut ranges = RangeSet::default();
+        ranges.extend([10..12, 3..5, 5..7, 11..20, 4..6, 30..30]);
+        assert_eq!(
+            ranges.ranges,
+            BTreeMap::from([(3, 7), (10, 20)]),
+            "touching and overlapping ranges merge, empty ones are left out"
+        );
+        assert!(!ranges.contains(2));
+        assert!(ranges.contains(3));
+        assert!(ranges.contains(6));
+        assert!(!ranges.contains(7));
+        assert!(ranges.contains(19));
+        assert!(!ranges.contains(20));
+        assert!(!ranges.contains(30));
+    }
+}
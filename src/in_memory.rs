@@ -0,0 +1,67 @@
+use std::io::Cursor;
+
+#[cfg(feature = "justify")]
+use crate::{JustifierConfig, justifier::Justifier};
+use crate::{PBarberError, ProofFileStats, TrimmerConfig, trimmer::Trimmer};
+
+/// A trimmed or styled proof held in memory, with its stats if they were asked for.
+pub struct ProofBuffer {
+    pub proof: Vec<u8>,
+    pub stats: Option<(ProofFileStats, ProofFileStats)>,
+}
+
+/// Trim a proof held in memory, without touching the file system.
+pub fn trim_bytes(proof: &[u8], config: TrimmerConfig) -> Result<ProofBuffer, PBarberError> {
+    let mut out = Vec::new();
+    let stats = Trimmer::try_with_config(Cursor::new(proof), &mut out, config)?.trim()?;
+    Ok(ProofBuffer { proof: out, stats })
+}
+
+/// Justify the assertions in a proof held in memory, against a FlatZinc model (in JSON) and
+/// literal mapping also held in memory. The paths in `config` aren't used.
+#[cfg(feature = "justify")]
+pub fn style_bytes(
+    proof: &[u8],
+    fzn: &[u8],
+    lits: &[u8],
+    config: JustifierConfig,
+) -> Result<ProofBuffer, PBarberError> {
+    let mut out = Vec::new();
+    let stats =
+        Justifier::try_with_sources(Cursor::new(proof.to_vec()), &mut out, fzn, lits, config)?
+            .style()?;
+    Ok(ProofBuffer { proof: out, stats })
+}
+
+#[cfg(all(test, feature = "justify"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn depth_limited_proofs_can_be_styled() {
+        let proof = "\
+pseudo-Boolean proof version 2.0
+f 0
+@c1 pol x1 x2 + ;
+@c2 pol @c1 x3 + ;
+@c3 pol @c2 2 * ;
+output NONE
+conclusion UNSAT : @c3 ;
+end pseudo-Boolean proof
+";
+        let restated = "@c2 a 1 x1 1 x2 1 x3 >= 0 ;";
+        let config = TrimmerConfig {
+            max_depth: Some(1),
+            ..TrimmerConfig::default()
+        };
+        let trimmed = trim_bytes(proof.as_bytes(), config).unwrap().proof;
+        assert!(String::from_utf8_lossy(&trimmed).contains(restated));
+
+        // The restated constraint has no justifier, so is written as it is
+        let fzn = br#"{"solve": {"method": "satisfy"}}"#;
+        let styled = style_bytes(&trimmed, fzn, b"{}", JustifierConfig::default())
+            .unwrap()
+            .proof;
+        assert!(String::from_utf8_lossy(&styled).contains(restated));
+    }
+}
@@ -1,95 +1,309 @@
 use crate::{
-    ALLOWED_RULES, FORWARD_LIT_DEF_PREFIX, JustifierConfig, PBarberError, ProofFileStats,
-    ProofReader, REVERSE_LIT_DEF_PREFIX,
+    FORWARD_LIT_DEF_PREFIX, JustifierConfig, LinePosition, PBarberError, ProofFileStats,
+    ProofReader, REVERSE_LIT_DEF_PREFIX, SUPPORTED_JUSTIFIERS, WarningKind,
+    checkpoint::Checkpoint,
     cp_lit_map::{CPLitData, CPLitMap, CPOperator},
+    diagnostics::{Diagnostics, LogDiagnostics, SilentDiagnostics},
+    fzn_model::FznModel,
+    limits::ResourceGuard,
+    line_map::LineMap,
+    observer::{Observer, ObserverSlot},
+    opb_model::OpbModel,
+    proof_line::{PolToken, ProofLine, line_label, line_rule},
+    reverse_lines::ReverseLines,
+    split_debug_marker,
+    stats::{HeavyLine, JustificationLineKinds},
+    trimmer::spool,
+    verify::LineOrigin,
 };
-use flatzinc_serde::{Domain, FlatZinc, RangeList};
+use coverage::CoverageRecorder;
+use flatzinc_serde::{Domain, RangeList};
 use int_linear::IntLinearJustifier;
 use int_var_def::IntVarDefJustifier;
+use lit_set::LitSet;
 use logos::Logos;
 use pboxide_formula::{
-    lit::Lit as PBLiteral,
+    lit::{Lit as PBLiteral, Var},
     prelude::{DynPBConstraint, ToPrettyString, VarNameManager as PBVarNameManager},
 };
 use pboxide_parser::{opb_parser::parse_single_constraint, opb_token::OPBToken};
+use profile::{Profile, Section, SectionStart};
 use rangelist::IntervalIterator;
-use rev_buf_reader::RevBufReader;
+use rayon::prelude::*;
 use std::{
-    collections::{HashMap, HashSet},
-    fs::OpenOptions,
-    io::{self, BufRead, BufReader, Read, Seek, Write},
-    rc::Rc,
+    collections::{HashMap, VecDeque},
+    fmt::{self, Write as _},
+    fs::File,
+    io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write},
+    iter, mem,
+    path::Path,
+    sync::Arc,
 };
-use ustr::Ustr;
+use tracing::{debug, trace};
+use ustr::{Ustr, UstrMap, UstrSet, existing_ustr};
 
+pub(crate) mod coverage;
 pub(crate) mod int_linear;
 pub(crate) mod int_var_def;
-
-pub(crate) trait JustifierActions {
+mod lit_set;
+pub mod profile;
+
+// The crates whose types the justifier traits use, so justifiers outside this crate get the
+// same versions
+pub use flatzinc_serde;
+pub use pboxide_formula;
+pub use ustr;
+
+/// What a [`Justify`] implementation can do while justifying an assertion: look up the model,
+/// define the literals and bounds it needs, and write proof lines.
+pub trait JustifierActions {
+    /// Make sure the definition of `lit` in terms of its CP variable's bits has been written,
+    /// returning its ID. Fails with [`PBarberError::LiteralLookupError`] if the literal isn't
+    /// in the literal mapping.
     fn ensure_lit_defined(&mut self, lit: &PBLiteral) -> Result<String, PBarberError>;
+
+    /// [`ensure_lit_defined`](Self::ensure_lit_defined) for each literal of `constraint` and
+    /// its negation, returning the definition IDs of the literals and of their negations.
+    /// Literals that aren't in the mapping are skipped unless `strict`.
     fn ensure_all_lits_defined(
         &mut self,
-        constraint: &Box<dyn DynPBConstraint + 'static>,
+        constraint: &dyn DynPBConstraint,
         strict: bool,
     ) -> Result<(Vec<String>, Vec<String>), PBarberError>;
 
+    /// Make sure the lower and upper bounds of a CP variable's bits have been written,
+    /// returning their IDs.
     fn ensure_bounds_defined(&mut self, cp_var_id: &Ustr)
     -> Result<(String, String), PBarberError>;
+
+    /// The smallest and largest values in a CP variable's domain.
     fn get_min_max_for_var(&mut self, cp_var_id: &Ustr) -> Result<(i64, i64), PBarberError>;
+
+    /// The weighted sum of a CP variable's bits, each weight multiplied by `multiplier`.
     fn cp_var_bits_str(
         &mut self,
         cp_var_id: &Ustr,
         multiplier: i64,
     ) -> Result<String, PBarberError>;
+
+    /// The names of the PB variables, e.g. for printing constraints.
     fn pb_var_names(&self) -> &PBVarNameManager;
+
+    /// Write a line to the styled proof.
     fn write(&mut self, content: &str) -> Result<(), PBarberError>;
+
+    /// Write a line formatted from `args`, so `write!(justifier, ...)` writes a line without
+    /// allocating a string for it.
+    fn write_fmt(&mut self, args: fmt::Arguments<'_>) -> Result<(), PBarberError> {
+        self.write(&args.to_string())
+    }
+
+    /// An empty string to build a line in piece by piece, to hand back to
+    /// [`write_line_buffer`](Self::write_line_buffer) so the next line can reuse it.
+    fn take_line_buffer(&mut self) -> String {
+        String::new()
+    }
+
+    /// Write the line built in `line`, keeping the string for the next
+    /// [`take_line_buffer`](Self::take_line_buffer).
+    fn write_line_buffer(&mut self, line: String) -> Result<(), PBarberError> {
+        self.write(&line)
+    }
+
+    /// The FlatZinc constraint referred to by an ID like `@f12`.
     fn get_fzn_constraint(
         &self,
         fzn_id: &str,
     ) -> Result<&flatzinc_serde::Constraint<Ustr>, PBarberError>;
+
     fn get_fzn_array(&self, fzn_id: &Ustr) -> Result<&flatzinc_serde::Array<Ustr>, PBarberError>;
+
     fn get_fzn_variable(
         &self,
         fzn_id: &Ustr,
     ) -> Result<&flatzinc_serde::Variable<Ustr>, PBarberError>;
+
+    /// What a PB literal means in terms of the CP variables.
     fn get_cp_lit_data(&self, lit: &PBLiteral) -> Result<CPLitData, PBarberError>;
 }
 
-pub(crate) trait Justify {
+/// Justifies assertions (`a` lines) made with a particular justifier name, by writing the
+/// lines that derive them. Install one with [`Justifier::register_justifier`].
+pub trait Justify: Send + Sync {
+    /// Write the lines deriving `constraint` as `id_str`. Fail with
+    /// [`PBarberError::JustificationError`] to have the assertion kept as it is.
     fn justify(
         &self,
-        var_manager: &mut dyn JustifierActions,
-        constraint: Box<dyn DynPBConstraint + 'static>,
+        justifier: &mut dyn JustifierActions,
+        constraint: &dyn DynPBConstraint,
         id_str: &str,
     ) -> Result<(), PBarberError>;
 }
 
+/// Makes the [`Justify`] for an assertion from its antecedents (the text between the `:`s,
+/// e.g. the FlatZinc constraint ID).
+pub type JustifyFactory = Arc<
+    dyn Fn(&mut dyn JustifierActions, &str) -> Result<Arc<dyn Justify>, PBarberError> + Send + Sync,
+>;
+
+/// Cached assertions justified ahead by each worker at a time in parallel mode.
+const ASSERTIONS_PER_WORKER: usize = 64;
+
+/// How an attempt to justify an assertion went, before it's finished off by
+/// [`Justifier::finish_justification`].
+enum Outcome {
+    /// The assertion names no justifier, so it was written as it is
+    Written,
+    Justified {
+        id: Ustr,
+        name: Ustr,
+        antecedents: Ustr,
+    },
+    /// The justifier failed with `message`, so the assertion is to be kept, written out as
+    /// `assertion`
+    Failed {
+        id: Ustr,
+        name: Ustr,
+        antecedents: Ustr,
+        message: String,
+        assertion: String,
+    },
+}
+
+/// A cached assertion justified ahead on a worker, with the lines it wrote and the
+/// definition IDs of the Boolvar literals it defined.
+struct Ahead {
+    outcome: Result<Outcome, PBarberError>,
+    lines: VecDeque<String>,
+    boolvars: Vec<String>,
+}
+
 pub struct Justifier<W> {
-    lines: Box<dyn Iterator<Item = io::Result<String>>>,
+    lines: Box<dyn Iterator<Item = io::Result<String>> + Send>,
     out: W,
     config: JustifierConfig,
+    guard: ResourceGuard,
     input_stats: ProofFileStats,
     output_stats: ProofFileStats,
-    lines_to_justify: HashMap<String, String>,
-    justifiers: HashMap<String, Rc<dyn Justify>>,
+    /// Cached assertions by ID, with the number of the line each was read from
+    lines_to_justify: UstrMap<(u64, String)>,
+    /// Shared with the workers, which install into their own copy of it if they install any
+    justifiers: Arc<UstrMap<Arc<dyn Justify>>>,
+    /// Justifiers registered by name, besides the built in ones
+    factories: HashMap<String, JustifyFactory>,
+
+    /// Cached assertions not yet justified ahead, in the order they were read
+    unjustified: Vec<Ustr>,
+    justified_ahead: UstrMap<Ahead>,
+    /// Justifiers for [`Justifier::justify_ahead`], kept from one batch to the next
+    workers: Vec<Justifier<io::Sink>>,
 
     pb_var_names: PBVarNameManager,
-    defined_lits: HashSet<PBLiteral>,
-    defined_bounds: HashSet<String>,
-    fzn: FlatZinc<Ustr>,
-    cp_lit_map: CPLitMap,
+    /// At least the number of PB variables named, so that workers can be given the names
+    /// they don't have yet
+    var_count: u32,
+    /// Literals whose definitions have been written
+    defined_lits: LitSet,
+    /// The Boolvar literals among `defined_lits`, as derivations only use their definitions
+    /// once they've been written
+    defined_boolvars: LitSet,
+    defined_bounds: UstrSet,
+    fzn: Arc<FznModel>,
+    cp_lit_map: Arc<CPLitMap>,
+    /// Domain bounds of the CP variables looked up so far
+    var_bounds: UstrMap<(i64, i64)>,
+    /// Bit strings already built, by CP variable and multiplier
+    var_bits: HashMap<(Ustr, i64), String>,
+    /// Model to check the bit encodings written against
+    opb_model: Option<Arc<OpbModel>>,
+
+    lines_read: u64,
+    /// Bytes of input read so far, and how many of them were the last line read
+    input_bytes: u64,
+    line_bytes: u64,
+    /// Length of the input when it's read in reverse, for the offsets of lines in errors
+    input_len: Option<u64>,
+    written_bytes: u64,
+    written_lines: u64,
+    /// Reused to build the lines the justifiers write
+    line_buffer: String,
+    /// Lines written but not yet taken from [`Justifier::lines`]
+    collected: Option<VecDeque<String>>,
+    observer: ObserverSlot,
+    diagnostics: Box<dyn Diagnostics>,
+    profile: Option<Profile>,
+    coverage: Option<CoverageRecorder>,
+    /// Lines written since the justification being written started, by kind
+    justification_lines: JustificationLineKinds,
+    /// The line of the assertion being justified, if one is
+    justifying: Option<u64>,
+    /// Where each line written came from, with the input lines in the order they're read, if
+    /// a line map was asked for
+    line_map: Option<LineMap>,
+}
+
+/// The lines of a styled proof, from [`Justifier::lines`].
+pub struct JustifiedLines<W> {
+    justifier: Justifier<W>,
+    done: bool,
+}
+
+impl<W: Write> JustifiedLines<W> {
+    /// The justifier, e.g. for its stats once all the lines have been taken.
+    pub fn justifier(&self) -> &Justifier<W> {
+        &self.justifier
+    }
+}
+
+impl<W: Write> Iterator for JustifiedLines<W> {
+    type Item = Result<String, PBarberError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(line) = self
+                .justifier
+                .collected
+                .as_mut()
+                .and_then(VecDeque::pop_front)
+            {
+                return Some(Ok(line));
+            }
+            if self.done {
+                return None;
+            }
+            match self.justifier.style_next_line() {
+                Ok(true) => (),
+                Ok(false) => {
+                    self.done = true;
+                    self.justifier.finish_stats();
+                }
+                Err(err) => {
+                    self.done = true;
+                    return Some(Err(err));
+                }
+            }
+        }
+    }
 }
 
+/// Builds a `pol` line adding up constraints, e.g. `pol @a @b + @c 2 * + ;`. Terms are
+/// written straight into the line, which can be sized up front with
+/// [`PolBuilder::with_capacity`].
 pub struct PolBuilder {
     pol_line: String,
-    empty: bool,
+    terms: usize,
+    done: bool,
 }
 
-impl PolBuilder {}
-
 impl<W: Write> ProofReader<W> for Justifier<W> {
     fn lines_next(&mut self) -> Option<Result<String, io::Error>> {
-        self.lines.next()
+        let line = self.lines.next();
+        if let Some(Ok(line)) = &line {
+            // Lines are given without their newlines
+            self.line_bytes = line.len() as u64 + 1;
+            self.input_bytes += self.line_bytes;
+        }
+        line
     }
 
     fn has_stats(&self) -> bool {
@@ -107,173 +321,1014 @@ impl<W: Write> ProofReader<W> for Justifier<W> {
     fn out_mut(&mut self) -> &mut W {
         &mut self.out
     }
+
+    fn write_line(&mut self, content: &str) -> io::Result<()> {
+        // Counted for checkpoints
+        self.written_bytes += content.len() as u64 + 1;
+        self.written_lines += 1;
+        if let Some(line_map) = self.line_map.as_mut() {
+            line_map.push(match self.justifying {
+                Some(line_number) => LineOrigin::WrittenFor(line_number),
+                None => LineOrigin::Original(self.lines_read + 1),
+            });
+        }
+        if self.has_stats() {
+            self.output_stats.record_line(content);
+            self.justification_lines
+                .record(line_label(content).unwrap_or_default(), content);
+        }
+        if let Some(collected) = self.collected.as_mut() {
+            collected.push_back(content.to_string());
+            return Ok(());
+        }
+        writeln!(self.out, "{}", content)
+    }
+}
+
+/// Open a file named in the config, `what` it is being for the error if it can't be.
+fn open_source(what: &str, path: &Path) -> Result<File, PBarberError> {
+    if path.as_os_str().is_empty() {
+        return Err(PBarberError::InvalidConfig(format!("no {what} given")));
+    }
+    File::open(path).map_err(|err| PBarberError::reading(what, path, err))
+}
+
+/// The FlatZinc model and literal mapping proofs are justified against, parsed once so they
+/// can be shared between justifiers, e.g. styling several proofs on different threads.
+#[derive(Clone)]
+pub struct JustifierModel {
+    fzn: Arc<FznModel>,
+    cp_lit_map: Arc<CPLitMap>,
+}
+
+impl JustifierModel {
+    /// Read the model and literal mapping at the paths in `config`, parsing the model's
+    /// constraints as they're used if [`JustifierConfig::lazy_fzn`] is set.
+    pub fn open(config: &JustifierConfig) -> Result<Self, PBarberError> {
+        let fzn = open_source("FlatZinc model", &config.fzn_path)?;
+        let lits = open_source("literal mapping", &config.lits_path)?;
+        Ok(Self {
+            fzn: Arc::new(FznModel::from_reader(fzn, config.lazy_fzn)?),
+            cp_lit_map: Arc::new(CPLitMap::try_from_reader(lits)?),
+        })
+    }
+
+    /// Read the model (in JSON) from `fzn` and the literal mapping from `lits`.
+    pub fn from_readers(fzn: impl Read, lits: impl Read) -> Result<Self, PBarberError> {
+        Self::with_lit_map(fzn, CPLitMap::try_from_reader(lits)?)
+    }
+
+    /// Read the model from `fzn`, with a literal mapping built in memory.
+    pub fn with_lit_map(fzn: impl Read, cp_lit_map: CPLitMap) -> Result<Self, PBarberError> {
+        Ok(Self {
+            fzn: Arc::new(FznModel::from_reader(fzn, false)?),
+            cp_lit_map: Arc::new(cp_lit_map),
+        })
+    }
 }
 
 impl<W: Write> Justifier<W> {
-    pub fn new<R: Read + Seek + 'static>(input: R, out: W) -> Self {
-        Self::with_config(input, out, JustifierConfig::default())
+    /// Panicking form of [`Justifier::try_new`].
+    #[deprecated(note = "use `Justifier::try_new`, which returns the error instead")]
+    pub fn new<R: Read + Seek + Send + 'static>(input: R, out: W) -> Self {
+        Self::try_new(input, out).expect("Failed to set up justifier.")
+    }
+
+    /// Justify `input` with the default config.
+    pub fn try_new<R: Read + Seek + Send + 'static>(
+        input: R,
+        out: W,
+    ) -> Result<Self, PBarberError> {
+        Self::try_with_config(input, out, JustifierConfig::default())
+    }
+
+    /// Justify a proof read from a non-seekable stream, e.g. a pipe from the solver or the
+    /// trimmer. Read forwards, it's styled as it arrives; read in reverse, it's spooled to a
+    /// temporary file first so it can be read from the end.
+    pub fn from_stream<S: Read + Send + 'static>(
+        input: S,
+        out: W,
+        config: JustifierConfig,
+    ) -> Result<Self, PBarberError> {
+        if config.read_forwards {
+            let input = BufReader::with_capacity(config.buffers.read_buffer, input);
+            return Self::try_from_lines(input.lines(), out, config);
+        }
+        let mut spooled = spool(input)?;
+        spooled.rewind()?;
+        Self::try_with_config(spooled, out, config)
     }
 
-    pub fn with_config<R: Read + Seek + 'static>(
+    /// Panicking form of [`Justifier::try_with_config`].
+    #[deprecated(note = "use `Justifier::try_with_config`, which returns the error instead")]
+    pub fn with_config<R: Read + Seek + Send + 'static>(
         input: R,
         out: W,
         config: JustifierConfig,
     ) -> Self {
+        Self::try_with_config(input, out, config).expect("Failed to set up justifier.")
+    }
+
+    /// Justify `input` against the model and literal mapping at the paths in `config`. Fails
+    /// if the options don't make sense (see [`JustifierConfig::validate`]) or the files they
+    /// name can't be read.
+    pub fn try_with_config<R: Read + Seek + Send + 'static>(
+        input: R,
+        out: W,
+        config: JustifierConfig,
+    ) -> Result<Self, PBarberError> {
+        let model = JustifierModel::open(&config)?;
+        Self::try_with_model(input, out, model, config)
+    }
+
+    /// Justify against a FlatZinc model (in JSON) and literal mapping read from `fzn` and
+    /// `lits` rather than the paths in `config`, e.g. held in memory.
+    pub fn try_with_sources<R: Read + Seek + Send + 'static>(
+        proof: R,
+        out: W,
+        fzn: impl Read,
+        lits: impl Read,
+        config: JustifierConfig,
+    ) -> Result<Self, PBarberError> {
+        let model = JustifierModel::from_readers(fzn, lits)?;
+        Self::try_with_model(proof, out, model, config)
+    }
+
+    /// Justify against a literal mapping built in memory rather than read from JSON.
+    pub fn try_with_lit_map<R: Read + Seek + Send + 'static>(
+        proof: R,
+        out: W,
+        fzn: impl Read,
+        cp_lit_map: CPLitMap,
+        config: JustifierConfig,
+    ) -> Result<Self, PBarberError> {
+        let model = JustifierModel::with_lit_map(fzn, cp_lit_map)?;
+        Self::try_with_model(proof, out, model, config)
+    }
+
+    /// Justify against an already loaded model, which may be shared with other justifiers.
+    pub fn try_with_model<R: Read + Seek + Send + 'static>(
+        mut proof: R,
+        out: W,
+        model: JustifierModel,
+        config: JustifierConfig,
+    ) -> Result<Self, PBarberError> {
         // Read file in reverse by default, but read forwards if the option is enabled
-        let lines: Box<dyn Iterator<Item = io::Result<String>>> = if config.read_forwards {
-            Box::new(BufReader::new(input).lines())
+        let mut input_len = None;
+        let lines: Box<dyn Iterator<Item = io::Result<String>> + Send> = if config.read_forwards {
+            Box::new(BufReader::with_capacity(config.buffers.read_buffer, proof).lines())
         } else {
-            Box::new(RevBufReader::new(input).lines())
+            input_len = line_offset_len(&mut proof).ok();
+            let lines = ReverseLines::new(proof).block_size(config.buffers.block_size);
+            Box::new(lines.map(|line| Ok(line?.1)))
         };
+        let mut justifier = Self::try_from_lines_with_model(lines, out, model, config)?;
+        justifier.input_len = input_len;
+        Ok(justifier)
+    }
 
-        let fzn_file = OpenOptions::new()
-            .read(true)
-            .open(&config.fzn_path)
-            .expect("Failed to open fzn file for justifier.");
+    /// Justify lines from any source, e.g. streamed from the trimmer. The lines must come in
+    /// the order `config.read_forwards` says.
+    pub fn try_from_lines<I: Iterator<Item = io::Result<String>> + Send + 'static>(
+        lines: I,
+        out: W,
+        config: JustifierConfig,
+    ) -> Result<Self, PBarberError> {
+        let model = JustifierModel::open(&config)?;
+        Self::try_from_lines_with_model(lines, out, model, config)
+    }
 
-        let lits_file = OpenOptions::new()
-            .read(true)
-            .open(&config.lits_path)
-            .expect("Failed to open lits file for justifier.");
+    /// [`Justifier::try_from_lines`] with the model and literal mapping read from `fzn` and
+    /// `lits`.
+    pub fn try_from_lines_with_sources<I: Iterator<Item = io::Result<String>> + Send + 'static>(
+        lines: I,
+        out: W,
+        fzn: impl Read,
+        lits: impl Read,
+        config: JustifierConfig,
+    ) -> Result<Self, PBarberError> {
+        let model = JustifierModel::from_readers(fzn, lits)?;
+        Self::try_from_lines_with_model(lines, out, model, config)
+    }
 
-        let fzn: FlatZinc<Ustr> =
-            serde_json::from_reader(fzn_file).expect("Unable to parse fzn input.");
-        Self {
-            lines,
+    /// [`Justifier::try_from_lines`] against an already loaded model. All the other
+    /// constructors end up here, where the options are checked.
+    pub fn try_from_lines_with_model<I: Iterator<Item = io::Result<String>> + Send + 'static>(
+        lines: I,
+        out: W,
+        model: JustifierModel,
+        config: JustifierConfig,
+    ) -> Result<Self, PBarberError> {
+        config.validate()?;
+        let opb_model = match &config.encoding_model_path {
+            Some(opb_path) => {
+                Some(Arc::new(OpbModel::open(opb_path).map_err(|err| {
+                    PBarberError::reading("encoding model", opb_path, err)
+                })?))
+            }
+            None => None,
+        };
+        let profile = config.profile.map(|_| Profile::default());
+        let coverage = config.justifier_stats.then(CoverageRecorder::default);
+        let line_map = config.line_map_path.as_ref().map(|_| LineMap::default());
+        Ok(Self {
+            lines: Box::new(lines),
             out,
+            guard: ResourceGuard::new(&config.limits),
             config,
             input_stats: ProofFileStats::default(),
             output_stats: ProofFileStats::default(),
-            lines_to_justify: HashMap::<String, String>::new(),
-            justifiers: HashMap::<String, Rc<dyn Justify>>::new(),
+            lines_to_justify: UstrMap::default(),
+            justifiers: Arc::default(),
+            factories: HashMap::new(),
+            unjustified: Vec::new(),
+            justified_ahead: UstrMap::default(),
+            workers: Vec::new(),
             pb_var_names: PBVarNameManager::default(),
-            defined_lits: HashSet::<PBLiteral>::new(),
-            defined_bounds: HashSet::<String>::new(),
-            cp_lit_map: CPLitMap::from_reader(lits_file),
-            fzn,
+            var_count: 0,
+            defined_lits: LitSet::default(),
+            defined_boolvars: LitSet::default(),
+            defined_bounds: UstrSet::default(),
+            cp_lit_map: model.cp_lit_map,
+            fzn: model.fzn,
+            var_bounds: UstrMap::default(),
+            var_bits: HashMap::new(),
+            opb_model,
             // fzn_encoded: HashMap::<String, Vec<String>>::new(),
+            lines_read: 0,
+            input_bytes: 0,
+            line_bytes: 0,
+            input_len: None,
+            written_bytes: 0,
+            written_lines: 0,
+            line_buffer: String::new(),
+            collected: None,
+            observer: ObserverSlot::default(),
+            diagnostics: Box::new(LogDiagnostics),
+            profile,
+            coverage,
+            justification_lines: JustificationLineKinds::default(),
+            justifying: None,
+            line_map,
+        })
+    }
+
+    /// Continue from a checkpoint of an earlier run on the same input, whose output (up to
+    /// `checkpoint.output_bytes`) `out` appends to. The lines styled before it are skipped.
+    pub fn resume(&mut self, checkpoint: Checkpoint) -> Result<(), PBarberError> {
+        if checkpoint.read_forwards != self.config.read_forwards {
+            return Err(PBarberError::MalformedInput(
+                "the checkpoint was made reading the input in the other direction (check `--read-forwards`)"
+                    .to_string(),
+            ));
+        }
+        if self.has_stats() != checkpoint.stats.is_some() {
+            return Err(PBarberError::MalformedInput(
+                "the checkpoint was made with stats recorded if and only if they aren't now (check `--stats`)"
+                    .to_string(),
+            ));
+        }
+        // The stats of the skipped lines are in the checkpoint, so the lines aren't recorded
+        // again
+        while self.lines_read < checkpoint.input_lines {
+            match self.lines_next() {
+                Some(line) => line?,
+                None => {
+                    return Err(PBarberError::MalformedInput(
+                        "the input is shorter than the checkpoint says, so isn't the one it was made for"
+                            .to_string(),
+                    ));
+                }
+            };
+            self.lines_read += 1;
+        }
+        self.written_bytes = checkpoint.output_bytes;
+        self.written_lines = checkpoint.output_lines;
+        for def_id in &checkpoint.defined_lits {
+            let (var, negated) = self.checkpointed_lit(def_id)?;
+            self.defined_lits.insert(var, negated);
+        }
+        for def_id in &checkpoint.defined_boolvars {
+            let (var, negated) = self.checkpointed_lit(def_id)?;
+            self.defined_boolvars.insert(var, negated);
+        }
+        self.defined_bounds = checkpoint
+            .defined_bounds
+            .iter()
+            .map(|id| Ustr::from(id.as_str()))
+            .collect();
+        self.lines_to_justify = checkpoint
+            .pending_assertions
+            .into_iter()
+            .map(|(id, pending)| (Ustr::from(id.as_str()), pending))
+            .collect();
+        if let Some((input_stats, output_stats)) = checkpoint.stats {
+            self.input_stats = ProofFileStats::from_recorded(input_stats);
+            self.output_stats = ProofFileStats::from_recorded(output_stats);
+            self.coverage = Some(checkpoint.coverage.unwrap_or_default());
         }
+        debug!(lines = self.lines_read, "resumed from checkpoint");
+        Ok(())
+    }
+
+    /// The literal that the checkpointed definition ID `def_id` defines.
+    fn checkpointed_lit(&mut self, def_id: &str) -> Result<(Var, bool), PBarberError> {
+        self.definition_lit(def_id).ok_or_else(|| {
+            PBarberError::MalformedInput(format!(
+                "the checkpoint has `{def_id}` as a literal definition, which it isn't"
+            ))
+        })
+    }
+
+    fn save_checkpoint(&mut self) -> Result<(), PBarberError> {
+        let Some(checkpoint_path) = &self.config.checkpoint_path else {
+            return Ok(());
+        };
+        // Everything the checkpoint counts must be in the output first
+        self.out.flush()?;
+        let mut defined_lits: Vec<String> = self
+            .defined_lits
+            .iter()
+            .map(|(var, negated)| self.definition_id(var, negated))
+            .collect();
+        defined_lits.sort();
+        let mut defined_boolvars: Vec<String> = self
+            .defined_boolvars
+            .iter()
+            .map(|(var, negated)| self.definition_id(var, negated))
+            .collect();
+        defined_boolvars.sort();
+        let mut defined_bounds: Vec<String> = self
+            .defined_bounds
+            .iter()
+            .map(|id| id.to_string())
+            .collect();
+        defined_bounds.sort();
+        let checkpoint = Checkpoint {
+            input_lines: self.lines_read,
+            output_bytes: self.written_bytes,
+            output_lines: self.written_lines,
+            read_forwards: self.config.read_forwards,
+            defined_lits,
+            defined_boolvars,
+            defined_bounds,
+            pending_assertions: self
+                .lines_to_justify
+                .iter()
+                .map(|(id, (line_number, line))| (id.to_string(), (*line_number, line.clone())))
+                .collect(),
+            stats: self
+                .has_stats()
+                .then(|| (self.input_stats.recorded(), self.output_stats.recorded())),
+            coverage: self.coverage.clone(),
+        };
+        checkpoint.save(checkpoint_path)?;
+        debug!(lines = self.lines_read, "saved checkpoint");
+        Ok(())
     }
 
     pub fn style(&mut self) -> Result<Option<(ProofFileStats, ProofFileStats)>, PBarberError> {
-        while let Some(current_line) = self.next_line() {
-            let current_line = current_line.unwrap();
-            if current_line.starts_with("@") {
-                let mut split_line = current_line.split(" ");
-                let id = split_line.next().unwrap();
-                let rule = split_line.next().unwrap();
-                assert!(ALLOWED_RULES.contains(&rule));
-                if rule == "pol" || rule == "p" {
-                    for term in split_line {
-                        if term == "+" || term == "s" || term == ";" {
-                            continue;
-                        } else {
-                            self.assert_starts_with(&term.to_string(), "@")?;
+        while self.style_next_line()? {}
+        self.out.flush()?;
+        self.finish_stats();
+        self.write_line_map()?;
+        Ok(self.take_stats())
+    }
+
+    /// Where each line of the styled proof came from in the input, once it has been styled,
+    /// if [`JustifierConfig::line_map_path`] is set. Lines from before a resumed checkpoint
+    /// aren't in it.
+    pub fn line_map(&self) -> Option<LineMap> {
+        let line_map = self.line_map.clone()?;
+        if self.config.read_forwards {
+            return Some(line_map);
+        }
+        // Lines were counted from the end
+        let lines = self.lines_read;
+        Some(line_map.renumbered(|line_number| lines + 1 - line_number))
+    }
+
+    fn write_line_map(&self) -> Result<(), PBarberError> {
+        match (self.line_map(), &self.config.line_map_path) {
+            (Some(line_map), Some(path)) => line_map.write_to_path(path),
+            _ => Ok(()),
+        }
+    }
+
+    /// Justify the proof, giving the lines of the styled proof instead of writing them to the
+    /// output (which isn't used). Input lines are read as the styled lines are taken.
+    pub fn lines(mut self) -> JustifiedLines<W> {
+        self.collected = Some(VecDeque::new());
+        JustifiedLines {
+            justifier: self,
+            done: false,
+        }
+    }
+
+    /// Check the bit encodings written against `opb_model`, as with
+    /// [`JustifierConfig::check_encodings`].
+    pub fn set_opb_model(&mut self, opb_model: OpbModel) {
+        self.opb_model = Some(Arc::new(opb_model));
+    }
+
+    /// Justify assertions made with the justifier `name` with the [`Justify`] that `factory`
+    /// makes, instead of any built in justifier of that name.
+    pub fn register_justifier(
+        &mut self,
+        name: impl Into<String>,
+        factory: impl Fn(&mut dyn JustifierActions, &str) -> Result<Arc<dyn Justify>, PBarberError>
+        + Send
+        + Sync
+        + 'static,
+    ) {
+        self.factories.insert(name.into(), Arc::new(factory));
+    }
+
+    /// Call `observer` as the proof is styled.
+    pub fn set_observer(&mut self, observer: impl Observer + 'static) {
+        self.observer.set(observer);
+    }
+
+    /// Number the PB variables in `names` before any others, in order, so that variable
+    /// indices are the same from run to run. Call before styling; variables that already
+    /// have a number keep it.
+    pub fn seed_var_names<S: AsRef<str>>(&mut self, names: impl IntoIterator<Item = S>) {
+        for name in names {
+            let var = self.pb_var_names.get_or_create_var(name.as_ref());
+            self.note_var(var);
+        }
+    }
+
+    /// [`Justifier::seed_var_names`] with the variables of an OPB model in the order they
+    /// first appear, so the indices match the checker's numbering.
+    pub fn seed_var_names_from_model(&mut self, model: &OpbModel) {
+        self.seed_var_names(model.variables());
+    }
+
+    /// Send the justifier's messages, e.g. the comments on assertions it couldn't justify,
+    /// to `diagnostics` rather than logging them.
+    pub fn set_diagnostics(&mut self, diagnostics: impl Diagnostics + 'static) {
+        self.diagnostics = Box::new(diagnostics);
+    }
+
+    /// Input and output stats, if they were requested in the config. Complete once the proof
+    /// has been styled, and empty once [`Justifier::style`] has returned them.
+    pub fn stats(&self) -> Option<(&ProofFileStats, &ProofFileStats)> {
+        self.config
+            .justifier_stats
+            .then_some((&self.input_stats, &self.output_stats))
+    }
+
+    /// Move the stats out, if they were requested, rather than copying them.
+    fn take_stats(&mut self) -> Option<(ProofFileStats, ProofFileStats)> {
+        self.config.justifier_stats.then(|| {
+            (
+                mem::take(&mut self.input_stats),
+                mem::take(&mut self.output_stats),
+            )
+        })
+    }
+
+    /// Where the justifier's time went, if profiling was requested in the config.
+    pub fn profile(&self) -> Option<&Profile> {
+        self.profile.as_ref()
+    }
+
+    fn finish_stats(&mut self) {
+        if self.config.justifier_stats {
+            self.input_stats.finish(!self.config.read_forwards);
+            self.output_stats.finish(false);
+            self.output_stats.fzn_coverage = self
+                .coverage
+                .as_ref()
+                .map(|coverage| coverage.coverage(&self.fzn));
+            self.output_stats.justification_lines = self
+                .coverage
+                .as_ref()
+                .map(|coverage| coverage.justification_lines(&self.fzn));
+        }
+    }
+
+    /// Count an unlabelled line the justifier passes through without knowing its rule.
+    fn record_if_unknown(&mut self, line: &str, raw_line: &str) {
+        if let Ok(ProofLine::Other { rule, .. }) = ProofLine::parse(line) {
+            self.input_stats.record_unknown(rule, raw_line, true);
+        }
+    }
+
+    /// Style the next input line, returning whether there was one.
+    fn style_next_line(&mut self) -> Result<bool, PBarberError> {
+        if let Some(current_line) = self.profiled(Section::Phase("read"), Self::next_line) {
+            self.guard.tick()?;
+            let raw_line = current_line?;
+            self.observer.notify(|o| o.on_input_line(&raw_line));
+            let line_number = self.lines_read + 1;
+            // Debug markers are kept on copied lines, but otherwise ignored
+            let current_line = split_debug_marker(&raw_line).1;
+            if self.lines_to_justify.is_empty()
+                && current_line.starts_with("@")
+                && matches!(line_rule(current_line), "pol" | "p")
+            {
+                // No cached assertion for it to use, so there's no need to parse its terms
+                self.write_line(&raw_line)?;
+            } else if current_line.starts_with("@") {
+                let parsed = self.profiled(Section::Phase("parse"), |justifier| {
+                    justifier.parse(line_number, current_line)
+                });
+                match parsed? {
+                    ProofLine::Pol { terms, .. } => {
+                        for token in terms {
+                            let PolToken::Constraint(term) = token else {
+                                continue;
+                            };
                             // If possible justify an assertion right before the first time
                             // it is used.
-                            if let Some(line_to_justify) = self.lines_to_justify.remove(term) {
-                                self.justify(&line_to_justify)?;
-                                //self.write_line(&line_to_justify)?;
+                            // An ID that was never interned can't be cached
+                            if let Some((term, (line_number, line_to_justify))) =
+                                existing_ustr(term)
+                                    .and_then(|term| self.lines_to_justify.remove_entry(&term))
+                            {
+                                match self.justified_ahead.remove(&term) {
+                                    Some(ahead) if !self.is_stale(&ahead) => self
+                                        .profiled(Section::Phase("write ahead"), |justifier| {
+                                            justifier.write_ahead(line_number, ahead)
+                                        })?,
+                                    _ => self.justify(line_number, &line_to_justify)?,
+                                }
                             }
                         }
+                        self.write_line(&raw_line)?;
                     }
-                    self.write_line(&current_line)?;
-                } else if rule == "a" {
-                    if self.lines_to_justify.len() < self.config.max_line_cache {
-                        self.lines_to_justify.insert(id.to_string(), current_line);
-                    } else {
+                    ProofLine::Assertion {
+                        label: Some(id), ..
+                    } if self.lines_to_justify.len() < self.config.max_line_cache => {
+                        let id = Ustr::from(id);
+                        self.lines_to_justify
+                            .insert(id, (line_number, current_line.to_string()));
+                        if self.config.parallel {
+                            self.unjustified.push(id);
+                        }
+                    }
+                    ProofLine::Assertion { .. } => {
                         // Can't cache so have to justify it right now
-                        self.justify(&current_line)?;
-                        //self.write_line(&current_line)?;
+                        self.justify(line_number, current_line)?;
+                    }
+                    line => {
+                        return Err(self.malformed(
+                            line_number,
+                            current_line,
+                            &format!("unexpected rule `{}`", line.rule()),
+                        ));
                     }
                 }
             } else {
                 // Not a labelled line, ignore :-)
-                self.write_line(&current_line)?;
+                if self.has_stats() {
+                    self.record_if_unknown(current_line, &raw_line);
+                }
+                self.write_line(&raw_line)?;
+            }
+            self.lines_read += 1;
+            if self.config.parallel
+                && self.unjustified.len() >= rayon::current_num_threads() * ASSERTIONS_PER_WORKER
+            {
+                self.profiled(Section::Phase("justify ahead"), Self::justify_ahead);
             }
+            if self
+                .lines_read
+                .is_multiple_of(self.config.checkpoint_every.max(1))
+            {
+                self.profiled(Section::Phase("checkpoint"), Self::save_checkpoint)?;
+            }
+            return Ok(true);
         }
-        if self.config.justifier_stats {
-            Ok(Some((self.input_stats.clone(), self.output_stats.clone())))
+        Ok(false)
+    }
+
+    /// The position of input line `line_number`, for errors. Only the line being styled has
+    /// a known byte offset, not cached assertions.
+    fn position(&self, line_number: u64) -> LinePosition {
+        let byte_offset = if line_number != self.lines_read + 1 {
+            None
+        } else if self.config.read_forwards {
+            Some(self.input_bytes - self.line_bytes)
         } else {
-            Ok(None)
+            self.input_len
+                .map(|len| len.saturating_sub(self.input_bytes))
+        };
+        LinePosition {
+            byte_offset,
+            ..LinePosition::line(line_number, !self.config.read_forwards)
+        }
+    }
+
+    /// An error for the proof line `line_number`.
+    fn malformed(&self, line_number: u64, line: &str, message: &str) -> PBarberError {
+        PBarberError::MalformedLine {
+            position: Some(Box::new(self.position(line_number))),
+            line: line.to_string(),
+            message: message.to_string(),
         }
     }
 
-    fn justify(&mut self, current_line: &str) -> Result<(), PBarberError> {
-        let (id, constraint_str, constraint, antecedents_str, opt_name) =
-            self.parse_assertion_line(current_line);
+    /// Parse the proof line `line_number`.
+    fn parse<'l>(&self, line_number: u64, line: &'l str) -> Result<ProofLine<'l>, PBarberError> {
+        ProofLine::parse(line).map_err(|err| err.at(self.position(line_number)))
+    }
+
+    /// Justify the assertion on line `line_number`, giving any error that stops the styling
+    /// the line's position.
+    fn justify(&mut self, line_number: u64, current_line: &str) -> Result<(), PBarberError> {
+        self.profiled(Section::Phase("justify"), |justifier| {
+            justifier.justify_line(line_number, current_line)
+        })
+        .map_err(|err| err.at(self.position(line_number)))
+    }
+
+    fn justify_line(&mut self, line_number: u64, current_line: &str) -> Result<(), PBarberError> {
+        let written_before = self.start_justification(line_number);
+        let outcome = self.attempt_justification(line_number, current_line)?;
+        self.finish_justification(outcome, written_before)
+    }
 
-        let Some(name) = opt_name else {
+    /// Write the lines justifying an assertion, or its literal definitions if the justifier
+    /// fails, leaving the observer and the fallback to [`Justifier::finish_justification`].
+    fn attempt_justification(
+        &mut self,
+        line_number: u64,
+        current_line: &str,
+    ) -> Result<Outcome, PBarberError> {
+        let ProofLine::Assertion {
+            label: Some(id),
+            constraint: constraint_str,
+            antecedents,
+            justifier: opt_name,
+        } = self.parse(line_number, current_line)?
+        else {
+            return Err(self.malformed(line_number, current_line, "expected an assertion"));
+        };
+        // Assertions without a justifier, e.g. those the trimmer restates at its depth limit,
+        // are left for the checker to take on trust
+        let (Some(antecedents_str), Some(name)) = (antecedents, opt_name) else {
             self.write_line(current_line)?;
-            return Ok(());
+            return Ok(Outcome::Written);
         };
-        let name = trim_sc(name.trim());
-        let install_result = if let Some(justifier) = self.justifiers.get(antecedents_str) {
-            Ok(Rc::clone(justifier))
+        let constraint = self.parse_constraint(line_number, constraint_str)?;
+        self.profiled(Section::Justifier(name), |justifier| {
+            justifier.justify_with(name, antecedents_str, &*constraint, id)
+        })
+    }
+
+    /// Justify the assertion `id` of `constraint` with the justifier `name`.
+    fn justify_with(
+        &mut self,
+        name: &str,
+        antecedents_str: &str,
+        constraint: &dyn DynPBConstraint,
+        id: &str,
+    ) -> Result<Outcome, PBarberError> {
+        let cached = existing_ustr(antecedents_str).and_then(|key| self.justifiers.get(&key));
+        let install_result = if let Some(justifier) = cached {
+            Ok(Arc::clone(justifier))
         } else {
             self.install_justifier(name, antecedents_str)
         };
 
-        match install_result {
+        let message = match install_result {
             Err(PBarberError::JustificationError(msg)) => {
-                let constraint = self.parse_constraint(constraint_str, id);
-                self.ensure_all_lits_defined(&constraint, false)?;
-                self.failed_to_justify(constraint, id, name, msg.as_str())
+                self.ensure_all_lits_defined(constraint, false)?;
+                msg
             }
-            Err(e) => Err(e),
+            Err(e) => return Err(e),
             Ok(justifier) => match justifier.justify(self, constraint, id) {
-                Err(PBarberError::JustificationError(msg)) => {
-                    let constraint = self.parse_constraint(constraint_str, id);
-                    self.failed_to_justify(constraint, id, name, msg.as_str())
+                Ok(()) => {
+                    return Ok(Outcome::Justified {
+                        id: Ustr::from(id),
+                        name: Ustr::from(name),
+                        antecedents: Ustr::from(antecedents_str),
+                    });
                 }
-                res => res,
+                Err(PBarberError::JustificationError(msg)) => msg,
+                Err(e) => return Err(e),
             },
-        }
+        };
+        Ok(Outcome::Failed {
+            id: Ustr::from(id),
+            name: Ustr::from(name),
+            antecedents: Ustr::from(antecedents_str),
+            assertion: self.bare_assertion(constraint, id, name),
+            message,
+        })
     }
 
-    fn parse_assertion_line<'a>(
+    /// Start counting the lines of the justification of the assertion on line `line_number`,
+    /// returning the lines written before it.
+    fn start_justification(&mut self, line_number: u64) -> u64 {
+        self.justification_lines = JustificationLineKinds::default();
+        self.justifying = Some(line_number);
+        self.written_lines
+    }
+
+    /// Tell the observer how justifying an assertion went, and keep it as an assertion if it
+    /// failed. The lines written since `written_before` are counted as its justification.
+    fn finish_justification(
         &mut self,
-        current_line: &'a str,
-    ) -> (
-        &'a str,
-        &'a str,
-        Box<dyn DynPBConstraint + 'static>,
-        &'a str,
-        Option<&'a str>,
-    ) {
-        let mut split_line = current_line.split(":");
-        let before_colon = split_line.next().unwrap();
-        let mut split_before_colon = before_colon.splitn(2, " a ");
-        let id = split_before_colon.next().unwrap();
-        let constraint_str = split_before_colon.next().unwrap();
-        let constraint = self.parse_constraint(constraint_str, id);
-        let antecedents_str = split_line.next().unwrap();
-        let opt_name = split_line.next();
-        let _opt_hints = split_line.next();
-        (id, constraint_str, constraint, antecedents_str, opt_name)
+        outcome: Outcome,
+        written_before: u64,
+    ) -> Result<(), PBarberError> {
+        match outcome {
+            Outcome::Written => (),
+            Outcome::Justified {
+                id,
+                name,
+                antecedents,
+            } => {
+                self.record_coverage(antecedents, name);
+                self.record_justification_length(id, name, written_before);
+                trace!(%id, justifier = %name, "justified assertion");
+                self.observer.notify(|o| o.on_justified(&id, &name));
+            }
+            Outcome::Failed {
+                id,
+                name,
+                antecedents,
+                message,
+                assertion,
+            } => {
+                self.failed_to_justify(&id, &name, &message, &assertion)?;
+                self.record_coverage(antecedents, name);
+            }
+        }
+        self.justifying = None;
+        Ok(())
+    }
+
+    /// Count an assertion about the FlatZinc constraints in `antecedents`, and the lines
+    /// written for it, for the coverage stats, if they're being recorded.
+    fn record_coverage(&mut self, antecedents: Ustr, name: Ustr) {
+        if let Some(coverage) = self.coverage.as_mut() {
+            let supported = self.factories.contains_key(name.as_str())
+                || SUPPORTED_JUSTIFIERS.contains(&name.as_str());
+            coverage.record(&antecedents, name, supported);
+            coverage.record_lines(&antecedents, name, &self.justification_lines);
+        }
+    }
+
+    /// Count the lines written since `written_before` as the justification of `id`, for the
+    /// stats.
+    fn record_justification_length(&mut self, id: Ustr, name: Ustr, written_before: u64) {
+        let lines = self.written_lines - written_before;
+        let heaviest = &mut self.output_stats.heaviest_justifications;
+        if self.config.justifier_stats && heaviest.would_keep(lines) {
+            heaviest.record(HeavyLine {
+                id: id.to_string(),
+                name: Some(name.to_string()),
+                weight: lines,
+            });
+        }
+    }
+
+    /// Justify the cached assertions that haven't been yet, in batches on the rayon thread
+    /// pool. Each is justified by a worker as if nothing had been defined yet, so what it
+    /// writes doesn't depend on the order the assertions are used in, and the definitions
+    /// already written are left out when it's written by [`Justifier::write_ahead`].
+    fn justify_ahead(&mut self) {
+        let ids: Vec<Ustr> = mem::take(&mut self.unjustified)
+            .into_iter()
+            .filter(|id| self.lines_to_justify.contains_key(id))
+            .collect();
+        if ids.is_empty() {
+            return;
+        }
+        // Name the variables of the assertions here, so the workers only need their names
+        // and number them as this justifier does. Unreadable ones fail on the worker.
+        for id in &ids {
+            let (line_number, line) = self.lines_to_justify[id].clone();
+            if let Ok(ProofLine::Assertion { constraint, .. }) = ProofLine::parse(&line) {
+                let _ = self.parse_constraint(line_number, constraint);
+            }
+        }
+        let worker_count = rayon::current_num_threads().clamp(1, ids.len());
+        let mut workers = mem::take(&mut self.workers);
+        while workers.len() < worker_count {
+            workers.push(self.worker());
+        }
+        for worker in &mut workers {
+            self.update_worker(worker);
+        }
+        let batch_size = ids.len().div_ceil(worker_count);
+        let cached = &self.lines_to_justify;
+        let justified: Vec<Vec<(Ustr, Ahead)>> = workers[..worker_count]
+            .par_iter_mut()
+            .zip(ids.par_chunks(batch_size))
+            .map(|(worker, ids)| {
+                ids.iter()
+                    .map(|id| {
+                        let (line_number, line) = &cached[id];
+                        (*id, worker.justify_alone(*line_number, line))
+                    })
+                    .collect()
+            })
+            .collect();
+        self.justified_ahead.extend(justified.into_iter().flatten());
+        self.merge_workers(&mut workers);
+        // A worker that named a variable this justifier hasn't would number it differently
+        workers.retain(|worker| worker.var_count <= self.var_count);
+        self.workers = workers;
+    }
+
+    /// Give `worker` the variable names, justifiers and position it's missing to justify the
+    /// next batch.
+    fn update_worker(&self, worker: &mut Justifier<io::Sink>) {
+        for var in worker.var_count..self.var_count {
+            let name = self.pb_var_names.get_name(Var(var));
+            worker.pb_var_names.get_or_create_var(name);
+        }
+        worker.var_count = worker.var_count.max(self.var_count);
+        worker.justifiers = Arc::clone(&self.justifiers);
+        // So errors give cached lines the positions they would have had
+        worker.lines_read = self.lines_read;
+        worker.input_bytes = self.input_bytes;
+        worker.line_bytes = self.line_bytes;
+        worker.input_len = self.input_len;
+    }
+
+    /// Take the justifiers the workers installed and the profiles of their batches.
+    fn merge_workers(&mut self, workers: &mut [Justifier<io::Sink>]) {
+        let installed: Vec<_> = workers
+            .iter_mut()
+            .map(|worker| mem::take(&mut worker.justifiers))
+            .filter(|justifiers| !Arc::ptr_eq(justifiers, &self.justifiers))
+            .collect();
+        for justifiers in installed {
+            for (antecedents, justifier) in justifiers.iter() {
+                Arc::make_mut(&mut self.justifiers)
+                    .entry(*antecedents)
+                    .or_insert_with(|| Arc::clone(justifier));
+            }
+        }
+        if let Some(profile) = self.profile.as_mut() {
+            for worker_profile in workers
+                .iter_mut()
+                .filter_map(|worker| worker.profile.as_mut())
+            {
+                profile.merge(&mem::take(worker_profile));
+            }
+        }
+    }
+
+    /// A justifier for [`Justifier::justify_ahead`] to justify assertions with on another
+    /// thread. It shares the model, but none of the definitions, and collects its lines.
+    fn worker(&self) -> Justifier<io::Sink> {
+        Justifier {
+            lines: Box::new(iter::empty()),
+            out: io::sink(),
+            config: JustifierConfig {
+                justifier_stats: false,
+                parallel: false,
+                checkpoint_path: None,
+                line_map_path: None,
+                ..self.config.clone()
+            },
+            guard: ResourceGuard::new(&self.config.limits),
+            input_stats: ProofFileStats::default(),
+            output_stats: ProofFileStats::default(),
+            lines_to_justify: UstrMap::default(),
+            justifiers: Arc::clone(&self.justifiers),
+            factories: self.factories.clone(),
+            unjustified: Vec::new(),
+            justified_ahead: UstrMap::default(),
+            workers: Vec::new(),
+            pb_var_names: self.pb_var_names.clone(),
+            var_count: self.var_count,
+            defined_lits: LitSet::default(),
+            defined_boolvars: LitSet::default(),
+            defined_bounds: UstrSet::default(),
+            fzn: Arc::clone(&self.fzn),
+            cp_lit_map: Arc::clone(&self.cp_lit_map),
+            var_bounds: UstrMap::default(),
+            var_bits: HashMap::new(),
+            opb_model: self.opb_model.clone(),
+            // So errors give cached lines the positions they would have had
+            lines_read: self.lines_read,
+            input_bytes: self.input_bytes,
+            line_bytes: self.line_bytes,
+            input_len: self.input_len,
+            written_bytes: 0,
+            written_lines: 0,
+            line_buffer: String::new(),
+            collected: Some(VecDeque::new()),
+            observer: ObserverSlot::default(),
+            diagnostics: Box::new(SilentDiagnostics),
+            profile: self.profile.as_ref().map(|_| Profile::default()),
+            coverage: None,
+            justification_lines: JustificationLineKinds::default(),
+            justifying: None,
+            line_map: None,
+        }
+    }
+
+    /// Justify an assertion on a worker, from no definitions.
+    fn justify_alone(&mut self, line_number: u64, line: &str) -> Ahead {
+        self.defined_lits.clear();
+        self.defined_boolvars.clear();
+        self.defined_bounds.clear();
+        let outcome = self.attempt_justification(line_number, line);
+        Ahead {
+            outcome,
+            lines: self.collected.as_mut().map(mem::take).unwrap_or_default(),
+            boolvars: self
+                .defined_boolvars
+                .iter()
+                .map(|(var, negated)| self.definition_id(var, negated))
+                .collect(),
+        }
+    }
+
+    /// Whether an assertion justified ahead defined a Boolvar literal that's been defined
+    /// since, so justifying it here would use the definition and write something else.
+    fn is_stale(&mut self, ahead: &Ahead) -> bool {
+        ahead.boolvars.iter().any(|def_id| {
+            self.definition_lit(def_id)
+                .is_some_and(|(var, negated)| self.defined_lits.contains(var, negated))
+        })
+    }
+
+    /// Write an assertion justified ahead, leaving out the definitions that have already been
+    /// written, as justifying it here would have. The definitions are told apart by their
+    /// labels.
+    fn write_ahead(&mut self, line_number: u64, ahead: Ahead) -> Result<(), PBarberError> {
+        let outcome = ahead
+            .outcome
+            .map_err(|err| err.at(self.position(line_number)))?;
+        let written_before = self.start_justification(line_number);
+        // Both bounds of a variable are written together, so they're only marked afterwards
+        let mut defined_bounds = Vec::new();
+        for line in &ahead.lines {
+            let label = line_label(line).unwrap_or_default();
+            let written = match self.definition_lit(label) {
+                Some((var, negated)) => !self.defined_lits.insert(var, negated),
+                None => match ["@lb", "@ub"]
+                    .iter()
+                    .find_map(|prefix| label.strip_prefix(prefix))
+                {
+                    Some(var) => {
+                        let var = Ustr::from(var);
+                        defined_bounds.push(var);
+                        self.defined_bounds.contains(&var)
+                    }
+                    None => false,
+                },
+            };
+            if !written {
+                self.write_line(line)?;
+            }
+        }
+        self.defined_bounds.extend(defined_bounds);
+        self.finish_justification(outcome, written_before)
+    }
+
+    /// Run `f` as a call of `section`, counting it in the profile if profiling.
+    fn profiled<T>(&mut self, section: Section, f: impl FnOnce(&mut Self) -> T) -> T {
+        let start = self
+            .profile
+            .is_some()
+            .then(|| SectionStart::new(self.written_lines));
+        let result = f(self);
+        if let (Some(profile), Some(start)) = (self.profile.as_mut(), start) {
+            profile.record(section, start, self.written_lines);
+        }
+        result
     }
 
     fn failed_to_justify(
         &mut self,
-        constraint: Box<dyn DynPBConstraint + 'static>,
         id_str: &str,
         name_str: &str,
         msg: &str,
+        assertion: &str,
     ) -> Result<(), PBarberError> {
-        self.write_line(
-            format!("% PBarber Justifier failed to justify the following: (error msg: {msg})")
-                .as_str(),
-        )?;
-        self.write_bare_assertion(constraint, id_str, name_str)?;
+        debug!(
+            id = id_str,
+            justifier = name_str,
+            "failed to justify assertion: {msg}"
+        );
+        self.observer
+            .notify(|o| o.on_fallback(id_str, name_str, msg));
+        if let Some(comment) = self.diagnostics.failed_to_justify(id_str, name_str, msg) {
+            self.write_line(&format!("% {comment}"))?;
+        }
+        self.write_line(assertion)?;
         Ok(())
     }
 
-    fn write_bare_assertion(
-        &mut self,
-        constraint: Box<dyn DynPBConstraint + 'static>,
+    /// The assertion `id_str` of `constraint`, without its antecedents.
+    fn bare_assertion(
+        &self,
+        constraint: &dyn DynPBConstraint,
         id_str: &str,
         name_str: &str,
-    ) -> Result<(), PBarberError> {
+    ) -> String {
         let mut a_line = String::new();
         a_line.push_str(id_str);
         a_line.push_str(" a ");
@@ -282,16 +1337,15 @@ impl<W: Write> Justifier<W> {
         ));
         a_line.push_str(" :: ");
         a_line.push_str(name_str);
-        a_line.push_str(";");
-        self.write_line(a_line.as_str())?;
-        Ok(())
+        a_line.push(';');
+        a_line
     }
 
     fn parse_constraint(
         &mut self,
+        line_number: u64,
         constraint_str: &str,
-        id_str: &str,
-    ) -> Box<dyn DynPBConstraint + 'static> {
+    ) -> Result<Box<dyn DynPBConstraint + 'static>, PBarberError> {
         // Annoying hack to parse constraint for now
         // -- TODO: see if we can get better parsing tools from PBOxide
         let mut constraint_str = String::from(constraint_str);
@@ -299,30 +1353,50 @@ impl<W: Write> Justifier<W> {
         let constraint_str = constraint_str.as_str();
         let mut lex = OPBToken::lexer(constraint_str);
         let (constraint, _opt_leq) = parse_single_constraint(&mut lex, &mut self.pb_var_names)
-            .expect(format!("Constraint with id {id_str} was not parsed correctly.").as_str());
+            .map_err(|err| {
+                self.malformed(
+                    line_number,
+                    constraint_str,
+                    &format!("constraint not parsed correctly ({err:?})"),
+                )
+            })?;
         // ---
-        constraint
-    }
-
-    fn is_defined(&self, lit: &PBLiteral) -> bool {
-        self.defined_lits.contains(lit)
-    }
-
-    fn set_defined(&mut self, lit: &PBLiteral) {
-        self.defined_lits.insert(lit.clone());
+        for lit in constraint.get_constraint_lits() {
+            self.note_var(lit.get_var());
+        }
+        Ok(constraint)
     }
 
-    fn definition_id(&self, lit: &PBLiteral) -> String {
-        let mut id = "@".to_string();
-        if lit.is_negated() {
+    /// The ID of the definition of the literal of `var` (negated if `negated`).
+    fn definition_id(&self, var: Var, negated: bool) -> String {
+        let mut id = String::from("@");
+        if negated {
             id.push_str(REVERSE_LIT_DEF_PREFIX);
         } else {
             id.push_str(FORWARD_LIT_DEF_PREFIX);
         }
-        id.push_str(self.pb_var_names.get_name(lit.get_var()));
+        id.push_str(self.pb_var_names.get_name(var));
         id
     }
 
+    /// The variable and polarity of the literal that `def_id` is the definition ID of, if
+    /// it is one.
+    fn definition_lit(&mut self, def_id: &str) -> Option<(Var, bool)> {
+        let prefixed = def_id.strip_prefix('@')?;
+        let (name, negated) = match prefixed.strip_prefix(FORWARD_LIT_DEF_PREFIX) {
+            Some(name) => (name, false),
+            None => (prefixed.strip_prefix(REVERSE_LIT_DEF_PREFIX)?, true),
+        };
+        let var = self.pb_var_names.get_or_create_var(name);
+        self.note_var(var);
+        Some((var, negated))
+    }
+
+    /// Count `var` as named, for the workers to be given its name.
+    fn note_var(&mut self, var: Var) {
+        self.var_count = self.var_count.max(var.0 + 1);
+    }
+
     // fn cp_var_bits_eq(&mut self, cp_var: &str, val: i64) -> Result<String, PBarberError> {
     //     let (min, max) = self.get_min_max_for_var(cp_var)?;
     //     if val < min || val > max {
@@ -358,11 +1432,12 @@ impl<W: Write> Justifier<W> {
         &mut self,
         name: &str,
         antecedents_str: &str,
-    ) -> Result<Rc<dyn Justify>, PBarberError> {
+    ) -> Result<Arc<dyn Justify>, PBarberError> {
         let cache = false;
-        let justifier: Rc<dyn Justify> = match name {
-            "IntVarDef" => Rc::new(IntVarDefJustifier {}),
-            "IntLinear" => Rc::new(IntLinearJustifier::new(self, antecedents_str)?),
+        let justifier: Arc<dyn Justify> = match (name, self.factories.get(name).cloned()) {
+            (_, Some(factory)) => factory(self, antecedents_str)?,
+            ("IntVarDef", None) => Arc::new(IntVarDefJustifier {}),
+            ("IntLinear", None) => Arc::new(IntLinearJustifier::new(self, antecedents_str)?),
             _ => {
                 return Err(PBarberError::JustificationError(format!(
                     "{} not yet supported",
@@ -372,114 +1447,19 @@ impl<W: Write> Justifier<W> {
         };
 
         if cache {
-            Ok(Rc::clone(
-                self.justifiers
-                    .entry(name.to_string())
+            Ok(Arc::clone(
+                Arc::make_mut(&mut self.justifiers)
+                    .entry(Ustr::from(name))
                     .or_insert_with(|| justifier),
             ))
         } else {
             Ok(justifier)
         }
     }
-}
 
-impl<W: Write> JustifierActions for Justifier<W> {
-    fn write(&mut self, content: &str) -> Result<(), PBarberError> {
-        self.write_line(content)?;
-        Ok(())
-    }
-    fn get_min_max_for_var(&mut self, fzn_id: &Ustr) -> Result<(i64, i64), PBarberError> {
-        let fzn_var = self.get_fzn_variable(&fzn_id)?;
-        let domain = fzn_var
-            .domain
-            .as_ref()
-            .ok_or(PBarberError::JustificationError(format!(
-                "No domain found for {} in the fzn file (unsupported).",
-                fzn_id.as_str()
-            )))?;
-
-        let int_domain = match domain {
-            Domain::Int(r) => r,
-            _ => {
-                return Err(PBarberError::JustificationError(format!(
-                    "Expected Int domain for {} but found Float (unsupported).",
-                    fzn_id.as_str()
-                )));
-            }
-        };
-
-        let (min, max) = min_max(int_domain).ok_or(PBarberError::JustificationError(format!(
-            "Couldn't get the min and max domain values for {}",
-            fzn_id.as_str()
-        )))?;
-        Ok((min, max))
-    }
-
-    fn cp_var_bits_str(&mut self, cp_var: &Ustr, multiplier: i64) -> Result<String, PBarberError> {
-        let (min, max) = self.get_min_max_for_var(cp_var)?;
-        let mut num_bits = num_bits_for_range(min, max);
-        let mut bits = String::new();
-        if min < 0 {
-            bits.push_str(&(i64::pow(2, num_bits) * -multiplier).to_string());
-            bits.push(' ');
-            bits.push_str(cp_var);
-            bits.push_str("_b");
-            bits.push_str(&(num_bits + 1).to_string());
-            num_bits -= 1;
-        }
-
-        for i in (0..num_bits + 1).rev() {
-            bits.push(' ');
-            bits.push_str(&(i64::pow(2, i) * multiplier).to_string());
-            bits.push(' ');
-            bits.push_str(cp_var);
-            bits.push_str("_b");
-            bits.push_str(&(i).to_string());
-        }
-
-        Ok(bits.trim().to_string())
-    }
-
-    fn ensure_all_lits_defined(
-        &mut self,
-        constraint: &Box<dyn DynPBConstraint + 'static>,
-        strict: bool,
-    ) -> Result<(Vec<String>, Vec<String>), PBarberError> {
-        let lits = constraint.get_constraint_lits();
-        let mut pos_def_ids = Vec::<String>::new();
-        let mut neg_def_ids = Vec::<String>::new();
-        for lit in lits {
-            let pos_def_result = self.ensure_lit_defined(lit);
-            match pos_def_result {
-                Ok(id) => pos_def_ids.push(id),
-                Err(PBarberError::LiteralLookupError(m)) => {
-                    if strict {
-                        return Err(PBarberError::LiteralLookupError(m));
-                    }
-                }
-                Err(e) => return Err(e),
-            }
-
-            let mut neg_lit = lit.clone();
-            neg_lit.negate();
-
-            let neg_def_result = self.ensure_lit_defined(&neg_lit);
-            match neg_def_result {
-                Ok(id) => neg_def_ids.push(id),
-                Err(PBarberError::LiteralLookupError(m)) => {
-                    if strict {
-                        return Err(PBarberError::LiteralLookupError(m));
-                    }
-                }
-                Err(e) => return Err(e),
-            }
-        }
-        Ok((pos_def_ids, neg_def_ids))
-    }
-
-    fn ensure_lit_defined(&mut self, lit: &PBLiteral) -> Result<String, PBarberError> {
-        let def_id = self.definition_id(lit);
-        if self.is_defined(lit) {
+    fn define_lit(&mut self, lit: &PBLiteral) -> Result<String, PBarberError> {
+        let def_id = self.definition_id(lit.get_var(), lit.is_negated());
+        if self.defined_lits.contains_lit(lit) {
             return Ok(def_id);
         }
         let pb_lit_name = self
@@ -508,9 +1488,15 @@ impl<W: Write> JustifierActions for Justifier<W> {
                 } else {
                     operator
                 };
+                let parsed_value = value.parse::<i32>().map_err(|_| {
+                    PBarberError::LiteralLookupError(format!(
+                        "Value {value} of literal {} is not an integer",
+                        self.pb_var_names.get_name(lit.get_var())
+                    ))
+                });
                 let (value, operator_str) = match operator {
-                    CPOperator::GreaterEqual => (value.parse::<i32>().unwrap(), ">="),
-                    CPOperator::Less => (value.parse::<i32>().unwrap() - 1, "<="),
+                    CPOperator::GreaterEqual => (parsed_value?, ">="),
+                    CPOperator::Less => (parsed_value? - 1, "<="),
                     _ => {
                         return Err(PBarberError::JustificationError(
                             "Can't handle equality literals yet.".to_string(),
@@ -519,28 +1505,23 @@ impl<W: Write> JustifierActions for Justifier<W> {
                 };
 
                 let bits = self.cp_var_bits_str(&Ustr::from(name.as_str()), 1)?;
-                self.write_line(
-                    format!(
-                        "{} red {}{} ==> {} {} {} : {} -> {} ;",
-                        def_id,
-                        tilde_if_neg,
-                        pb_lit_name,
-                        bits,
-                        operator_str,
-                        value,
-                        pb_lit_name,
-                        if lit.is_negated() { 1 } else { 0 }
-                    )
-                    .as_str(),
+                write!(
+                    self,
+                    "{} red {}{} ==> {} {} {} : {} -> {} ;",
+                    def_id,
+                    tilde_if_neg,
+                    pb_lit_name,
+                    bits,
+                    operator_str,
+                    value,
+                    pb_lit_name,
+                    if lit.is_negated() { 1 } else { 0 }
                 )?;
 
-                self.set_defined(lit);
+                self.defined_lits.insert(lit.get_var(), lit.is_negated());
                 return Ok(def_id);
             }
-            CPLitData::Boolvar {
-                _cpvartype: _,
-                name,
-            } => {
+            CPLitData::Boolvar { name, .. } => {
                 // TODO: Probably want a better way of dealing with boolvars
                 let mut split_var = name.split("=");
                 let Some(var) = split_var.next() else {
@@ -583,27 +1564,188 @@ impl<W: Write> JustifierActions for Justifier<W> {
                     )));
                 };
 
-                self.write_line(
-                    format!(
-                        "{} red {}{} ==> {} {} {} : {} -> {} ;",
-                        def_id,
-                        tilde_if_neg,
-                        pb_lit_name,
-                        bits,
-                        operator,
-                        val,
-                        pb_lit_name,
-                        if lit.is_negated() { 1 } else { 0 }
-                    )
-                    .as_str(),
+                write!(
+                    self,
+                    "{} red {}{} ==> {} {} {} : {} -> {} ;",
+                    def_id,
+                    tilde_if_neg,
+                    pb_lit_name,
+                    bits,
+                    operator,
+                    val,
+                    pb_lit_name,
+                    if lit.is_negated() { 1 } else { 0 }
                 )?;
 
-                self.set_defined(lit);
+                self.defined_lits.insert(lit.get_var(), lit.is_negated());
+                self.defined_boolvars
+                    .insert(lit.get_var(), lit.is_negated());
                 return Ok("".to_string());
             }
         }
     }
 
+    fn define_bounds(&mut self, cp_var_id: &Ustr) -> Result<(String, String), PBarberError> {
+        let mut lb_id = String::from("@lb");
+        lb_id.push_str(&cp_var_id.as_str());
+        let mut ub_id = String::from("@ub");
+        ub_id.push_str(&cp_var_id.as_str());
+        if self.defined_bounds.contains(cp_var_id) {
+            return Ok((lb_id, ub_id));
+        }
+
+        let (min, max) = self.get_min_max_for_var(cp_var_id)?;
+        let bits = self.cp_var_bits_str(cp_var_id, 1)?;
+        write!(self, "{lb_id} a {bits} >={min}:: bits_lower_bound ;")?;
+        write!(self, "{ub_id} a {bits} <={max}:: bits_upper_bound ;")?;
+        self.defined_bounds.insert(*cp_var_id);
+        return Ok((lb_id, ub_id));
+    }
+
+    fn build_var_bits(&mut self, cp_var: &Ustr, multiplier: i64) -> Result<String, PBarberError> {
+        if let Some(bits) = self.var_bits.get(&(*cp_var, multiplier)) {
+            return Ok(bits.clone());
+        }
+        let (min, max) = self.get_min_max_for_var(cp_var)?;
+        let mut num_bits = num_bits_for_range(min, max);
+        let mut bits = String::new();
+        if min < 0 {
+            bits.push_str(&(i64::pow(2, num_bits) * -multiplier).to_string());
+            bits.push(' ');
+            bits.push_str(cp_var);
+            bits.push_str("_b");
+            bits.push_str(&(num_bits + 1).to_string());
+            num_bits -= 1;
+        }
+
+        for i in (0..num_bits + 1).rev() {
+            bits.push(' ');
+            bits.push_str(&(i64::pow(2, i) * multiplier).to_string());
+            bits.push(' ');
+            bits.push_str(cp_var);
+            bits.push_str("_b");
+            bits.push_str(&(i).to_string());
+        }
+
+        let unknown_bit = self
+            .opb_model
+            .as_ref()
+            .and_then(|opb_model| opb_model.unknown_variables(&bits).first().copied());
+        if let Some(bit) = unknown_bit {
+            return Err(PBarberError::JustificationError(format!(
+                "the encoding of {cp_var} uses {bit}, which isn't in the OPB model"
+            )));
+        }
+        let bits = bits.trim().to_string();
+        self.var_bits.insert((*cp_var, multiplier), bits.clone());
+        Ok(bits)
+    }
+}
+
+impl<W: Write> JustifierActions for Justifier<W> {
+    fn write(&mut self, content: &str) -> Result<(), PBarberError> {
+        self.write_line(content)?;
+        Ok(())
+    }
+
+    fn write_fmt(&mut self, args: fmt::Arguments<'_>) -> Result<(), PBarberError> {
+        let mut line = self.take_line_buffer();
+        // Writing to a String can't fail
+        let _ = line.write_fmt(args);
+        self.write_line_buffer(line)
+    }
+
+    fn take_line_buffer(&mut self) -> String {
+        let mut line = mem::take(&mut self.line_buffer);
+        line.clear();
+        line
+    }
+
+    fn write_line_buffer(&mut self, line: String) -> Result<(), PBarberError> {
+        self.write_line(&line)?;
+        self.line_buffer = line;
+        Ok(())
+    }
+    fn get_min_max_for_var(&mut self, fzn_id: &Ustr) -> Result<(i64, i64), PBarberError> {
+        if let Some(bounds) = self.var_bounds.get(fzn_id) {
+            return Ok(*bounds);
+        }
+        let fzn_var = self.get_fzn_variable(&fzn_id)?;
+        let domain = fzn_var
+            .domain
+            .as_ref()
+            .ok_or(PBarberError::JustificationError(format!(
+                "No domain found for {} in the fzn file (unsupported).",
+                fzn_id.as_str()
+            )))?;
+
+        let int_domain = match domain {
+            Domain::Int(r) => r,
+            _ => {
+                return Err(PBarberError::JustificationError(format!(
+                    "Expected Int domain for {} but found Float (unsupported).",
+                    fzn_id.as_str()
+                )));
+            }
+        };
+
+        let (min, max) = min_max(int_domain).ok_or(PBarberError::JustificationError(format!(
+            "Couldn't get the min and max domain values for {}",
+            fzn_id.as_str()
+        )))?;
+        self.var_bounds.insert(*fzn_id, (min, max));
+        Ok((min, max))
+    }
+
+    fn cp_var_bits_str(&mut self, cp_var: &Ustr, multiplier: i64) -> Result<String, PBarberError> {
+        self.profiled(Section::Helper("encoding"), |justifier| {
+            justifier.build_var_bits(cp_var, multiplier)
+        })
+    }
+
+    fn ensure_all_lits_defined(
+        &mut self,
+        constraint: &dyn DynPBConstraint,
+        strict: bool,
+    ) -> Result<(Vec<String>, Vec<String>), PBarberError> {
+        let lits = constraint.get_constraint_lits();
+        let mut pos_def_ids = Vec::<String>::new();
+        let mut neg_def_ids = Vec::<String>::new();
+        for lit in lits {
+            let pos_def_result = self.ensure_lit_defined(lit);
+            match pos_def_result {
+                Ok(id) => pos_def_ids.push(id),
+                Err(PBarberError::LiteralLookupError(m)) => {
+                    if strict {
+                        return Err(PBarberError::LiteralLookupError(m));
+                    }
+                }
+                Err(e) => return Err(e),
+            }
+
+            let mut neg_lit = lit.clone();
+            neg_lit.negate();
+
+            let neg_def_result = self.ensure_lit_defined(&neg_lit);
+            match neg_def_result {
+                Ok(id) => neg_def_ids.push(id),
+                Err(PBarberError::LiteralLookupError(m)) => {
+                    if strict {
+                        return Err(PBarberError::LiteralLookupError(m));
+                    }
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        Ok((pos_def_ids, neg_def_ids))
+    }
+
+    fn ensure_lit_defined(&mut self, lit: &PBLiteral) -> Result<String, PBarberError> {
+        self.profiled(Section::Helper("lit definition"), |justifier| {
+            justifier.define_lit(lit)
+        })
+    }
+
     fn pb_var_names(&self) -> &PBVarNameManager {
         &self.pb_var_names
     }
@@ -612,29 +1754,12 @@ impl<W: Write> JustifierActions for Justifier<W> {
         &self,
         fzn_id: &str,
     ) -> Result<&flatzinc_serde::Constraint<Ustr>, PBarberError> {
-        let fzn_line: usize = match fzn_id.trim().trim_start_matches("@f").parse::<usize>() {
-            Ok(f) => f,
-            Err(_) => {
-                return Err(PBarberError::JustificationError(format!(
-                    "Failed to get line number from fzn_id `{fzn_id}`"
-                )));
-            }
-        };
-
-        let fzn_constraint =
-            self.fzn
-                .constraints
-                .get(fzn_line)
-                .ok_or(PBarberError::JustificationError(format!(
-                    "Couldn't find fzn constraint for id {fzn_id}"
-                )))?;
-        Ok(fzn_constraint)
+        fzn_constraint(&self.fzn, fzn_id)
     }
 
     fn get_fzn_array(&self, id: &Ustr) -> Result<&flatzinc_serde::Array<Ustr>, PBarberError> {
         self.fzn
-            .arrays
-            .get(id)
+            .array(id)
             .ok_or(PBarberError::JustificationError(format!(
                 "Expected array, but got {:?}",
                 id
@@ -643,8 +1768,7 @@ impl<W: Write> JustifierActions for Justifier<W> {
 
     fn get_fzn_variable(&self, id: &Ustr) -> Result<&flatzinc_serde::Variable<Ustr>, PBarberError> {
         self.fzn
-            .variables
-            .get(id)
+            .variable(id)
             .ok_or(PBarberError::JustificationError(format!(
                 "Expected variable, but got {:?}",
                 id
@@ -672,78 +1796,91 @@ impl<W: Write> JustifierActions for Justifier<W> {
         &mut self,
         cp_var_id: &Ustr,
     ) -> Result<(String, String), PBarberError> {
-        let mut lb_id = String::from("@lb");
-        lb_id.push_str(&cp_var_id.as_str());
-        let mut ub_id = String::from("@ub");
-        ub_id.push_str(&cp_var_id.as_str());
-        if self.defined_bounds.contains(&cp_var_id.to_string()) {
-            return Ok((lb_id, ub_id));
-        }
+        self.profiled(Section::Helper("bounds definition"), |justifier| {
+            justifier.define_bounds(cp_var_id)
+        })
+    }
+}
 
-        self.defined_bounds.insert(cp_var_id.to_string());
-        let (min, max) = self.get_min_max_for_var(cp_var_id)?;
-        let mut pb_line = String::from(&lb_id);
-        pb_line.push_str(" a ");
-        pb_line.push_str(&self.cp_var_bits_str(&cp_var_id, 1)?);
-        pb_line.push_str(" >=");
-        pb_line.push_str(&min.to_string());
-        pb_line.push_str(":: bits_lower_bound ;");
-        self.write_line(&pb_line)?;
-        let mut pb_line = String::from(&ub_id);
-        pb_line.push_str(" a ");
-        pb_line.push_str(&self.cp_var_bits_str(&cp_var_id, 1)?);
-        pb_line.push_str(" <=");
-        pb_line.push_str(&max.to_string());
-        pb_line.push_str(":: bits_upper_bound ;");
-        self.write_line(&pb_line)?;
-        return Ok((lb_id, ub_id));
+/// Bytes reserved for each term of a [`PolBuilder`] line, enough for a weighted ID.
+const POL_TERM_BYTES: usize = 16;
+
+impl Default for PolBuilder {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
 impl PolBuilder {
-    fn new() -> Self {
+    pub fn new() -> Self {
+        Self::with_capacity(0)
+    }
+
+    /// A builder with room for about `terms` terms before it has to reallocate.
+    pub fn with_capacity(terms: usize) -> Self {
+        let mut pol_line = String::with_capacity("pol ;".len() + terms * POL_TERM_BYTES);
+        pol_line.push_str("pol");
         Self {
-            pol_line: String::from("pol "),
-            empty: true,
+            pol_line,
+            terms: 0,
+            done: false,
         }
     }
-    fn done(&mut self) -> &str {
-        self.pol_line.push(';');
+
+    /// The finished line, to write once everything has been added.
+    pub fn done(&mut self) -> &str {
+        if !self.done {
+            self.pol_line.push_str(" ;");
+            self.done = true;
+        }
         self.pol_line.as_str()
     }
 
-    fn add(&mut self, term: &String) -> &mut Self {
-        self.pol_line.push_str(term.as_str());
-        if self.empty {
-            self.pol_line.push_str(" ");
-            self.empty = false;
-        } else {
-            self.pol_line.push_str(" + ");
-        }
-        self
+    /// Add the constraint (or literal axiom) `term`.
+    pub fn add(&mut self, term: &str) -> &mut Self {
+        self.pol_line.push(' ');
+        self.pol_line.push_str(term);
+        self.add_to_sum()
+    }
+
+    /// Add the constraint numbered `id`, for proofs with implicit IDs.
+    pub fn add_id(&mut self, id: u64) -> &mut Self {
+        // Writing to a String can't fail
+        let _ = write!(self.pol_line, " {id}");
+        self.add_to_sum()
     }
 
-    fn add_all(&mut self, terms: &Vec<String>) -> &mut Self {
+    pub fn add_all(&mut self, terms: &[String]) -> &mut Self {
         for t in terms {
             self.add(t);
         }
         self
     }
 
-    fn add_weighted(&mut self, term: &String, weight: u32) -> &mut Self {
-        self.pol_line.push_str(term.as_str());
+    /// Add `weight` times the constraint `term`.
+    pub fn add_weighted(&mut self, term: &str, weight: u64) -> &mut Self {
         self.pol_line.push(' ');
-        self.pol_line.push_str(weight.to_string().as_str());
-        self.pol_line.push_str(" *");
-        if self.empty {
-            self.pol_line.push_str(" ");
-            self.empty = false;
-        } else {
-            self.pol_line.push_str(" + ");
+        self.pol_line.push_str(term);
+        let _ = write!(self.pol_line, " {weight} *");
+        self.add_to_sum()
+    }
+
+    /// Add `weight` times the constraint numbered `id`.
+    pub fn add_weighted_id(&mut self, id: u64, weight: u64) -> &mut Self {
+        let _ = write!(self.pol_line, " {id} {weight} *");
+        self.add_to_sum()
+    }
+
+    /// Sum the term just written with the ones before it, if there are any.
+    fn add_to_sum(&mut self) -> &mut Self {
+        if self.terms > 0 {
+            self.pol_line.push_str(" +");
         }
+        self.terms += 1;
         self
     }
 }
+
 fn min_max<T: Copy + Ord>(range_list: &RangeList<T>) -> Option<(T, T)> {
     let mut intervals = range_list.intervals();
 
@@ -768,3 +1905,140 @@ fn num_bits_for_range(min: i64, max: i64) -> u32 {
 fn trim_sc(to_trim: &str) -> &str {
     to_trim.trim_end_matches(';')
 }
+
+/// Warn if `input` looks like it is in the opposite order to the one the justifier reads it
+/// in, judging by whether it starts with the proof header or the end of the proof. The input
+/// is left at its start.
+pub fn check_input_order<R: Read + Seek>(
+    input: &mut R,
+    read_forwards: bool,
+    diagnostics: &mut dyn Diagnostics,
+) -> Result<(), PBarberError> {
+    let mut first_line = String::new();
+    BufReader::new(&mut *input).read_line(&mut first_line)?;
+    input.seek(SeekFrom::Start(0))?;
+    if !read_forwards && first_line.starts_with("pseudo-Boolean proof") {
+        diagnostics.warning(
+            WarningKind::InputOrder,
+            "the input starts with the proof header, so looks forwards, but is read in reverse. For non-reversed files use the `--read-forwards` option.",
+        )?;
+    } else if read_forwards && first_line.starts_with("end pseudo-Boolean") {
+        diagnostics.warning(
+            WarningKind::InputOrder,
+            "the input starts with the end of the proof, so looks reversed, but is read forwards due to `--read-forwards`.",
+        )?;
+    }
+    Ok(())
+}
+
+/// The length of `input`, plus one if it doesn't end with a newline, so that the lines read
+/// from the end can be given offsets from the start.
+fn line_offset_len<R: Read + Seek>(input: &mut R) -> io::Result<u64> {
+    let start = input.stream_position()?;
+    let len = input.seek(SeekFrom::End(0))?;
+    let mut last = [b'\n'];
+    if len > 0 {
+        input.seek(SeekFrom::End(-1))?;
+        input.read_exact(&mut last)?;
+    }
+    input.seek(SeekFrom::Start(start))?;
+    Ok(if last[0] == b'\n' { len } else { len + 1 })
+}
+
+/// The FlatZinc constraint referred to by an ID like `@f12`.
+pub(crate) fn fzn_constraint<'a>(
+    fzn: &'a FznModel,
+    fzn_id: &str,
+) -> Result<&'a flatzinc_serde::Constraint<Ustr>, PBarberError> {
+    let fzn_line: usize = match fzn_id.trim().trim_start_matches("@f").parse::<usize>() {
+        Ok(f) => f,
+        Err(_) => {
+            return Err(PBarberError::JustificationError(format!(
+                "Failed to get line number from fzn_id `{fzn_id}`"
+            )));
+        }
+    };
+
+    let fzn_constraint = fzn
+        .constraint(fzn_line)?
+        .ok_or(PBarberError::JustificationError(format!(
+            "Couldn't find fzn constraint for id {fzn_id}"
+        )))?;
+    Ok(fzn_constraint)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    // The assertions are still cached when the checkpoint is made after line 7
+    const PROOF: &str = "\
+pseudo-Boolean proof version 2.0
+f 0
+@c1 pol x1 x2 + ;
+@c2 a 1 x1 1 x2 >= 1 ;
+@c3 pol @c1 x3 + ;
+@c4 a 1 x3 >= 1 ;
+@c5 pol @c3 @c1 + ;
+@c6 pol @c2 @c4 + ;
+output NONE
+conclusion UNSAT : @c6 ;
+end pseudo-Boolean proof
+";
+
+    const FZN: &[u8] = br#"{"solve": {"method": "satisfy"}}"#;
+
+    fn justifier<W: Write>(out: W, config: JustifierConfig) -> Justifier<W> {
+        Justifier::try_with_sources(Cursor::new(PROOF), out, FZN, &b"{}"[..], config).unwrap()
+    }
+
+    #[test]
+    fn resuming_from_a_checkpoint_finishes_the_output() {
+        let dir = tempfile::tempdir().unwrap();
+        let checkpoint_path = dir.path().join("checkpoint.json");
+        let config = JustifierConfig::default()
+            .read_forwards(true)
+            .justifier_stats(true)
+            .checkpoint(&checkpoint_path, 7);
+
+        let mut styled = Vec::new();
+        let stats = justifier(&mut styled, config.clone())
+            .style()
+            .unwrap()
+            .unwrap();
+        let checkpoint = Checkpoint::load(&checkpoint_path).unwrap();
+        assert_eq!(checkpoint.input_lines, 7);
+        assert_eq!(checkpoint.pending_assertions.len(), 2);
+
+        // Pick up from the output written up to the checkpoint, as if the run had stopped there
+        let mut resumed = styled[..checkpoint.output_bytes as usize].to_vec();
+        let mut justifier = justifier(&mut resumed, config);
+        justifier.resume(checkpoint).unwrap();
+        let resumed_stats = justifier.style().unwrap().unwrap();
+        drop(justifier);
+        assert_eq!(
+            String::from_utf8(resumed).unwrap(),
+            String::from_utf8(styled).unwrap()
+        );
+        assert_eq!(
+            serde_json::to_value(resumed_stats).unwrap(),
+            serde_json::to_value(stats).unwrap()
+        );
+    }
+
+    #[test]
+    fn mismatched_checkpoints_are_malformed_input() {
+        let checkpoint = Checkpoint {
+            input_lines: 12,
+            read_forwards: true,
+            ..Checkpoint::default()
+        };
+        let mut justifier = justifier(io::sink(), JustifierConfig::default().read_forwards(true));
+        assert!(matches!(
+            justifier.resume(checkpoint),
+            Err(PBarberError::MalformedInput(_))
+        ));
+    }
+}
@@ -0,0 +1,122 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    hash::Hash,
+};
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use ustr::Ustr;
+
+use crate::{
+    fzn_model::FznModel,
+    stats::{FznCoverage, JustificationLineKinds, JustificationLines},
+};
+
+/// What the lines justifying assertions that name no FlatZinc constraint are counted under.
+const NO_CONSTRAINT: &str = "(no constraint)";
+
+/// The assertions made about each constraint of the FlatZinc model, for its [`FznCoverage`].
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub(crate) struct CoverageRecorder {
+    /// Assertions, assertions naming a missing justifier and the justifiers named, by the
+    /// index of the constraint in the model
+    assertions: HashMap<usize, (u64, u64, BTreeSet<Ustr>)>,
+    /// Lines written by each justifier, by the index of the first constraint the assertions
+    /// name
+    #[serde(with = "entries")]
+    lines: HashMap<(Option<usize>, Ustr), JustificationLineKinds>,
+}
+
+/// Saves a map as a list of its entries, for keys that JSON can't have.
+mod entries {
+    use super::*;
+
+    pub(super) fn serialize<K: Serialize, V: Serialize, S: Serializer>(
+        map: &HashMap<K, V>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(map)
+    }
+
+    pub(super) fn deserialize<'de, K, V, D>(deserializer: D) -> Result<HashMap<K, V>, D::Error>
+    where
+        K: Deserialize<'de> + Eq + Hash,
+        V: Deserialize<'de>,
+        D: Deserializer<'de>,
+    {
+        Ok(Vec::<(K, V)>::deserialize(deserializer)?
+            .into_iter()
+            .collect())
+    }
+}
+
+impl CoverageRecorder {
+    /// Record an assertion with the antecedents `antecedents` naming the justifier `name`,
+    /// which is `supported` if it exists.
+    pub(crate) fn record(&mut self, antecedents: &str, name: Ustr, supported: bool) {
+        for constraint in constraint_indices(antecedents) {
+            let (assertions, unsupported, names) = self.assertions.entry(constraint).or_default();
+            *assertions += 1;
+            *unsupported += u64::from(!supported);
+            names.insert(name);
+        }
+    }
+
+    /// Record the `lines` written by the justifier `name` for an assertion with the
+    /// antecedents `antecedents`.
+    pub(crate) fn record_lines(
+        &mut self,
+        antecedents: &str,
+        name: Ustr,
+        lines: &JustificationLineKinds,
+    ) {
+        let constraint = constraint_indices(antecedents).next();
+        self.lines.entry((constraint, name)).or_default().add(lines);
+    }
+
+    /// The lines written for the assertions about each type of constraint in `fzn`.
+    pub(crate) fn justification_lines(&self, fzn: &FznModel) -> JustificationLines {
+        let mut justification_lines = JustificationLines::default();
+        for ((constraint, name), lines) in &self.lines {
+            let constraint_type = match constraint.map(|index| fzn.constraint(index)) {
+                Some(Ok(Some(constraint))) => constraint.id.to_string(),
+                _ => NO_CONSTRAINT.to_string(),
+            };
+            justification_lines
+                .types
+                .entry(constraint_type)
+                .or_default()
+                .entry(name.to_string())
+                .or_default()
+                .add(lines);
+        }
+        justification_lines
+    }
+
+    /// The coverage of the constraints in `fzn`. Constraints that can't be read are left out.
+    pub(crate) fn coverage(&self, fzn: &FznModel) -> FznCoverage {
+        let mut coverage = FznCoverage::default();
+        for index in 0..fzn.constraint_count() {
+            let Ok(Some(constraint)) = fzn.constraint(index) else {
+                continue;
+            };
+            let type_coverage = coverage.types.entry(constraint.id.to_string()).or_default();
+            type_coverage.constraints += 1;
+            if let Some((assertions, unsupported, names)) = self.assertions.get(&index) {
+                type_coverage.referenced += 1;
+                type_coverage.assertions += assertions;
+                type_coverage.unsupported += unsupported;
+                type_coverage
+                    .justifiers
+                    .extend(names.iter().map(Ustr::to_string));
+            }
+        }
+        coverage
+    }
+}
+
+/// The indices of the FlatZinc constraints named in `antecedents`, e.g. 12 for `@f12`.
+fn constraint_indices(antecedents: &str) -> impl Iterator<Item = usize> + '_ {
+    antecedents
+        .split_whitespace()
+        .filter_map(|antecedent| antecedent.strip_prefix("@f")?.parse().ok())
+}
@@ -1,4 +1,4 @@
-use std::os::unix::process;
+use std::fmt::Write as _;
 
 use flatzinc_serde::Argument;
 use flatzinc_serde::Literal as FZNLiteral;
@@ -28,10 +28,10 @@ impl Justify for IntLinearJustifier {
     fn justify(
         &self,
         justifier: &mut dyn JustifierActions,
-        constraint: Box<dyn pboxide_formula::prelude::DynPBConstraint + 'static>,
+        constraint: &dyn pboxide_formula::prelude::DynPBConstraint,
         id_str: &str,
     ) -> Result<(), crate::PBarberError> {
-        let (_, neg_def_ids) = justifier.ensure_all_lits_defined(&constraint, true)?;
+        let (_, neg_def_ids) = justifier.ensure_all_lits_defined(constraint, true)?;
 
         if self.constraint_name != "int_lin_le" && self.constraint_name != "int_lin_eq" {
             return Err(PBarberError::JustificationError(format!(
@@ -42,11 +42,11 @@ impl Justify for IntLinearJustifier {
 
         let enc_id = self.reif_implies_le.as_ref().unwrap();
 
-        self.sub_lits_into_ineq(justifier, &neg_def_ids, &constraint, enc_id, 1)?;
+        self.sub_lits_into_ineq(justifier, &neg_def_ids, constraint, enc_id, 1)?;
         if self.constraint_name == "int_lin_eq" {
             let enc_id = self.reif_implies_ge.as_ref().unwrap();
 
-            self.sub_lits_into_ineq(justifier, &neg_def_ids, &constraint, enc_id, -1)?;
+            self.sub_lits_into_ineq(justifier, &neg_def_ids, constraint, enc_id, -1)?;
         }
         justifier.write(
             format!(
@@ -76,12 +76,15 @@ impl IntLinearJustifier {
         let fzn_constraint = justifier.get_fzn_constraint(fzn_id)?;
 
         let (coeffs, vars_l, rhs, reif) = match fzn_constraint.id.as_str() {
-            "int_lin_le" | "int_lin_eq" => (
-                &fzn_constraint.args[0],
-                &fzn_constraint.args[1],
-                &fzn_constraint.args[2],
-                None::<String>,
-            ),
+            "int_lin_le" | "int_lin_eq" => {
+                let [coeffs, vars_l, rhs] = fzn_constraint.args.as_slice() else {
+                    return Err(PBarberError::JustificationError(format!(
+                        "IntLinear: expected 3 arguments but got {}",
+                        fzn_constraint.args.len()
+                    )));
+                };
+                (coeffs, vars_l, rhs, None::<String>)
+            }
             id => {
                 return Err(PBarberError::JustificationError(format!(
                     "Don't know how to encode constraint {id}"
@@ -189,21 +192,20 @@ impl IntLinearJustifier {
         operator: &str,
         id: &str,
     ) -> Result<(), PBarberError> {
-        let mut pb_line = String::from(id);
+        let mut pb_line = justifier.take_line_buffer();
+        pb_line.push_str(id);
         pb_line.push_str(" a");
         for (coeff, var) in self.coeffs.iter().zip(self.vars.iter()) {
             pb_line.push(' ');
             pb_line.push_str(&justifier.cp_var_bits_str(&Ustr::from(var), *coeff)?);
         }
-        pb_line.push(' ');
-        pb_line.push_str(operator);
-        pb_line.push(' ');
-        pb_line.push_str(&self.rhs.to_string());
-        pb_line.push_str(" :: ");
-        pb_line.push_str(&self.constraint_name);
-        pb_line.push(';');
-
-        justifier.write(&pb_line)?;
+        let _ = write!(
+            pb_line,
+            " {operator} {} :: {};",
+            self.rhs, self.constraint_name
+        );
+
+        justifier.write_line_buffer(pb_line)?;
         Ok(())
     }
 
@@ -211,11 +213,11 @@ impl IntLinearJustifier {
         &self,
         justifier: &mut dyn JustifierActions,
         neg_def_ids: &Vec<String>,
-        constraint: &Box<dyn DynPBConstraint>,
+        constraint: &dyn DynPBConstraint,
         enc_id: &String,
         mult: i64,
     ) -> Result<(), PBarberError> {
-        let mut pol = PolBuilder::new();
+        let mut pol = PolBuilder::with_capacity(self.coeffs.len() + 1);
         pol.add(enc_id);
         let mut reason_vars = Vec::<String>::new();
         for l in constraint.get_constraint_lits() {
@@ -229,14 +231,14 @@ impl IntLinearJustifier {
         for (coeff, var) in self.coeffs.iter().zip(self.vars.iter()) {
             if let Some(i) = reason_vars.iter().position(|v| v == var) {
                 if neg_def_ids.get(i).unwrap() != "" {
-                    pol.add_weighted(neg_def_ids.get(i).unwrap(), coeff.abs() as u32);
+                    pol.add_weighted(neg_def_ids.get(i).unwrap(), coeff.unsigned_abs());
                 }
             } else {
                 let (lb, ub) = justifier.ensure_bounds_defined(&Ustr::from(var))?;
                 if *coeff * mult > 0 {
-                    pol.add_weighted(&lb, coeff.abs() as u32);
+                    pol.add_weighted(&lb, coeff.unsigned_abs());
                 } else if *coeff * mult < 0 {
-                    pol.add_weighted(&ub, coeff.abs() as u32);
+                    pol.add_weighted(&ub, coeff.unsigned_abs());
                 }
             }
         }
@@ -9,10 +9,10 @@ impl Justify for IntVarDefJustifier {
     fn justify(
         &self,
         justifier: &mut dyn JustifierActions,
-        constraint: Box<dyn pboxide_formula::prelude::DynPBConstraint + 'static>,
+        constraint: &dyn pboxide_formula::prelude::DynPBConstraint,
         id_str: &str,
     ) -> Result<(), crate::PBarberError> {
-        let (_, neg_def_ids) = justifier.ensure_all_lits_defined(&constraint, true)?;
+        let (_, neg_def_ids) = justifier.ensure_all_lits_defined(constraint, true)?;
 
         if neg_def_ids.len() > 2 {
             return Err(PBarberError::JustificationError(
@@ -20,7 +20,11 @@ impl Justify for IntVarDefJustifier {
             ));
         }
 
-        justifier.write(PolBuilder::new().add_all(&neg_def_ids).done())?;
+        justifier.write(
+            PolBuilder::with_capacity(neg_def_ids.len())
+                .add_all(&neg_def_ids)
+                .done(),
+        )?;
 
         let mut imp_line = String::new();
         imp_line.push_str(id_str);
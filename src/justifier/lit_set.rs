@@ -0,0 +1,51 @@
+use pboxide_formula::lit::{Lit as PBLiteral, Var};
+
+/// Set of PB literals, as two bits per variable index, one for each polarity. Much smaller
+/// than a hash set once most variables have a literal in it.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct LitSet {
+    words: Vec<u64>,
+}
+
+impl LitSet {
+    fn position(var: Var, negated: bool) -> (usize, u64) {
+        let bit = var.0 as usize * 2 + usize::from(negated);
+        (bit / 64, 1 << (bit % 64))
+    }
+
+    pub(crate) fn contains(&self, var: Var, negated: bool) -> bool {
+        let (word, mask) = Self::position(var, negated);
+        self.words.get(word).is_some_and(|bits| bits & mask != 0)
+    }
+
+    pub(crate) fn contains_lit(&self, lit: &PBLiteral) -> bool {
+        self.contains(lit.get_var(), lit.is_negated())
+    }
+
+    /// Returns whether the literal was newly inserted.
+    pub(crate) fn insert(&mut self, var: Var, negated: bool) -> bool {
+        let (word, mask) = Self::position(var, negated);
+        if word >= self.words.len() {
+            self.words.resize(word + 1, 0);
+        }
+        let inserted = self.words[word] & mask == 0;
+        self.words[word] |= mask;
+        inserted
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.words.clear();
+    }
+
+    /// The variables and polarities of the literals in the set, by variable index.
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (Var, bool)> + '_ {
+        self.words.iter().enumerate().flat_map(|(word, &bits)| {
+            (0..64)
+                .filter(move |bit| bits & (1 << bit) != 0)
+                .map(move |bit| {
+                    let bit = word * 64 + bit;
+                    (Var((bit / 2) as u32), bit % 2 == 1)
+                })
+        })
+    }
+}
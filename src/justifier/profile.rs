@@ -0,0 +1,164 @@
+#[cfg(feature = "cli")]
+use clap::ValueEnum;
+use serde::{Serialize, Serializer};
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Write as _},
+    io,
+    time::{Duration, Instant},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(ValueEnum))]
+pub enum ProfileFormat {
+    Table,
+    Json,
+}
+
+/// Calls, lines written and time taken by one part of the justifier.
+#[derive(Debug, Default, Clone, Copy, Serialize)]
+pub struct ProfileCounter {
+    pub calls: u64,
+    pub lines: u64,
+    #[serde(rename = "seconds", serialize_with = "as_seconds")]
+    pub time: Duration,
+}
+
+/// Where the justifier's time went, from a run with
+/// [`JustifierConfig::profile`](crate::JustifierConfig::profile) set. The justifiers and
+/// helpers are timed within the phases that call them, and the helpers within the
+/// justifiers. Work done ahead on other threads is added up over the threads.
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct Profile {
+    /// By phase of styling a line: reading, parsing, justifying, ...
+    pub phases: BTreeMap<String, ProfileCounter>,
+    /// By the name of the justifier used
+    pub justifiers: BTreeMap<String, ProfileCounter>,
+    /// By helper: literal definitions, bounds definitions and bit encodings
+    pub helpers: BTreeMap<String, ProfileCounter>,
+}
+
+/// A part of the justifier being profiled.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Section<'a> {
+    Phase(&'static str),
+    Justifier(&'a str),
+    Helper(&'static str),
+}
+
+/// When a section started, and the lines written by then.
+pub(crate) struct SectionStart {
+    time: Instant,
+    lines: u64,
+}
+
+impl SectionStart {
+    pub(crate) fn new(lines: u64) -> Self {
+        Self {
+            time: Instant::now(),
+            lines,
+        }
+    }
+}
+
+impl ProfileCounter {
+    fn add(&mut self, other: &ProfileCounter) {
+        self.calls += other.calls;
+        self.lines += other.lines;
+        self.time += other.time;
+    }
+}
+
+impl Profile {
+    /// Count a call of `section` from `start`, with `lines` written by now.
+    pub(crate) fn record(&mut self, section: Section, start: SectionStart, lines: u64) {
+        let (counters, name) = match section {
+            Section::Phase(name) => (&mut self.phases, name),
+            Section::Justifier(name) => (&mut self.justifiers, name),
+            Section::Helper(name) => (&mut self.helpers, name),
+        };
+        let counter = match counters.get_mut(name) {
+            Some(counter) => counter,
+            None => counters.entry(name.to_string()).or_default(),
+        };
+        counter.add(&ProfileCounter {
+            calls: 1,
+            lines: lines - start.lines,
+            time: start.time.elapsed(),
+        });
+    }
+
+    /// Add the counts of `other`, e.g. from another thread.
+    pub fn merge(&mut self, other: &Profile) {
+        for (counters, other_counters) in [
+            (&mut self.phases, &other.phases),
+            (&mut self.justifiers, &other.justifiers),
+            (&mut self.helpers, &other.helpers),
+        ] {
+            for (name, counter) in other_counters {
+                counters.entry(name.clone()).or_default().add(counter);
+            }
+        }
+    }
+
+    fn sections(&self) -> impl Iterator<Item = (&'static str, &String, &ProfileCounter)> {
+        [
+            ("phase", &self.phases),
+            ("justifier", &self.justifiers),
+            ("helper", &self.helpers),
+        ]
+        .into_iter()
+        .flat_map(|(kind, counters)| {
+            counters
+                .iter()
+                .map(move |(name, counter)| (kind, name, counter))
+        })
+    }
+
+    /// The counts as `profile.<kind>.<name>.<count> <value>` lines, for `--porcelain`.
+    pub fn porcelain(&self) -> String {
+        let mut lines = String::new();
+        for (kind, name, counter) in self.sections() {
+            let key = format!("profile.{kind}.{}", name.replace(' ', "_"));
+            // Writing to a String can't fail
+            let _ = writeln!(lines, "{key}.calls {}", counter.calls);
+            let _ = writeln!(lines, "{key}.lines {}", counter.lines);
+            let _ = writeln!(lines, "{key}.seconds {:.3}", counter.time.as_secs_f64());
+        }
+        lines
+    }
+
+    pub fn write_json<W: io::Write>(&self, out: W) -> io::Result<()> {
+        serde_json::to_writer_pretty(out, self).map_err(io::Error::other)
+    }
+}
+
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let width = self
+            .sections()
+            .map(|(kind, name, _)| kind.len() + name.len() + 1)
+            .max()
+            .unwrap_or_default();
+        writeln!(
+            f,
+            " {:width$}  {:>10}  {:>10}  {:>9}",
+            "", "calls", "lines", "seconds"
+        )?;
+        for (kind, name, counter) in self.sections() {
+            writeln!(
+                f,
+                " {:width$}  {:>10}  {:>10}  {:>9.3}",
+                format!("{kind} {name}"),
+                counter.calls,
+                counter.lines,
+                counter.time.as_secs_f64()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+fn as_seconds<S: Serializer>(time: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_f64(time.as_secs_f64())
+}
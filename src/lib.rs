@@ -1,11 +1,59 @@
-pub(crate) mod cp_lit_map;
+pub mod advisor;
+#[cfg(feature = "justify")]
+pub mod annotate;
+#[cfg(feature = "tokio")]
+pub mod async_io;
+pub mod checkpoint;
+pub mod cp_lit_map;
+pub mod diagnostics;
+#[cfg(feature = "cdylib")]
+pub mod ffi;
+#[cfg(feature = "justify")]
+pub(crate) mod fzn_model;
+pub mod graph;
+pub(crate) mod id_set;
+pub mod in_memory;
+#[cfg(feature = "justify")]
 pub mod justifier;
+pub(crate) mod limits;
+pub mod line_map;
+pub mod observer;
+pub mod opb_model;
+pub mod pipeline;
+pub(crate) mod pol_eval;
+pub mod proof_line;
+pub(crate) mod propagator;
+#[cfg(feature = "pyo3")]
+pub mod python;
+pub mod renumber;
+pub mod reverse_lines;
+pub mod split;
+pub mod stats;
 pub mod trimmer;
-use clap::Args;
+pub mod verify;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+#[cfg(feature = "cli")]
+use clap::{ArgGroup, Args, ValueEnum};
+use diagnostics::Diagnostics;
+use graph::GraphFormat;
+#[cfg(feature = "justify")]
+use justifier::profile::ProfileFormat;
+use serde::{Deserialize, Serialize};
+use stats::{
+    ConstraintSizes, DefinitionRecorder, DefinitionStats, DerivationStats, FznCoverage, Heaviest,
+    HeavyLine, JustificationLines, UnknownLines,
+};
 use std::fmt;
-use std::io::Write;
-use std::path::PathBuf;
-use std::{collections::HashMap, io};
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, HashMap},
+    io,
+};
 use thiserror::Error;
 
 #[derive(Debug, Error)]
@@ -13,97 +61,1415 @@ pub enum PBarberError {
     #[error("IO error: {0}")]
     Io(#[from] io::Error),
 
-    #[error("Expected line to start with `{expected}`, got `{found}`")]
-    UnexpectedLineStart { expected: String, found: String },
+    #[error("Expected line to start with `{expected}`, got `{found}`{}", position_suffix(.position))]
+    UnexpectedLineStart {
+        expected: String,
+        found: String,
+        position: Option<Box<LinePosition>>,
+    },
 
-    #[error("Missing or malformed constraint ID in line: {0}")]
-    MalformedConstraintId(String),
+    #[error("Missing or malformed constraint ID in line{}: {line}", position_suffix(.position))]
+    MalformedConstraintId {
+        line: String,
+        position: Option<Box<LinePosition>>,
+    },
 
     #[error("Unknown rule encountered: {0}")]
     UnknownRule(String),
 
+    /// A proof line that can't be read.
+    #[error("Malformed proof line{}: {message}: `{line}`", position_suffix(.position))]
+    MalformedLine {
+        position: Option<Box<LinePosition>>,
+        line: String,
+        message: String,
+    },
+
+    /// An input other than a proof line that can be read but isn't what it should be, e.g. a
+    /// checkpoint that doesn't match the proof it's resumed on.
+    #[error("Malformed input: {0}")]
+    MalformedInput(String),
+
     #[error("Internal logic error: {0}")]
     Internal(String),
 
     #[error("Missing proof conclusion")]
     MissingConclusion,
 
-    #[error("Parse error: expected `{expected}`, got `{found}`")]
-    ParseError { expected: String, found: String },
+    #[error("Parse error{}: expected `{expected}`, got `{found}`", position_suffix(.position))]
+    ParseError {
+        expected: String,
+        found: String,
+        position: Option<Box<LinePosition>>,
+    },
 
     #[error("Justification error: {0}")]
     JustificationError(String),
 
     #[error("Justification error: {0}")]
     LiteralLookupError(String),
+
+    #[error("Verification failed: {0}")]
+    VerificationFailed(String),
+
+    #[error("{message} (denied by `--deny-warnings`)")]
+    DeniedWarning { kind: WarningKind, message: String },
+
+    #[error("Resource limit exceeded: {0}")]
+    ResourceLimitExceeded(String),
+
+    /// Options that don't make sense, alone or together.
+    #[error("Invalid options: {0}")]
+    InvalidConfig(String),
+
+    #[error("{failed} of {total} inputs failed")]
+    BatchFailed {
+        failed: usize,
+        total: usize,
+        /// Exit code of the first input that failed
+        exit_code: u8,
+    },
+}
+
+impl PBarberError {
+    /// The process exit code for the error:
+    ///
+    /// | Code | Meaning |
+    /// |------|---------|
+    /// | 1    | Internal error |
+    /// | 2    | Invalid command line (reported by clap) or options |
+    /// | 3-6  | Warning denied by `--deny-warnings`, see [`WarningKind::exit_code`] |
+    /// | 10   | I/O error |
+    /// | 11   | Malformed or unsupported proof line, or other malformed input |
+    /// | 12   | Proof without a conclusion |
+    /// | 13   | Assertion couldn't be justified (with `--strict`) |
+    /// | 14   | Proof rejected by VeriPB |
+    /// | 15   | Resource limit exceeded |
+    ///
+    /// When a batch of inputs fails, the code is that of the first input that failed.
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            PBarberError::Internal(_) => 1,
+            PBarberError::InvalidConfig(_) => 2,
+            PBarberError::DeniedWarning { kind, .. } => kind.exit_code(),
+            PBarberError::Io(_) => 10,
+            PBarberError::UnexpectedLineStart { .. }
+            | PBarberError::MalformedConstraintId { .. }
+            | PBarberError::UnknownRule(_)
+            | PBarberError::MalformedLine { .. }
+            | PBarberError::MalformedInput(_)
+            | PBarberError::ParseError { .. } => 11,
+            PBarberError::MissingConclusion => 12,
+            PBarberError::JustificationError(_) | PBarberError::LiteralLookupError(_) => 13,
+            PBarberError::VerificationFailed(_) => 14,
+            PBarberError::ResourceLimitExceeded(_) => 15,
+            PBarberError::BatchFailed { exit_code, .. } => *exit_code,
+        }
+    }
+
+    /// Give the error the position of the input line it's about, unless it has one already.
+    /// Justification errors only have a message, so the position is added to that.
+    pub fn at(self, line_position: LinePosition) -> Self {
+        match self {
+            PBarberError::UnexpectedLineStart {
+                expected,
+                found,
+                position,
+            } => PBarberError::UnexpectedLineStart {
+                expected,
+                found,
+                position: position.or_else(|| Some(Box::new(line_position))),
+            },
+            PBarberError::MalformedConstraintId { line, position } => {
+                PBarberError::MalformedConstraintId {
+                    line,
+                    position: position.or_else(|| Some(Box::new(line_position))),
+                }
+            }
+            PBarberError::MalformedLine {
+                position,
+                line,
+                message,
+            } => PBarberError::MalformedLine {
+                position: position.or_else(|| Some(Box::new(line_position))),
+                line,
+                message,
+            },
+            PBarberError::ParseError {
+                expected,
+                found,
+                position,
+            } => PBarberError::ParseError {
+                expected,
+                found,
+                position: position.or_else(|| Some(Box::new(line_position))),
+            },
+            PBarberError::JustificationError(message) => {
+                PBarberError::JustificationError(format!("{message} ({line_position})"))
+            }
+            PBarberError::LiteralLookupError(message) => {
+                PBarberError::LiteralLookupError(format!("{message} ({line_position})"))
+            }
+            err => err,
+        }
+    }
+
+    /// A [`PBarberError::ParseError`] for a JSON file that isn't what was `expected`, at the
+    /// line and column serde_json stopped at.
+    pub(crate) fn from_json(expected: &str, err: serde_json::Error) -> Self {
+        let message = err.to_string();
+        let at = format!(" at line {} column {}", err.line(), err.column());
+        PBarberError::ParseError {
+            expected: expected.to_string(),
+            found: message.strip_suffix(&at).unwrap_or(&message).to_string(),
+            // serde_json counts from 1, and gives 0 for errors that aren't at a position
+            position: (err.line() > 0).then(|| {
+                Box::new(LinePosition {
+                    line_number: Some(err.line() as u64),
+                    column: Some(err.column() as u64),
+                    ..LinePosition::default()
+                })
+            }),
+        }
+    }
+
+    /// A [`PBarberError::Io`] for a file that couldn't be read, naming `what` it is and its
+    /// path.
+    pub(crate) fn reading(what: &str, path: &Path, err: io::Error) -> Self {
+        PBarberError::Io(io::Error::new(
+            err.kind(),
+            format!("{what} `{}`: {err}", path.display()),
+        ))
+    }
+}
+
+/// Where a line is in an input, as far as it's known.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LinePosition {
+    /// The number of the line in the order lines were read, from 1
+    pub line_number: Option<u64>,
+    /// Whether the input was read from the end, so lines are numbered from the end
+    pub from_end: bool,
+    /// The number of the line counting from the start, if it was read from the end
+    pub original_line_number: Option<u64>,
+    pub column: Option<u64>,
+    /// Offset of the start of the line in the input
+    pub byte_offset: Option<u64>,
+}
+
+impl LinePosition {
+    /// Line `line_number` in the order lines were read.
+    pub fn line(line_number: u64, from_end: bool) -> Self {
+        Self {
+            line_number: Some(line_number),
+            from_end,
+            ..Self::default()
+        }
+    }
 }
 
-#[derive(Default, Args)]
+impl fmt::Display for LinePosition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts = Vec::new();
+        match self.line_number {
+            Some(line_number) if self.from_end => {
+                parts.push(format!("line {line_number} from the end"))
+            }
+            Some(line_number) => parts.push(format!("line {line_number}")),
+            None => (),
+        }
+        if let Some(original_line_number) = self.original_line_number.filter(|_| self.from_end) {
+            parts.push(format!("line {original_line_number} from the start"));
+        }
+        if let Some(column) = self.column {
+            parts.push(format!("column {column}"));
+        }
+        if let Some(byte_offset) = self.byte_offset {
+            parts.push(format!("byte {byte_offset}"));
+        }
+        if parts.is_empty() {
+            return write!(f, "unknown position");
+        }
+        write!(f, "{}", parts.join(", "))
+    }
+}
+
+fn position_suffix(position: &Option<Box<LinePosition>>) -> String {
+    match position {
+        Some(position) => format!(" ({position})"),
+        None => String::new(),
+    }
+}
+
+/// The kinds of conditions that are normally only warned about, but become errors with
+/// `--deny-warnings`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WarningKind {
+    /// An option was ignored as it doesn't combine with the others
+    IgnoredOption,
+    /// The input looks like it is in the opposite order to the one it is read in
+    InputOrder,
+    /// A line was kept without knowing everything it depends on, e.g. one with a rule the
+    /// trimmer doesn't understand
+    UnknownRule,
+    /// The proof was processed leniently, e.g. without a conclusion or against a model it
+    /// doesn't match
+    Recovered,
+}
+
+impl WarningKind {
+    pub fn exit_code(self) -> u8 {
+        match self {
+            WarningKind::IgnoredOption => 3,
+            WarningKind::InputOrder => 4,
+            WarningKind::UnknownRule => 5,
+            WarningKind::Recovered => 6,
+        }
+    }
+}
+
+#[derive(Default, Clone)]
+#[cfg_attr(feature = "cli", derive(Args))]
+#[cfg_attr(feature = "cli", command(next_help_heading = "Trimming options"))]
 pub struct TrimmerConfig {
-    #[arg(
-        short,
-        long,
-        help = "Add all possible deletions for logged constraints when trimming."
+    #[cfg_attr(
+        feature = "cli",
+        arg(
+            short = 'e',
+            long,
+            help = "Add all possible deletions for logged constraints when trimming."
+        )
     )]
     pub eager_deletion: bool,
 
-    #[arg(short, long, help = "Record and print trimming statistics.")]
+    #[cfg_attr(
+        feature = "cli",
+        arg(
+            short = 's',
+            long = "trim-stats",
+            visible_alias = "stats",
+            help = "Record and print trimming statistics."
+        )
+    )]
     pub stats: bool,
 
-    #[arg(
-        short,
-        long,
-        help = "Add deletions for potential literal definitions at when trimming."
+    #[cfg_attr(
+        feature = "cli",
+        arg(
+            short = 'l',
+            long,
+            help = "Add deletions for potential literal definitions at when trimming."
+        )
     )]
     pub lit_deletion: bool,
+
+    #[cfg_attr(
+        feature = "cli",
+        arg(
+            long,
+            help = "Rewrite constraint IDs in the output to compact sequential IDs."
+        )
+    )]
+    pub renumber: bool,
+
+    #[cfg_attr(
+        feature = "cli",
+        arg(
+            long,
+            value_name = "N|fixpoint",
+            help = "Number of trimming passes to run, or `fixpoint` to re-trim until no lines are removed.",
+            default_value = "1"
+        )
+    )]
+    pub passes: TrimPasses,
+
+    #[cfg_attr(
+        feature = "cli",
+        arg(
+            long,
+            help = "Report the formula constraints used by the trimmed proof."
+        )
+    )]
+    pub core: bool,
+
+    #[cfg_attr(
+        feature = "cli",
+        arg(
+            long,
+            value_name = "CORE_FILE",
+            help = "Write the formula constraints used by the trimmed proof to a file (implies --core)."
+        )
+    )]
+    pub core_path: Option<PathBuf>,
+
+    #[cfg_attr(
+        feature = "cli",
+        arg(
+            long,
+            value_name = "FORMAT",
+            help = "Write the antecedent graph of the kept constraints."
+        )
+    )]
+    pub emit_graph: Option<GraphFormat>,
+
+    #[cfg_attr(
+        feature = "cli",
+        arg(
+            long,
+            value_name = "GRAPH_FILE",
+            help = "Path for the emitted graph. Defaults to <OUTPUT_FILE>.graph.<dot|json>."
+        )
+    )]
+    pub graph_path: Option<PathBuf>,
+
+    #[cfg_attr(
+        feature = "cli",
+        arg(
+            long = "line-map",
+            value_name = "PATH",
+            help = "Write the line of the input each output line came from to PATH, for `advise --veripb-log`. With `trim-and-style`, the lines of the styled proof are mapped to the original input."
+        )
+    )]
+    pub line_map_path: Option<PathBuf>,
+
+    #[cfg_attr(
+        feature = "cli",
+        arg(
+            long,
+            help = "Write the trimmed proof in reverse, ready to be read by the justifier without reversing it first."
+        )
+    )]
+    pub reverse_output: bool,
+
+    #[cfg_attr(
+        feature = "cli",
+        arg(
+            long = "opb",
+            value_name = "MODEL_OPB",
+            help = "OPB model to validate the proof's formula references against."
+        )
+    )]
+    pub opb_path: Option<PathBuf>,
+
+    #[cfg_attr(feature = "cli", arg(
+        long,
+        value_enum,
+        help = "What to do with lines using rules the trimmer doesn't understand.",
+        default_value_t = UnknownRulePolicy::WarnKeep
+    ))]
+    pub unknown_rules: UnknownRulePolicy,
+
+    #[cfg_attr(
+        feature = "cli",
+        arg(
+            long,
+            help = "Resolve numeric constraint references against implicit (counted) constraint IDs."
+        )
+    )]
+    pub implicit_ids: bool,
+
+    #[cfg_attr(
+        feature = "cli",
+        arg(
+            long,
+            help = "Keep deletions of core constraints (formula constraints and those moved to the core) where they are, as the checker may check them. Only derived constraints get their deletions moved."
+        )
+    )]
+    pub faithful_deletions: bool,
+
+    #[cfg_attr(
+        feature = "cli",
+        arg(
+            long,
+            value_name = "N",
+            help = "Line budget for the trimmed proof (before styling). Over budget, derivations far from the conclusion are cut and restated as assertions."
+        )
+    )]
+    pub target_lines: Option<u64>,
+
+    #[cfg_attr(
+        feature = "cli",
+        arg(
+            long,
+            value_name = "N",
+            help = "Byte budget for the trimmed proof (before styling), like `--target-lines`."
+        )
+    )]
+    pub target_bytes: Option<u64>,
+
+    #[cfg_attr(
+        feature = "cli",
+        arg(
+            long,
+            value_name = "ID|last",
+            help = "Trim a proof without a conclusion towards this constraint ID (or the last derived one)."
+        )
+    )]
+    pub assume_contradiction: Option<String>,
+
+    #[cfg_attr(
+        feature = "cli",
+        arg(
+            long,
+            value_name = "N",
+            help = "Only keep derivations within N antecedent steps of the conclusion, restating the rest as assertions."
+        )
+    )]
+    pub max_depth: Option<usize>,
+
+    #[cfg_attr(
+        feature = "cli",
+        arg(
+            long,
+            help = "Find the needed lines with a parallel pre-pass over the proof. Faster on large proofs, but keeps the whole dependency graph in memory."
+        )
+    )]
+    pub parallel: bool,
+
+    /// Fail with [`PBarberError::DeniedWarning`] instead of recording warnings
+    #[cfg_attr(feature = "cli", arg(skip))]
+    pub deny_warnings: bool,
+
+    #[cfg_attr(feature = "cli", arg(skip))]
+    pub buffers: IoBuffers,
+
+    #[cfg_attr(feature = "cli", arg(skip))]
+    pub limits: ResourceLimits,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(ValueEnum))]
+pub enum UnknownRulePolicy {
+    /// Stop with an error
+    Error,
+    /// Keep the line unchanged and print a warning
+    #[default]
+    WarnKeep,
+    /// Silently drop the line
+    Drop,
 }
 
-#[derive(Default, Args)]
+impl TrimmerConfig {
+    pub fn wants_core(&self) -> bool {
+        self.core || self.core_path.is_some()
+    }
+
+    /// Whether the trimmed proof can be passed to the justifier from the trimmer's buffer of
+    /// kept lines, rather than needing to be written out in full first.
+    pub fn can_stream(&self) -> bool {
+        self.passes.max_passes() == 1
+            && self.max_depth.is_none()
+            && !self.has_output_budget()
+            // The line maps of trimming and styling are joined up from their files
+            && self.line_map_path.is_none()
+    }
+
+    pub fn has_output_budget(&self) -> bool {
+        self.target_lines.is_some() || self.target_bytes.is_some()
+    }
+
+    /// Whether a trimmed proof of this size is within the output budget.
+    pub fn within_budget(&self, lines: u64, bytes: u64) -> bool {
+        self.target_lines.is_none_or(|target| lines <= target)
+            && self.target_bytes.is_none_or(|target| bytes <= target)
+    }
+
+    /// Check that the options make sense together, as
+    /// [`Trimmer::try_with_config`](trimmer::Trimmer::try_with_config) does.
+    pub fn validate(&self) -> Result<(), PBarberError> {
+        let invalid = |message: &str| Err(PBarberError::InvalidConfig(message.to_string()));
+        if self.lit_deletion {
+            return invalid(
+                "`lit_deletion` would produce invalid proofs without expanding assertions",
+            );
+        }
+        if self.reverse_output && self.passes.max_passes() > 1 {
+            return invalid("`reverse_output` needs a single trimming pass");
+        }
+        if self.reverse_output && (self.max_depth.is_some() || self.has_output_budget()) {
+            return invalid("`reverse_output` can't be used with depth-limited trimming");
+        }
+        if self.graph_path.is_some() && self.emit_graph.is_none() {
+            return invalid("`graph_path` is set without `emit_graph`");
+        }
+        self.limits.validate()
+    }
+
+    /// Turn off `lit_deletion`, which would produce invalid proofs without expanding
+    /// assertions, telling `diagnostics` it was ignored.
+    pub fn without_lit_deletion(
+        self,
+        diagnostics: &mut dyn Diagnostics,
+    ) -> Result<Self, PBarberError> {
+        if !self.lit_deletion {
+            return Ok(self);
+        }
+        diagnostics.warning(
+            WarningKind::IgnoredOption,
+            "ignoring `--lit-deletion` as it would produce invalid proofs without expanding assertions.",
+        )?;
+        Ok(Self {
+            lit_deletion: false,
+            ..self
+        })
+    }
+
+    /// Turn off `reverse_output` if the other options need forwards output, telling
+    /// `diagnostics` it was ignored.
+    pub fn resolve_reverse_output(
+        &mut self,
+        diagnostics: &mut dyn Diagnostics,
+    ) -> Result<(), PBarberError> {
+        if self.reverse_output && self.passes.max_passes() > 1 {
+            diagnostics.warning(
+                WarningKind::IgnoredOption,
+                "ignoring `--reverse-output` as multiple trimming passes need forwards output.",
+            )?;
+            self.reverse_output = false;
+        }
+        if self.reverse_output && (self.max_depth.is_some() || self.has_output_budget()) {
+            diagnostics.warning(
+                WarningKind::IgnoredOption,
+                "ignoring `--reverse-output` as depth-limited trimming needs forwards output.",
+            )?;
+            self.reverse_output = false;
+        }
+        Ok(())
+    }
+
+    /// Default the graph path to sit next to the trimmer output if a graph was requested.
+    pub fn resolve_graph_path(&mut self, output_path: &Path) {
+        if let (Some(format), None) = (self.emit_graph, &self.graph_path) {
+            self.graph_path = Some(output_path.with_extension(format.extension()));
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrimPasses {
+    Count(usize),
+    Fixpoint,
+}
+
+impl Default for TrimPasses {
+    fn default() -> Self {
+        TrimPasses::Count(1)
+    }
+}
+
+impl TrimPasses {
+    pub fn max_passes(&self) -> usize {
+        match self {
+            TrimPasses::Count(n) => *n,
+            TrimPasses::Fixpoint => usize::MAX,
+        }
+    }
+}
+
+impl FromStr for TrimPasses {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "fixpoint" {
+            return Ok(TrimPasses::Fixpoint);
+        }
+        match s.parse::<usize>() {
+            Ok(n) if n > 0 => Ok(TrimPasses::Count(n)),
+            _ => Err(format!(
+                "expected a positive number of passes or `fixpoint`, got `{s}`"
+            )),
+        }
+    }
+}
+
+#[derive(Clone)]
+#[cfg_attr(feature = "cli", derive(Args))]
+#[cfg_attr(feature = "cli", command(next_help_heading = "Advice options"))]
+pub struct AdviseConfig {
+    #[cfg_attr(
+        feature = "cli",
+        arg(
+            long = "lits",
+            value_name = "LITS_JSON",
+            help = "Literal mapping file to check the assertions' literals against."
+        )
+    )]
+    pub lits_path: Option<PathBuf>,
+
+    #[cfg_attr(
+        feature = "cli",
+        arg(
+            long,
+            value_name = "LOG",
+            help = "Saved VeriPB output for a proof made from this one, to find the lines of this proof its errors come from."
+        )
+    )]
+    pub veripb_log: Option<PathBuf>,
+
+    #[cfg_attr(
+        feature = "cli",
+        arg(
+            long = "line-map",
+            value_name = "PATH",
+            requires = "veripb_log",
+            help = "Line map written by `--line-map` when trimming or styling this proof into the one VeriPB checked. Without it, VeriPB is assumed to have checked this proof."
+        )
+    )]
+    pub line_map_path: Option<PathBuf>,
+
+    #[cfg_attr(
+        feature = "cli",
+        arg(
+            long,
+            value_name = "OUTPUT",
+            requires = "checker",
+            help = "Shrink the proof to a smaller one that still fails the checker given after `--`, and write it to OUTPUT. Each line is removed along with the lines that use it."
+        )
+    )]
+    pub minimize: Option<PathBuf>,
+
+    #[cfg_attr(
+        feature = "cli",
+        arg(
+            last = true,
+            value_name = "CHECKER",
+            help = "Command for `--minimize`, after `--`, run with the path of a proof appended. The proof fails if it exits non-zero, so it should only fail on the error being minimized."
+        )
+    )]
+    pub checker: Vec<String>,
+
+    #[cfg_attr(
+        feature = "cli",
+        arg(
+            long,
+            value_name = "ID",
+            conflicts_with = "minimize",
+            help = "Print the tree of constraints the constraint ID (e.g. `@c12`) is derived from, instead of the report."
+        )
+    )]
+    pub explain: Option<String>,
+
+    #[cfg_attr(
+        feature = "cli",
+        arg(
+            long,
+            value_name = "N",
+            requires = "explain",
+            default_value_t = DEFAULT_EXPLAIN_DEPTH,
+            help = "Levels of antecedents to show for `--explain`. Deeper ones are counted."
+        )
+    )]
+    pub explain_depth: usize,
+
+    #[cfg_attr(
+        feature = "cli",
+        arg(
+            long,
+            help = "Also check for IDs defined again while live, conclusions of missing constraints and malformed lines."
+        )
+    )]
+    pub lint: bool,
+
+    #[cfg_attr(
+        feature = "cli",
+        arg(
+            long,
+            requires = "opb_path",
+            help = "Check whether each assertion is RUP (follows by unit propagation) from the formula and the constraints derived before it, and from its hints if it lists some."
+        )
+    )]
+    pub rup: bool,
+
+    #[cfg_attr(
+        feature = "cli",
+        arg(
+            long = "opb",
+            value_name = "MODEL_OPB",
+            help = "OPB model of the proof's formula, for `--rup`."
+        )
+    )]
+    pub opb_path: Option<PathBuf>,
+}
+
+const DEFAULT_EXPLAIN_DEPTH: usize = 4;
+
+impl Default for AdviseConfig {
+    fn default() -> Self {
+        Self {
+            lits_path: None,
+            veripb_log: None,
+            line_map_path: None,
+            minimize: None,
+            checker: Vec::new(),
+            explain: None,
+            explain_depth: DEFAULT_EXPLAIN_DEPTH,
+            lint: false,
+            rup: false,
+            opb_path: None,
+        }
+    }
+}
+
+#[derive(Default, Clone)]
+#[cfg_attr(feature = "cli", derive(Args))]
+#[cfg_attr(feature = "cli", command(next_help_heading = "Stats options"))]
+pub struct StatsConfig {
+    #[cfg_attr(
+        feature = "cli",
+        arg(
+            long,
+            help = "Print the counters of all the proofs in one aligned table, each compared to the first."
+        )
+    )]
+    pub table: bool,
+
+    #[cfg_attr(
+        feature = "cli",
+        arg(
+            long,
+            help = "Print the table as CSV, with a column of changes for each proof."
+        )
+    )]
+    pub csv: bool,
+}
+
+#[cfg(feature = "justify")]
+#[derive(Default, Clone)]
+#[cfg_attr(feature = "cli", derive(Args))]
+#[cfg_attr(feature = "cli", command(next_help_heading = "Annotation options"))]
+pub struct AnnotateConfig {
+    #[cfg_attr(
+        feature = "cli",
+        arg(
+            long = "fzn",
+            value_name = "FZN_JSON",
+            help = "Path to FlatZinc file in the JSON format."
+        )
+    )]
+    pub fzn_path: PathBuf,
+
+    #[cfg_attr(
+        feature = "cli",
+        arg(
+            long = "lits",
+            value_name = "LITS_JSON",
+            help = "Literal mapping file, to describe what literal definitions mean."
+        )
+    )]
+    pub lits_path: Option<PathBuf>,
+}
+
+#[derive(Default, Clone)]
+#[cfg_attr(feature = "cli", derive(Args))]
+#[cfg_attr(feature = "cli", command(next_help_heading = "Split options"))]
+// Only the sizes are one of
+#[cfg_attr(feature = "cli", group(skip))]
+#[cfg_attr(feature = "cli", command(group(ArgGroup::new("size").required(true).args(["lines", "bytes"]))))]
+pub struct SplitConfig {
+    #[cfg_attr(
+        feature = "cli",
+        arg(
+            long,
+            value_name = "N",
+            help = "Put at most N lines of the proof in each chunk."
+        )
+    )]
+    pub lines: Option<u64>,
+
+    #[cfg_attr(feature = "cli", arg(
+        long,
+        value_name = "SIZE",
+        value_parser = parse_size,
+        help = "Put at most SIZE bytes of the proof in each chunk, with an optional K, M, G or T suffix."
+    ))]
+    pub bytes: Option<u64>,
+
+    #[cfg_attr(
+        feature = "cli",
+        arg(
+            long = "opb",
+            value_name = "MODEL_OPB",
+            help = "OPB model of the proof's formula, so that constraints derived from it can be restated in later chunks."
+        )
+    )]
+    pub opb_path: Option<PathBuf>,
+}
+
+#[derive(Clone)]
+#[cfg_attr(feature = "cli", derive(Args))]
+#[cfg_attr(feature = "cli", command(next_help_heading = "Verification options"))]
+pub struct VerifyConfig {
+    #[cfg_attr(
+        feature = "cli",
+        arg(
+            long,
+            value_name = "VERIPB",
+            help = "VeriPB executable to run.",
+            default_value = "veripb"
+        )
+    )]
+    pub veripb: PathBuf,
+
+    #[cfg_attr(
+        feature = "cli",
+        arg(
+            long,
+            value_name = "ORIGINAL_PROOF",
+            help = "Proof that was trimmed or styled to give PROOF_FILE, to map failing lines back to."
+        )
+    )]
+    pub original: Option<PathBuf>,
+
+    #[cfg_attr(
+        feature = "cli",
+        arg(
+            last = true,
+            value_name = "VERIPB_ARGS",
+            help = "Extra arguments for VeriPB, after `--`."
+        )
+    )]
+    pub veripb_args: Vec<String>,
+}
+
+/// Options for the [`Justifier`](justifier::Justifier). Outside of the command line, start from
+/// [`JustifierConfig::new`] and set the options with the builder methods.
+#[cfg(feature = "justify")]
+#[derive(Clone)]
+#[cfg_attr(feature = "cli", derive(Args))]
+#[cfg_attr(feature = "cli", command(next_help_heading = "Styling options"))]
 pub struct JustifierConfig {
-    #[arg(
-        long = "fzn",
-        value_name = "FZN_JSON",
-        help = "Path to FlatZinc file in the JSON format."
+    #[cfg_attr(
+        feature = "cli",
+        arg(
+            long = "fzn",
+            value_name = "FZN_JSON",
+            help = "Path to FlatZinc file in the JSON format."
+        )
     )]
     fzn_path: PathBuf,
 
-    #[arg(
-        long = "lits",
-        value_name = "LITS_JSON",
-        help = "Literal mapping file in the JSON format."
+    #[cfg_attr(
+        feature = "cli",
+        arg(
+            long = "lits",
+            value_name = "LITS_JSON",
+            help = "Literal mapping file in the JSON format."
+        )
     )]
     lits_path: PathBuf,
 
-    #[arg(
-        short,
-        long,
-        help = "Justify a file that is NOT in reverse (disabled if trimming)."
+    #[cfg_attr(
+        feature = "cli",
+        arg(
+            short = 'r',
+            long,
+            help = "Justify a file that is NOT in reverse (implied if trimming)."
+        )
     )]
     pub read_forwards: bool,
-    #[arg(short, long, help = "Record and print justifier statistics.")]
+    #[cfg_attr(
+        feature = "cli",
+        arg(
+            short = 'j',
+            long = "style-stats",
+            visible_alias = "justifier-stats",
+            help = "Record and print justifier statistics."
+        )
+    )]
     pub justifier_stats: bool,
-    #[arg(
-        short,
+    #[cfg_attr(feature = "cli", arg(
+        short = 'm',
         long,
         help = "Max number of lines to cache before being forced to expand an assertion.",
-        default_value_t = 10000
-    )]
+        default_value_t = DEFAULT_MAX_LINE_CACHE
+    ))]
     pub max_line_cache: usize,
+    #[cfg_attr(
+        feature = "cli",
+        arg(
+            long,
+            help = "Fail if an assertion can't be justified, rather than keeping it as an assertion."
+        )
+    )]
+    pub strict: bool,
+
+    #[cfg_attr(
+        feature = "cli",
+        arg(
+            id = "parallel_style",
+            long = "parallel-style",
+            help = "Justify batches of cached assertions on several threads. The output is the same as without it."
+        )
+    )]
+    pub parallel: bool,
+
+    #[cfg_attr(
+        feature = "cli",
+        arg(
+            long,
+            help = "Parse the FlatZinc constraints as assertions use them, rather than all of them up front. Starts faster and uses less memory on huge models."
+        )
+    )]
+    pub lazy_fzn: bool,
+
+    #[cfg_attr(
+        feature = "cli",
+        arg(
+            long = "checkpoint",
+            value_name = "PATH",
+            help = "Save progress to PATH every `--checkpoint-every` lines, so an interrupted run can be continued with `--resume`. The output is kept if styling fails."
+        )
+    )]
+    pub checkpoint_path: Option<PathBuf>,
+
+    #[cfg_attr(feature = "cli", arg(
+        long,
+        value_name = "N",
+        default_value_t = DEFAULT_CHECKPOINT_EVERY,
+        requires = "checkpoint_path",
+        help = "Lines to style between checkpoints."
+    ))]
+    pub checkpoint_every: u64,
+
+    #[cfg_attr(
+        feature = "cli",
+        arg(
+            long,
+            requires = "checkpoint_path",
+            help = "Continue from the checkpoint at `--checkpoint`, keeping the output written up to it. Output stats only count the lines written after it."
+        )
+    )]
+    pub resume: bool,
+
+    #[cfg_attr(
+        feature = "cli",
+        arg(
+            long = "check-encodings",
+            value_name = "MODEL_OPB",
+            help = "Check that the bit encodings of CP variables written by the justifier use variables of this OPB model, and keep the assertions whose encodings don't."
+        )
+    )]
+    pub encoding_model_path: Option<PathBuf>,
+
+    #[cfg_attr(
+        feature = "cli",
+        arg(
+            id = "style_line_map",
+            long = "style-line-map",
+            value_name = "PATH",
+            help = "Write the line of the input each styled line came from (or was written for) to PATH."
+        )
+    )]
+    pub line_map_path: Option<PathBuf>,
+
+    #[cfg_attr(
+        feature = "cli",
+        arg(
+            long,
+            value_name = "FORMAT",
+            num_args = 0..=1,
+            require_equals = true,
+            default_missing_value = "table",
+            help = "Count the calls, lines written and time taken by each justifier, phase and helper (literal and bounds definitions, bit encodings), and print them at the end."
+        )
+    )]
+    pub profile: Option<ProfileFormat>,
+
+    #[cfg_attr(feature = "cli", arg(skip))]
+    pub buffers: IoBuffers,
+
+    #[cfg_attr(feature = "cli", arg(skip))]
+    pub limits: ResourceLimits,
 }
 
-#[derive(Default, Clone)]
+#[cfg(feature = "justify")]
+const DEFAULT_MAX_LINE_CACHE: usize = 10000;
+#[cfg(feature = "justify")]
+const DEFAULT_CHECKPOINT_EVERY: u64 = 1_000_000;
+
+#[cfg(feature = "justify")]
+impl Default for JustifierConfig {
+    fn default() -> Self {
+        Self {
+            fzn_path: PathBuf::new(),
+            lits_path: PathBuf::new(),
+            read_forwards: false,
+            justifier_stats: false,
+            max_line_cache: DEFAULT_MAX_LINE_CACHE,
+            strict: false,
+            parallel: false,
+            lazy_fzn: false,
+            checkpoint_path: None,
+            checkpoint_every: DEFAULT_CHECKPOINT_EVERY,
+            resume: false,
+            encoding_model_path: None,
+            line_map_path: None,
+            profile: None,
+            buffers: IoBuffers::default(),
+            limits: ResourceLimits::default(),
+        }
+    }
+}
+
+#[cfg(feature = "justify")]
+impl JustifierConfig {
+    /// Options for justifying against the FlatZinc model (in JSON) at `fzn_path` and the
+    /// literal mapping at `lits_path`, with the other options as on the command line.
+    pub fn new(fzn_path: impl Into<PathBuf>, lits_path: impl Into<PathBuf>) -> Self {
+        Self {
+            fzn_path: fzn_path.into(),
+            lits_path: lits_path.into(),
+            ..Self::default()
+        }
+    }
+
+    pub fn fzn_path(&self) -> &Path {
+        &self.fzn_path
+    }
+
+    pub fn lits_path(&self) -> &Path {
+        &self.lits_path
+    }
+
+    /// Read the proof forwards rather than in reverse.
+    pub fn read_forwards(mut self, read_forwards: bool) -> Self {
+        self.read_forwards = read_forwards;
+        self
+    }
+
+    pub fn justifier_stats(mut self, justifier_stats: bool) -> Self {
+        self.justifier_stats = justifier_stats;
+        self
+    }
+
+    pub fn max_line_cache(mut self, max_line_cache: usize) -> Self {
+        self.max_line_cache = max_line_cache;
+        self
+    }
+
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Justify cached assertions in parallel batches.
+    pub fn parallel(mut self, parallel: bool) -> Self {
+        self.parallel = parallel;
+        self
+    }
+
+    /// Parse the FlatZinc constraints only when they're used.
+    pub fn lazy_fzn(mut self, lazy_fzn: bool) -> Self {
+        self.lazy_fzn = lazy_fzn;
+        self
+    }
+
+    /// Save a checkpoint to `path` every `every` lines.
+    pub fn checkpoint(mut self, path: impl Into<PathBuf>, every: u64) -> Self {
+        self.checkpoint_path = Some(path.into());
+        self.checkpoint_every = every;
+        self
+    }
+
+    /// Check the bit encodings the justifier writes against the OPB model at `path`.
+    pub fn check_encodings(mut self, path: impl Into<PathBuf>) -> Self {
+        self.encoding_model_path = Some(path.into());
+        self
+    }
+
+    /// Profile the justifier, see [`Justifier::profile`](justifier::Justifier::profile).
+    pub fn profile(mut self, format: ProfileFormat) -> Self {
+        self.profile = Some(format);
+        self
+    }
+
+    pub fn buffers(mut self, buffers: IoBuffers) -> Self {
+        self.buffers = buffers;
+        self
+    }
+
+    pub fn limits(mut self, limits: ResourceLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Check that the options make sense together, as
+    /// [`Justifier::try_with_config`](justifier::Justifier::try_with_config) does. The
+    /// paths are checked when they're opened.
+    pub fn validate(&self) -> Result<(), PBarberError> {
+        let invalid = |message: &str| Err(PBarberError::InvalidConfig(message.to_string()));
+        if self.resume && self.checkpoint_path.is_none() {
+            return invalid("`resume` needs a checkpoint path");
+        }
+        self.limits.validate()
+    }
+}
+
+/// Sizes of the buffers proofs are read and written through. The best sizes depend a lot on
+/// the storage: big ones mean fewer reads and writes, which helps most on network
+/// filesystems and spinning disks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(Args))]
+#[cfg_attr(feature = "cli", command(next_help_heading = "I/O options"))]
+pub struct IoBuffers {
+    #[cfg_attr(feature = "cli", arg(
+        long,
+        global = true,
+        value_name = "SIZE",
+        value_parser = parse_buffer_size,
+        default_value = "8K",
+        help = "Size of the buffer proofs read forwards are read through, with an optional K, M or G suffix."
+    ))]
+    pub read_buffer: usize,
+
+    #[cfg_attr(feature = "cli", arg(
+        long,
+        global = true,
+        value_name = "SIZE",
+        value_parser = parse_buffer_size,
+        default_value = "1M",
+        help = "Size of the buffer output files are written through, with an optional K, M or G suffix."
+    ))]
+    pub write_buffer: usize,
+
+    #[cfg_attr(feature = "cli", arg(
+        long,
+        global = true,
+        value_name = "SIZE",
+        value_parser = parse_buffer_size,
+        default_value = "1M",
+        help = "Size of the blocks proofs read in reverse are read in, with an optional K, M or G suffix."
+    ))]
+    pub block_size: usize,
+}
+
+impl Default for IoBuffers {
+    fn default() -> Self {
+        Self {
+            read_buffer: 8 << 10,
+            write_buffer: 1 << 20,
+            block_size: 1 << 20,
+        }
+    }
+}
+
+/// Bounds on the work done for a proof, beyond which processing stops with
+/// [`PBarberError::ResourceLimitExceeded`].
+#[derive(Debug, Default, Clone)]
+#[cfg_attr(feature = "cli", derive(Args))]
+#[cfg_attr(feature = "cli", command(next_help_heading = "Resource limits"))]
+pub struct ResourceLimits {
+    #[cfg_attr(
+        feature = "cli",
+        arg(
+            long,
+            value_name = "N",
+            help = "Stop if a proof has more than N lines."
+        )
+    )]
+    pub max_lines: Option<u64>,
+
+    #[cfg_attr(feature = "cli", arg(
+        long,
+        value_name = "SIZE",
+        value_parser = parse_size,
+        help = "Stop if memory use (resident set size) exceeds SIZE bytes, with an optional K, M, G or T suffix. Only supported on Linux."
+    ))]
+    pub max_memory: Option<u64>,
+
+    #[cfg_attr(feature = "cli", arg(
+        long,
+        value_name = "SECONDS",
+        value_parser = parse_seconds,
+        help = "Stop if processing takes longer than SECONDS."
+    ))]
+    pub timeout: Option<Duration>,
+
+    #[cfg_attr(feature = "cli", arg(skip))]
+    deadline: Option<Instant>,
+}
+
+impl ResourceLimits {
+    /// Start the clock for the timeout, so that it covers everything run with these limits
+    /// from now on rather than restarting for each step.
+    pub fn start_clock(&mut self) {
+        if let Some(timeout) = self.timeout {
+            self.deadline.get_or_insert(Instant::now() + timeout);
+        }
+    }
+
+    pub(crate) fn deadline(&self) -> Option<Instant> {
+        self.deadline
+            .or_else(|| self.timeout.map(|timeout| Instant::now() + timeout))
+    }
+
+    /// Check the limits can be enforced here: memory use can only be measured on platforms
+    /// with `/proc/self/status`.
+    pub fn validate(&self) -> Result<(), PBarberError> {
+        if self.max_memory.is_some() && limits::resident_memory().is_none() {
+            return Err(PBarberError::InvalidConfig(
+                "`max_memory` is set, but memory use can't be measured on this platform"
+                    .to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "cli")]
+fn parse_size(size: &str) -> Result<u64, String> {
+    let (digits, multiplier) = match size.trim().to_ascii_uppercase().as_str() {
+        s if s.ends_with('K') => (s[..s.len() - 1].to_string(), 1 << 10),
+        s if s.ends_with('M') => (s[..s.len() - 1].to_string(), 1 << 20),
+        s if s.ends_with('G') => (s[..s.len() - 1].to_string(), 1 << 30),
+        s if s.ends_with('T') => (s[..s.len() - 1].to_string(), 1 << 40),
+        s => (s.to_string(), 1),
+    };
+    digits
+        .parse::<u64>()
+        .ok()
+        .and_then(|n| n.checked_mul(multiplier))
+        .ok_or_else(|| format!("expected a size such as `512M`, got `{size}`"))
+}
+
+#[cfg(feature = "cli")]
+fn parse_buffer_size(size: &str) -> Result<usize, String> {
+    parse_size(size)
+        .ok()
+        .and_then(|size| usize::try_from(size).ok())
+        .filter(|size| *size > 0)
+        .ok_or_else(|| format!("expected a buffer size such as `64K`, got `{size}`"))
+}
+
+#[cfg(feature = "cli")]
+fn parse_seconds(seconds: &str) -> Result<Duration, String> {
+    seconds
+        .parse::<f64>()
+        .ok()
+        .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok())
+        .ok_or_else(|| format!("expected a number of seconds, got `{seconds}`"))
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct ProofFileStats {
     pub total_lines: u64,
     pub pol_lines: u64,
     pub red_lines: u64,
     pub a_lines: u64,
     pub del_lines: u64,
+    pub unknown_lines: u64,
+    /// The unknown lines by rule, with examples
+    #[serde(default)]
+    pub unknown: UnknownLines,
+    /// Most constraint IDs live (derived but not yet deleted) at any point in the proof
+    pub peak_live_constraints: u64,
+    /// Live constraints summed over the lines of the proof, the memory the checker needs
+    /// over time
+    #[serde(default)]
+    pub live_area: u64,
+    /// [`ProofFileStats::live_area`] if nothing were ever deleted
+    #[serde(default)]
+    pub live_area_without_deletions: u64,
     pub a_lines_by_name: HashMap<String, u64>,
+    /// Lines by rule, see [`rule_key`]
+    #[serde(default)]
+    pub lines_by_rule: BTreeMap<String, u64>,
+    /// The pol lines with the most antecedents
+    #[serde(default)]
+    pub heaviest_pol_lines: Heaviest,
+    /// The assertions whose justifications were the most lines, found when styling
+    #[serde(default)]
+    pub heaviest_justifications: Heaviest,
+    /// The shape of the conclusion's derivation, found when trimming
+    #[serde(default)]
+    pub derivation: Option<DerivationStats>,
+    /// How the assertions used the FlatZinc model, found when styling
+    #[serde(default)]
+    pub fzn_coverage: Option<FznCoverage>,
+    /// The lines written for the assertions about each type of FlatZinc constraint, found
+    /// when styling
+    #[serde(default)]
+    pub justification_lines: Option<JustificationLines>,
+    /// How the literal and bounds definitions were used
+    #[serde(default)]
+    pub definitions: Option<DefinitionStats>,
+    #[serde(default)]
+    pub sizes: ConstraintSizes,
+    /// Sizes of the input lines kept and dropped, found when trimming
+    #[serde(default)]
+    pub kept_sizes: Option<ConstraintSizes>,
+    #[serde(default)]
+    pub dropped_sizes: Option<ConstraintSizes>,
+    // Running count of definitions minus deletions, in the order lines were recorded
+    #[serde(skip)]
+    live_balance: i64,
+    #[serde(skip)]
+    max_live_balance: i64,
+    #[serde(skip)]
+    min_live_balance: i64,
+    // Running balances summed over the lines recorded, with and without deletions
+    #[serde(skip)]
+    live_balance_sum: i64,
+    #[serde(skip)]
+    growth: i64,
+    #[serde(skip)]
+    growth_sum: i64,
+    #[serde(skip)]
+    definition_uses: DefinitionRecorder,
+}
+
+/// [`ProofFileStats`] part way through recording a proof, with the running counts they're
+/// finished from, so that recording can carry on from them in a later run.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct RecordedStats {
+    stats: ProofFileStats,
+    live_balance: i64,
+    max_live_balance: i64,
+    min_live_balance: i64,
+    live_balance_sum: i64,
+    growth: i64,
+    growth_sum: i64,
+    definition_uses: DefinitionRecorder,
 }
 
+/// Stats of a proof compared to those of a reference proof, which it either borrows or owns.
 pub struct ProofFileStatsComparison<'a> {
-    current: &'a ProofFileStats,
-    reference: &'a ProofFileStats,
+    current: Cow<'a, ProofFileStats>,
+    reference: Cow<'a, ProofFileStats>,
+}
+
+/// A stat of a proof compared to a reference proof.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StatChange {
+    pub current: u64,
+    pub reference: u64,
+}
+
+impl StatChange {
+    /// The change from the reference as a percentage, or `None` if the reference is 0.
+    pub fn percent(&self) -> Option<f64> {
+        (self.reference != 0)
+            .then(|| 100.0 * (self.current as f64 - self.reference as f64) / self.reference as f64)
+    }
+}
+
+/// A [`ProofFileStatsComparison`] in a form that can be saved.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProofFileStatsChanges {
+    pub total_lines: StatChange,
+    pub a_lines: StatChange,
+    pub pol_lines: StatChange,
+    pub red_lines: StatChange,
+    pub del_lines: StatChange,
+    pub unknown_lines: StatChange,
+    pub peak_live_constraints: StatChange,
+    #[serde(default)]
+    pub live_area: StatChange,
+    /// Assertion lines by name, for the names in either proof
+    pub a_lines_by_name: BTreeMap<String, StatChange>,
+    /// Lines by rule, for the rules in either proof
+    #[serde(default)]
+    pub lines_by_rule: BTreeMap<String, StatChange>,
 }
 
 pub trait ProofReader<W: Write> {
@@ -115,77 +1481,461 @@ pub trait ProofReader<W: Write> {
 
     fn next_line(&mut self) -> Option<Result<String, io::Error>> {
         let line = self.lines_next();
-        if self.has_stats() {
-            if let Some(line) = line.as_ref() {
-                let line = line.as_ref().unwrap();
-                self.input_stats_mut().record_line(&line);
-            }
+        // Only lines actually read count, I/O errors are passed on
+        if self.has_stats()
+            && let Some(Ok(line)) = line.as_ref()
+        {
+            self.input_stats_mut().record_line(line);
         }
         line
     }
 
     fn write_line(&mut self, content: &str) -> io::Result<()> {
         if self.has_stats() {
-            self.output_stats_mut().record_line(&content);
+            self.output_stats_mut().record_line(content);
         }
         writeln!(self.out_mut(), "{}", content)
     }
 
-    fn assert_starts_with(&self, line: &String, pattern: &str) -> Result<(), PBarberError> {
+    fn assert_starts_with(&self, line: &str, pattern: &str) -> Result<(), PBarberError> {
         if !line.starts_with(pattern) {
             return Err(PBarberError::UnexpectedLineStart {
                 expected: pattern.into(),
-                found: line.clone(),
+                found: line.to_string(),
+                position: None,
             });
         };
         Ok(())
     }
 }
 
-static ALLOWED_RULES: [&str; 3] = ["a", "pol", "p"];
+/// Rules that derive a new constraint, and so consume a constraint ID.
+static DERIVING_RULES: [&str; 11] = [
+    "pol", "p", "rup", "u", "a", "ia", "i", "red", "dom", "soli", "solx",
+];
+/// Assertion names that have a justifier.
+pub(crate) static SUPPORTED_JUSTIFIERS: [&str; 2] = ["IntVarDef", "IntLinear"];
 static FORWARD_LIT_DEF_PREFIX: &str = "lf";
 static REVERSE_LIT_DEF_PREFIX: &str = "lr";
 
+/// Split a `#<level>` debug marker off the start of a line, returning the marker (if any) and
+/// the rest of the line. A line holding only a marker (`#2` or `# 2`) has an empty rest.
+pub(crate) fn split_debug_marker(line: &str) -> (Option<&str>, &str) {
+    let Some(rest) = line.strip_prefix('#') else {
+        return (None, line);
+    };
+    let level_len = rest.len() - rest.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    if level_len > 0 {
+        let (marker, rest) = line.split_at(level_len + 1);
+        return (Some(marker), rest.trim_start());
+    }
+    let level = rest.trim();
+    if !level.is_empty() && level.chars().all(|c| c.is_ascii_digit()) {
+        return (Some(line), "");
+    }
+    (None, line)
+}
+
+/// The name a line is counted under in [`ProofFileStats::lines_by_rule`], from its `rule` and
+/// the token after it: the short forms of rules under their long names, deletions by kind, and
+/// the end of the proof apart from the ends of subproofs.
+fn rule_key<'l>(rule: &'l str, next: &str) -> &'l str {
+    match (rule, next) {
+        ("", _) => "blank",
+        ("pseudo-Boolean", _) => "header",
+        ("p", _) => "pol",
+        ("u", _) => "rup",
+        ("i", _) => "ia",
+        ("del", "id") => "del id",
+        ("del", "range") => "del range",
+        ("del", "spec") => "del spec",
+        ("del", "find") => "del find",
+        ("end", "pseudo-Boolean") => "end proof",
+        _ if rule.starts_with(['%', '*']) => "comment",
+        _ => rule,
+    }
+}
+
 impl ProofFileStats {
-    fn record_line(&mut self, line: &str) {
+    /// The stats of a proof given line by line, first line first.
+    pub fn from_lines<I: IntoIterator<Item = S>, S: AsRef<str>>(lines: I) -> Self {
+        let mut stats = Self::default();
+        stats.extend(lines);
+        stats.finish(false);
+        stats
+    }
+
+    /// The stats of a proof given line by line, last line first.
+    pub fn from_reversed_lines<I: IntoIterator<Item = S>, S: AsRef<str>>(lines: I) -> Self {
+        let mut stats = Self::default();
+        stats.extend(lines);
+        stats.finish(true);
+        stats
+    }
+
+    /// The stats of the proof read from `reader`, first line first.
+    pub fn from_reader<R: BufRead>(reader: R) -> io::Result<Self> {
+        let mut stats = Self::default();
+        for line in reader.lines() {
+            stats.record_line(&line?);
+        }
+        stats.finish(false);
+        Ok(stats)
+    }
+
+    /// Record a line of a proof. The lines can be recorded in either order, as long as it's the
+    /// same throughout; call [`ProofFileStats::finish`] once they all have been.
+    pub fn record_line(&mut self, line: &str) {
+        let line = split_debug_marker(line).1;
         self.total_lines += 1;
         let mut split_line = line.split(" ");
-        let mut rule = split_line.next().unwrap();
+        let mut rule = split_line.next().unwrap_or_default();
         if rule.starts_with("@") {
-            rule = split_line.next().unwrap()
+            rule = split_line.next().unwrap_or_default()
+        }
+        let key = rule_key(rule, split_line.next().unwrap_or_default());
+        match self.lines_by_rule.get_mut(key) {
+            Some(count) => *count += 1,
+            None => {
+                self.lines_by_rule.insert(key.to_string(), 1);
+            }
         }
         match rule {
             "a" => self.record_assertion(line),
             "red" => self.red_lines += 1,
-            "pol" | "p" => self.pol_lines += 1,
+            "pol" | "p" => self.record_pol_line(line),
             "del" => self.del_lines += 1,
             _ => (),
         };
+        self.sizes.record_line(line);
+        self.definition_uses.record(line, rule);
+        self.record_live_change(line, rule);
+    }
+
+    /// Record a line with an unknown `rule`, that was `kept` or dropped.
+    pub(crate) fn record_unknown(&mut self, rule: &str, line: &str, kept: bool) {
+        self.unknown_lines += 1;
+        self.unknown.record(rule, line, kept);
+    }
+
+    fn record_pol_line(&mut self, line: &str) {
+        self.pol_lines += 1;
+        let mut tokens = line.split_whitespace().peekable();
+        let label = tokens.next_if(|token| token.starts_with('@'));
+        // Skip the rule
+        tokens.next();
+        let mut antecedents = 0;
+        while let Some(token) = tokens.next() {
+            let is_id = token.starts_with('@')
+                || (token.bytes().all(|b| b.is_ascii_digit())
+                    // Numbers multiplied or divided by are scalars
+                    && !matches!(tokens.peek(), Some(&"*") | Some(&"d")));
+            antecedents += u64::from(is_id);
+        }
+        if self.heaviest_pol_lines.would_keep(antecedents) {
+            self.heaviest_pol_lines.record(HeavyLine {
+                id: label.unwrap_or("(unlabelled)").to_string(),
+                name: None,
+                weight: antecedents,
+            });
+        }
+    }
+
+    fn record_live_change(&mut self, line: &str, rule: &str) {
+        let mut terms = line.split_whitespace().filter(|t| *t != ";");
+        let change = if DERIVING_RULES.contains(&rule) {
+            1
+        } else if rule == "f" {
+            // Formula constraints are live from the start
+            terms.nth(1).and_then(|n| n.parse().ok()).unwrap_or(0)
+        } else if rule == "del" {
+            match terms.nth(1) {
+                Some("id") => -(terms.count() as i64),
+                Some("range") => match (terms.next(), terms.next()) {
+                    (Some(start), Some(end)) => match (start.parse::<i64>(), end.parse::<i64>()) {
+                        (Ok(start), Ok(end)) => -(end - start).max(0),
+                        _ => 0,
+                    },
+                    _ => 0,
+                },
+                // Each deletes the one constraint it gives
+                Some("spec" | "find") => -1,
+                _ => 0,
+            }
+        } else {
+            0
+        };
+        self.live_balance += change;
+        self.max_live_balance = self.max_live_balance.max(self.live_balance);
+        self.min_live_balance = self.min_live_balance.min(self.live_balance);
+        self.live_balance_sum += self.live_balance;
+        self.growth += change.max(0);
+        self.growth_sum += self.growth;
+    }
+
+    /// Add the stats of a part of the proof that comes after the lines recorded so far. Both
+    /// must have been recorded forwards.
+    pub(crate) fn append(&mut self, later: &ProofFileStats) {
+        self.total_lines += later.total_lines;
+        self.pol_lines += later.pol_lines;
+        self.red_lines += later.red_lines;
+        self.a_lines += later.a_lines;
+        self.del_lines += later.del_lines;
+        self.unknown_lines += later.unknown_lines;
+        self.unknown.merge(&later.unknown);
+        for (name, count) in &later.a_lines_by_name {
+            *self.a_lines_by_name.entry(name.clone()).or_insert(0) += count;
+        }
+        for (rule, count) in &later.lines_by_rule {
+            *self.lines_by_rule.entry(rule.clone()).or_insert(0) += count;
+        }
+        self.sizes.merge(&later.sizes);
+        self.definition_uses.merge(&later.definition_uses);
+        self.heaviest_pol_lines.merge(&later.heaviest_pol_lines);
+        self.heaviest_justifications
+            .merge(&later.heaviest_justifications);
+        self.max_live_balance = self
+            .max_live_balance
+            .max(self.live_balance + later.max_live_balance);
+        self.min_live_balance = self
+            .min_live_balance
+            .min(self.live_balance + later.min_live_balance);
+        // Each later line has the balance so far on top of its own
+        let later_lines = later.total_lines as i64;
+        self.live_balance_sum += later.live_balance_sum + later_lines * self.live_balance;
+        self.growth_sum += later.growth_sum + later_lines * self.growth;
+        self.live_balance += later.live_balance;
+        self.growth += later.growth;
+    }
+
+    /// Add the stats of a separate proof, e.g. to total those of a batch. Both must be
+    /// finished; the peak live constraint count is the larger of the two.
+    pub fn merge(&mut self, other: &ProofFileStats) {
+        self.total_lines += other.total_lines;
+        self.pol_lines += other.pol_lines;
+        self.red_lines += other.red_lines;
+        self.a_lines += other.a_lines;
+        self.del_lines += other.del_lines;
+        self.unknown_lines += other.unknown_lines;
+        self.unknown.merge(&other.unknown);
+        for (name, count) in &other.a_lines_by_name {
+            *self.a_lines_by_name.entry(name.clone()).or_insert(0) += count;
+        }
+        for (rule, count) in &other.lines_by_rule {
+            *self.lines_by_rule.entry(rule.clone()).or_insert(0) += count;
+        }
+        self.peak_live_constraints = self.peak_live_constraints.max(other.peak_live_constraints);
+        self.live_area += other.live_area;
+        self.live_area_without_deletions += other.live_area_without_deletions;
+        if let Some(other_derivation) = &other.derivation {
+            self.derivation
+                .get_or_insert_with(DerivationStats::default)
+                .merge(other_derivation);
+        }
+        if let Some(other_coverage) = &other.fzn_coverage {
+            self.fzn_coverage
+                .get_or_insert_with(FznCoverage::default)
+                .merge(other_coverage);
+        }
+        if let Some(other_definitions) = &other.definitions {
+            self.definitions
+                .get_or_insert_with(DefinitionStats::default)
+                .merge(other_definitions);
+        }
+        if let Some(other_lines) = &other.justification_lines {
+            self.justification_lines
+                .get_or_insert_with(JustificationLines::default)
+                .merge(other_lines);
+        }
+        self.sizes.merge(&other.sizes);
+        self.heaviest_pol_lines.merge(&other.heaviest_pol_lines);
+        self.heaviest_justifications
+            .merge(&other.heaviest_justifications);
+        for (sizes, other_sizes) in [
+            (&mut self.kept_sizes, &other.kept_sizes),
+            (&mut self.dropped_sizes, &other.dropped_sizes),
+        ] {
+            if let Some(other_sizes) = other_sizes {
+                sizes
+                    .get_or_insert_with(ConstraintSizes::default)
+                    .merge(other_sizes);
+            }
+        }
+    }
+
+    /// Work out the peak live constraint count and live area once every line has been
+    /// recorded. When lines were recorded from last to first the running balance covers
+    /// suffixes of the proof, so the peak is the overall balance less the smallest suffix
+    /// balance, and the balance after each line the overall balance less that of the suffix
+    /// after it.
+    pub fn finish(&mut self, recorded_in_reverse: bool) {
+        let (peak, area, area_without_deletions) = if recorded_in_reverse {
+            let lines = self.total_lines as i64 + 1;
+            (
+                self.live_balance - self.min_live_balance,
+                lines * self.live_balance - self.live_balance_sum,
+                lines * self.growth - self.growth_sum,
+            )
+        } else {
+            (
+                self.max_live_balance,
+                self.live_balance_sum,
+                self.growth_sum,
+            )
+        };
+        self.peak_live_constraints = peak.max(0) as u64;
+        self.live_area = area.max(0) as u64;
+        self.live_area_without_deletions = area_without_deletions.max(0) as u64;
+        self.definitions = self.definition_uses.finish();
+    }
+
+    /// The stats recorded so far, to carry on recording from with
+    /// [`ProofFileStats::from_recorded`].
+    pub fn recorded(&self) -> RecordedStats {
+        RecordedStats {
+            stats: self.clone(),
+            live_balance: self.live_balance,
+            max_live_balance: self.max_live_balance,
+            min_live_balance: self.min_live_balance,
+            live_balance_sum: self.live_balance_sum,
+            growth: self.growth,
+            growth_sum: self.growth_sum,
+            definition_uses: self.definition_uses.clone(),
+        }
+    }
+
+    pub fn from_recorded(recorded: RecordedStats) -> Self {
+        ProofFileStats {
+            live_balance: recorded.live_balance,
+            max_live_balance: recorded.max_live_balance,
+            min_live_balance: recorded.min_live_balance,
+            live_balance_sum: recorded.live_balance_sum,
+            growth: recorded.growth,
+            growth_sum: recorded.growth_sum,
+            definition_uses: recorded.definition_uses,
+            ..recorded.stats
+        }
+    }
+
+    /// The share of the live area that deletions saved, as a percentage, or `None` if nothing
+    /// was ever live.
+    pub fn deletion_savings(&self) -> Option<f64> {
+        (self.live_area_without_deletions > 0).then(|| {
+            100.0 * (1.0 - self.live_area as f64 / self.live_area_without_deletions as f64)
+        })
     }
 
     fn record_assertion(&mut self, line: &str) {
         self.a_lines += 1;
         let mut split_line = line.split(":");
 
-        match split_line.nth(2) {
-            Some(name) => {
-                *self
-                    .a_lines_by_name
-                    .entry(name.trim().trim_matches(';').to_string())
-                    .or_insert(0) += 1;
-            }
-            None => (),
+        if let Some(name) = split_line.nth(2) {
+            *self
+                .a_lines_by_name
+                .entry(name.trim().trim_end_matches(';').trim_end().to_string())
+                .or_insert(0) += 1;
         }
     }
 
     pub fn compared_to<'a>(&'a self, other: &'a ProofFileStats) -> ProofFileStatsComparison<'a> {
         ProofFileStatsComparison {
-            current: self,
-            reference: other,
+            current: Cow::Borrowed(self),
+            reference: Cow::Borrowed(other),
+        }
+    }
+
+    /// [`ProofFileStats::compared_to`], taking the stats rather than borrowing them.
+    pub fn into_compared_to(self, other: ProofFileStats) -> ProofFileStatsComparison<'static> {
+        ProofFileStatsComparison {
+            current: Cow::Owned(self),
+            reference: Cow::Owned(other),
+        }
+    }
+
+    /// The stats as `<prefix>.<stat> <value>` lines, for `--porcelain` output.
+    pub fn porcelain<'a>(&'a self, prefix: &'a str) -> PorcelainStats<'a> {
+        PorcelainStats {
+            stats: self,
+            prefix,
         }
     }
 }
 
+impl<S: AsRef<str>> Extend<S> for ProofFileStats {
+    fn extend<I: IntoIterator<Item = S>>(&mut self, lines: I) {
+        for line in lines {
+            self.record_line(line.as_ref());
+        }
+    }
+}
+
+pub struct PorcelainStats<'a> {
+    stats: &'a ProofFileStats,
+    prefix: &'a str,
+}
+
+impl fmt::Display for PorcelainStats<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (stats, prefix) = (self.stats, self.prefix);
+        writeln!(f, "{prefix}.total_lines {}", stats.total_lines)?;
+        writeln!(f, "{prefix}.a_lines {}", stats.a_lines)?;
+        writeln!(f, "{prefix}.pol_lines {}", stats.pol_lines)?;
+        writeln!(f, "{prefix}.red_lines {}", stats.red_lines)?;
+        writeln!(f, "{prefix}.del_lines {}", stats.del_lines)?;
+        writeln!(f, "{prefix}.unknown_lines {}", stats.unknown_lines)?;
+        stats.unknown.write_porcelain(f, prefix)?;
+        writeln!(
+            f,
+            "{prefix}.peak_live_constraints {}",
+            stats.peak_live_constraints
+        )?;
+        writeln!(f, "{prefix}.live_area {}", stats.live_area)?;
+        writeln!(
+            f,
+            "{prefix}.live_area_without_deletions {}",
+            stats.live_area_without_deletions
+        )?;
+        let mut by_name: Vec<_> = stats.a_lines_by_name.iter().collect();
+        by_name.sort();
+        for (name, count) in by_name {
+            writeln!(f, "{prefix}.a_lines_by_name.{} {count}", name.trim())?;
+        }
+        for (rule, count) in &stats.lines_by_rule {
+            writeln!(
+                f,
+                "{prefix}.lines_by_rule.{} {count}",
+                rule.replace(' ', "_")
+            )?;
+        }
+        if let Some(derivation) = &stats.derivation {
+            derivation.write_porcelain(f, prefix)?;
+        }
+        if let Some(coverage) = &stats.fzn_coverage {
+            coverage.write_porcelain(f, prefix)?;
+        }
+        if let Some(justification_lines) = &stats.justification_lines {
+            justification_lines.write_porcelain(f, prefix)?;
+        }
+        if let Some(definitions) = &stats.definitions {
+            definitions.write_porcelain(f, prefix)?;
+        }
+        stats
+            .heaviest_pol_lines
+            .write_porcelain(f, &format!("{prefix}.heaviest_pol_lines"))?;
+        stats
+            .heaviest_justifications
+            .write_porcelain(f, &format!("{prefix}.heaviest_justifications"))?;
+        stats.sizes.write_porcelain(f, &format!("{prefix}.sizes"))?;
+        if let Some(kept_sizes) = &stats.kept_sizes {
+            kept_sizes.write_porcelain(f, &format!("{prefix}.kept_sizes"))?;
+        }
+        if let Some(dropped_sizes) = &stats.dropped_sizes {
+            dropped_sizes.write_porcelain(f, &format!("{prefix}.dropped_sizes"))?;
+        }
+        Ok(())
+    }
+}
+
 impl fmt::Display for ProofFileStats {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         writeln!(f, "Total lines: {}", self.total_lines)?;
@@ -193,26 +1943,175 @@ impl fmt::Display for ProofFileStats {
         writeln!(f, "Pol lines: {}", self.pol_lines)?;
         writeln!(f, "Red lines: {}", self.red_lines)?;
         writeln!(f, "Del lines: {}", self.del_lines)?;
+        if self.unknown_lines > 0 {
+            writeln!(f, "Unknown lines: {}", self.unknown_lines)?;
+            write!(f, "{}", self.unknown)?;
+        }
+        writeln!(f, "Peak live constraints: {}", self.peak_live_constraints)?;
+        writeln!(f, "Live constraint area: {}", self.live_area)?;
+        write_deletion_savings(f, self)?;
         writeln!(f, "Assertion lines by name:")?;
         for (name, count) in &self.a_lines_by_name {
             writeln!(f, " ∟ `{}`: {}", name, count)?;
         }
-        Ok(())
+        writeln!(f, "Lines by rule:")?;
+        for (rule, count) in &self.lines_by_rule {
+            writeln!(f, " ∟ `{rule}`: {count}")?;
+        }
+        write_details(f, self)
+    }
+}
+
+/// How much deletions cut the live constraint area, if anything was live.
+fn write_deletion_savings(f: &mut fmt::Formatter<'_>, stats: &ProofFileStats) -> fmt::Result {
+    match stats.deletion_savings() {
+        Some(savings) => writeln!(
+            f,
+            " ∟ {:.1}% less than the {} without deletions",
+            savings, stats.live_area_without_deletions
+        ),
+        None => Ok(()),
+    }
+}
+
+/// The stats beyond the line counts: the derivation and FlatZinc coverage if known, the line
+/// and constraint sizes, and those of the kept and dropped lines if known.
+fn write_details(f: &mut fmt::Formatter<'_>, stats: &ProofFileStats) -> fmt::Result {
+    if let Some(derivation) = &stats.derivation {
+        write!(f, "{derivation}")?;
+    }
+    if let Some(coverage) = &stats.fzn_coverage {
+        write!(f, "{coverage}")?;
+    }
+    let justification_lines = stats.justification_lines.as_ref();
+    if let Some(justification_lines) = justification_lines.filter(|lines| !lines.types.is_empty()) {
+        justification_lines.write_shares(f, stats.total_lines)?;
+    }
+    if let Some(definitions) = &stats.definitions {
+        write!(f, "{definitions}")?;
+    }
+    stats
+        .heaviest_pol_lines
+        .write_list(f, "Pol lines with the most antecedents", "antecedents")?;
+    stats
+        .heaviest_justifications
+        .write_list(f, "Longest justifications", "lines")?;
+    writeln!(f, "Line sizes:")?;
+    write!(f, "{}", stats.sizes)?;
+    if let Some(kept_sizes) = &stats.kept_sizes {
+        writeln!(f, "Kept input line sizes:")?;
+        write!(f, "{kept_sizes}")?;
+    }
+    if let Some(dropped_sizes) = &stats.dropped_sizes {
+        writeln!(f, "Dropped input line sizes:")?;
+        write!(f, "{dropped_sizes}")?;
+    }
+    Ok(())
+}
+
+impl ProofFileStatsComparison<'_> {
+    pub fn current(&self) -> &ProofFileStats {
+        &self.current
+    }
+
+    pub fn reference(&self) -> &ProofFileStats {
+        &self.reference
+    }
+
+    /// The current and reference stats, moved out if they're owned.
+    pub fn into_inner(self) -> (ProofFileStats, ProofFileStats) {
+        (self.current.into_owned(), self.reference.into_owned())
+    }
+
+    /// The compared stats, in a form that can be saved or checked programmatically.
+    pub fn changes(&self) -> ProofFileStatsChanges {
+        let change = |stat: fn(&ProofFileStats) -> u64| StatChange {
+            current: stat(&self.current),
+            reference: stat(&self.reference),
+        };
+        let names = self
+            .current
+            .a_lines_by_name
+            .keys()
+            .chain(self.reference.a_lines_by_name.keys());
+        let a_lines_by_name = self.changes_by_key(names, |stats, name| {
+            stats.a_lines_by_name.get(name).copied()
+        });
+        let rules = self
+            .current
+            .lines_by_rule
+            .keys()
+            .chain(self.reference.lines_by_rule.keys());
+        let lines_by_rule =
+            self.changes_by_key(rules, |stats, rule| stats.lines_by_rule.get(rule).copied());
+        ProofFileStatsChanges {
+            total_lines: change(|stats| stats.total_lines),
+            a_lines: change(|stats| stats.a_lines),
+            pol_lines: change(|stats| stats.pol_lines),
+            red_lines: change(|stats| stats.red_lines),
+            del_lines: change(|stats| stats.del_lines),
+            unknown_lines: change(|stats| stats.unknown_lines),
+            peak_live_constraints: change(|stats| stats.peak_live_constraints),
+            live_area: change(|stats| stats.live_area),
+            a_lines_by_name,
+            lines_by_rule,
+        }
+    }
+
+    /// The changes in a count kept by key, for each of `keys`.
+    fn changes_by_key<'k>(
+        &self,
+        keys: impl Iterator<Item = &'k String>,
+        count: impl Fn(&ProofFileStats, &str) -> Option<u64>,
+    ) -> BTreeMap<String, StatChange> {
+        keys.map(|key| {
+            let change = StatChange {
+                current: count(&self.current, key).unwrap_or(0),
+                reference: count(&self.reference, key).unwrap_or(0),
+            };
+            (key.clone(), change)
+        })
+        .collect()
+    }
+}
+
+impl ProofFileStatsChanges {
+    /// Every counter by name, the assertion lines by name as `a_lines_by_name.<name>` and
+    /// the lines by rule as `lines_by_rule.<rule>`.
+    pub fn counters(&self) -> Vec<(String, StatChange)> {
+        let mut counters: Vec<_> = [
+            ("total_lines", self.total_lines),
+            ("a_lines", self.a_lines),
+            ("pol_lines", self.pol_lines),
+            ("red_lines", self.red_lines),
+            ("del_lines", self.del_lines),
+            ("unknown_lines", self.unknown_lines),
+            ("peak_live_constraints", self.peak_live_constraints),
+            ("live_area", self.live_area),
+        ]
+        .into_iter()
+        .map(|(name, change)| (name.to_string(), change))
+        .collect();
+        counters.extend(
+            self.a_lines_by_name
+                .iter()
+                .map(|(name, change)| (format!("a_lines_by_name.{name}"), *change)),
+        );
+        counters.extend(
+            self.lines_by_rule
+                .iter()
+                .map(|(rule, change)| (format!("lines_by_rule.{rule}"), *change)),
+        );
+        counters
     }
 }
 
 impl fmt::Display for ProofFileStatsComparison<'_> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let percent = |current, reference| {
-            if reference == 0 {
-                return "N/A".to_string();
-            }
-            let change = 100.0 * (current as f64 - reference as f64) / reference as f64;
-            if change == 0.0 {
-                format!("No change")
-            } else {
-                format!("{}{:.1}%", if change >= 0.0 { "+" } else { "" }, change)
-            }
+        let percent = |current, reference| match (StatChange { current, reference }).percent() {
+            None => "N/A".to_string(),
+            Some(0.0) => "No change".to_string(),
+            Some(change) => format!("{}{:.1}%", if change >= 0.0 { "+" } else { "" }, change),
         };
 
         writeln!(
@@ -245,6 +2144,22 @@ impl fmt::Display for ProofFileStatsComparison<'_> {
             self.current.del_lines,
             percent(self.current.del_lines, self.reference.del_lines)
         )?;
+        writeln!(
+            f,
+            "Peak live constraints: {} ({})",
+            self.current.peak_live_constraints,
+            percent(
+                self.current.peak_live_constraints,
+                self.reference.peak_live_constraints
+            )
+        )?;
+        writeln!(
+            f,
+            "Live constraint area: {} ({})",
+            self.current.live_area,
+            percent(self.current.live_area, self.reference.live_area)
+        )?;
+        write_deletion_savings(f, &self.current)?;
 
         writeln!(f, "Assertion lines by name:")?;
         for (name, count) in &self.current.a_lines_by_name {
@@ -262,7 +2177,103 @@ impl fmt::Display for ProofFileStatsComparison<'_> {
                 percent(*count, ref_count)
             )?;
         }
+        writeln!(f, "Lines by rule:")?;
+        for (rule, count) in &self.current.lines_by_rule {
+            let ref_count = self.reference.lines_by_rule.get(rule).copied().unwrap_or(0);
+            writeln!(f, " ∟ `{rule}`: {count} ({})", percent(*count, ref_count))?;
+        }
+        write_details(f, &self.current)
+    }
+}
 
-        Ok(())
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROOF: &str = "\
+pseudo-Boolean proof version 2.0
+f 2 ;
+@c1 pol 1 2 + ;
+@c2 a 1 x1 >= 1 : @f1 : int_lin_le ;
+del id @c1 ;
+del spec 1 x1 >= 1 ;
+@c3 red 1 x2 >= 1 : x2 -> 1 ;
+del range 1 3 ;
+@c4 pol @c3 @c2 + 2 + ;
+output NONE ;
+conclusion UNSAT : @c4 ;
+end pseudo-Boolean proof
+";
+
+    fn json(stats: &ProofFileStats) -> serde_json::Value {
+        serde_json::to_value(stats).unwrap()
+    }
+
+    #[test]
+    fn lines_are_counted_by_rule() {
+        let stats = ProofFileStats::from_lines(PROOF.lines());
+        assert_eq!(stats.total_lines, 12);
+        assert_eq!(
+            (
+                stats.pol_lines,
+                stats.a_lines,
+                stats.red_lines,
+                stats.del_lines
+            ),
+            (2, 1, 1, 3)
+        );
+        assert_eq!(
+            stats.a_lines_by_name,
+            HashMap::from([("int_lin_le".to_string(), 1)])
+        );
+        assert_eq!(stats.lines_by_rule["del spec"], 1);
+        assert_eq!(stats.lines_by_rule["end proof"], 1);
+    }
+
+    #[test]
+    fn live_constraints_follow_derivations_and_deletions() {
+        // Live after each line: 0 2 3 4 3 2 3 1 2 2 2 2, or without deletions
+        // 0 2 3 4 4 4 5 5 6 6 6 6
+        let stats = ProofFileStats::from_lines(PROOF.lines());
+        assert_eq!(stats.peak_live_constraints, 4);
+        assert_eq!(stats.live_area, 26);
+        assert_eq!(stats.live_area_without_deletions, 51);
+    }
+
+    #[test]
+    fn stats_recorded_in_reverse_are_the_same() {
+        let forwards = ProofFileStats::from_lines(PROOF.lines());
+        let backwards = ProofFileStats::from_reversed_lines(PROOF.lines().rev());
+        assert_eq!(json(&backwards), json(&forwards));
+    }
+
+    #[test]
+    fn appended_parts_have_the_stats_of_the_whole() {
+        let lines: Vec<&str> = PROOF.lines().collect();
+        for split in 0..=lines.len() {
+            let mut first = ProofFileStats::default();
+            first.extend(&lines[..split]);
+            let mut second = ProofFileStats::default();
+            second.extend(&lines[split..]);
+            first.append(&second);
+            first.finish(false);
+            assert_eq!(
+                json(&first),
+                json(&ProofFileStats::from_lines(&lines)),
+                "split after {split} lines"
+            );
+        }
+    }
+
+    #[test]
+    fn merged_proofs_add_up() {
+        let one = ProofFileStats::from_lines(PROOF.lines());
+        let mut both = one.clone();
+        both.merge(&one);
+        assert_eq!(both.total_lines, 2 * one.total_lines);
+        assert_eq!(both.lines_by_rule["pol"], 2 * one.lines_by_rule["pol"]);
+        assert_eq!(both.live_area, 2 * one.live_area);
+        // The proofs are checked separately, so the peak is that of either
+        assert_eq!(both.peak_live_constraints, one.peak_live_constraints);
     }
 }
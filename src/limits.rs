@@ -0,0 +1,81 @@
+use std::{fs, time::Instant};
+
+use crate::{PBarberError, ResourceLimits};
+
+/// Lines to process between the (more expensive) time and memory checks.
+const CHECK_INTERVAL: u64 = 1024;
+
+/// Enforces [`ResourceLimits`] while a proof is read, one line at a time.
+pub(crate) struct ResourceGuard {
+    max_lines: Option<u64>,
+    max_memory: Option<u64>,
+    deadline: Option<Instant>,
+    lines: u64,
+}
+
+impl ResourceGuard {
+    pub(crate) fn new(limits: &ResourceLimits) -> Self {
+        Self {
+            max_lines: limits.max_lines,
+            max_memory: limits.max_memory,
+            deadline: limits.deadline(),
+            lines: 0,
+        }
+    }
+
+    /// Count a line read, failing once any limit is exceeded.
+    pub(crate) fn tick(&mut self) -> Result<(), PBarberError> {
+        self.lines += 1;
+        if let Some(max_lines) = self.max_lines
+            && self.lines > max_lines
+        {
+            return Err(PBarberError::ResourceLimitExceeded(format!(
+                "input has more than {max_lines} lines (`--max-lines`)"
+            )));
+        }
+        if !self.lines.is_multiple_of(CHECK_INTERVAL) {
+            return Ok(());
+        }
+        if let Some(deadline) = self.deadline
+            && Instant::now() > deadline
+        {
+            return Err(PBarberError::ResourceLimitExceeded(
+                "timed out (`--timeout`)".to_string(),
+            ));
+        }
+        if let Some(max_memory) = self.max_memory
+            && let Some(resident) = resident_memory()
+            && resident > max_memory
+        {
+            return Err(PBarberError::ResourceLimitExceeded(format!(
+                "using {resident} bytes of memory, over the limit of {max_memory} (`--max-memory`)"
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Resident memory of this process in bytes, on platforms with `/proc/self/status`.
+pub(crate) fn resident_memory() -> Option<u64> {
+    memory_status("VmRSS:")
+}
+
+/// The most resident memory this process has used so far in bytes, on platforms with
+/// `/proc/self/status`.
+pub(crate) fn peak_resident_memory() -> Option<u64> {
+    memory_status("VmHWM:")
+}
+
+/// A memory size from `/proc/self/status`, in bytes.
+fn memory_status(field: &str) -> Option<u64> {
+    let status = fs::read_to_string("/proc/self/status").ok()?;
+    let kilobytes = status
+        .lines()
+        .find_map(|line| line.strip_prefix(field))?
+        .trim()
+        .strip_suffix("kB")?
+        .trim()
+        .parse::<u64>()
+        .ok()?;
+    Some(kilobytes * 1024)
+}
@@ -0,0 +1,174 @@
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    path::Path,
+};
+
+use crate::{PBarberError, verify::LineOrigin};
+
+/// Where each line of a trimmed or styled proof came from in the proof it was made from. As
+/// a file (from `--line-map`) it has a line for each output line: the number of the input
+/// line it was copied from, `+` and the number of the input line it was written for (e.g. a
+/// deletion or a justification step), or `-` if neither.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct LineMap {
+    origins: Vec<LineOrigin>,
+}
+
+impl LineMap {
+    /// Add the origin of the next output line.
+    pub fn push(&mut self, origin: LineOrigin) {
+        self.origins.push(origin);
+    }
+
+    pub fn len(&self) -> usize {
+        self.origins.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.origins.is_empty()
+    }
+
+    /// Where output line `line_number` (from 1) came from, if there is such a line.
+    pub fn origin(&self, line_number: u64) -> Option<&LineOrigin> {
+        self.origins.get(line_number.checked_sub(1)? as usize)
+    }
+
+    /// The map with the output lines in reverse order, for output written from the end.
+    pub fn reversed(mut self) -> Self {
+        self.origins.reverse();
+        self
+    }
+
+    /// The map with the input line numbers changed by `renumber`, e.g. to count them from the
+    /// start of an input read from the end.
+    pub fn renumbered(mut self, renumber: impl Fn(u64) -> u64) -> Self {
+        for origin in &mut self.origins {
+            match origin {
+                LineOrigin::Original(line_number) | LineOrigin::WrittenFor(line_number) => {
+                    *line_number = renumber(*line_number);
+                }
+                LineOrigin::Generated => (),
+            }
+        }
+        self
+    }
+
+    /// Where the lines of a later step's output came from in this map's input, given the map
+    /// `later` from that output to this map's output, e.g. styling after trimming.
+    pub fn then(&self, later: &LineMap) -> LineMap {
+        let origins = later
+            .origins
+            .iter()
+            .map(|origin| match origin {
+                LineOrigin::Original(line_number) => self
+                    .origin(*line_number)
+                    .cloned()
+                    .unwrap_or(LineOrigin::Generated),
+                LineOrigin::WrittenFor(line_number) => match self.origin(*line_number) {
+                    Some(LineOrigin::Original(original) | LineOrigin::WrittenFor(original)) => {
+                        LineOrigin::WrittenFor(*original)
+                    }
+                    _ => LineOrigin::Generated,
+                },
+                LineOrigin::Generated => LineOrigin::Generated,
+            })
+            .collect();
+        LineMap { origins }
+    }
+
+    pub fn write<W: Write>(&self, mut out: W) -> io::Result<()> {
+        for origin in &self.origins {
+            match origin {
+                LineOrigin::Original(line_number) => writeln!(out, "{line_number}")?,
+                LineOrigin::WrittenFor(line_number) => writeln!(out, "+{line_number}")?,
+                LineOrigin::Generated => writeln!(out, "-")?,
+            }
+        }
+        out.flush()
+    }
+
+    pub fn read<R: BufRead>(input: R) -> io::Result<Self> {
+        let mut map = LineMap::default();
+        for (i, line) in input.lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+            let parsed = match line.strip_prefix('+') {
+                _ if line == "-" => Some(LineOrigin::Generated),
+                Some(number) => number.parse().ok().map(LineOrigin::WrittenFor),
+                None => line.parse().ok().map(LineOrigin::Original),
+            };
+            let origin = parsed.ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "line map line {}: expected a line number, got `{line}`",
+                        i + 1
+                    ),
+                )
+            })?;
+            map.push(origin);
+        }
+        Ok(map)
+    }
+
+    /// Read the line map written to `path`.
+    pub fn from_path(path: &Path) -> Result<Self, PBarberError> {
+        File::open(path)
+            .and_then(|file| Self::read(BufReader::new(file)))
+            .map_err(|err| PBarberError::reading("line map", path, err))
+    }
+
+    /// Write the line map to `path`, replacing any file there.
+    pub fn write_to_path(&self, path: &Path) -> Result<(), PBarberError> {
+        File::create(path)
+            .and_then(|file| self.write(BufWriter::new(file)))
+            .map_err(|err| PBarberError::reading("line map", path, err))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn map(origins: &[LineOrigin]) -> LineMap {
+        LineMap {
+            origins: origins.to_vec(),
+        }
+    }
+
+    #[test]
+    fn reads_back_what_it_writes() {
+        let written = map(&[
+            LineOrigin::Original(1),
+            LineOrigin::WrittenFor(4),
+            LineOrigin::Generated,
+        ]);
+        let mut out = Vec::new();
+        written.write(&mut out).unwrap();
+        assert_eq!(String::from_utf8(out.clone()).unwrap(), "1\n+4\n-\n");
+        assert_eq!(LineMap::read(out.as_slice()).unwrap(), written);
+        assert!(LineMap::read("1\nx\n".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn joined_maps_point_at_the_first_input() {
+        // Trimming kept lines 2 and 5, then styling added a justification step for line 2
+        let trimmed = map(&[LineOrigin::Original(2), LineOrigin::Original(5)]);
+        let styled = map(&[
+            LineOrigin::WrittenFor(1),
+            LineOrigin::Original(1),
+            LineOrigin::Original(2),
+            LineOrigin::Original(3),
+        ]);
+        assert_eq!(
+            trimmed.then(&styled),
+            map(&[
+                LineOrigin::WrittenFor(2),
+                LineOrigin::Original(2),
+                LineOrigin::Original(5),
+                LineOrigin::Generated,
+            ])
+        );
+    }
+}
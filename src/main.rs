@@ -1,11 +1,50 @@
-use clap::{Args, Parser, Subcommand};
+use clap::{ArgAction, Args, CommandFactory, Parser, Subcommand, ValueEnum};
+use clap_complete::{Shell, generate};
+use clap_mangen::Man;
 use colored::Colorize;
-use pbarber::JustifierConfig;
-use pbarber::justifier::Justifier;
-use pbarber::{PBarberError, ProofFileStats, TrimmerConfig, trimmer::Trimmer};
-use rev_buf_reader::RevBufReader;
-use std::fs::{File, rename};
+use pbarber::advisor::{advise, explain, minimize};
+#[cfg(feature = "justify")]
+use pbarber::annotate::annotate;
+#[cfg(feature = "justify")]
+use pbarber::checkpoint::Checkpoint;
+use pbarber::diagnostics::{DenyDiagnostics, Diagnostics, LogDiagnostics, SilentDiagnostics};
+use pbarber::graph::{GraphFormat, ProofGraph};
+#[cfg(feature = "justify")]
+use pbarber::justifier::{
+    Justifier, check_input_order,
+    profile::{Profile, ProfileFormat},
+};
+use pbarber::line_map::LineMap;
+#[cfg(feature = "justify")]
+use pbarber::pipeline::trim_and_style;
+use pbarber::verify::{LineOrigin, map_line_to_original, run_veripb};
+use pbarber::{
+    AdviseConfig, IoBuffers, ResourceLimits, SplitConfig, StatsConfig, VerifyConfig, WarningKind,
+};
+#[cfg(feature = "justify")]
+use pbarber::{AnnotateConfig, JustifierConfig};
+use pbarber::{
+    PBarberError, ProofFileStats, TrimmerConfig,
+    renumber::renumber,
+    reverse_lines::reverse_file,
+    split::split,
+    stats::{PhaseStats, RunStats, StatsTable},
+    trimmer::{Trimmer, spool},
+};
+use rayon::prelude::*;
+use std::env;
+use std::ffi::OsString;
+use std::fs::{self, File, rename};
+use std::io::{self, BufReader, BufWriter, IsTerminal, Seek, SeekFrom};
+use std::path::Path;
+use std::process::ExitCode;
+use std::time::Instant;
 use std::{fs::OpenOptions, io::BufRead, io::Write, path::PathBuf};
+use tempfile::{NamedTempFile, TempPath};
+use tracing::{Level, error, info, info_span};
+
+/// The input and output stats of a run, if they were collected.
+type StatsPair = Option<(ProofFileStats, ProofFileStats)>;
 
 #[derive(Parser)]
 #[command(
@@ -15,232 +54,1798 @@ use std::{fs::OpenOptions, io::BufRead, io::Write, path::PathBuf};
 struct Cli {
     #[command(subcommand)]
     command: Commands,
+
+    #[arg(
+        short = 'v',
+        long,
+        global = true,
+        help_heading = "Global options",
+        action = ArgAction::Count,
+        help = "Log more detail to stderr (-v for debug, -vv for trace)."
+    )]
+    verbose: u8,
+
+    #[arg(
+        short = 'q',
+        long,
+        global = true,
+        help_heading = "Global options",
+        conflicts_with = "verbose",
+        help = "Only log errors, hiding warnings and progress messages."
+    )]
+    quiet: bool,
+
+    #[arg(
+        long,
+        global = true,
+        help_heading = "Global options",
+        help = "Fail on conditions that are normally only warned about, with an exit code for each kind of warning."
+    )]
+    deny_warnings: bool,
+
+    #[arg(
+        long,
+        global = true,
+        help_heading = "Global options",
+        help = "Print results as `<key> <value>` lines on stdout for scripts, ending with the status."
+    )]
+    porcelain: bool,
+
+    #[arg(
+        long,
+        global = true,
+        value_enum,
+        value_name = "WHEN",
+        default_value_t = ColorChoice::Auto,
+        help_heading = "Global options",
+        help = "Color the output and logs. `auto` colors them when going to a terminal, unless NO_COLOR is set."
+    )]
+    color: ColorChoice,
+
+    #[arg(
+        long,
+        global = true,
+        value_enum,
+        value_name = "FORMAT",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "table",
+        help_heading = "Global options",
+        help = "Print how long each phase (trim, style, renumber, commit) took, how many bytes it read and wrote and how many lines a second it read, and the peak memory used."
+    )]
+    timings: Option<ReportFormat>,
+
+    #[command(flatten)]
+    buffers: IoBuffers,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ReportFormat {
+    Table,
+    Json,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ColorChoice {
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    /// Whether to color a stream, given if it goes to a terminal.
+    fn enabled(self, is_terminal: bool) -> bool {
+        match self {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => {
+                is_terminal && env::var_os("NO_COLOR").is_none_or(|no_color| no_color.is_empty())
+            }
+        }
+    }
+}
+
+impl Cli {
+    fn log_level(&self) -> Level {
+        match (self.quiet, self.verbose) {
+            (true, _) => Level::ERROR,
+            (false, 0) => Level::INFO,
+            (false, 1) => Level::DEBUG,
+            (false, _) => Level::TRACE,
+        }
+    }
+}
+
+/// The global options that change how each command runs and reports.
+#[derive(Clone, Copy)]
+struct RunOptions {
+    deny_warnings: bool,
+    porcelain: bool,
+    timings: Option<ReportFormat>,
+    buffers: IoBuffers,
 }
 
 #[derive(Subcommand)]
 enum Commands {
-    // TODO: fix the overlapping options here
     /// Trim a proof log
     Trim {
         #[clap(flatten)]
         io: IOPaths,
         #[clap(flatten)]
         trimmer_config: TrimmerConfig,
+        #[clap(flatten)]
+        limits: ResourceLimits,
     },
 
     /// (Default) Trim a proof log and justify assertions
+    #[cfg(feature = "justify")]
     TrimAndStyle {
         #[clap(flatten)]
         io: IOPaths,
         #[clap(flatten)]
         trimmer_config: TrimmerConfig,
+        // Boxed to keep the variants' sizes close
         #[clap(flatten)]
-        justifier_config: JustifierConfig,
+        justifier_config: Box<JustifierConfig>,
+        #[clap(flatten)]
+        limits: ResourceLimits,
+        #[arg(
+            long,
+            value_name = "PATH",
+            num_args = 0..=1,
+            help_heading = "Output options",
+            help = "Also keep the trimmed proof before styling, at PATH or <OUTPUT_FILE> with a .trimmed.pbp extension."
+        )]
+        keep_intermediate: Option<Option<PathBuf>>,
     },
 
     /// Justify assertions only
+    #[cfg(feature = "justify")]
     Style {
         #[clap(flatten)]
         io: IOPaths,
         #[clap(flatten)]
         justifier_config: JustifierConfig,
+        #[clap(flatten)]
+        limits: ResourceLimits,
+    },
+
+    /// Trim and/or style several proof logs, optionally in parallel
+    Batch {
+        #[command(subcommand)]
+        command: BatchCommand,
+    },
+
+    /// Check a proof with VeriPB, mapping failing lines back to the original proof
+    Verify {
+        #[arg(value_name = "OPB_FILE", help = "OPB model.")]
+        opb_path: PathBuf,
+        #[arg(value_name = "PROOF_FILE", help = "Proof to check.")]
+        proof_path: PathBuf,
+        #[clap(flatten)]
+        verify_config: VerifyConfig,
+    },
+
+    /// Reverse the order of the lines of a proof log, e.g. to read it with the justifier
+    Reverse {
+        #[clap(flatten)]
+        io: IOPaths,
+    },
+
+    /// Copy a proof log with comments describing the model constraints and literals it uses
+    #[cfg(feature = "justify")]
+    Annotate {
+        #[clap(flatten)]
+        io: IOPaths,
+        #[clap(flatten)]
+        annotate_config: AnnotateConfig,
+    },
+
+    /// Cut a proof log into chunks, each starting with the proof header, plus a manifest
+    Split {
+        #[arg(value_name = "PROOF_FILE", help = "Proof to split.")]
+        input_path: PathBuf,
+        #[clap(flatten)]
+        split_config: SplitConfig,
+        #[arg(
+            long,
+            value_name = "DIR",
+            help = "Directory for the chunks and manifest (<PROOF_FILE>.partNNN.pbp and <PROOF_FILE>.split.json). Defaults to the proof's directory."
+        )]
+        output_dir: Option<PathBuf>,
     },
 
-    /// Future concept: help tools for debugging a failing proof
+    /// Analyse a proof log: assertions to justify, broken constraint references and the lines
+    /// VeriPB errors come from. With `--minimize`, shrink a failing proof for a bug report
     Advise {
         #[arg(value_name = "INPUT_FILE", help = "Input file.")]
         input_path: PathBuf,
+        #[clap(flatten)]
+        advise_config: AdviseConfig,
+    },
+
+    /// Print the stats of proof logs, each compared to the first
+    Stats {
+        #[arg(value_name = "PROOF_FILE", required = true, help = "Proofs to read.")]
+        input_paths: Vec<PathBuf>,
+        #[clap(flatten)]
+        stats_config: StatsConfig,
+    },
+
+    /// Generate shell completions or a man page
+    Generate {
+        #[command(subcommand)]
+        target: GenerateTarget,
+    },
+}
+
+#[derive(Subcommand)]
+enum BatchCommand {
+    /// Trim each proof log
+    Trim {
+        #[clap(flatten)]
+        paths: BatchPaths,
+        #[clap(flatten)]
+        trimmer_config: TrimmerConfig,
+        #[clap(flatten)]
+        limits: ResourceLimits,
+    },
+
+    /// Trim each proof log and justify assertions
+    #[cfg(feature = "justify")]
+    TrimAndStyle {
+        #[clap(flatten)]
+        paths: BatchPaths,
+        #[clap(flatten)]
+        trimmer_config: TrimmerConfig,
+        // Boxed to keep the variants' sizes close
+        #[clap(flatten)]
+        justifier_config: Box<JustifierConfig>,
+        #[clap(flatten)]
+        limits: ResourceLimits,
+    },
+
+    /// Justify assertions only, in each proof log
+    #[cfg(feature = "justify")]
+    Style {
+        #[clap(flatten)]
+        paths: BatchPaths,
+        #[clap(flatten)]
+        justifier_config: JustifierConfig,
+        #[clap(flatten)]
+        limits: ResourceLimits,
+    },
+}
+
+#[derive(Args)]
+struct BatchPaths {
+    #[arg(
+        value_name = "INPUT_FILE",
+        required = true,
+        num_args = 1..,
+        help = "Input files. Each output is named after its input, as for a single file."
+    )]
+    input_paths: Vec<PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "DIR",
+        help_heading = "Output options",
+        help = "Write the outputs into DIR (created if missing) rather than next to their inputs."
+    )]
+    output_dir: Option<PathBuf>,
+
+    #[arg(
+        short = 'f',
+        long,
+        help_heading = "Output options",
+        help = "Overwrite output files that already exist and aren't empty."
+    )]
+    force: bool,
+
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = 1,
+        help_heading = "Batch options",
+        help = "Process up to N inputs at once, or one per CPU for 0."
+    )]
+    jobs: usize,
+}
+
+impl BatchPaths {
+    /// The paths for processing one of the inputs.
+    fn io(&self, input_path: &Path) -> IOPaths {
+        IOPaths {
+            input_path: input_path.to_path_buf(),
+            output_path: None,
+            output_dir: self.output_dir.clone(),
+            in_place: false,
+            backup_suffix: None,
+            force: self.force,
+        }
+    }
+}
+
+#[derive(Subcommand)]
+enum GenerateTarget {
+    /// Print a completion script for the shell
+    Completions {
+        #[arg(value_enum, help = "Shell to generate completions for.")]
+        shell: Shell,
     },
+
+    /// Print the man page in roff format
+    Man,
 }
 
 #[derive(Args)]
 struct IOPaths {
-    #[arg(value_name = "INPUT_FILE", help = "Input file.")]
+    #[arg(
+        value_name = "INPUT_FILE",
+        help = "Input file, or `-` to read from stdin."
+    )]
     input_path: PathBuf,
 
     #[arg(
         value_name = "OUTPUT_FILE",
-        help = "Optional output file. Defaults to <INPUT_FILE>.smol.pbp."
+        help = "Optional output file. Defaults to INPUT_FILE with its extensions (including any compression extension) replaced by .smol.pbp, or .styled.pbp for `style`."
     )]
     output_path: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "DIR",
+        help_heading = "Output options",
+        conflicts_with_all = ["output_path", "in_place"],
+        help = "Write the output into DIR (created if missing), named after INPUT_FILE as for the default OUTPUT_FILE."
+    )]
+    output_dir: Option<PathBuf>,
+
+    #[arg(
+        long,
+        help_heading = "Output options",
+        conflicts_with = "output_path",
+        help = "Replace INPUT_FILE with the output. Written to a temporary file alongside it and renamed into place on success."
+    )]
+    in_place: bool,
+
+    #[arg(
+        long,
+        help_heading = "Output options",
+        value_name = "SUFFIX",
+        requires = "in_place",
+        help = "With `--in-place`, keep a copy of the original input at <INPUT_FILE><SUFFIX> (e.g. `.orig`)."
+    )]
+    backup_suffix: Option<String>,
+
+    #[arg(
+        short = 'f',
+        long,
+        help_heading = "Output options",
+        help = "Overwrite the output file if it already exists and isn't empty."
+    )]
+    force: bool,
+}
+
+/// Compression extensions dropped from the input's name when naming the output.
+const COMPRESSION_EXTENSIONS: [&str; 4] = ["gz", "bz2", "xz", "zst"];
+
+/// Where a command writes its output. For `--in-place` this is a temporary file next to the
+/// input, which only replaces the input once [`Output::commit`] is called. Output that is
+/// never committed, as the command failed, is removed when this is dropped.
+struct Output {
+    path: PathBuf,
+    /// The path the output ends up at, for reporting and for deriving sibling paths
+    final_path: PathBuf,
+    temp_path: Option<TempPath>,
+    committed: bool,
+    /// Leave the output in place even if the command fails
+    keep_on_failure: bool,
 }
 
 impl IOPaths {
-    fn resolved_output_path(&self) -> PathBuf {
-        self.output_path.clone().unwrap_or_else(|| {
-            let mut path = self.input_path.clone();
-            path.set_extension("smol.pbp");
-            path
+    /// Where to write the output, which is named `<input stem><suffix>` unless an output file
+    /// was given.
+    fn output(&self, suffix: &str) -> Result<Output, PBarberError> {
+        if !self.in_place {
+            let path = self.resolved_output_path(suffix)?;
+            if !is_stdin(&self.input_path) && is_same_file(&self.input_path, &path) {
+                return Err(PBarberError::InvalidConfig(format!(
+                    "refusing to overwrite the input file {} (use `--in-place` to replace it)",
+                    self.input_path.to_str().unwrap()
+                )));
+            }
+            let existing_len = fs::metadata(&path).map(|metadata| metadata.len());
+            if !self.force && existing_len.is_ok_and(|len| len > 0) {
+                return Err(PBarberError::InvalidConfig(format!(
+                    "refusing to overwrite {} (use `--force` to replace it)",
+                    path.to_str().unwrap()
+                )));
+            }
+            // Create the output now, so it is ours to clean up if the command fails
+            File::create(&path)?;
+            return Ok(Output {
+                final_path: path.clone(),
+                path,
+                temp_path: None,
+                committed: false,
+                keep_on_failure: false,
+            });
+        }
+        if is_stdin(&self.input_path) {
+            return Err(PBarberError::InvalidConfig(
+                "`--in-place` needs an input file, not stdin".to_string(),
+            ));
+        }
+        let temp_path = tempfile::Builder::new()
+            .prefix(".pbarber-")
+            .suffix(".pbp.tmp")
+            .tempfile_in(parent_dir(&self.input_path))?
+            .into_temp_path();
+        Ok(Output {
+            path: temp_path.to_path_buf(),
+            final_path: self.input_path.clone(),
+            temp_path: Some(temp_path),
+            committed: false,
+            keep_on_failure: false,
+        })
+    }
+
+    /// The output of an earlier, interrupted run, cut back to the `len` bytes it is to be
+    /// continued from.
+    #[cfg(feature = "justify")]
+    fn resumed_output(&self, suffix: &str, len: u64) -> Result<Output, PBarberError> {
+        let path = self.resolved_output_path(suffix)?;
+        let existing_len = fs::metadata(&path)?.len();
+        if existing_len < len {
+            return Err(PBarberError::MalformedInput(format!(
+                "{} is shorter than the checkpoint says, so can't be resumed",
+                path.to_str().unwrap()
+            )));
+        }
+        OpenOptions::new().write(true).open(&path)?.set_len(len)?;
+        Ok(Output {
+            final_path: path.clone(),
+            path,
+            temp_path: None,
+            committed: false,
+            keep_on_failure: true,
+        })
+    }
+
+    fn resolved_output_path(&self, suffix: &str) -> Result<PathBuf, PBarberError> {
+        if let Some(output_path) = &self.output_path {
+            return Ok(output_path.clone());
+        }
+        let mut name = input_stem(&self.input_path);
+        name.push(suffix);
+        Ok(match &self.output_dir {
+            Some(output_dir) => {
+                fs::create_dir_all(output_dir)?;
+                output_dir.join(name)
+            }
+            None if is_stdin(&self.input_path) => PathBuf::from(name),
+            None => self.input_path.with_file_name(name),
         })
     }
 }
 
+impl Output {
+    /// Flush the output to disk and, when processing in place, atomically rename it over the
+    /// input (after copying the input to its backup path, if one was asked for).
+    fn commit(mut self, io: &IOPaths) -> Result<(), PBarberError> {
+        self.committed = true;
+        let Some(temp_path) = self.temp_path.take() else {
+            return Ok(());
+        };
+        File::open(&temp_path)?.sync_all()?;
+        if let Some(suffix) = &io.backup_suffix {
+            let mut backup_path = io.input_path.clone().into_os_string();
+            backup_path.push(suffix);
+            fs::copy(&io.input_path, &backup_path)?;
+            File::open(&backup_path)?.sync_all()?;
+        }
+        temp_path
+            .persist(&io.input_path)
+            .map_err(|err| PBarberError::Io(err.error))?;
+        Ok(())
+    }
+}
+
+impl Drop for Output {
+    fn drop(&mut self) {
+        // A temporary in-place output removes itself
+        if !self.committed && !self.keep_on_failure && self.temp_path.is_none() {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
+
 #[derive(Args)]
 struct InputPathOnly {
     #[arg(value_name = "INPUT_FILE", help = "Input file.")]
     input_path: PathBuf,
 }
 
-fn main() -> Result<(), PBarberError> {
+fn main() -> ExitCode {
     let cli = Cli::parse();
+    colored::control::set_override(cli.color.enabled(io::stdout().is_terminal()));
+    tracing_subscriber::fmt()
+        .with_max_level(cli.log_level())
+        .with_writer(io::stderr)
+        .with_ansi(cli.color.enabled(io::stderr().is_terminal()))
+        .with_target(false)
+        .without_time()
+        .init();
 
+    let porcelain = cli.porcelain;
+    match run(cli) {
+        Ok(()) => {
+            if porcelain {
+                println!("status ok");
+            }
+            ExitCode::SUCCESS
+        }
+        Err(err) => {
+            error!("{err}");
+            if porcelain {
+                println!("status error");
+                println!("exit_code {}", err.exit_code());
+                println!("error {err}");
+            }
+            ExitCode::from(err.exit_code())
+        }
+    }
+}
+
+fn run(cli: Cli) -> Result<(), PBarberError> {
+    let options = RunOptions {
+        deny_warnings: cli.deny_warnings,
+        porcelain: cli.porcelain,
+        timings: cli.timings,
+        buffers: cli.buffers,
+    };
+    let porcelain = cli.porcelain;
     match cli.command {
-        Commands::Trim { io, trimmer_config } => {
-            let output_path = io.resolved_output_path();
-            let (input_file, output_file) = open_files(&io.input_path, &output_path);
-            let trim_result = run_trimmer(trimmer_config, input_file, output_file)?;
-            print_results(
-                io.input_path.to_str().unwrap(),
-                output_path.to_str().unwrap(),
-                trim_result,
-            );
-            reverse_file(&output_path)?;
+        Commands::Trim {
+            io,
+            trimmer_config,
+            limits,
+        } => {
+            trim_file(&io, trimmer_config, limits, options, &mut io::stdout())?;
         }
+        #[cfg(feature = "justify")]
         Commands::TrimAndStyle {
             io,
             trimmer_config,
             justifier_config,
+            limits,
+            keep_intermediate,
         } => {
-            let output_path = io.resolved_output_path();
-            let (input_file, output_file) = open_files(&io.input_path, &output_path);
-            let _trim_result = run_trimmer(trimmer_config, input_file, output_file)?;
-            let style_result = run_justifier(justifier_config, &output_path)?;
-            print_results(
-                io.input_path.to_str().unwrap(),
-                output_path.to_str().unwrap(),
-                style_result,
-            );
+            trim_and_style_file(
+                &io,
+                trimmer_config,
+                *justifier_config,
+                limits,
+                keep_intermediate,
+                options,
+                &mut io::stdout(),
+            )?;
         }
+        #[cfg(feature = "justify")]
         Commands::Style {
             io,
             justifier_config,
+            limits,
         } => {
-            let output_path = io.resolved_output_path();
-            let (input_file, output_file) = open_files(&io.input_path, &output_path);
-            println!(
-                "Warning: justifier expects the input file to be reversed by default. For non-reversed files use the `--read-forwards` option."
-            );
-
-            let mut justifier = Justifier::with_config(input_file, output_file, justifier_config);
-            let style_result = justifier.style()?;
-            print_results(
-                io.input_path.to_str().unwrap(),
-                output_path.to_str().unwrap(),
-                style_result,
-            );
+            style_file(&io, justifier_config, limits, options, &mut io::stdout())?;
+        }
+        Commands::Batch { command } => run_batch(command, options)?,
+        Commands::Verify {
+            opb_path,
+            proof_path,
+            verify_config,
+        } => {
+            let outcome = run_veripb(&opb_path, &proof_path, &verify_config)?;
+            if porcelain {
+                for line_number in &outcome.reported_lines {
+                    println!("failing_line {line_number}");
+                }
+            }
+            if let Some(original_path) = &verify_config.original {
+                for line_number in &outcome.reported_lines {
+                    report_line_origin(&proof_path, *line_number, original_path, porcelain)?;
+                }
+            }
+            if !outcome.success {
+                if porcelain {
+                    println!("verified false");
+                }
+                return Err(PBarberError::VerificationFailed(format!(
+                    "VeriPB rejected {}",
+                    proof_path.to_str().unwrap()
+                )));
+            }
+            if porcelain {
+                println!("verified true");
+            } else {
+                println!("{}", "VeriPB accepted the proof.".green());
+            }
+        }
+        Commands::Reverse { io } => {
+            let output = io.output(".rev.pbp")?;
+            let final_path = output.final_path.clone();
+            let (input_file, output_file) =
+                open_files(&io.input_path, &output.path, options.buffers.write_buffer)?;
+            let lines = reverse_file(input_file, output_file)?;
+            output.commit(&io)?;
+            if porcelain {
+                println!("input {}", io.input_path.to_str().unwrap());
+                println!("output {}", final_path.to_str().unwrap());
+                println!("lines {lines}");
+            } else {
+                info!(
+                    "Reversed {lines} lines into {}",
+                    final_path.to_str().unwrap()
+                );
+            }
+        }
+        #[cfg(feature = "justify")]
+        Commands::Annotate {
+            io,
+            annotate_config,
+        } => {
+            let output = io.output(".annotated.pbp")?;
+            let final_path = output.final_path.clone();
+            let (input_file, output_file) =
+                open_files(&io.input_path, &output.path, options.buffers.write_buffer)?;
+            let annotated = annotate(
+                BufReader::with_capacity(options.buffers.read_buffer, input_file),
+                output_file,
+                &annotate_config,
+            )?;
+            output.commit(&io)?;
+            if porcelain {
+                println!("input {}", io.input_path.to_str().unwrap());
+                println!("output {}", final_path.to_str().unwrap());
+                println!("annotated_lines {annotated}");
+            } else {
+                info!(
+                    "Annotated {annotated} lines into {}",
+                    final_path.to_str().unwrap()
+                );
+            }
         }
-        Commands::Advise { input_path: _ } => {
-            println!("`advise` not yet implemented, sorry :-(");
+        Commands::Split {
+            input_path,
+            split_config,
+            output_dir,
+        } => {
+            let output_dir = match output_dir {
+                Some(output_dir) => {
+                    fs::create_dir_all(&output_dir)?;
+                    output_dir
+                }
+                None => input_path.with_file_name(""),
+            };
+            let stem = input_stem(&input_path);
+            let output_name = |suffix: &str| {
+                let mut name = stem.clone();
+                name.push(suffix);
+                output_dir.join(name)
+            };
+            let input_file = OpenOptions::new().read(true).open(&input_path)?;
+            let manifest = split(
+                BufReader::with_capacity(options.buffers.read_buffer, input_file),
+                &split_config,
+                |index| output_name(&format!(".part{:03}.pbp", index + 1)),
+            )?;
+            let manifest_path = output_name(".split.json");
+            manifest.write_json(BufWriter::new(File::create(&manifest_path)?))?;
+            if porcelain {
+                for chunk in &manifest.chunks {
+                    println!("chunk {}", chunk.path.to_str().unwrap());
+                }
+                println!("manifest {}", manifest_path.to_str().unwrap());
+            } else {
+                info!(
+                    "Split into {} chunks, listed in {}",
+                    manifest.chunks.len(),
+                    manifest_path.to_str().unwrap()
+                );
+            }
+        }
+        Commands::Advise {
+            input_path,
+            advise_config,
+        } => {
+            let input_file = File::open(&input_path)?;
+            if let Some(output_path) = &advise_config.minimize {
+                let outcome = minimize(
+                    BufReader::with_capacity(options.buffers.read_buffer, input_file),
+                    &advise_config.checker,
+                    output_path,
+                )?;
+                if porcelain {
+                    println!("original_lines {}", outcome.original_lines);
+                    println!("minimized_lines {}", outcome.minimized_lines);
+                    println!("checks {}", outcome.checks);
+                } else {
+                    info!(
+                        "Minimized {} lines to {} in {} checks, written to {}",
+                        outcome.original_lines,
+                        outcome.minimized_lines,
+                        outcome.checks,
+                        output_path.to_str().unwrap()
+                    );
+                }
+                return Ok(());
+            }
+            if let Some(id) = &advise_config.explain {
+                let explanation = explain(
+                    BufReader::with_capacity(options.buffers.read_buffer, input_file),
+                    id,
+                    advise_config.explain_depth,
+                )?;
+                if porcelain {
+                    print!("{}", explanation.porcelain());
+                } else {
+                    println!("{}", format!("Derivation of {id}:").yellow());
+                    print!("{}", explanation);
+                }
+                return Ok(());
+            }
+            let report = advise(
+                BufReader::with_capacity(options.buffers.read_buffer, input_file),
+                &advise_config,
+            )?;
+            if porcelain {
+                print!("{}", report.porcelain());
+            } else {
+                println!(
+                    "{}",
+                    format!("Advice for {}:", input_path.to_str().unwrap()).yellow()
+                );
+                print!("{}", report);
+            }
+        }
+        Commands::Stats {
+            input_paths,
+            stats_config,
+        } => {
+            print_stats_of_files(&input_paths, &stats_config, options, &mut io::stdout())?;
+        }
+        Commands::Generate { target } => {
+            // Name the output after the binary rather than the display name
+            let mut command = Cli::command().name(env!("CARGO_BIN_NAME"));
+            match target {
+                GenerateTarget::Completions { shell } => {
+                    generate(
+                        shell,
+                        &mut command,
+                        env!("CARGO_BIN_NAME"),
+                        &mut io::stdout(),
+                    );
+                }
+                GenerateTarget::Man => Man::new(command).render(&mut io::stdout())?,
+            }
         }
     }
 
     Ok(())
 }
 
+/// `trim` for one input.
+fn trim_file(
+    io: &IOPaths,
+    mut trimmer_config: TrimmerConfig,
+    mut limits: ResourceLimits,
+    options: RunOptions,
+    out: &mut impl Write,
+) -> Result<StatsPair, PBarberError> {
+    trimmer_config.deny_warnings = options.deny_warnings;
+    trimmer_config.buffers = options.buffers;
+    limits.start_clock();
+    trimmer_config.limits = limits;
+    let output = io.output(".smol.pbp")?;
+    let output_path = output.path.clone();
+    let final_path = output.final_path.clone();
+    trimmer_config.resolve_graph_path(&final_path);
+    trimmer_config.resolve_reverse_output(diagnostics(trimmer_config.deny_warnings).as_mut())?;
+    let renumber_ids = if trimmer_config.reverse_output && trimmer_config.renumber {
+        warning(
+            options.deny_warnings,
+            WarningKind::IgnoredOption,
+            "ignoring `--renumber` as the output is written in reverse.",
+        )?;
+        false
+    } else {
+        trimmer_config.renumber
+    };
+    let core_path = trimmer_config.core_path.clone();
+    let mut timings = Timings::default();
+    let (trim_result, core, _) = timings.phase("trim", &io.input_path, &output_path, || {
+        run_trim_to_budget(
+            trimmer_config,
+            &io.input_path,
+            &output_path,
+            options.buffers.write_buffer,
+        )
+    })?;
+    timings.count_lines(trim_result.as_ref());
+    if renumber_ids {
+        timings.phase("renumber", &output_path, &output_path, || {
+            renumber_file(&output_path, options.buffers.write_buffer)
+        })?;
+    }
+    timings.phase("commit", &output_path, &final_path, || output.commit(io))?;
+    print_results(
+        out,
+        io.input_path.to_str().unwrap(),
+        final_path.to_str().unwrap(),
+        trim_result.as_ref(),
+        options.porcelain,
+    )?;
+    if let Some(core) = core {
+        report_core(out, &core, &core_path, options.porcelain)?;
+    }
+    if let Some(format) = options.timings {
+        timings.print(out, format, options.porcelain)?;
+    }
+    Ok(trim_result)
+}
+
+/// `trim-and-style` for one input.
+#[cfg(feature = "justify")]
+fn trim_and_style_file(
+    io: &IOPaths,
+    mut trimmer_config: TrimmerConfig,
+    mut justifier_config: JustifierConfig,
+    mut limits: ResourceLimits,
+    keep_intermediate: Option<Option<PathBuf>>,
+    options: RunOptions,
+    out: &mut impl Write,
+) -> Result<StatsPair, PBarberError> {
+    trimmer_config.deny_warnings = options.deny_warnings;
+    trimmer_config.buffers = options.buffers;
+    justifier_config.buffers = options.buffers;
+    limits.start_clock();
+    trimmer_config.limits = limits.clone();
+    justifier_config.limits = limits;
+    if justifier_config.checkpoint_path.is_some() {
+        warning(
+            options.deny_warnings,
+            WarningKind::IgnoredOption,
+            "ignoring `--checkpoint` as only `style` runs can be resumed.",
+        )?;
+        justifier_config.checkpoint_path = None;
+        justifier_config.resume = false;
+    }
+    let output = io.output(".smol.pbp")?;
+    let output_path = output.path.clone();
+    let final_path = output.final_path.clone();
+    let intermediate_path = keep_intermediate
+        .map(|path| path.unwrap_or_else(|| final_path.with_extension("trimmed.pbp")));
+    trimmer_config.resolve_graph_path(&final_path);
+    trimmer_config.resolve_reverse_output(diagnostics(trimmer_config.deny_warnings).as_mut())?;
+    let renumber_ids = trimmer_config.renumber;
+    let core_path = trimmer_config.core_path.clone();
+    // The styled proof is mapped to the trimmed one, then through the trimmer's map
+    let line_map_path = trimmer_config.line_map_path.clone();
+    let keep_style_map = justifier_config.line_map_path.is_some();
+    let style_map_path = line_map_path.as_ref().map(|line_map_path| {
+        justifier_config
+            .line_map_path
+            .get_or_insert_with(|| line_map_path.with_extension("style.tmp"))
+            .clone()
+    });
+    let profile_format = justifier_config.profile;
+    let mut timings = Timings::default();
+    // The trimmed proof only goes to disk if it isn't streamed into the justifier
+    let (style_result, profile) = if trimmer_config.can_stream() && intermediate_path.is_none() {
+        // Pass the trimmed proof from the trimmer's buffer into the justifier
+        let (input_file, output_file) =
+            open_files(&io.input_path, &output_path, options.buffers.write_buffer)?;
+        let graph_output = trimmer_config
+            .emit_graph
+            .zip(trimmer_config.graph_path.clone());
+        let mut diagnostics = diagnostics(trimmer_config.deny_warnings);
+        let outcome = timings.phase("trim and style", &io.input_path, &output_path, || {
+            trim_and_style(
+                input_file,
+                output_file,
+                trimmer_config.without_lit_deletion(diagnostics.as_mut())?,
+                justifier_config,
+            )
+        })?;
+        timings.count_lines(outcome.trim_stats.as_ref());
+        for (kind, warning) in &outcome.warnings {
+            diagnostics.warning(*kind, warning)?;
+        }
+        if let (Some((format, graph_path)), Some(graph)) = (graph_output, &outcome.graph) {
+            write_graph(graph, format, &graph_path)?;
+        }
+        if let Some(core) = outcome.formula_core {
+            report_core(out, &core, &core_path, options.porcelain)?;
+        }
+        print_trim_stats(out, outcome.trim_stats, options.porcelain)?;
+        (outcome.style_stats, outcome.profile)
+    } else {
+        // Read the trimmer output in whichever order it was written
+        justifier_config.read_forwards = !trimmer_config.reverse_output;
+        let (trim_result, core, _) = timings.phase("trim", &io.input_path, &output_path, || {
+            run_trim_to_budget(
+                trimmer_config,
+                &io.input_path,
+                &output_path,
+                options.buffers.write_buffer,
+            )
+        })?;
+        timings.count_lines(trim_result.as_ref());
+        if let Some(core) = core {
+            report_core(out, &core, &core_path, options.porcelain)?;
+        }
+        print_trim_stats(out, trim_result, options.porcelain)?;
+        if let Some(intermediate_path) = &intermediate_path {
+            fs::copy(&output_path, intermediate_path)?;
+        }
+        let style_result = timings.phase("style", &output_path, &output_path, || {
+            run_justifier(justifier_config, &output_path, options.buffers.write_buffer)
+        })?;
+        timings.count_lines(style_result.0.as_ref());
+        if let (Some(line_map_path), Some(style_map_path)) = (&line_map_path, &style_map_path) {
+            join_line_maps(line_map_path, style_map_path, line_map_path)?;
+            if !keep_style_map {
+                fs::remove_file(style_map_path)?;
+            }
+        }
+        style_result
+    };
+    if renumber_ids {
+        timings.phase("renumber", &output_path, &output_path, || {
+            renumber_file(&output_path, options.buffers.write_buffer)
+        })?;
+    }
+    timings.phase("commit", &output_path, &final_path, || output.commit(io))?;
+    print_results(
+        out,
+        io.input_path.to_str().unwrap(),
+        final_path.to_str().unwrap(),
+        style_result.as_ref(),
+        options.porcelain,
+    )?;
+    if let Some(format) = options.timings {
+        timings.print(out, format, options.porcelain)?;
+    }
+    if let (Some(format), Some(profile)) = (profile_format, &profile) {
+        print_profile(out, profile, format, options.porcelain)?;
+    }
+    Ok(style_result)
+}
+
+/// `style` for one input.
+#[cfg(feature = "justify")]
+fn style_file(
+    io: &IOPaths,
+    mut justifier_config: JustifierConfig,
+    mut limits: ResourceLimits,
+    options: RunOptions,
+    out: &mut impl Write,
+) -> Result<StatsPair, PBarberError> {
+    justifier_config.buffers = options.buffers;
+    limits.start_clock();
+    justifier_config.limits = limits;
+    let checkpoint_path = justifier_config.checkpoint_path.clone();
+    if checkpoint_path.is_some() && io.in_place {
+        return Err(PBarberError::InvalidConfig(
+            "`--checkpoint` needs an output file to keep, so can't be used with `--in-place`"
+                .to_string(),
+        ));
+    }
+    let checkpoint = match &checkpoint_path {
+        Some(checkpoint_path) if justifier_config.resume => {
+            if is_stdin(&io.input_path) {
+                return Err(PBarberError::InvalidConfig(
+                    "`--resume` needs to read the input again, so can't read it from stdin"
+                        .to_string(),
+                ));
+            }
+            Some(Checkpoint::load(checkpoint_path)?)
+        }
+        _ => None,
+    };
+    let mut output = match &checkpoint {
+        Some(checkpoint) => io.resumed_output(".styled.pbp", checkpoint.output_bytes)?,
+        None => io.output(".styled.pbp")?,
+    };
+    // Keep the output of an interrupted run to resume from
+    output.keep_on_failure = checkpoint_path.is_some();
+    let final_path = output.final_path.clone();
+    let input_file = open_input(&io.input_path)?;
+    let output_file = BufWriter::with_capacity(
+        options.buffers.write_buffer,
+        match checkpoint {
+            Some(_) => OpenOptions::new().append(true).open(&output.path)?,
+            None => File::create(&output.path)?,
+        },
+    );
+    check_input_order(
+        &mut input_file.try_clone()?,
+        justifier_config.read_forwards,
+        diagnostics(options.deny_warnings).as_mut(),
+    )?;
+
+    let profile_format = justifier_config.profile;
+    let mut timings = Timings::default();
+    let (style_result, profile) = timings.phase("style", &io.input_path, &output.path, || {
+        let mut justifier = Justifier::try_with_config(input_file, output_file, justifier_config)?;
+        if let Some(checkpoint) = checkpoint {
+            justifier.resume(checkpoint)?;
+        }
+        Ok((justifier.style()?, justifier.profile().cloned()))
+    })?;
+    timings.count_lines(style_result.as_ref());
+    let output_path = output.path.clone();
+    timings.phase("commit", &output_path, &final_path, || output.commit(io))?;
+    if let Some(checkpoint_path) = &checkpoint_path {
+        match fs::remove_file(checkpoint_path) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err.into()),
+            _ => (),
+        }
+    }
+    print_results(
+        out,
+        io.input_path.to_str().unwrap(),
+        final_path.to_str().unwrap(),
+        style_result.as_ref(),
+        options.porcelain,
+    )?;
+    if let Some(format) = options.timings {
+        timings.print(out, format, options.porcelain)?;
+    }
+    if let (Some(format), Some(profile)) = (profile_format, &profile) {
+        print_profile(out, profile, format, options.porcelain)?;
+    }
+    Ok(style_result)
+}
+
+/// Run a batch command on each of its inputs, `--jobs` at a time. Logs are tagged with the
+/// input they are about, and each input's results are printed together once it is done. All
+/// inputs are processed even if some fail, and with `--stats` the totals over those that didn't
+/// are printed at the end.
+fn run_batch(command: BatchCommand, options: RunOptions) -> Result<(), PBarberError> {
+    let paths = match &command {
+        BatchCommand::Trim { paths, .. } => paths,
+        #[cfg(feature = "justify")]
+        BatchCommand::TrimAndStyle { paths, .. } | BatchCommand::Style { paths, .. } => paths,
+    };
+    let process = |io: &IOPaths, out: &mut Vec<u8>| match &command {
+        BatchCommand::Trim {
+            trimmer_config,
+            limits,
+            ..
+        } => trim_file(io, trimmer_config.clone(), limits.clone(), options, out),
+        #[cfg(feature = "justify")]
+        BatchCommand::TrimAndStyle {
+            trimmer_config,
+            justifier_config,
+            limits,
+            ..
+        } => trim_and_style_file(
+            io,
+            trimmer_config.clone(),
+            justifier_config.as_ref().clone(),
+            limits.clone(),
+            None,
+            options,
+            out,
+        ),
+        #[cfg(feature = "justify")]
+        BatchCommand::Style {
+            justifier_config,
+            limits,
+            ..
+        } => style_file(io, justifier_config.clone(), limits.clone(), options, out),
+    };
+
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(paths.jobs)
+        .build()
+        .map_err(|err| PBarberError::Internal(err.to_string()))?;
+    let results = pool.install(|| {
+        paths
+            .input_paths
+            .par_iter()
+            .map(|input_path| -> io::Result<Result<StatsPair, u8>> {
+                let _span = info_span!("file", path = %input_path.display()).entered();
+                let mut results = Vec::new();
+                let result = process(&paths.io(input_path), &mut results);
+                let mut stdout = io::stdout().lock();
+                stdout.write_all(&results)?;
+                let err = match result {
+                    Ok(stats) => return Ok(Ok(stats)),
+                    Err(err) => err,
+                };
+                error!("{err}");
+                if options.porcelain {
+                    writeln!(
+                        stdout,
+                        "failed {} {}",
+                        input_path.to_str().unwrap(),
+                        err.exit_code()
+                    )?;
+                }
+                Ok(Err(err.exit_code()))
+            })
+            .collect::<io::Result<Vec<_>>>()
+    })?;
+
+    let mut totals: StatsPair = None;
+    let mut failed = Vec::new();
+    for result in results {
+        match result {
+            Ok(Some((input_stats, output_stats))) => {
+                let (input_total, output_total) = totals.get_or_insert_default();
+                input_total.merge(&input_stats);
+                output_total.merge(&output_stats);
+            }
+            Ok(None) => (),
+            Err(exit_code) => failed.push(exit_code),
+        }
+    }
+    if let Some(totals) = &totals {
+        print_batch_totals(&mut io::stdout(), totals, options.porcelain)?;
+    }
+    match failed.first() {
+        Some(&exit_code) => Err(PBarberError::BatchFailed {
+            failed: failed.len(),
+            total: paths.input_paths.len(),
+            exit_code,
+        }),
+        None => Ok(()),
+    }
+}
+
+/// How long each phase of a command took, and how much it read and wrote, for `--timings`.
+#[derive(Default)]
+struct Timings(RunStats);
+
+impl Timings {
+    /// Run a phase that reads `read_path` and writes `written_path`, timing it. The sizes of the
+    /// files are taken as the bytes read and written.
+    fn phase<T>(
+        &mut self,
+        name: &str,
+        read_path: &Path,
+        written_path: &Path,
+        run: impl FnOnce() -> Result<T, PBarberError>,
+    ) -> Result<T, PBarberError> {
+        let bytes_read = file_len(read_path);
+        let start = Instant::now();
+        let result = run()?;
+        self.0.record_phase(PhaseStats {
+            name: name.to_string(),
+            elapsed: start.elapsed(),
+            bytes_read,
+            bytes_written: file_len(written_path),
+            lines: None,
+        });
+        Ok(result)
+    }
+
+    /// Count the input lines of the last phase from its stats, if it collected them.
+    fn count_lines(&mut self, stats: Option<&(ProofFileStats, ProofFileStats)>) {
+        if let Some((input_stats, _)) = stats {
+            self.0.set_lines(input_stats.total_lines);
+        }
+    }
+
+    /// Print the phases, and the peak memory used by now.
+    fn print(
+        &mut self,
+        out: &mut impl Write,
+        format: ReportFormat,
+        porcelain: bool,
+    ) -> io::Result<()> {
+        self.0.record_peak_memory();
+        match format {
+            ReportFormat::Json => {
+                self.0.write_json(&mut *out)?;
+                writeln!(out)
+            }
+            ReportFormat::Table if porcelain => write!(out, "{}", self.0.porcelain()),
+            ReportFormat::Table => {
+                writeln!(out, "{}", "Timings:".yellow())?;
+                write!(out, "{}", self.0)
+            }
+        }
+    }
+}
+
+/// The size of a file, or 0 if it can't be read (e.g. stdin).
+fn file_len(path: &Path) -> u64 {
+    fs::metadata(path).map_or(0, |metadata| metadata.len())
+}
+
+/// The stats and formula core of a trim, and how many derivations at or past the depth limit
+/// were kept as they couldn't be restated.
+type TrimResult = (
+    Option<(ProofFileStats, ProofFileStats)>,
+    Option<Vec<String>>,
+    usize,
+);
+
 fn run_trimmer(
     trimmer_config: TrimmerConfig,
     input_file: File,
-    output_file: File,
-) -> Result<Option<(ProofFileStats, ProofFileStats)>, PBarberError> {
-    let trimmer_config = if trimmer_config.lit_deletion {
-        println!(
-            "Warning: ignoring `--lit-deletion` as it would produce invalid proofs without expanding assertions."
-        );
-        TrimmerConfig {
-            lit_deletion: false,
-            ..trimmer_config
-        }
+    output_file: BufWriter<File>,
+    quiet: bool,
+) -> Result<TrimResult, PBarberError> {
+    let mut diagnostics: Box<dyn Diagnostics> = if quiet {
+        Box::new(SilentDiagnostics)
     } else {
-        trimmer_config
+        diagnostics(trimmer_config.deny_warnings)
     };
-    let mut trimmer = Trimmer::with_config(input_file, output_file, trimmer_config);
+    let trimmer_config = trimmer_config.without_lit_deletion(diagnostics.as_mut())?;
+    let graph_output = trimmer_config
+        .emit_graph
+        .zip(trimmer_config.graph_path.clone());
+    let mut trimmer = Trimmer::from_file(input_file, output_file, trimmer_config)?;
     let trim_result = trimmer.trim()?;
-    Ok(trim_result)
+    trimmer.report_warnings(diagnostics.as_mut())?;
+    if let (Some((format, graph_path)), Some(graph)) = (graph_output, trimmer.graph()) {
+        write_graph(graph, format, &graph_path)?;
+    }
+    Ok((
+        trim_result,
+        trimmer.formula_core(),
+        trimmer.kept_past_depth_limit(),
+    ))
 }
 
-fn reverse_file(output_path: &PathBuf) -> Result<(), PBarberError> {
-    let file_to_reverse = OpenOptions::new()
-        .read(true)
-        .open(&output_path)
-        .expect("Failed to re-poen output file for reversal");
-    let rev_reader = RevBufReader::new(file_to_reverse);
+/// Log a warning, or fail with it under `--deny-warnings`.
+fn warning(deny_warnings: bool, kind: WarningKind, message: &str) -> Result<(), PBarberError> {
+    diagnostics(deny_warnings).warning(kind, message)
+}
+
+/// Where warnings go: to the log, or failing the run under `--deny-warnings`.
+fn diagnostics(deny_warnings: bool) -> Box<dyn Diagnostics> {
+    if deny_warnings {
+        Box::new(DenyDiagnostics)
+    } else {
+        Box::new(LogDiagnostics)
+    }
+}
 
-    // Open temporary file to write the reversed file into
-    let temp_path = output_path.with_extension("tmp");
-    let mut final_output_file = OpenOptions::new()
+fn write_graph(
+    graph: &ProofGraph,
+    format: GraphFormat,
+    graph_path: &PathBuf,
+) -> Result<(), PBarberError> {
+    let graph_file = OpenOptions::new()
         .create(true)
         .write(true)
         .truncate(true)
-        .open(temp_path.as_path())
-        .expect("Failed to open temp file.");
+        .open(graph_path)?;
+    let mut writer = BufWriter::new(graph_file);
+    graph.write(format, &mut writer)?;
+    writer.flush()?;
+    Ok(())
+}
+
+fn report_core(
+    out: &mut impl Write,
+    core: &[String],
+    core_path: &Option<PathBuf>,
+    porcelain: bool,
+) -> Result<(), PBarberError> {
+    if porcelain {
+        writeln!(out, "core {}", core.join(" "))?;
+    } else {
+        writeln!(
+            out,
+            "{}",
+            format!("Formula core ({} constraints):", core.len()).yellow()
+        )?;
+        writeln!(out, "{}", core.join(" "))?;
+    }
+    if let Some(core_path) = core_path {
+        let mut core_file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(core_path)?;
+        for id in core {
+            writeln!(core_file, "{}", id)?;
+        }
+    }
+    Ok(())
+}
+
+fn run_trim_passes(
+    trimmer_config: TrimmerConfig,
+    input_path: &PathBuf,
+    output_path: &PathBuf,
+    write_buffer: usize,
+    quiet: bool,
+) -> Result<TrimResult, PBarberError> {
+    let max_passes = trimmer_config.passes.max_passes();
+    let report_passes = max_passes > 1;
 
-    //  Rewrite lines in correct order
-    for line in rev_reader.lines() {
-        writeln!(final_output_file, "{}", line.unwrap())?;
+    let (input_file, output_file) = open_files(input_path, output_path, write_buffer)?;
+    let counted_input = input_file.try_clone()?;
+    let (mut trim_result, mut core, mut kept_past_depth_limit) =
+        run_trimmer(trimmer_config.clone(), input_file, output_file, quiet)?;
+    if !report_passes {
+        return Ok((trim_result, core, kept_past_depth_limit));
     }
 
-    // Replace the output file with the reversed file
-    rename(temp_path.as_path(), output_path)?;
+    let mut lines_before = count_lines(counted_input)?;
+    let mut pass = 1;
+    loop {
+        let lines_after = count_lines(File::open(output_path)?)?;
+        if !quiet {
+            info!(
+                "Trimming pass {pass}: {lines_before} -> {lines_after} lines ({} removed)",
+                lines_before.saturating_sub(lines_after)
+            );
+        }
+        if pass >= max_passes || lines_after >= lines_before {
+            break;
+        }
+
+        // Re-trim the previous pass's output
+        let pass_input_path = output_path.with_extension("pass.tmp");
+        rename(output_path, &pass_input_path)?;
+        let pass_map_path = trimmer_config
+            .line_map_path
+            .as_ref()
+            .map(|line_map_path| line_map_path.with_extension("pass.tmp"));
+        if let (Some(line_map_path), Some(pass_map_path)) =
+            (&trimmer_config.line_map_path, &pass_map_path)
+        {
+            rename(line_map_path, pass_map_path)?;
+        }
+        let (input_file, output_file) = open_files(&pass_input_path, output_path, write_buffer)?;
+        let (pass_result, pass_core, pass_kept_past_depth_limit) =
+            run_trimmer(trimmer_config.clone(), input_file, output_file, quiet)?;
+        core = pass_core;
+        kept_past_depth_limit += pass_kept_past_depth_limit;
+        std::fs::remove_file(&pass_input_path)?;
+        if let (Some(line_map_path), Some(pass_map_path)) =
+            (&trimmer_config.line_map_path, &pass_map_path)
+        {
+            join_line_maps(pass_map_path, line_map_path, line_map_path)?;
+            std::fs::remove_file(pass_map_path)?;
+        }
+
+        // Compare the original input against the final output
+        trim_result = match (trim_result, pass_result) {
+            (Some((input_stats, _)), Some((_, output_stats))) => Some((input_stats, output_stats)),
+            _ => None,
+        };
+        lines_before = lines_after;
+        pass += 1;
+    }
+
+    Ok((trim_result, core, kept_past_depth_limit))
+}
+
+/// Trim, and if the result is over the output budget trim again with the largest depth limit
+/// that fits, reporting how much of the fully trimmed proof was kept.
+fn run_trim_to_budget(
+    trimmer_config: TrimmerConfig,
+    input_path: &PathBuf,
+    output_path: &PathBuf,
+    write_buffer: usize,
+) -> Result<TrimResult, PBarberError> {
+    if !trimmer_config.has_output_budget() {
+        return run_trim_passes(trimmer_config, input_path, output_path, write_buffer, false);
+    }
+    if is_stdin(input_path) {
+        warning(
+            trimmer_config.deny_warnings,
+            WarningKind::IgnoredOption,
+            "ignoring the output budget as stdin can only be trimmed once.",
+        )?;
+        return run_trim_passes(trimmer_config, input_path, output_path, write_buffer, false);
+    }
+
+    // Size the output quietly at each depth, then trim for real at the chosen one. A depth
+    // only fits if every derivation at the limit was restated rather than kept whole.
+    let trim_quietly =
+        |input_path: &PathBuf, max_depth: Option<usize>| -> Result<(u64, bool), PBarberError> {
+            let depth_config = TrimmerConfig {
+                max_depth,
+                ..trimmer_config.clone()
+            };
+            let (_, _, kept_past_depth_limit) =
+                run_trim_passes(depth_config, input_path, output_path, write_buffer, true)?;
+            let (lines, bytes) = output_size(output_path)?;
+            let fits = kept_past_depth_limit == 0 && trimmer_config.within_budget(lines, bytes);
+            Ok((lines, fits))
+        };
+    let (full_lines, fits) = trim_quietly(input_path, trimmer_config.max_depth)?;
+    if fits {
+        return run_trim_passes(trimmer_config, input_path, output_path, write_buffer, false);
+    }
+    // A depth limit only keeps lines that the full trim kept, so the fully trimmed proof is
+    // sized at each depth rather than the whole input
+    let fully_trimmed = temp_file_beside(output_path)?;
+    std::fs::copy(output_path, fully_trimmed.path())?;
+    let fully_trimmed_path = fully_trimmed.path().to_path_buf();
+    let trim_quietly = |max_depth| trim_quietly(&fully_trimmed_path, max_depth);
+
+    // Find the largest depth that fits by doubling then bisecting
+    let mut depth = 0;
+    let (mut kept_lines, fits) = trim_quietly(Some(0))?;
+    if fits {
+        let mut too_deep = None;
+        let mut candidate = 1;
+        loop {
+            let (lines, fits) = trim_quietly(Some(candidate))?;
+            if fits {
+                (kept_lines, depth) = (lines, candidate);
+            } else {
+                too_deep = Some(candidate);
+            }
+            match too_deep {
+                Some(too_deep) if too_deep - depth <= 1 => break,
+                Some(too_deep) => candidate = depth + (too_deep - depth) / 2,
+                None => candidate *= 2,
+            }
+        }
+    } else {
+        warning(
+            trimmer_config.deny_warnings,
+            WarningKind::Recovered,
+            "the trimmed proof doesn't fit the output budget at any depth limit that restates all the derivations it cuts, so it was trimmed in full.",
+        )?;
+        return run_trim_passes(trimmer_config, input_path, output_path, write_buffer, false);
+    }
+    let depth_config = TrimmerConfig {
+        max_depth: Some(depth),
+        ..trimmer_config
+    };
+    let result = run_trim_passes(depth_config, input_path, output_path, write_buffer, false)?;
+    // A proof trimmed to nothing always fits, so only a proof with lines gets here
+    let kept_percent = 100.0 * kept_lines as f64 / full_lines.max(1) as f64;
+    info!(
+        "Output budget: kept derivations within depth {depth}, {kept_lines} of {full_lines} lines ({kept_percent:.1}%) of the fully trimmed proof."
+    );
+    Ok(result)
+}
+
+fn output_size(output_path: &PathBuf) -> Result<(u64, u64), PBarberError> {
+    let lines = count_lines(File::open(output_path)?)? as u64;
+    let bytes = std::fs::metadata(output_path)?.len();
+    Ok((lines, bytes))
+}
+
+fn count_lines(mut file: File) -> Result<usize, PBarberError> {
+    file.seek(SeekFrom::Start(0))?;
+    Ok(BufReader::new(file).lines().count())
+}
+
+fn renumber_file(output_path: &Path, write_buffer: usize) -> Result<(), PBarberError> {
+    let file_to_renumber = File::open(output_path)?;
+    let temp_file = temp_file_beside(output_path)?;
+
+    let mut renumbered_file = BufWriter::with_capacity(write_buffer, temp_file.as_file());
+    renumber(BufReader::new(file_to_renumber), &mut renumbered_file)?;
+    renumbered_file.flush()?;
+    drop(renumbered_file);
+
+    // Replace the output file with the renumbered file
+    temp_file.persist(output_path).map_err(io::Error::from)?;
 
     Ok(())
 }
 
+#[cfg(feature = "justify")]
 fn run_justifier(
     justifier_config: JustifierConfig,
     output_path: &PathBuf,
-) -> Result<Option<(ProofFileStats, ProofFileStats)>, PBarberError> {
-    let file_to_style = OpenOptions::new()
-        .read(true)
-        .open(&output_path)
-        .expect("Failed to open input file for justifier.");
+    write_buffer: usize,
+) -> Result<(StatsPair, Option<Profile>), PBarberError> {
+    let file_to_style = File::open(output_path)?;
+    let temp_file = temp_file_beside(output_path)?;
+    let output_file = BufWriter::with_capacity(write_buffer, temp_file.as_file());
 
-    let temp_path = output_path.with_extension("tmp");
-    let output_file = OpenOptions::new()
-        .create(true)
-        .write(true)
-        .truncate(true)
-        .open(temp_path.as_path())
-        .expect("Failed to open temp file.");
+    let result = Justifier::try_with_config(file_to_style, output_file, justifier_config)
+        .and_then(|mut justifier| Ok((justifier.style()?, justifier.profile().cloned())))?;
+    // Replace the output file with the styled file, leaving it be if styling failed
+    temp_file.persist(output_path).map_err(io::Error::from)?;
+    Ok(result)
+}
+
+/// A new temporary file in the same directory as `path`, so it can be renamed over it.
+fn temp_file_beside(path: &Path) -> io::Result<NamedTempFile> {
+    NamedTempFile::new_in(parent_dir(path))
+}
+
+/// The directory `path` is in, which is `.` for a bare file name.
+fn parent_dir(path: &Path) -> &Path {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    }
+}
+
+/// Write the line map from the output of a later step to the input of an earlier one, given
+/// the earlier step's map at `earlier_path` and the later one's at `later_path`.
+fn join_line_maps(
+    earlier_path: &Path,
+    later_path: &Path,
+    joined_path: &Path,
+) -> Result<(), PBarberError> {
+    let earlier = LineMap::from_path(earlier_path)?;
+    let later = LineMap::from_path(later_path)?;
+    earlier.then(&later).write_to_path(joined_path)
+}
 
-    let mut justifier = Justifier::with_config(file_to_style, output_file, justifier_config);
+fn is_stdin(path: &Path) -> bool {
+    path.as_os_str() == "-"
+}
+
+/// The input's file name without its extension or compression extension, e.g. `proof` for
+/// `proof.pbp.gz`.
+fn input_stem(input_path: &Path) -> OsString {
+    if is_stdin(input_path) {
+        return OsString::from("stdin");
+    }
+    let mut name = PathBuf::from(input_path.file_name().unwrap_or_default());
+    let compressed = name.extension().is_some_and(|ext| {
+        COMPRESSION_EXTENSIONS
+            .iter()
+            .any(|compression| ext == *compression)
+    });
+    if compressed {
+        name.set_extension("");
+    }
+    name.set_extension("");
+    name.into_os_string()
+}
 
-    let justifier_result = justifier.style();
-    // Replace the output file with the reversed file
-    rename(temp_path.as_path(), output_path)?;
-    justifier_result
+fn is_same_file(a: &Path, b: &Path) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
 }
 
-fn open_files(input_path: &PathBuf, output_path: &PathBuf) -> (File, File) {
-    // Open input file and read from end
-    let input_file = OpenOptions::new()
-        .read(true)
-        .open(&input_path)
-        .expect("Failed to open input file.");
+/// Open the input, and the output truncated and buffered with `write_buffer` bytes.
+fn open_files(
+    input_path: &PathBuf,
+    output_path: &Path,
+    write_buffer: usize,
+) -> io::Result<(File, BufWriter<File>)> {
+    let input_file = open_input(input_path)?;
 
     // Open and truncate output file.
     let output_file = OpenOptions::new()
         .create(true)
         .write(true)
         .truncate(true)
-        .open(output_path.as_path())
-        .expect("Failed to open output file.");
+        .open(output_path)?;
 
-    (input_file, output_file)
+    Ok((
+        input_file,
+        BufWriter::with_capacity(write_buffer, output_file),
+    ))
+}
+
+/// Open the input file, spooling stdin so it can be read from the end.
+fn open_input(input_path: &PathBuf) -> io::Result<File> {
+    if is_stdin(input_path) {
+        spool(io::stdin().lock())
+    } else {
+        OpenOptions::new().read(true).open(input_path)
+    }
+}
+
+fn report_line_origin(
+    proof_path: &Path,
+    line_number: u64,
+    original_path: &Path,
+    porcelain: bool,
+) -> Result<(), PBarberError> {
+    let Some((line, origin)) = map_line_to_original(proof_path, line_number, original_path)? else {
+        return Ok(());
+    };
+    if porcelain {
+        match origin {
+            LineOrigin::Original(original_number) => {
+                println!("line_origin {line_number} {original_number}")
+            }
+            LineOrigin::WrittenFor(original_number) => {
+                println!("line_origin {line_number} written_for {original_number}")
+            }
+            LineOrigin::Generated => println!("line_origin {line_number} generated"),
+        }
+        return Ok(());
+    }
+    let origin = match origin {
+        LineOrigin::Original(original_number) => format!(
+            "line {original_number} of {}",
+            original_path.to_str().unwrap()
+        ),
+        LineOrigin::WrittenFor(original_number) => format!(
+            "written by PBarber for line {original_number} of {}",
+            original_path.to_str().unwrap()
+        ),
+        LineOrigin::Generated => "written by PBarber".to_string(),
+    };
+    println!(
+        "{}",
+        format!("Proof line {line_number} (`{line}`) is {origin}.").yellow()
+    );
+    Ok(())
 }
 
 fn print_results(
+    out: &mut impl Write,
     input_path: &str,
     output_path: &str,
-    results: Option<(ProofFileStats, ProofFileStats)>,
-) {
+    results: Option<&(ProofFileStats, ProofFileStats)>,
+    porcelain: bool,
+) -> io::Result<()> {
+    if porcelain {
+        writeln!(out, "input {input_path}")?;
+        writeln!(out, "output {output_path}")?;
+        if let Some((input_stats, output_stats)) = results {
+            write!(out, "{}", input_stats.porcelain("input"))?;
+            write!(out, "{}", output_stats.porcelain("output"))?;
+        }
+        return Ok(());
+    }
     if let Some(stats) = results {
-        dbg!();
-        println!("{}", format!("Input file ({}) stats:", input_path).yellow());
-        println!("{}", stats.0);
-        println!(
+        writeln!(
+            out,
+            "{}",
+            format!("Input file ({}) stats:", input_path).yellow()
+        )?;
+        writeln!(out, "{}", stats.0)?;
+        writeln!(
+            out,
             "{}",
             format!("Output file ({}) stats:", output_path).yellow()
-        );
-        println!("{}", stats.1.compared_to(&stats.0));
+        )?;
+        writeln!(out, "{}", stats.1.compared_to(&stats.0))?;
+    }
+    Ok(())
+}
+
+/// `stats`: print the stats of each proof read forwards, compared to those of the first, or
+/// all in one table.
+fn print_stats_of_files(
+    input_paths: &[PathBuf],
+    config: &StatsConfig,
+    options: RunOptions,
+    out: &mut impl Write,
+) -> Result<(), PBarberError> {
+    let mut all_stats = Vec::with_capacity(input_paths.len());
+    for input_path in input_paths {
+        let input_file = open_input(input_path)?;
+        let stats = ProofFileStats::from_reader(BufReader::with_capacity(
+            options.buffers.read_buffer,
+            input_file,
+        ))?;
+        all_stats.push((input_path.to_string_lossy().into_owned(), stats));
+    }
+    if config.table || config.csv {
+        let table = StatsTable::new(&all_stats);
+        if config.csv {
+            table.write_csv(&mut *out)?;
+        } else {
+            write!(out, "{table}")?;
+        }
+        return Ok(());
+    }
+    let Some((_, first)) = all_stats.first() else {
+        return Ok(());
+    };
+    for (i, (name, stats)) in all_stats.iter().enumerate() {
+        if options.porcelain {
+            write!(out, "{}", stats.porcelain(&format!("file.{i}")))?;
+            continue;
+        }
+        writeln!(out, "{}", format!("{name} stats:").yellow())?;
+        if i == 0 {
+            writeln!(out, "{stats}")?;
+        } else {
+            writeln!(out, "{}", stats.compared_to(first))?;
+        }
+    }
+    Ok(())
+}
+
+/// Print the justifier's profile, as a table (or porcelain lines) or as JSON.
+#[cfg(feature = "justify")]
+fn print_profile(
+    out: &mut impl Write,
+    profile: &Profile,
+    format: ProfileFormat,
+    porcelain: bool,
+) -> io::Result<()> {
+    match format {
+        ProfileFormat::Json => {
+            profile.write_json(&mut *out)?;
+            writeln!(out)
+        }
+        ProfileFormat::Table if porcelain => write!(out, "{}", profile.porcelain()),
+        ProfileFormat::Table => {
+            writeln!(out, "{}", "Profile:".yellow())?;
+            write!(out, "{profile}")
+        }
+    }
+}
+
+/// Print the stats of a batch's inputs and outputs, totalled over the inputs that succeeded.
+fn print_batch_totals(
+    out: &mut impl Write,
+    (input_stats, output_stats): &(ProofFileStats, ProofFileStats),
+    porcelain: bool,
+) -> io::Result<()> {
+    if porcelain {
+        write!(out, "{}", input_stats.porcelain("total.input"))?;
+        write!(out, "{}", output_stats.porcelain("total.output"))?;
+        return Ok(());
+    }
+    writeln!(out, "{}", "Total input stats:".yellow())?;
+    writeln!(out, "{}", input_stats)?;
+    writeln!(out, "{}", "Total output stats:".yellow())?;
+    writeln!(out, "{}", output_stats.compared_to(input_stats))?;
+    Ok(())
+}
+
+/// Print the stats of the trimming step of `trim-and-style`, before those of the styled proof.
+#[cfg(feature = "justify")]
+fn print_trim_stats(
+    out: &mut impl Write,
+    results: Option<(ProofFileStats, ProofFileStats)>,
+    porcelain: bool,
+) -> io::Result<()> {
+    let Some((input_stats, trimmed_stats)) = results else {
+        return Ok(());
+    };
+    if porcelain {
+        write!(out, "{}", input_stats.porcelain("trim.input"))?;
+        write!(out, "{}", trimmed_stats.porcelain("trim.output"))?;
+    } else {
+        writeln!(out, "{}", "Trimming input stats:".yellow())?;
+        writeln!(out, "{}", input_stats)?;
+        writeln!(out, "{}", "Trimmed proof stats:".yellow())?;
+        writeln!(out, "{}", trimmed_stats.compared_to(&input_stats))?;
+    }
+    Ok(())
+}
+
+#[cfg(all(test, feature = "justify"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn failed_styling_leaves_the_output_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let fzn_path = dir.path().join("model.fzn.json");
+        let lits_path = dir.path().join("model.lits.json");
+        let output_path = dir.path().join("proof.pbp");
+        fs::write(&fzn_path, r#"{"solve": {"method": "satisfy"}}"#).unwrap();
+        fs::write(&lits_path, "{}").unwrap();
+        let trimmed = "\
+pseudo-Boolean proof version 2.0
+f 0
+@c1 a ;
+end pseudo-Boolean proof
+";
+        fs::write(&output_path, trimmed).unwrap();
+
+        let config = JustifierConfig::new(&fzn_path, &lits_path);
+        assert!(run_justifier(config, &output_path, 1 << 10).is_err());
+
+        assert_eq!(fs::read_to_string(&output_path).unwrap(), trimmed);
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 3);
     }
 }
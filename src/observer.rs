@@ -0,0 +1,41 @@
+/// Callbacks for what happens while trimming or styling a proof, e.g. to show progress or
+/// collect metrics. Install one with [`Trimmer::set_observer`](crate::trimmer::Trimmer::set_observer)
+/// or [`Justifier::set_observer`](crate::justifier::Justifier::set_observer). Every method
+/// does nothing by default.
+pub trait Observer: Send {
+    /// A line was read from the input, in the order it's read (in reverse when trimming). The
+    /// trimmer's parallel pre-pass skips the lines it rules out without reading them here.
+    fn on_input_line(&mut self, _line: &str) {}
+
+    /// The trimmer kept an input line.
+    fn on_kept(&mut self, _line: &str) {}
+
+    /// The trimmer dropped an input line.
+    fn on_dropped(&mut self, _line: &str) {}
+
+    /// The justifier justified the assertion `id` with `justifier`.
+    fn on_justified(&mut self, _id: &str, _justifier: &str) {}
+
+    /// The justifier couldn't justify the assertion `id` with `justifier`, so wrote it as a
+    /// bare assertion instead.
+    fn on_fallback(&mut self, _id: &str, _justifier: &str, _reason: &str) {}
+
+    /// The trimmer wrote a deletion.
+    fn on_deletion_emitted(&mut self, _line: &str) {}
+}
+
+/// The observer installed on a trimmer or justifier, if any.
+#[derive(Default)]
+pub(crate) struct ObserverSlot(Option<Box<dyn Observer>>);
+
+impl ObserverSlot {
+    pub(crate) fn set(&mut self, observer: impl Observer + 'static) {
+        self.0 = Some(Box::new(observer));
+    }
+
+    pub(crate) fn notify(&mut self, event: impl FnOnce(&mut dyn Observer)) {
+        if let Some(observer) = self.0.as_mut() {
+            event(observer.as_mut());
+        }
+    }
+}
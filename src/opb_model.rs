@@ -0,0 +1,130 @@
+use std::{
+    collections::HashSet,
+    fs::File,
+    io::{self, BufRead, BufReader},
+    path::Path,
+};
+
+use crate::pol_eval::Constraint;
+
+/// A constraint of an OPB model.
+#[derive(Debug, Clone)]
+pub struct OpbConstraint {
+    text: String,
+    /// `None` if the constraint couldn't be parsed (e.g. an equality)
+    parsed: Option<Constraint>,
+}
+
+impl OpbConstraint {
+    /// The constraint as written in the model, without the `;`.
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub(crate) fn parsed(&self) -> Option<&Constraint> {
+        self.parsed.as_ref()
+    }
+}
+
+/// The constraints of an OPB model, numbered from 1 as proofs refer to them, and the
+/// variables it uses.
+#[derive(Debug, Clone, Default)]
+pub struct OpbModel {
+    constraints: Vec<OpbConstraint>,
+    variables: HashSet<String>,
+    /// The variables in the order they first appear
+    variable_order: Vec<String>,
+}
+
+impl OpbModel {
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::from_reader(BufReader::new(File::open(path)?))
+    }
+
+    /// Read a model, skipping comments. The objective's variables count as the model's, but
+    /// it isn't a constraint.
+    pub fn from_reader<R: BufRead>(reader: R) -> io::Result<Self> {
+        let mut model = Self::default();
+        for line in reader.lines() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('*') {
+                continue;
+            }
+            if let Some(objective) = line
+                .strip_prefix("min:")
+                .or_else(|| line.strip_prefix("max:"))
+            {
+                model.add_variables(objective);
+                continue;
+            }
+            for constraint in line.split(';').filter(|c| !c.trim().is_empty()) {
+                model.add_variables(constraint);
+                model.constraints.push(OpbConstraint {
+                    text: constraint.trim().to_string(),
+                    parsed: Constraint::parse(constraint),
+                });
+            }
+        }
+        Ok(model)
+    }
+
+    fn add_variables(&mut self, constraint: &str) {
+        for var in variables(constraint) {
+            if self.variables.insert(var.to_string()) {
+                self.variable_order.push(var.to_string());
+            }
+        }
+    }
+
+    /// The number of constraints in the model.
+    pub fn len(&self) -> usize {
+        self.constraints.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.constraints.is_empty()
+    }
+
+    /// The constraint a proof refers to by `id`, if it's the number of one of the model's
+    /// constraints. (`@f<n>` labels name FlatZinc constraints, not formula constraints.)
+    pub fn resolve(&self, id: &str) -> Option<&OpbConstraint> {
+        let index = id.parse::<usize>().ok()?;
+        self.constraints.get(index.checked_sub(1)?)
+    }
+
+    /// The constraints in order, the first being constraint 1.
+    pub fn constraints(&self) -> impl Iterator<Item = &OpbConstraint> {
+        self.constraints.iter()
+    }
+
+    /// The model's variables in the order they first appear, which is how the checker
+    /// numbers them.
+    pub fn variables(&self) -> impl Iterator<Item = &str> {
+        self.variable_order.iter().map(String::as_str)
+    }
+
+    /// Whether the model uses the variable of `lit` (which may be negated with `~`).
+    pub fn has_variable(&self, lit: &str) -> bool {
+        self.variables.contains(lit.trim_start_matches('~'))
+    }
+
+    /// The variables of `constraint` (e.g. from a proof line) that the model doesn't use.
+    pub fn unknown_variables<'c>(&self, constraint: &'c str) -> Vec<&'c str> {
+        variables(constraint)
+            .filter(|var| !self.variables.contains(*var))
+            .collect()
+    }
+}
+
+/// The variables in the terms of a constraint.
+fn variables(constraint: &str) -> impl Iterator<Item = &str> {
+    constraint
+        .split_whitespace()
+        .map(|token| token.trim_end_matches(';').trim_start_matches('~'))
+        .filter(|token| {
+            !token.is_empty()
+                && !matches!(*token, "+" | ">=" | "<=" | "=" | "==>")
+                && token.parse::<i128>().is_err()
+        })
+}
@@ -0,0 +1,313 @@
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader, BufWriter, Read, Seek, Write},
+    iter, mem,
+    sync::{Arc, Mutex},
+};
+
+#[cfg(feature = "justify")]
+use crate::{AnnotateConfig, JustifierConfig, annotate::Annotator, justifier::Justifier};
+use crate::{
+    PBarberError, ProofFileStats, TrimmerConfig,
+    diagnostics::{Diagnostics, LogDiagnostics},
+    renumber::Renumberer,
+    reverse_lines::ReverseLines,
+    trimmer::Trimmer,
+};
+
+#[cfg(feature = "justify")]
+mod fused;
+
+#[cfg(feature = "justify")]
+pub use fused::{TrimAndStyleOutcome, trim_and_style};
+
+/// The lines of a proof, first line first, as passed between the passes of a [`Pipeline`].
+pub type ProofLines = Box<dyn Iterator<Item = Result<String, PBarberError>> + Send>;
+
+/// A pass over a proof in a [`Pipeline`], taking the lines of a proof and giving those of the
+/// transformed proof. Input lines should be read as the output lines are taken; passes that
+/// need the whole proof first can buffer it with [`spool_lines`].
+pub trait ProofTransform: Send {
+    /// What the pass is called in the pipeline's stats.
+    fn name(&self) -> &str;
+
+    fn apply(self: Box<Self>, lines: ProofLines) -> Result<ProofLines, PBarberError>;
+}
+
+/// The stats of a proof at some point in a [`Pipeline`].
+#[derive(Debug, Clone)]
+pub struct PassStats {
+    /// `input`, or the name of the pass that gave the proof
+    pub name: String,
+    pub stats: ProofFileStats,
+}
+
+/// Passes over a proof run one after another, each reading the lines of the one before as
+/// they're given, so that the only file written is the final output.
+#[derive(Default)]
+pub struct Pipeline {
+    passes: Vec<Box<dyn ProofTransform>>,
+    stats: bool,
+    reversed_input: bool,
+}
+
+impl Pipeline {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a pass after those added so far.
+    pub fn then(mut self, pass: impl ProofTransform + 'static) -> Self {
+        self.passes.push(Box::new(pass));
+        self
+    }
+
+    /// Record the stats of the input and of the output of every pass.
+    pub fn stats(mut self, stats: bool) -> Self {
+        self.stats = stats;
+        self
+    }
+
+    /// Read the input from the last line to the first, e.g. a proof trimmed with
+    /// `--reverse-output`.
+    pub fn reversed_input(mut self, reversed_input: bool) -> Self {
+        self.reversed_input = reversed_input;
+        self
+    }
+
+    /// Run the passes over `input`, writing the output of the last to `out`. Returns the
+    /// stats, if they were asked for, of the input followed by those of each pass.
+    pub fn run<R: Read + Seek + Send + 'static, W: Write>(
+        self,
+        input: R,
+        out: W,
+    ) -> Result<Vec<PassStats>, PBarberError> {
+        let mut lines: ProofLines = if self.reversed_input {
+            Box::new(ReverseLines::new(input).map(|line| Ok(line?.1)))
+        } else {
+            Box::new(BufReader::new(input).lines().map(|line| Ok(line?)))
+        };
+        let mut recorded = Vec::new();
+        let mut record = |name: &str, lines: ProofLines| -> ProofLines {
+            if !self.stats {
+                return lines;
+            }
+            let stats = Arc::new(Mutex::new(ProofFileStats::default()));
+            recorded.push((name.to_string(), stats.clone()));
+            Box::new(lines.inspect(move |line| {
+                if let Ok(line) = line {
+                    stats.lock().unwrap().record_line(line);
+                }
+            }))
+        };
+        lines = record("input", lines);
+        for pass in self.passes {
+            let name = pass.name().to_string();
+            lines = record(&name, pass.apply(lines)?);
+        }
+
+        let mut out = BufWriter::new(out);
+        for line in lines {
+            writeln!(out, "{}", line?)?;
+        }
+        out.flush()?;
+        Ok(recorded
+            .into_iter()
+            .map(|(name, stats)| {
+                let mut stats = mem::take(&mut *stats.lock().unwrap());
+                // Every entry, the input too, is recorded first line first: reversed input is
+                // read from the end of the file, which is the start of the proof
+                stats.finish(false);
+                PassStats { name, stats }
+            })
+            .collect())
+    }
+}
+
+/// Write `lines` to an anonymous temporary file, rewound to the start, for a pass that needs
+/// the whole proof.
+pub fn spool_lines(lines: ProofLines) -> Result<File, PBarberError> {
+    let mut out = BufWriter::new(tempfile::tempfile()?);
+    for line in lines {
+        writeln!(out, "{}", line?)?;
+    }
+    let mut spooled = out.into_inner().map_err(|err| err.into_error())?;
+    spooled.rewind()?;
+    Ok(spooled)
+}
+
+/// Trims the proof, as [`Trimmer`] does. The trimmer's warnings are passed to `diagnostics`
+/// once it's done.
+pub struct TrimPass {
+    pub config: TrimmerConfig,
+    pub diagnostics: Box<dyn Diagnostics>,
+}
+
+impl TrimPass {
+    /// Trim with `config`, logging the warnings.
+    pub fn new(config: TrimmerConfig) -> Self {
+        Self {
+            config,
+            diagnostics: Box::new(LogDiagnostics),
+        }
+    }
+}
+
+impl ProofTransform for TrimPass {
+    fn name(&self) -> &str {
+        "trim"
+    }
+
+    fn apply(self: Box<Self>, lines: ProofLines) -> Result<ProofLines, PBarberError> {
+        let TrimPass {
+            config,
+            diagnostics,
+        } = *self;
+        let mut diagnostics = Some(diagnostics);
+        let config = TrimmerConfig {
+            reverse_output: false,
+            ..config
+        };
+        let mut trimmed =
+            Trimmer::try_with_config(spool_lines(lines)?, io::sink(), config)?.lines();
+        let lines = iter::from_fn(move || {
+            let line = trimmed.next();
+            // Report the warnings once the proof is trimmed, ending it with the error the
+            // diagnostics fail with, if any
+            if line.is_none()
+                && let Some(mut diagnostics) = diagnostics.take()
+                && let Err(err) = trimmed.trimmer().report_warnings(diagnostics.as_mut())
+            {
+                return Some(Err(err));
+            }
+            line
+        });
+        Ok(Box::new(lines.fuse()))
+    }
+}
+
+/// Renumbers the constraint IDs of the proof, as [`renumber`](crate::renumber::renumber)
+/// does.
+pub struct RenumberPass;
+
+impl ProofTransform for RenumberPass {
+    fn name(&self) -> &str {
+        "renumber"
+    }
+
+    fn apply(self: Box<Self>, lines: ProofLines) -> Result<ProofLines, PBarberError> {
+        let mut renumberer = Renumberer::default();
+        Ok(Box::new(
+            lines.map(move |line| Ok(renumberer.renumber_line(&line?))),
+        ))
+    }
+}
+
+/// Justifies the assertions in the proof, as [`Justifier`] does. The proof is read forwards
+/// whatever the config says.
+#[cfg(feature = "justify")]
+pub struct JustifyPass(pub JustifierConfig);
+
+#[cfg(feature = "justify")]
+impl ProofTransform for JustifyPass {
+    fn name(&self) -> &str {
+        "justify"
+    }
+
+    fn apply(self: Box<Self>, lines: ProofLines) -> Result<ProofLines, PBarberError> {
+        let config = JustifierConfig {
+            read_forwards: true,
+            ..self.0
+        };
+        // The justifier reads lines as `io::Result`s, so errors from earlier passes are
+        // wrapped to get them through and unwrapped on the way out
+        let lines = lines.map(|line| line.map_err(io::Error::other));
+        let justified = Justifier::try_from_lines(lines, io::sink(), config)?.lines();
+        Ok(Box::new(
+            justified.map(|line| line.map_err(unwrap_passed_error)),
+        ))
+    }
+}
+
+/// Adds comments describing the FlatZinc constraints and literals in the proof, as
+/// [`annotate`](crate::annotate::annotate) does.
+#[cfg(feature = "justify")]
+pub struct AnnotatePass(pub AnnotateConfig);
+
+#[cfg(feature = "justify")]
+impl ProofTransform for AnnotatePass {
+    fn name(&self) -> &str {
+        "annotate"
+    }
+
+    fn apply(self: Box<Self>, lines: ProofLines) -> Result<ProofLines, PBarberError> {
+        let annotator = Annotator::new(&self.0)?;
+        Ok(Box::new(lines.flat_map(move |line| match line {
+            Ok(line) => {
+                let mut annotated = annotator.comments(&line);
+                annotated.push(line);
+                annotated.into_iter().map(Ok).collect()
+            }
+            Err(err) => vec![Err(err)],
+        })))
+    }
+}
+
+/// The error of an earlier pass that was wrapped in an I/O error to get it through a pass.
+#[cfg(feature = "justify")]
+fn unwrap_passed_error(err: PBarberError) -> PBarberError {
+    match err {
+        PBarberError::Io(err) => err.downcast().unwrap_or_else(PBarberError::Io),
+        err => err,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    const PROOF: &str = "\
+pseudo-Boolean proof version 2.0
+f 2 ;
+@c1 pol 1 2 + ;
+@c2 pol @c1 2 + ;
+del id 1 2 @c1 ;
+@c3 pol @c2 @c2 + ;
+output NONE ;
+conclusion UNSAT : @c3 ;
+end pseudo-Boolean proof
+";
+
+    fn input_stats(proof: String, reversed_input: bool) -> ProofFileStats {
+        let mut out = Vec::new();
+        let mut stats = Pipeline::new()
+            .stats(true)
+            .reversed_input(reversed_input)
+            .run(Cursor::new(proof), &mut out)
+            .unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), PROOF);
+        assert_eq!(stats.len(), 1);
+        stats.remove(0).stats
+    }
+
+    #[test]
+    fn reversed_input_has_the_same_stats() {
+        let reversed: String = PROOF
+            .lines()
+            .rev()
+            .map(|line| format!("{line}\n"))
+            .collect();
+
+        let forwards = input_stats(PROOF.to_string(), false);
+        let backwards = input_stats(reversed, true);
+
+        // Two formula constraints, then two derived before three are deleted
+        assert_eq!(backwards.peak_live_constraints, 4);
+        assert_eq!(backwards.live_area, forwards.live_area);
+        assert_eq!(backwards.total_lines, 9);
+        assert_eq!(backwards.del_lines, 1);
+        assert_eq!(backwards.lines_by_rule, forwards.lines_by_rule);
+    }
+}
@@ -0,0 +1,153 @@
+use std::{
+    fs::File,
+    io::{self, Write},
+    mem,
+    sync::mpsc::{Receiver, SyncSender, sync_channel},
+    thread,
+};
+
+use crate::{
+    JustifierConfig, PBarberError, ProofFileStats, TrimmerConfig, WarningKind,
+    graph::ProofGraph,
+    justifier::{Justifier, profile::Profile},
+    trimmer::Trimmer,
+};
+
+/// Lines buffered between the trimmer and the justifier.
+const CHANNEL_LINES: usize = 4096;
+
+/// Everything produced by a fused trim and style run.
+pub struct TrimAndStyleOutcome {
+    pub trim_stats: Option<(ProofFileStats, ProofFileStats)>,
+    pub style_stats: Option<(ProofFileStats, ProofFileStats)>,
+    pub warnings: Vec<(WarningKind, String)>,
+    pub formula_core: Option<Vec<String>>,
+    pub graph: Option<ProofGraph>,
+    pub profile: Option<Profile>,
+}
+
+/// Trim a proof and justify its assertions in one go, so the only file written is the final
+/// output. The trimmer runs on its own thread, but only knows which lines are kept once its
+/// reverse scan is done: until then the trimmed proof is buffered in memory (as the input
+/// offsets of the kept lines, and the text of those the trimmer wrote), and the justifier
+/// waits. The kept lines are then read back and streamed into the justifier.
+///
+/// Options that need the whole trimmed proof on disk (several passes, depth limits, output
+/// budgets) aren't supported, see [`TrimmerConfig::can_stream`].
+pub fn trim_and_style<W: Write>(
+    input: File,
+    out: W,
+    trimmer_config: TrimmerConfig,
+    justifier_config: JustifierConfig,
+) -> Result<TrimAndStyleOutcome, PBarberError> {
+    if !trimmer_config.can_stream() {
+        return Err(PBarberError::Internal(
+            "trimmer options need the trimmed proof on disk, so it can't be streamed".to_string(),
+        ));
+    }
+    let trimmer_config = TrimmerConfig {
+        reverse_output: false,
+        ..trimmer_config
+    };
+    let justifier_config = JustifierConfig {
+        read_forwards: true,
+        ..justifier_config
+    };
+
+    let (sender, receiver) = sync_channel(CHANNEL_LINES);
+    let mut trimmer = Trimmer::from_file(input, LineSender::new(sender), trimmer_config)?;
+    let mut justifier = Justifier::try_from_lines(received_lines(receiver), out, justifier_config)?;
+    let trimmer_thread = thread::spawn(move || {
+        let trim_stats = trimmer.trim()?;
+        let mut warnings = Vec::new();
+        trimmer.report_warnings(&mut warnings)?;
+        // The trimmer is dropped on return, which ends the justifier's input
+        Ok::<_, PBarberError>(TrimAndStyleOutcome {
+            trim_stats,
+            style_stats: None,
+            warnings,
+            formula_core: trimmer.formula_core(),
+            graph: trimmer.graph().cloned(),
+            profile: None,
+        })
+    });
+
+    let style_result = justifier.style();
+    let profile = justifier.profile().cloned();
+    // Stop the trimmer if the justifier gave up early
+    drop(justifier);
+    let trim_result = trimmer_thread
+        .join()
+        .map_err(|_| PBarberError::Internal("trimmer thread panicked".to_string()))?;
+
+    // A trimmer failure cuts the justifier's input short, so it's the error to report,
+    // unless the trimmer only failed because the justifier stopped reading
+    let mut outcome = match (trim_result, style_result) {
+        (Err(PBarberError::Io(e)), Err(style_err)) if e.kind() == io::ErrorKind::BrokenPipe => {
+            return Err(style_err);
+        }
+        (trim_result, style_result) => {
+            let mut outcome = trim_result?;
+            outcome.style_stats = style_result?;
+            outcome
+        }
+    };
+    outcome.profile = profile;
+    Ok(outcome)
+}
+
+fn received_lines(receiver: Receiver<String>) -> impl Iterator<Item = io::Result<String>> {
+    receiver.into_iter().map(Ok)
+}
+
+/// Writer that sends each complete line written to it down a channel.
+struct LineSender {
+    sender: SyncSender<String>,
+    partial: Vec<u8>,
+}
+
+impl LineSender {
+    fn new(sender: SyncSender<String>) -> Self {
+        Self {
+            sender,
+            partial: Vec::new(),
+        }
+    }
+
+    fn send(&mut self, line: Vec<u8>) -> io::Result<()> {
+        let line =
+            String::from_utf8(line).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        self.sender
+            .send(line)
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "justifier stopped reading"))
+    }
+}
+
+impl Write for LineSender {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        for chunk in buf.split_inclusive(|b| *b == b'\n') {
+            match chunk.strip_suffix(b"\n") {
+                Some(end) => {
+                    self.partial.extend_from_slice(end);
+                    let line = mem::take(&mut self.partial);
+                    self.send(line)?;
+                }
+                None => self.partial.extend_from_slice(chunk),
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for LineSender {
+    fn drop(&mut self) {
+        if !self.partial.is_empty() {
+            let line = mem::take(&mut self.partial);
+            let _ = self.send(line);
+        }
+    }
+}
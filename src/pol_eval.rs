@@ -0,0 +1,385 @@
+use std::{collections::BTreeMap, fmt};
+
+/// A pseudo-Boolean constraint `sum coeff * var >= degree`, with coefficients stored on the
+/// positive literal of each variable. Negated literals are folded in, so the normalised form
+/// is only produced when it's needed (for division, saturation, weakening and display).
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub(crate) struct Constraint {
+    coeffs: BTreeMap<String, i128>,
+    degree: i128,
+}
+
+/// A term of a pol (reverse polish) derivation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PolToken<'a> {
+    /// Reference to a previously derived (or formula) constraint
+    Constraint(&'a str),
+    /// Scalar operand of a multiplication or division
+    Scalar(i128),
+    /// Literal, used as an axiom `lit >= 0` or as the operand of a weakening
+    Literal(&'a str),
+    Add,
+    Multiply,
+    Divide,
+    Saturate,
+    Weaken,
+}
+
+impl Constraint {
+    /// Parse the constraint part of a proof line, e.g. `1 x1 +2 ~x2 >= 1` or the reified
+    /// `x3 ==> 1 x1 >= 1` used by literal definitions.
+    pub(crate) fn parse(text: &str) -> Option<Self> {
+        let tokens: Vec<&str> = text
+            .split_whitespace()
+            .map(|t| t.trim_end_matches(';'))
+            .filter(|t| !t.is_empty())
+            .collect();
+
+        if tokens.get(1) == Some(&"==>") {
+            let mut constraint = Self::parse_tokens(&tokens[2..])?;
+            constraint.reify(tokens[0]);
+            return Some(constraint);
+        }
+        Self::parse_tokens(&tokens)
+    }
+
+    fn parse_tokens(tokens: &[&str]) -> Option<Self> {
+        let mut constraint = Self::default();
+        let mut coeff = None;
+        let mut tokens = tokens.iter();
+        for token in tokens.by_ref() {
+            match *token {
+                "+" => continue,
+                ">=" | "<=" => {
+                    let degree = tokens.next()?.parse::<i128>().ok()?;
+                    constraint.degree = constraint.degree.checked_add(degree)?;
+                    if *token == "<=" {
+                        constraint.multiply(-1)?;
+                    }
+                    return constraint.bounded().map(|_| constraint);
+                }
+                _ => {
+                    if let Ok(c) = token.parse::<i128>() {
+                        coeff = Some(c);
+                    } else {
+                        constraint.add_literal(token, coeff.take().unwrap_or(1));
+                    }
+                }
+            }
+        }
+        // No relation, or an equality (which is really two constraints)
+        None
+    }
+
+    /// The axiom `lit >= 0`.
+    pub(crate) fn axiom(lit: &str) -> Self {
+        let mut constraint = Self::default();
+        constraint.add_literal(lit, 1);
+        constraint
+    }
+
+    fn add_literal(&mut self, lit: &str, coeff: i128) {
+        match lit.strip_prefix('~') {
+            // c ~x = c - c x
+            Some(var) => {
+                *self.coeffs.entry(var.to_string()).or_insert(0) -= coeff;
+                self.degree -= coeff;
+            }
+            None => *self.coeffs.entry(lit.to_string()).or_insert(0) += coeff,
+        }
+    }
+
+    /// `lit ==> C` is `C + degree * ~lit`.
+    fn reify(&mut self, lit: &str) {
+        let degree = self.normalised().1;
+        if degree <= 0 {
+            return;
+        }
+        let negated = match lit.strip_prefix('~') {
+            Some(var) => var.to_string(),
+            None => format!("~{lit}"),
+        };
+        self.add_literal(&negated, degree);
+    }
+
+    /// Terms as `(variable, coefficient, negated)` with positive coefficients, and the degree.
+    pub(crate) fn normalised(&self) -> (Vec<(&str, i128, bool)>, i128) {
+        let mut degree = self.degree;
+        let mut terms = Vec::with_capacity(self.coeffs.len());
+        for (var, coeff) in &self.coeffs {
+            match coeff.signum() {
+                1 => terms.push((var.as_str(), *coeff, false)),
+                -1 => {
+                    // -c x = c ~x - c
+                    degree -= coeff;
+                    terms.push((var.as_str(), -coeff, true));
+                }
+                _ => (),
+            }
+        }
+        (terms, degree)
+    }
+
+    fn from_normalised(terms: Vec<(&str, i128, bool)>, degree: i128) -> Self {
+        let mut constraint = Self {
+            coeffs: BTreeMap::new(),
+            degree,
+        };
+        for (var, coeff, negated) in terms {
+            if negated {
+                constraint.add_literal(&format!("~{var}"), coeff);
+            } else {
+                constraint.add_literal(var, coeff);
+            }
+        }
+        constraint
+    }
+
+    /// Returns `None` on overflow.
+    pub(crate) fn add(&mut self, other: &Constraint) -> Option<()> {
+        for (var, coeff) in &other.coeffs {
+            let sum = self.coeffs.entry(var.clone()).or_insert(0);
+            *sum = sum.checked_add(*coeff)?;
+        }
+        self.degree = self.degree.checked_add(other.degree)?;
+        self.coeffs.retain(|_, c| *c != 0);
+        self.bounded()
+    }
+
+    /// Returns `None` on overflow.
+    pub(crate) fn multiply(&mut self, factor: i128) -> Option<()> {
+        for coeff in self.coeffs.values_mut() {
+            *coeff = coeff.checked_mul(factor)?;
+        }
+        self.degree = self.degree.checked_mul(factor)?;
+        self.bounded()
+    }
+
+    /// Whether the coefficients and degree are small enough to normalise without overflow.
+    fn bounded(&self) -> Option<()> {
+        self.coeffs
+            .values()
+            .try_fold(self.degree.checked_abs()?, |total, coeff| {
+                total.checked_add(coeff.checked_abs()?)
+            })
+            .map(|_| ())
+    }
+
+    pub(crate) fn divide(&mut self, divisor: i128) {
+        let (terms, degree) = self.normalised();
+        let terms = terms
+            .into_iter()
+            .map(|(var, coeff, negated)| (var, ceil_div(coeff, divisor), negated))
+            .collect();
+        *self = Self::from_normalised(terms, ceil_div(degree, divisor));
+    }
+
+    pub(crate) fn saturate(&mut self) {
+        let (terms, degree) = self.normalised();
+        let terms = terms
+            .into_iter()
+            .map(|(var, coeff, negated)| (var, coeff.min(degree.max(0)), negated))
+            .filter(|(_, coeff, _)| *coeff > 0)
+            .collect();
+        *self = Self::from_normalised(terms, degree);
+    }
+
+    pub(crate) fn weaken(&mut self, var: &str) {
+        let var = var.trim_start_matches('~');
+        let (terms, mut degree) = self.normalised();
+        let terms = terms
+            .into_iter()
+            .filter(|(v, coeff, _)| {
+                if *v == var {
+                    degree -= coeff;
+                    false
+                } else {
+                    true
+                }
+            })
+            .collect();
+        *self = Self::from_normalised(terms, degree);
+    }
+}
+
+impl fmt::Display for Constraint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (terms, degree) = self.normalised();
+        for (var, coeff, negated) in terms {
+            write!(f, "{} {}{} ", coeff, if negated { "~" } else { "" }, var)?;
+        }
+        write!(f, ">= {}", degree)
+    }
+}
+
+fn ceil_div(n: i128, d: i128) -> i128 {
+    n.div_euclid(d) + if n.rem_euclid(d) > 0 { 1 } else { 0 }
+}
+
+/// Split the terms of a pol line (everything after the rule) into tokens. A number is a
+/// constraint reference unless it's the operand of a following `*` or `d`.
+pub(crate) fn parse_pol<'a>(terms: impl IntoIterator<Item = &'a str>) -> Vec<PolToken<'a>> {
+    let mut tokens = Vec::new();
+    for term in terms {
+        let term = term.trim_end_matches(';');
+        let token = match term {
+            "" => continue,
+            "+" => PolToken::Add,
+            "*" | "d" => {
+                // A number right before `*` or `d` is its scalar operand, not a reference
+                let scalar = match tokens.last() {
+                    Some(PolToken::Constraint(operand)) => operand.parse::<i128>().ok(),
+                    _ => None,
+                };
+                if let Some(n) = scalar {
+                    tokens.pop();
+                    tokens.push(PolToken::Scalar(n));
+                }
+                if term == "*" {
+                    PolToken::Multiply
+                } else {
+                    PolToken::Divide
+                }
+            }
+            "s" => PolToken::Saturate,
+            "w" => PolToken::Weaken,
+            _ if term.starts_with('@') => PolToken::Constraint(term),
+            _ => match term.parse::<i128>() {
+                Ok(_) => PolToken::Constraint(term),
+                Err(_) => PolToken::Literal(term),
+            },
+        };
+        tokens.push(token);
+    }
+    tokens
+}
+
+/// Whether the `i`th pol term is the scalar operand of a following `*` or `d`, rather than a
+/// numeric constraint reference.
+pub(crate) fn is_scalar(terms: &[&str], i: usize) -> bool {
+    let next = terms[i + 1..]
+        .iter()
+        .map(|t| t.trim_end_matches(';'))
+        .find(|t| !t.is_empty());
+    terms[i].trim_end_matches(';').parse::<i128>().is_ok() && matches!(next, Some("*" | "d"))
+}
+
+/// Evaluate a pol derivation, looking up referenced constraints with `lookup`. Returns `None`
+/// if a referenced constraint is unknown or the derivation is malformed.
+pub(crate) fn evaluate_pol<'c>(
+    tokens: &[PolToken<'_>],
+    lookup: impl Fn(&str) -> Option<&'c Constraint>,
+) -> Option<Constraint> {
+    enum Item<'b> {
+        Constraint(Constraint),
+        Scalar(i128),
+        Literal(&'b str),
+    }
+
+    fn pop_constraint(stack: &mut Vec<Item<'_>>) -> Option<Constraint> {
+        match stack.pop()? {
+            Item::Constraint(c) => Some(c),
+            Item::Literal(lit) => Some(Constraint::axiom(lit)),
+            Item::Scalar(_) => None,
+        }
+    }
+
+    let mut stack = Vec::<Item>::new();
+    for token in tokens {
+        match token {
+            PolToken::Constraint(id) => stack.push(Item::Constraint(lookup(id)?.clone())),
+            PolToken::Scalar(n) => stack.push(Item::Scalar(*n)),
+            PolToken::Literal(lit) => stack.push(Item::Literal(lit)),
+            PolToken::Add => {
+                let rhs = pop_constraint(&mut stack)?;
+                let mut lhs = pop_constraint(&mut stack)?;
+                lhs.add(&rhs)?;
+                stack.push(Item::Constraint(lhs));
+            }
+            PolToken::Multiply | PolToken::Divide => {
+                let Item::Scalar(n) = stack.pop()? else {
+                    return None;
+                };
+                let mut c = pop_constraint(&mut stack)?;
+                if *token == PolToken::Multiply {
+                    c.multiply(n)?;
+                } else if n > 0 {
+                    c.divide(n);
+                } else {
+                    return None;
+                }
+                stack.push(Item::Constraint(c));
+            }
+            PolToken::Saturate => {
+                let mut c = pop_constraint(&mut stack)?;
+                c.saturate();
+                stack.push(Item::Constraint(c));
+            }
+            PolToken::Weaken => {
+                let Item::Literal(lit) = stack.pop()? else {
+                    return None;
+                };
+                let mut c = pop_constraint(&mut stack)?;
+                c.weaken(lit);
+                stack.push(Item::Constraint(c));
+            }
+        }
+    }
+    let result = pop_constraint(&mut stack)?;
+    stack.is_empty().then_some(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    fn constraint(text: &str) -> Constraint {
+        Constraint::parse(text).unwrap()
+    }
+
+    fn evaluate(pol: &str, constraints: &HashMap<&str, Constraint>) -> Option<String> {
+        let tokens = parse_pol(pol.split_whitespace());
+        evaluate_pol(&tokens, |id| constraints.get(id)).map(|c| c.to_string())
+    }
+
+    #[test]
+    fn parses_negated_literals_and_upper_bounds() {
+        assert_eq!(constraint("1 x1 2 ~x2 >= 2").to_string(), "1 x1 2 ~x2 >= 2");
+        assert_eq!(constraint("1 x1 1 x2 <= 1").to_string(), "1 ~x1 1 ~x2 >= 1");
+        assert_eq!(
+            constraint("x3 ==> 1 x1 >= 1").to_string(),
+            "1 x1 1 ~x3 >= 1"
+        );
+        assert_eq!(Constraint::parse("1 x1 = 1"), None);
+    }
+
+    #[test]
+    fn evaluates_derivations() {
+        let constraints = HashMap::from([
+            ("1", constraint("2 x1 1 x2 >= 2")),
+            ("@c", constraint("1 ~x1 1 x3 >= 1")),
+        ]);
+        assert_eq!(
+            evaluate("1 @c 2 * +", &constraints).unwrap(),
+            "1 x2 2 x3 >= 2"
+        );
+        assert_eq!(evaluate("1 2 d", &constraints).unwrap(), "1 x1 1 x2 >= 1");
+        assert_eq!(evaluate("1 s", &constraints).unwrap(), "2 x1 1 x2 >= 2");
+        assert_eq!(evaluate("1 x2 w", &constraints).unwrap(), "2 x1 >= 1");
+        assert_eq!(
+            evaluate("1 x4 +", &constraints).unwrap(),
+            "2 x1 1 x2 1 x4 >= 2"
+        );
+    }
+
+    #[test]
+    fn fails_on_unknown_or_malformed_derivations() {
+        let constraints = HashMap::from([("1", constraint("1 x1 >= 1"))]);
+        assert_eq!(evaluate("2", &constraints), None);
+        assert_eq!(evaluate("1 +", &constraints), None);
+        assert_eq!(evaluate("1 1", &constraints), None);
+        assert_eq!(evaluate("1 0 d", &constraints), None);
+    }
+}
@@ -0,0 +1,413 @@
+use std::ops::Range;
+
+use crate::{DERIVING_RULES, PBarberError, pol_eval::parse_pol};
+
+pub use crate::pol_eval::PolToken;
+pub use format::{IdStyle, LineFormat};
+
+mod format;
+
+/// A line of a VeriPB proof, borrowing its parts from the line text. Debug markers
+/// (`#<level>`) should be split off with the line before it's parsed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProofLine<'a> {
+    /// `pseudo-Boolean proof version <version>`
+    Header {
+        version: &'a str,
+    },
+    /// `f <constraints> ;`
+    Formula {
+        constraints: Option<u64>,
+    },
+    /// `pol`/`p` derivation, in reverse polish notation
+    Pol {
+        label: Option<&'a str>,
+        terms: Vec<PolToken<'a>>,
+    },
+    /// `a <constraint> : <antecedents> : <justifier> ;`, an assertion to be justified
+    Assertion {
+        label: Option<&'a str>,
+        constraint: &'a str,
+        antecedents: Option<&'a str>,
+        justifier: Option<&'a str>,
+    },
+    /// `rup`/`u <constraint> ; <hints> ;`
+    Rup {
+        label: Option<&'a str>,
+        constraint: &'a str,
+        hints: Vec<&'a str>,
+    },
+    /// `red <constraint> : <witness> ;`, e.g. a literal definition
+    Red {
+        label: Option<&'a str>,
+        constraint: &'a str,
+        witness: Option<&'a str>,
+    },
+    /// `ia`/`i <constraint> : <antecedent> ;`
+    Ia {
+        label: Option<&'a str>,
+        constraint: &'a str,
+        antecedent: Option<&'a str>,
+    },
+    Del(Deletion<'a>),
+    /// `core id <ids> ;`
+    Core {
+        ids: Vec<&'a str>,
+    },
+    /// `sol`, `soli` or `solx` with the literals of the solution
+    Solution {
+        label: Option<&'a str>,
+        rule: &'a str,
+        literals: Vec<&'a str>,
+    },
+    /// `output <kind>`
+    Output {
+        kind: &'a str,
+    },
+    /// `conclusion <kind> : <id>`, the ID of the contradiction for `UNSAT`
+    Conclusion {
+        kind: &'a str,
+        id: Option<&'a str>,
+    },
+    /// `end pseudo-Boolean proof`
+    End,
+    /// `% <text>`
+    Comment(&'a str),
+    Blank,
+    /// Any other rule, which the tools don't look into
+    Other {
+        label: Option<&'a str>,
+        rule: &'a str,
+        body: &'a str,
+    },
+}
+
+/// Constraints removed by a `del` line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Deletion<'a> {
+    Ids(Vec<&'a str>),
+    Range(Range<u64>),
+    /// `del spec`/`del find` identify the constraint by its contents rather than its ID, so
+    /// the trimmer can't tie them to a derivation. Dropping a deletion is always safe.
+    Spec {
+        kind: &'a str,
+        constraint: &'a str,
+    },
+}
+
+impl<'a> ProofLine<'a> {
+    /// Parse a proof line. Errors are [`PBarberError::MalformedLine`] without a position, see
+    /// [`PBarberError::at`].
+    pub fn parse(line: &'a str) -> Result<Self, PBarberError> {
+        let content = line.trim();
+        if content.is_empty() {
+            return Ok(Self::Blank);
+        }
+        if let Some(comment) = content.strip_prefix('%') {
+            return Ok(Self::Comment(comment.trim()));
+        }
+        if content.starts_with("pseudo-Boolean") {
+            let version = content.split_whitespace().last().unwrap_or_default();
+            return Ok(Self::Header { version });
+        }
+        if content.starts_with("end pseudo-Boolean") {
+            return Ok(Self::End);
+        }
+
+        let (first, rest) = split_token(content);
+        let (label, rule, body) = if first.starts_with('@') {
+            let (rule, body) = split_token(rest);
+            if rule.is_empty() {
+                return Err(malformed(line, "missing a rule after the ID"));
+            }
+            (Some(first), rule, body)
+        } else {
+            (None, first, rest)
+        };
+
+        let parsed = match rule {
+            "pol" | "p" => Self::Pol {
+                label,
+                terms: parse_pol(body.split_whitespace()),
+            },
+            "a" => {
+                let mut parts = body.splitn(4, ':');
+                let constraint = strip_end(parts.next().unwrap_or_default());
+                if constraint.is_empty() {
+                    return Err(malformed(line, "missing the asserted constraint"));
+                }
+                Self::Assertion {
+                    label,
+                    constraint,
+                    antecedents: parts.next().map(str::trim),
+                    justifier: parts.next().map(strip_end),
+                }
+            }
+            "rup" | "u" => {
+                let (constraint, hints) = body.split_once(';').unwrap_or((body, ""));
+                Self::Rup {
+                    label,
+                    constraint: constraint.trim(),
+                    hints: terms(hints),
+                }
+            }
+            "red" => {
+                let (constraint, witness) = match body.split_once(':') {
+                    Some((constraint, witness)) => (constraint, Some(strip_end(witness))),
+                    None => (body, None),
+                };
+                Self::Red {
+                    label,
+                    constraint: strip_end(constraint),
+                    witness,
+                }
+            }
+            "ia" | "i" => {
+                let (constraint, antecedent) = match body.split_once(':') {
+                    Some((constraint, antecedent)) => (constraint, Some(strip_end(antecedent))),
+                    None => (body, None),
+                };
+                Self::Ia {
+                    label,
+                    constraint: strip_end(constraint),
+                    antecedent,
+                }
+            }
+            "sol" | "soli" | "solx" => Self::Solution {
+                label,
+                rule,
+                literals: terms(body),
+            },
+            _ if label.is_some() => Self::Other { label, rule, body },
+            "f" => Self::Formula {
+                constraints: strip_end(body).parse().ok(),
+            },
+            "del" => {
+                Self::Del(parse_deletion(content).map_err(|message| malformed(line, &message))?)
+            }
+            "core" if split_token(body).0 == "id" => Self::Core {
+                ids: terms(split_token(body).1),
+            },
+            "output" => Self::Output {
+                kind: strip_end(body),
+            },
+            "conclusion" => {
+                let (kind, id) = match body.split_once(':') {
+                    Some((kind, id)) => {
+                        let id = id.split(';').next().unwrap_or_default().trim();
+                        (kind.trim(), Some(id))
+                    }
+                    None => (strip_end(body), None),
+                };
+                if kind == "UNSAT" && id.is_none() {
+                    return Err(malformed(line, "missing `:` before the constraint ID"));
+                }
+                Self::Conclusion { kind, id }
+            }
+            _ => Self::Other { label, rule, body },
+        };
+        Ok(parsed)
+    }
+
+    /// The `@` label of the line, if it has one.
+    pub fn label(&self) -> Option<&'a str> {
+        match self {
+            Self::Pol { label, .. }
+            | Self::Assertion { label, .. }
+            | Self::Rup { label, .. }
+            | Self::Red { label, .. }
+            | Self::Ia { label, .. }
+            | Self::Solution { label, .. }
+            | Self::Other { label, .. } => *label,
+            _ => None,
+        }
+    }
+
+    /// The rule of the line, by its long name (`pol` rather than `p`).
+    pub fn rule(&self) -> &'a str {
+        match self {
+            Self::Header { .. } => "pseudo-Boolean",
+            Self::Formula { .. } => "f",
+            Self::Pol { .. } => "pol",
+            Self::Assertion { .. } => "a",
+            Self::Rup { .. } => "rup",
+            Self::Red { .. } => "red",
+            Self::Ia { .. } => "ia",
+            Self::Del(_) => "del",
+            Self::Core { .. } => "core",
+            Self::Solution { rule, .. } | Self::Other { rule, .. } => rule,
+            Self::Output { .. } => "output",
+            Self::Conclusion { .. } => "conclusion",
+            Self::End => "end",
+            Self::Comment(_) => "%",
+            Self::Blank => "",
+        }
+    }
+
+    /// Whether the line derives a new constraint, and so consumes a constraint ID.
+    pub fn derives(&self) -> bool {
+        DERIVING_RULES.contains(&self.rule())
+    }
+}
+
+/// The label of a line, read without parsing the rest of it.
+pub(crate) fn line_label(line: &str) -> Option<&str> {
+    let (first, _) = split_token(line);
+    first.starts_with('@').then_some(first)
+}
+
+/// The rule of a line as written (so `p` rather than `pol`), read without parsing the rest of
+/// it.
+#[cfg(feature = "justify")]
+pub(crate) fn line_rule(line: &str) -> &str {
+    let (first, rest) = split_token(line);
+    if first.starts_with('@') {
+        split_token(rest).0
+    } else {
+        first
+    }
+}
+
+fn parse_deletion(line: &str) -> Result<Deletion<'_>, String> {
+    let mut tokens = terms(line).into_iter().skip(1);
+    match tokens.next() {
+        Some("id") => Ok(Deletion::Ids(tokens.collect())),
+        Some("range") => {
+            let mut bound = || {
+                tokens
+                    .next()
+                    .and_then(|t| t.parse::<u64>().ok())
+                    .ok_or("expected `del range <start> <end>`".to_string())
+            };
+            let start = bound()?;
+            let end = bound()?;
+            Ok(Deletion::Range(start..end))
+        }
+        Some(kind @ ("spec" | "find")) => {
+            let constraint = split_token(split_token(line).1).1;
+            Ok(Deletion::Spec {
+                kind,
+                constraint: strip_end(constraint),
+            })
+        }
+        _ => Err("expected `del id|range|spec|find`".to_string()),
+    }
+}
+
+fn malformed(line: &str, message: &str) -> PBarberError {
+    PBarberError::MalformedLine {
+        position: None,
+        line: line.to_string(),
+        message: message.to_string(),
+    }
+}
+
+/// Split the first whitespace-separated token off `text`.
+fn split_token(text: &str) -> (&str, &str) {
+    let text = text.trim_start();
+    match text.find(char::is_whitespace) {
+        Some(i) => (&text[..i], text[i..].trim_start()),
+        None => (text, ""),
+    }
+}
+
+/// The whitespace-separated tokens of `text`, without `;` terminators.
+fn terms(text: &str) -> Vec<&str> {
+    text.split_whitespace()
+        .map(|t| t.trim_end_matches(';'))
+        .filter(|t| !t.is_empty())
+        .collect()
+}
+
+/// `text` without surrounding whitespace or a trailing `;`.
+fn strip_end(text: &str) -> &str {
+    text.trim().trim_end_matches(';').trim_end()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lines_are_written_back_as_they_were_parsed() {
+        let lines = [
+            "pseudo-Boolean proof version 2.0",
+            "f 3 ;",
+            "@c1 pol 1 2 + 3 * x1 + 2 d s ;",
+            "pol @c1 ~x2 w ;",
+            "@a1 a 1 x1 1 ~x2 >= 1 : c7 : IntLinear ;",
+            "@r rup 1 x1 >= 1 ; 1 @c1 ;",
+            "@lfx red 1 ~x ==> 1 y >= 2 : x -> 1 ;",
+            "ia 1 x1 >= 1 : 2 ;",
+            "del id 1 @c1 ;",
+            "del range 4 9 ;",
+            "core id 1 2 ;",
+            "soli x1 ~x2 ;",
+            "output NONE ;",
+            "conclusion UNSAT : @c1 ;",
+            "end pseudo-Boolean proof",
+            "% a comment",
+        ];
+        for line in lines {
+            let parsed = ProofLine::parse(line).unwrap();
+            let written = parsed.to_string_with(&LineFormat::default());
+            assert_eq!(written, line);
+            assert_eq!(ProofLine::parse(&written).unwrap(), parsed);
+        }
+    }
+
+    #[test]
+    fn parses_the_parts_of_an_assertion() {
+        assert_eq!(
+            ProofLine::parse("@a1 a 1 x1 >= 1 : c7 : IntLinear ;").unwrap(),
+            ProofLine::Assertion {
+                label: Some("@a1"),
+                constraint: "1 x1 >= 1",
+                antecedents: Some("c7"),
+                justifier: Some("IntLinear"),
+            }
+        );
+    }
+
+    #[test]
+    fn numbers_before_a_multiplication_are_scalars() {
+        let ProofLine::Pol { label, terms } = ProofLine::parse("p 2 3 * 4 + ;").unwrap() else {
+            panic!("expected a pol line");
+        };
+        assert_eq!(label, None);
+        assert_eq!(
+            terms,
+            [
+                PolToken::Constraint("2"),
+                PolToken::Scalar(3),
+                PolToken::Multiply,
+                PolToken::Constraint("4"),
+                PolToken::Add,
+            ]
+        );
+    }
+
+    #[test]
+    fn only_derivations_consume_ids() {
+        let derives = |line| ProofLine::parse(line).unwrap().derives();
+        assert!(derives("pol 1 2 + ;"));
+        assert!(derives("u 1 x1 >= 1 ;"));
+        assert!(derives("@a a 1 x1 >= 1 : c1 ;"));
+        assert!(!derives("del id 1 ;"));
+        assert!(!derives("f 2 ;"));
+        assert!(!derives("% pol 1 2 +"));
+    }
+
+    #[test]
+    fn rejects_malformed_lines() {
+        for line in ["@c1", "a ;", "conclusion UNSAT ;", "del everything ;"] {
+            assert!(
+                matches!(
+                    ProofLine::parse(line),
+                    Err(PBarberError::MalformedLine { .. })
+                ),
+                "{line}"
+            );
+        }
+    }
+}
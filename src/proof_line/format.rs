@@ -0,0 +1,297 @@
+use std::{
+    borrow::Cow,
+    fmt::{self, Write},
+};
+
+use super::{Deletion, PolToken, ProofLine};
+
+/// Indent of the continuation lines of a wrapped pol line.
+const CONTINUATION_INDENT: &str = "  ";
+
+/// How [`ProofLine::write`] lays out a line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LineFormat {
+    /// Put a space before `:` and `;` separators (`x >= 1 ;` rather than `x >= 1;`)
+    pub spaced_separators: bool,
+    pub ids: IdStyle,
+    /// Wrap pol lines longer than this many bytes onto indented continuation lines. VeriPB
+    /// reads these, but pbarber itself expects a rule on each line.
+    pub max_width: Option<usize>,
+}
+
+/// How references to constraints are written. Labels are always written as they are.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum IdStyle {
+    /// As in the input
+    #[default]
+    AsWritten,
+    /// Derived constraints referred to by number are written as `@i<n>` labels, so they
+    /// don't depend on the implicit numbering. Formula constraints keep their numbers.
+    Symbolic { formula_constraints: u64 },
+    /// `@i<n>` labels are written as the number `n`
+    Numeric,
+}
+
+impl Default for LineFormat {
+    fn default() -> Self {
+        Self {
+            spaced_separators: true,
+            ids: IdStyle::default(),
+            max_width: None,
+        }
+    }
+}
+
+impl IdStyle {
+    /// Write the constraint reference `id` in this style.
+    pub fn apply<'i>(&self, id: &'i str) -> Cow<'i, str> {
+        match self {
+            IdStyle::AsWritten => Cow::Borrowed(id),
+            IdStyle::Symbolic {
+                formula_constraints,
+            } => match id.parse::<u64>() {
+                Ok(n) if n > *formula_constraints => Cow::Owned(format!("@i{n}")),
+                _ => Cow::Borrowed(id),
+            },
+            IdStyle::Numeric => match id.strip_prefix("@i") {
+                Some(n) if n.parse::<u64>().is_ok() => Cow::Borrowed(n),
+                _ => Cow::Borrowed(id),
+            },
+        }
+    }
+}
+
+impl ProofLine<'_> {
+    /// Write the line, without a newline, laid out as `format` says.
+    pub fn write(&self, out: &mut impl Write, format: &LineFormat) -> fmt::Result {
+        let mut line = LineWriter {
+            out,
+            format,
+            width: 0,
+        };
+        match self {
+            ProofLine::Header { version } => {
+                line.words(["pseudo-Boolean", "proof", "version", version])
+            }
+            ProofLine::Formula { constraints } => {
+                line.word("f")?;
+                if let Some(constraints) = constraints {
+                    line.word(&constraints.to_string())?;
+                }
+                line.end()
+            }
+            ProofLine::Pol { label, terms } => {
+                line.rule(*label, "pol")?;
+                for term in terms {
+                    match term {
+                        PolToken::Constraint(id) => line.wrapped_word(&format.ids.apply(id))?,
+                        PolToken::Scalar(n) => line.wrapped_word(&n.to_string())?,
+                        PolToken::Literal(lit) => line.wrapped_word(lit)?,
+                        PolToken::Add => line.wrapped_word("+")?,
+                        PolToken::Multiply => line.wrapped_word("*")?,
+                        PolToken::Divide => line.wrapped_word("d")?,
+                        PolToken::Saturate => line.wrapped_word("s")?,
+                        PolToken::Weaken => line.wrapped_word("w")?,
+                    }
+                }
+                line.end()
+            }
+            ProofLine::Assertion {
+                label,
+                constraint,
+                antecedents,
+                justifier,
+            } => {
+                line.rule(*label, "a")?;
+                line.words(constraint.split_whitespace())?;
+                if let Some(antecedents) = antecedents {
+                    line.separator(':')?;
+                    line.words(antecedents.split_whitespace())?;
+                }
+                if let Some(justifier) = justifier {
+                    line.separator(':')?;
+                    line.word(justifier)?;
+                }
+                line.end()
+            }
+            ProofLine::Rup {
+                label,
+                constraint,
+                hints,
+            } => {
+                line.rule(*label, "rup")?;
+                line.words(constraint.split_whitespace())?;
+                if !hints.is_empty() {
+                    line.separator(';')?;
+                    line.ids(hints)?;
+                }
+                line.end()
+            }
+            ProofLine::Red {
+                label,
+                constraint,
+                witness,
+            } => {
+                line.rule(*label, "red")?;
+                line.words(constraint.split_whitespace())?;
+                if let Some(witness) = witness {
+                    line.separator(':')?;
+                    line.words(witness.split_whitespace())?;
+                }
+                line.end()
+            }
+            ProofLine::Ia {
+                label,
+                constraint,
+                antecedent,
+            } => {
+                line.rule(*label, "ia")?;
+                line.words(constraint.split_whitespace())?;
+                if let Some(antecedent) = antecedent {
+                    line.separator(':')?;
+                    line.ids(&[antecedent])?;
+                }
+                line.end()
+            }
+            ProofLine::Del(deletion) => {
+                line.word("del")?;
+                match deletion {
+                    Deletion::Ids(ids) => {
+                        line.word("id")?;
+                        line.ids(ids)?;
+                    }
+                    Deletion::Range(range) => {
+                        line.word("range")?;
+                        line.word(&range.start.to_string())?;
+                        line.word(&range.end.to_string())?;
+                    }
+                    Deletion::Spec { kind, constraint } => {
+                        line.word(kind)?;
+                        line.words(constraint.split_whitespace())?;
+                    }
+                }
+                line.end()
+            }
+            ProofLine::Core { ids } => {
+                line.words(["core", "id"])?;
+                line.ids(ids)?;
+                line.end()
+            }
+            ProofLine::Solution {
+                label,
+                rule,
+                literals,
+            } => {
+                line.rule(*label, rule)?;
+                line.words(literals.iter().copied())?;
+                line.end()
+            }
+            ProofLine::Output { kind } => {
+                line.words(["output", kind])?;
+                line.end()
+            }
+            ProofLine::Conclusion { kind, id } => {
+                line.words(["conclusion", kind])?;
+                if let Some(id) = id {
+                    line.separator(':')?;
+                    line.ids(&[id])?;
+                }
+                line.end()
+            }
+            ProofLine::End => line.words(["end", "pseudo-Boolean", "proof"]),
+            ProofLine::Comment(text) => {
+                line.word("%")?;
+                if text.is_empty() {
+                    return Ok(());
+                }
+                line.word(text)
+            }
+            ProofLine::Blank => Ok(()),
+            ProofLine::Other { label, rule, body } => {
+                line.rule(*label, rule)?;
+                line.words(body.split_whitespace())
+            }
+        }
+    }
+
+    /// The line laid out as `format` says.
+    pub fn to_string_with(&self, format: &LineFormat) -> String {
+        let mut text = String::new();
+        // Writing to a string can't fail
+        let _ = self.write(&mut text, format);
+        text
+    }
+}
+
+impl fmt::Display for ProofLine<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write(f, &LineFormat::default())
+    }
+}
+
+/// Writes the words of a line separated by single spaces, keeping track of its width.
+struct LineWriter<'w, W> {
+    out: &'w mut W,
+    format: &'w LineFormat,
+    width: usize,
+}
+
+impl<W: Write> LineWriter<'_, W> {
+    fn word(&mut self, word: &str) -> fmt::Result {
+        if self.width > 0 {
+            self.out.write_char(' ')?;
+            self.width += 1;
+        }
+        self.out.write_str(word)?;
+        self.width += word.len();
+        Ok(())
+    }
+
+    fn words<'a>(&mut self, words: impl IntoIterator<Item = &'a str>) -> fmt::Result {
+        words.into_iter().try_for_each(|word| self.word(word))
+    }
+
+    /// Write a word, first starting a continuation line if it would go past the width limit.
+    fn wrapped_word(&mut self, word: &str) -> fmt::Result {
+        let too_wide = self
+            .format
+            .max_width
+            .is_some_and(|max_width| self.width + 1 + word.len() > max_width);
+        if too_wide && self.width > CONTINUATION_INDENT.len() {
+            self.out.write_char('\n')?;
+            self.out.write_str(CONTINUATION_INDENT)?;
+            self.out.write_str(word)?;
+            self.width = CONTINUATION_INDENT.len() + word.len();
+            return Ok(());
+        }
+        self.word(word)
+    }
+
+    fn ids(&mut self, ids: &[&str]) -> fmt::Result {
+        for id in ids {
+            self.word(&self.format.ids.apply(id))?;
+        }
+        Ok(())
+    }
+
+    fn rule(&mut self, label: Option<&str>, rule: &str) -> fmt::Result {
+        if let Some(label) = label {
+            self.word(label)?;
+        }
+        self.word(rule)
+    }
+
+    fn separator(&mut self, separator: char) -> fmt::Result {
+        if self.format.spaced_separators {
+            self.out.write_str(" ")?;
+            self.width += 1;
+        }
+        self.out.write_char(separator)?;
+        self.width += 1;
+        Ok(())
+    }
+
+    fn end(&mut self) -> fmt::Result {
+        self.separator(';')
+    }
+}
@@ -0,0 +1,227 @@
+use std::{cmp::Reverse, collections::HashMap};
+
+use crate::pol_eval::Constraint;
+
+/// A normalised constraint: literals with positive coefficients, largest first.
+#[derive(Debug)]
+struct Normalised {
+    terms: Vec<(u32, i128)>,
+    degree: i128,
+}
+
+/// Unit propagation over pseudo-Boolean constraints, to check whether a constraint is
+/// reverse unit propagation (RUP) with respect to the ones added so far. Literals are
+/// numbered as `2 * var + negated`. Each constraint's slack (the total coefficient of its
+/// literals that aren't false, less its degree) is kept up to date as literals are assigned:
+/// a negative slack is a conflict, and a literal with a coefficient above the slack has to
+/// be true.
+#[derive(Debug, Default)]
+pub(crate) struct Propagator {
+    vars: HashMap<String, u32>,
+    constraints: Vec<Normalised>,
+    slacks: Vec<i128>,
+    /// Constraints containing each literal, with its coefficient there
+    occurrences: Vec<Vec<(u32, i128)>>,
+    /// Value of each variable, if assigned
+    values: Vec<Option<bool>>,
+    /// Literals made true, in order
+    trail: Vec<u32>,
+    /// Trail literals whose effect on the slacks has been applied
+    propagated: usize,
+    /// Whether the added constraints propagate to a conflict on their own
+    contradiction: bool,
+}
+
+impl Propagator {
+    fn var(&mut self, name: &str) -> u32 {
+        if let Some(&var) = self.vars.get(name) {
+            return var;
+        }
+        let var = self.values.len() as u32;
+        self.vars.insert(name.to_string(), var);
+        self.values.push(None);
+        self.occurrences.extend([Vec::new(), Vec::new()]);
+        var
+    }
+
+    fn normalise(&mut self, constraint: &Constraint, negate: bool) -> Normalised {
+        let (terms, degree) = constraint.normalised();
+        let mut terms: Vec<(u32, i128)> = terms
+            .into_iter()
+            .map(|(var, coeff, negated)| (self.var(var) * 2 + u32::from(negated != negate), coeff))
+            .collect();
+        terms.sort_unstable_by_key(|&(_, coeff)| Reverse(coeff));
+        // not (sum a l >= d) is sum a ~l >= sum a - d + 1
+        let degree = if negate {
+            terms.iter().map(|(_, coeff)| coeff).sum::<i128>() - degree + 1
+        } else {
+            degree
+        };
+        Normalised { terms, degree }
+    }
+
+    fn is_false(&self, lit: u32) -> bool {
+        self.values[(lit / 2) as usize] == Some(lit % 2 == 1)
+    }
+
+    fn is_assigned(&self, lit: u32) -> bool {
+        self.values[(lit / 2) as usize].is_some()
+    }
+
+    /// Add a constraint and propagate. Returns whether there was a conflict.
+    fn push(&mut self, constraint: Normalised) -> bool {
+        let index = self.constraints.len() as u32;
+        let mut slack = -constraint.degree;
+        for &(lit, coeff) in &constraint.terms {
+            self.occurrences[lit as usize].push((index, coeff));
+            if !self.is_false(lit) {
+                slack += coeff;
+            }
+        }
+        self.constraints.push(constraint);
+        self.slacks.push(slack);
+        !self.propagate_constraint(index) || !self.propagate()
+    }
+
+    /// Remove the last constraint, which was added after anything now on the trail.
+    fn pop(&mut self) {
+        let constraint = self.constraints.pop().expect("a constraint to remove");
+        self.slacks.pop();
+        for (lit, _) in constraint.terms {
+            self.occurrences[lit as usize].pop();
+        }
+    }
+
+    /// Assign the literals constraint `index` implies. Returns false on a conflict.
+    fn propagate_constraint(&mut self, index: u32) -> bool {
+        let slack = self.slacks[index as usize];
+        if slack < 0 {
+            return false;
+        }
+        let mut implied = Vec::new();
+        for &(lit, coeff) in &self.constraints[index as usize].terms {
+            if coeff <= slack {
+                break;
+            }
+            if !self.is_assigned(lit) {
+                implied.push(lit);
+            }
+        }
+        for lit in implied {
+            if !self.is_assigned(lit) {
+                self.values[(lit / 2) as usize] = Some(lit % 2 == 0);
+                self.trail.push(lit);
+            }
+        }
+        true
+    }
+
+    /// Apply the trail to the slacks until everything implied is assigned. Returns false on
+    /// a conflict.
+    fn propagate(&mut self) -> bool {
+        while self.propagated < self.trail.len() {
+            let falsified = self.trail[self.propagated] ^ 1;
+            self.propagated += 1;
+            let occurrences = std::mem::take(&mut self.occurrences[falsified as usize]);
+            for &(index, coeff) in &occurrences {
+                self.slacks[index as usize] -= coeff;
+            }
+            let consistent = occurrences
+                .iter()
+                .all(|&(index, _)| self.propagate_constraint(index));
+            self.occurrences[falsified as usize] = occurrences;
+            if !consistent {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Unassign the literals after the first `len` of the trail.
+    fn backtrack(&mut self, len: usize) {
+        for i in (len..self.propagated).rev() {
+            let falsified = self.trail[i] ^ 1;
+            for &(index, coeff) in &self.occurrences[falsified as usize] {
+                self.slacks[index as usize] += coeff;
+            }
+        }
+        for lit in self.trail.drain(len..) {
+            self.values[(lit / 2) as usize] = None;
+        }
+        self.propagated = self.propagated.min(len);
+    }
+
+    /// Add a constraint, and what it implies with the others.
+    pub(crate) fn add(&mut self, constraint: &Constraint) {
+        if self.contradiction {
+            return;
+        }
+        let constraint = self.normalise(constraint, false);
+        if self.push(constraint) {
+            self.contradiction = true;
+        }
+    }
+
+    /// Whether `constraint` is RUP: whether its negation propagates to a conflict with the
+    /// constraints added so far.
+    pub(crate) fn is_rup(&mut self, constraint: &Constraint) -> bool {
+        if self.contradiction {
+            return true;
+        }
+        let trail_len = self.trail.len();
+        let negation = self.normalise(constraint, true);
+        let conflict = self.push(negation);
+        self.backtrack(trail_len);
+        self.pop();
+        conflict
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn propagator(constraints: &[&str]) -> Propagator {
+        let mut propagator = Propagator::default();
+        for constraint in constraints {
+            propagator.add(&Constraint::parse(constraint).unwrap());
+        }
+        propagator
+    }
+
+    fn is_rup(propagator: &mut Propagator, constraint: &str) -> bool {
+        propagator.is_rup(&Constraint::parse(constraint).unwrap())
+    }
+
+    #[test]
+    fn finds_constraints_that_propagate_to_a_conflict() {
+        let mut propagator = propagator(&["1 ~x1 1 x2 >= 1", "1 ~x2 1 x3 >= 1"]);
+        assert!(is_rup(&mut propagator, "1 ~x1 1 x3 >= 1"));
+        assert!(is_rup(&mut propagator, "1 ~x1 1 x2 >= 1"));
+        assert!(!is_rup(&mut propagator, "1 x1 >= 1"));
+        assert!(!is_rup(&mut propagator, "1 ~x3 1 x1 >= 1"));
+    }
+
+    #[test]
+    fn propagates_on_coefficients_above_the_slack() {
+        // Making x1 false leaves x2 and x3 to make up 3, so both are true
+        let mut propagator = propagator(&["3 x1 2 x2 1 x3 >= 3"]);
+        assert!(is_rup(&mut propagator, "1 x1 1 x2 >= 1"));
+        assert!(is_rup(&mut propagator, "1 x1 1 x3 >= 1"));
+        assert!(!is_rup(&mut propagator, "1 x2 >= 1"));
+    }
+
+    #[test]
+    fn checks_leave_no_assignments_behind() {
+        let mut propagator = propagator(&["1 ~x1 1 x2 >= 1"]);
+        assert!(!is_rup(&mut propagator, "1 x2 >= 1"));
+        assert!(!is_rup(&mut propagator, "1 ~x2 >= 1"));
+        assert!(is_rup(&mut propagator, "1 ~x1 1 x2 >= 1"));
+    }
+
+    #[test]
+    fn anything_follows_from_a_contradiction() {
+        let mut propagator = propagator(&["1 x1 >= 1", "1 ~x1 >= 1"]);
+        assert!(is_rup(&mut propagator, "1 x2 >= 1"));
+    }
+}
@@ -0,0 +1,215 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    ffi::CString,
+    fs::File,
+    io::BufReader,
+    path::PathBuf,
+};
+
+use pyo3::{
+    exceptions::{PyException, PyTypeError, PyUserWarning},
+    prelude::*,
+    types::PyDict,
+};
+
+#[cfg(feature = "justify")]
+use crate::{JustifierConfig, justifier::Justifier};
+use crate::{PBarberError, ProofFileStats, TrimmerConfig, trimmer::Trimmer};
+
+use exceptions::PBarberError as PyPBarberError;
+
+mod exceptions {
+    pyo3::create_exception!(
+        pbarber,
+        PBarberError,
+        super::PyException,
+        "Raised when trimming or styling fails."
+    );
+}
+
+/// Stats of a proof, as printed by the command line tool.
+#[pyclass(name = "ProofFileStats", frozen)]
+#[derive(Clone)]
+pub struct PyProofFileStats(ProofFileStats);
+
+#[pymethods]
+impl PyProofFileStats {
+    #[getter]
+    fn total_lines(&self) -> u64 {
+        self.0.total_lines
+    }
+
+    #[getter]
+    fn pol_lines(&self) -> u64 {
+        self.0.pol_lines
+    }
+
+    #[getter]
+    fn red_lines(&self) -> u64 {
+        self.0.red_lines
+    }
+
+    #[getter]
+    fn a_lines(&self) -> u64 {
+        self.0.a_lines
+    }
+
+    #[getter]
+    fn del_lines(&self) -> u64 {
+        self.0.del_lines
+    }
+
+    #[getter]
+    fn unknown_lines(&self) -> u64 {
+        self.0.unknown_lines
+    }
+
+    #[getter]
+    fn peak_live_constraints(&self) -> u64 {
+        self.0.peak_live_constraints
+    }
+
+    #[getter]
+    fn a_lines_by_name(&self) -> HashMap<String, u64> {
+        self.0.a_lines_by_name.clone()
+    }
+
+    #[getter]
+    fn lines_by_rule(&self) -> BTreeMap<String, u64> {
+        self.0.lines_by_rule.clone()
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "ProofFileStats(total_lines={}, pol_lines={}, red_lines={}, a_lines={}, del_lines={})",
+            self.0.total_lines,
+            self.0.pol_lines,
+            self.0.red_lines,
+            self.0.a_lines,
+            self.0.del_lines
+        )
+    }
+}
+
+type StatsPair = Option<(PyProofFileStats, PyProofFileStats)>;
+
+/// Trim the proof at `input_path` into `output_path`. Options are the command line options
+/// with underscores, e.g. `trim(i, o, implicit_ids=True, max_depth=3)`. Returns the input and
+/// output stats if `stats=True`. Warnings are raised as `UserWarning`s once the proof is trimmed.
+#[pyfunction]
+#[pyo3(signature = (input_path, output_path, **options))]
+fn trim(
+    py: Python<'_>,
+    input_path: PathBuf,
+    output_path: PathBuf,
+    options: Option<&Bound<'_, PyDict>>,
+) -> PyResult<StatsPair> {
+    let mut config = TrimmerConfig::default();
+    for (name, value) in options.into_iter().flatten() {
+        match name.extract::<String>()?.as_str() {
+            "stats" => config.stats = value.extract()?,
+            "eager_deletion" => config.eager_deletion = value.extract()?,
+            "lit_deletion" => config.lit_deletion = value.extract()?,
+            "implicit_ids" => config.implicit_ids = value.extract()?,
+            "faithful_deletions" => config.faithful_deletions = value.extract()?,
+            "reverse_output" => config.reverse_output = value.extract()?,
+            "parallel" => config.parallel = value.extract()?,
+            "max_depth" => config.max_depth = value.extract()?,
+            "opb" => config.opb_path = value.extract()?,
+            "deny_warnings" => config.deny_warnings = value.extract()?,
+            "line_map" => config.line_map_path = value.extract()?,
+            name => {
+                return Err(PyTypeError::new_err(format!(
+                    "unknown trim option `{name}`"
+                )));
+            }
+        }
+    }
+    let (stats, warnings) = py
+        .allow_threads(|| {
+            let input = File::open(input_path)?;
+            let output = File::create(output_path)?;
+            let mut trimmer = Trimmer::try_with_config(input, output, config)?;
+            let stats = trimmer.trim()?;
+            let warnings: Vec<_> = trimmer.warnings().map(str::to_string).collect();
+            Ok::<_, PBarberError>((stats, warnings))
+        })
+        .map_err(to_py_err)?;
+    let category = py.get_type::<PyUserWarning>();
+    for warning in warnings {
+        let message = CString::new(warning.replace('\0', " ")).expect("NULs were replaced");
+        PyErr::warn(py, &category, &message, 1)?;
+    }
+    Ok(stats.map(wrap_stats))
+}
+
+/// Justify the assertions in the proof at `input_path` into `output_path`, against the
+/// FlatZinc model (in JSON) at `fzn_path` and literal mapping at `lits_path`. Options are the
+/// command line options with underscores, as for `trim`.
+#[cfg(feature = "justify")]
+#[pyfunction]
+#[pyo3(signature = (input_path, output_path, fzn_path, lits_path, **options))]
+fn style(
+    py: Python<'_>,
+    input_path: PathBuf,
+    output_path: PathBuf,
+    fzn_path: PathBuf,
+    lits_path: PathBuf,
+    options: Option<&Bound<'_, PyDict>>,
+) -> PyResult<StatsPair> {
+    let mut config = JustifierConfig::new(fzn_path, lits_path);
+    for (name, value) in options.into_iter().flatten() {
+        match name.extract::<String>()?.as_str() {
+            "stats" => config.justifier_stats = value.extract()?,
+            "read_forwards" => config.read_forwards = value.extract()?,
+            "strict" => config.strict = value.extract()?,
+            "parallel" => config.parallel = value.extract()?,
+            "lazy_fzn" => config.lazy_fzn = value.extract()?,
+            "max_line_cache" => config.max_line_cache = value.extract()?,
+            "check_encodings" => config.encoding_model_path = value.extract()?,
+            name => {
+                return Err(PyTypeError::new_err(format!(
+                    "unknown style option `{name}`"
+                )));
+            }
+        }
+    }
+    let stats = py.allow_threads(|| {
+        let input = File::open(input_path)?;
+        let output = File::create(output_path)?;
+        Justifier::try_with_config(input, output, config)?.style()
+    });
+    Ok(stats.map_err(to_py_err)?.map(wrap_stats))
+}
+
+/// The stats of the proof at `path`, read forwards.
+#[pyfunction]
+fn stats(py: Python<'_>, path: PathBuf) -> PyResult<PyProofFileStats> {
+    let stats = py.allow_threads(|| {
+        Ok::<_, PBarberError>(ProofFileStats::from_reader(BufReader::new(File::open(
+            path,
+        )?))?)
+    });
+    Ok(PyProofFileStats(stats.map_err(to_py_err)?))
+}
+
+fn wrap_stats(
+    (input, output): (ProofFileStats, ProofFileStats),
+) -> (PyProofFileStats, PyProofFileStats) {
+    (PyProofFileStats(input), PyProofFileStats(output))
+}
+
+fn to_py_err(err: PBarberError) -> PyErr {
+    PyPBarberError::new_err(err.to_string())
+}
+
+#[pymodule]
+fn pbarber(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(trim, m)?)?;
+    #[cfg(feature = "justify")]
+    m.add_function(wrap_pyfunction!(style, m)?)?;
+    m.add_function(wrap_pyfunction!(stats, m)?)?;
+    m.add_class::<PyProofFileStats>()?;
+    m.add("PBarberError", m.py().get_type::<PyPBarberError>())?;
+    Ok(())
+}
@@ -0,0 +1,131 @@
+use std::{
+    collections::HashMap,
+    io::{BufRead, Write},
+};
+
+use crate::PBarberError;
+
+/// Rewrite every constraint ID defined in a proof (read forwards) to a compact sequential ID
+/// (`@1`, `@2`, ...) and update all references to it in later lines. IDs that are referenced
+/// but never defined in the proof (e.g. fzn antecedents) are left untouched.
+///
+/// Returns the number of IDs that were renumbered.
+pub fn renumber<R: BufRead, W: Write>(input: R, mut out: W) -> Result<u64, PBarberError> {
+    let mut renumberer = Renumberer::default();
+    for line in input.lines() {
+        writeln!(out, "{}", renumberer.renumber_line(&line?))?;
+    }
+    Ok(renumberer.renumbered())
+}
+
+/// Renumbers a proof a line at a time, as [`renumber`] does.
+#[derive(Debug, Clone, Default)]
+pub struct Renumberer {
+    new_ids: HashMap<String, String>,
+    next_id: u64,
+}
+
+impl Renumberer {
+    /// Renumber the next line of the proof.
+    pub fn renumber_line(&mut self, line: &str) -> String {
+        let mut renumbered = String::with_capacity(line.len());
+        let mut defined = None;
+
+        for (i, token) in line.split(' ').enumerate() {
+            if i > 0 {
+                renumbered.push(' ');
+            }
+
+            if i == 0 && token.starts_with('@') {
+                // Labelled line: give it the next ID, but only record the mapping once
+                // the rest of the line has been rewritten.
+                self.next_id += 1;
+                let new_id = format!("@{}", self.next_id);
+                renumbered.push_str(&new_id);
+                defined = Some((token.to_string(), new_id));
+                continue;
+            }
+
+            let (id, suffix) = match token.strip_suffix(';') {
+                Some(id) => (id, ";"),
+                None => (token, ""),
+            };
+            match self.new_ids.get(id) {
+                Some(new_id) => {
+                    renumbered.push_str(new_id);
+                    renumbered.push_str(suffix);
+                }
+                None => renumbered.push_str(token),
+            }
+        }
+
+        if let Some((old_id, new_id)) = defined {
+            self.new_ids.insert(old_id, new_id);
+        }
+        renumbered
+    }
+
+    /// The number of IDs renumbered so far.
+    pub fn renumbered(&self) -> u64 {
+        self.next_id
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn renumbered(proof: &str) -> (String, u64) {
+        let mut out = Vec::new();
+        let count = renumber(proof.as_bytes(), &mut out).unwrap();
+        (String::from_utf8(out).unwrap(), count)
+    }
+
+    #[test]
+    fn renumbers_labels_and_the_references_to_them() {
+        let proof = "\
+pseudo-Boolean proof version 2.0
+f 2 ;
+@lbx a 1 x >= 0 : c1 : IntVarDef ;
+@c7 pol 1 @lbx + ;
+@c9 pol @c7 c3 + ;
+conclusion UNSAT : @c9 ;
+";
+        assert_eq!(
+            renumbered(proof),
+            (
+                "\
+pseudo-Boolean proof version 2.0
+f 2 ;
+@1 a 1 x >= 0 : c1 : IntVarDef ;
+@2 pol 1 @1 + ;
+@3 pol @2 c3 + ;
+conclusion UNSAT : @3 ;
+"
+                .to_string(),
+                3
+            )
+        );
+    }
+
+    #[test]
+    fn a_line_can_refer_to_the_label_it_redefines() {
+        let proof = "\
+@c pol 1 2 + ;
+@c pol @c 3 + ;
+del id @c ;
+";
+        assert_eq!(
+            renumbered(proof),
+            (
+                "\
+@1 pol 1 2 + ;
+@2 pol @1 3 + ;
+del id @2 ;
+"
+                .to_string(),
+                2
+            )
+        );
+    }
+}
@@ -0,0 +1,310 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Read, Seek, SeekFrom, Write},
+};
+
+#[cfg(feature = "mmap")]
+use memmap2::Mmap;
+
+use crate::IoBuffers;
+
+/// Bytes of a memory-mapped input whose newlines are indexed at a time.
+#[cfg(feature = "mmap")]
+const INDEX_WINDOW: usize = 1024 * 1024;
+
+/// Iterates over the lines of a seekable input from last to first, yielding each line
+/// together with the byte offset at which it starts in the input. The input is read a block
+/// at a time into a single buffer, which grows to fit lines longer than a block.
+pub(crate) struct ReverseLines<R> {
+    input: R,
+    /// Offset in the input of the first byte in `buf`.
+    pos: u64,
+    /// Bytes that have been read but not yet yielded as lines. Blocks are read in front of
+    /// them.
+    buf: Vec<u8>,
+    block_size: usize,
+    started: bool,
+    done: bool,
+    /// The input mapped into memory, if it's a file read through a map
+    #[cfg(feature = "mmap")]
+    mapped: Option<MappedLines>,
+}
+
+impl<R: Read + Seek> ReverseLines<R> {
+    pub(crate) fn new(input: R) -> Self {
+        Self {
+            input,
+            pos: 0,
+            buf: Vec::new(),
+            block_size: IoBuffers::default().block_size,
+            started: false,
+            done: false,
+            #[cfg(feature = "mmap")]
+            mapped: None,
+        }
+    }
+
+    /// Read the input `block_size` bytes at a time, rather than the default.
+    pub(crate) fn block_size(mut self, block_size: usize) -> Self {
+        self.block_size = block_size.max(1);
+        self
+    }
+
+    pub(crate) fn get_mut(&mut self) -> &mut R {
+        &mut self.input
+    }
+
+    /// Read the block before `buf` in front of it, returning its size.
+    fn read_block(&mut self) -> io::Result<usize> {
+        // Reading at least as much as is left over doubles the buffer on a long line, so
+        // each byte of it is only moved a few times
+        let read_size = (self.block_size.max(self.buf.len()) as u64).min(self.pos) as usize;
+        self.pos -= read_size as u64;
+        self.input.seek(SeekFrom::Start(self.pos))?;
+        let left_over = self.buf.len();
+        self.buf.resize(left_over + read_size, 0);
+        self.buf.copy_within(..left_over, read_size);
+        self.input.read_exact(&mut self.buf[..read_size])?;
+        Ok(read_size)
+    }
+
+    /// Read the next line into `line`, replacing what it held, and return the offset it
+    /// starts at. Reusing `line` saves allocating a string for every line.
+    pub(crate) fn read_line(&mut self, line: &mut String) -> io::Result<Option<u64>> {
+        #[cfg(feature = "mmap")]
+        if let Some(mapped) = self.mapped.as_mut() {
+            let Some((offset, bytes)) = mapped.next_line() else {
+                return Ok(None);
+            };
+            set_line(line, bytes)?;
+            return Ok(Some(offset));
+        }
+        if self.done {
+            return Ok(None);
+        }
+        if !self.started {
+            self.started = true;
+            self.pos = self.input.seek(SeekFrom::End(0))?;
+            if self.pos == 0 {
+                self.done = true;
+                return Ok(None);
+            }
+            self.read_block()?;
+            // A trailing newline doesn't start another line
+            if self.buf.last() == Some(&b'\n') {
+                self.buf.pop();
+            }
+        }
+
+        // Bytes left over from the last line have no newline, so only new blocks are searched
+        let mut unsearched = self.buf.len();
+        loop {
+            if let Some(i) = self.buf[..unsearched].iter().rposition(|b| *b == b'\n') {
+                set_line(line, &self.buf[i + 1..])?;
+                self.buf.truncate(i);
+                return Ok(Some(self.pos + i as u64 + 1));
+            } else if self.pos == 0 {
+                self.done = true;
+                set_line(line, &self.buf)?;
+                self.buf.clear();
+                return Ok(Some(0));
+            }
+            unsearched = self.read_block()?;
+        }
+    }
+}
+
+impl<R: Read + Seek> Iterator for ReverseLines<R> {
+    type Item = io::Result<(u64, String)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut line = String::new();
+        self.read_line(&mut line)
+            .transpose()
+            .map(|offset| offset.map(|offset| (offset, line)))
+    }
+}
+
+impl ReverseLines<File> {
+    /// Read the file through a memory map, saving a copy of each block on large files. Must
+    /// be called before any lines are read.
+    #[cfg(feature = "mmap")]
+    pub(crate) fn mapped(self) -> io::Result<Self> {
+        // SAFETY: the file is only read, and proofs aren't changed while they're processed
+        let map = unsafe { Mmap::map(&self.input)? };
+        Ok(Self {
+            mapped: Some(MappedLines::new(map)),
+            ..self
+        })
+    }
+
+    /// Without the `mmap` feature, the file is read block by block as any other input.
+    #[cfg(not(feature = "mmap"))]
+    pub(crate) fn mapped(self) -> io::Result<Self> {
+        Ok(self)
+    }
+}
+
+/// The lines of a memory-mapped input from last to first, sliced out of the map between the
+/// newlines of a window at a time.
+#[cfg(feature = "mmap")]
+struct MappedLines {
+    map: Mmap,
+    /// End of the last line not yet given, before its newline
+    end: usize,
+    /// Start of the window whose newlines are in `newlines`
+    window_start: usize,
+    /// Offsets of the newlines in the window that haven't been passed yet, in order
+    newlines: Vec<usize>,
+    done: bool,
+}
+
+#[cfg(feature = "mmap")]
+impl MappedLines {
+    fn new(map: Mmap) -> Self {
+        // A trailing newline doesn't start another line
+        let end = map.len() - usize::from(map.last() == Some(&b'\n'));
+        Self {
+            done: map.is_empty(),
+            map,
+            end,
+            window_start: end,
+            newlines: Vec::new(),
+        }
+    }
+
+    fn next_line(&mut self) -> Option<(u64, &[u8])> {
+        loop {
+            if let Some(newline) = self.newlines.pop() {
+                let line = &self.map[newline + 1..self.end];
+                self.end = newline;
+                return Some(((newline + 1) as u64, line));
+            }
+            if self.window_start == 0 {
+                if self.done {
+                    return None;
+                }
+                self.done = true;
+                return Some((0, &self.map[..self.end]));
+            }
+            let window_end = self.window_start;
+            let window_start = window_end.saturating_sub(INDEX_WINDOW);
+            self.newlines.extend(
+                self.map[window_start..window_end]
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, b)| **b == b'\n')
+                    .map(|(i, _)| window_start + i),
+            );
+            self.window_start = window_start;
+        }
+    }
+}
+
+/// Write the lines of `input` to `out` in reverse order, returning the number of lines. Only
+/// a block of the input and the line being reversed are held in memory at a time.
+pub fn reverse<R: Read + Seek, W: Write>(input: R, out: W) -> io::Result<u64> {
+    write_reversed(ReverseLines::new(input), BufWriter::new(out))
+}
+
+/// [`reverse`] for a file, read through a memory map with the `mmap` feature. Lines are
+/// written to `out` one at a time, so it should be buffered.
+pub fn reverse_file<W: Write>(input: File, out: W) -> io::Result<u64> {
+    write_reversed(ReverseLines::new(input).mapped()?, out)
+}
+
+fn write_reversed<R: Read + Seek, W: Write>(
+    reverse_lines: ReverseLines<R>,
+    mut out: W,
+) -> io::Result<u64> {
+    let mut lines = 0;
+    for line in reverse_lines {
+        let (_, line) = line?;
+        writeln!(out, "{line}")?;
+        lines += 1;
+    }
+    out.flush()?;
+    Ok(lines)
+}
+
+/// Replace the contents of `line` with the line in `bytes`.
+pub(crate) fn set_line(line: &mut String, bytes: &[u8]) -> io::Result<()> {
+    let bytes = bytes.strip_suffix(b"\r").unwrap_or(bytes);
+    let text =
+        std::str::from_utf8(bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    line.clear();
+    line.push_str(text);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    /// The lines of `input` as `lines().rev()` gives them.
+    fn expected(input: &str) -> Vec<String> {
+        input.lines().rev().map(str::to_string).collect()
+    }
+
+    fn reversed<R: Read + Seek>(reverse_lines: ReverseLines<R>) -> Vec<String> {
+        reverse_lines.map(|line| line.unwrap().1).collect()
+    }
+
+    #[test]
+    fn lines_are_read_in_reverse_across_blocks() {
+        let inputs = [
+            "",
+            "\n",
+            "one line",
+            "first\nsecond\n",
+            "no trailing newline\nlast",
+            "blank\n\nlines\n\n",
+            "crlf\r\nline\r\nendings\r\n",
+        ];
+        for input in inputs {
+            for block_size in [1, 2, 3, 7, 64] {
+                let lines = ReverseLines::new(Cursor::new(input)).block_size(block_size);
+                assert_eq!(
+                    reversed(lines),
+                    expected(input),
+                    "{input:?} in blocks of {block_size}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn lines_longer_than_a_block_are_read_whole() {
+        let input = format!("short\n{}\nshort\n", "x".repeat(100_000));
+        let lines = ReverseLines::new(Cursor::new(&input)).block_size(16);
+        assert_eq!(reversed(lines), expected(&input));
+    }
+
+    #[test]
+    fn lines_are_given_with_their_offsets() {
+        let offsets: Vec<u64> = ReverseLines::new(Cursor::new("ab\ncde\n\nf"))
+            .block_size(2)
+            .map(|line| line.unwrap().0)
+            .collect();
+        assert_eq!(offsets, [8, 7, 3, 0]);
+    }
+
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn mapped_files_are_read_in_reverse() {
+        // Enough lines for several windows of newlines
+        let mut long = String::new();
+        for i in 0..300_000 {
+            long.push_str(&format!("line {i}\r\n"));
+        }
+        let inputs = ["", "\n", "no trailing newline\nlast", "a\n\nb\n", &long];
+        for input in inputs {
+            let mut file = tempfile::tempfile().unwrap();
+            file.write_all(input.as_bytes()).unwrap();
+            let lines = ReverseLines::new(file).mapped().unwrap();
+            assert_eq!(reversed(lines), expected(input));
+        }
+    }
+}
@@ -0,0 +1,384 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    fs::File,
+    io::{self, BufRead, BufWriter, Seek, Write},
+    path::PathBuf,
+};
+
+use serde::Serialize;
+
+use crate::{
+    DERIVING_RULES, PBarberError, SplitConfig,
+    opb_model::OpbModel,
+    pol_eval::{Constraint, PolToken, evaluate_pol},
+    proof_line::{Deletion, ProofLine},
+    split_debug_marker,
+    trimmer::antecedents_of,
+};
+
+/// Where the chunks of a split proof went, and what each needs from the chunks before it.
+#[derive(Debug, Default, Serialize)]
+pub struct SplitManifest {
+    /// Lines repeated at the start of every chunk after the first
+    pub header: Vec<String>,
+    pub chunks: Vec<ChunkInfo>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ChunkInfo {
+    pub path: PathBuf,
+    /// Range of lines of the original proof in the chunk (1-based, inclusive)
+    pub first_line: u64,
+    pub last_line: u64,
+    /// Implicit ID of the first constraint derived in the chunk
+    pub first_derived_id: u64,
+    /// Derived constraint IDs used in the chunk but derived in an earlier one. Those that are
+    /// labelled and whose constraint can be worked out are restated at the start of the chunk.
+    pub imports: BTreeSet<String>,
+}
+
+/// A chunk being written. Its lines go to a temporary file until it's finished, as the
+/// imports restated before them are only known then.
+struct Chunk {
+    index: usize,
+    info: ChunkInfo,
+    body: BufWriter<File>,
+    /// `a` lines restating the imports, and the imports that can't be restated
+    restated: Vec<String>,
+    unrestated: Vec<String>,
+    lines: u64,
+    bytes: u64,
+}
+
+impl Chunk {
+    fn new(
+        index: usize,
+        path: PathBuf,
+        first_line: u64,
+        first_derived_id: u64,
+    ) -> io::Result<Self> {
+        Ok(Chunk {
+            index,
+            info: ChunkInfo {
+                path,
+                first_line,
+                last_line: first_line,
+                first_derived_id,
+                imports: BTreeSet::new(),
+            },
+            body: BufWriter::new(tempfile::tempfile()?),
+            restated: Vec::new(),
+            unrestated: Vec::new(),
+            lines: 0,
+            bytes: 0,
+        })
+    }
+
+    /// Whether `line` would take the chunk past the size limits.
+    fn is_full(&self, config: &SplitConfig, line: &str) -> bool {
+        config
+            .lines
+            .is_some_and(|max_lines| self.lines >= max_lines)
+            || config
+                .bytes
+                .is_some_and(|max_bytes| self.bytes + line.len() as u64 + 1 > max_bytes)
+    }
+
+    /// Record the use of `id`, restating it if it's from an earlier chunk.
+    fn import(&mut self, id: &str, constraint: Option<&Constraint>) {
+        if !self.info.imports.insert(id.to_string()) {
+            return;
+        }
+        // An implicit ID can't be given back its number, so only labels are restated
+        match constraint.filter(|_| id.starts_with('@')) {
+            Some(constraint) => self.restated.push(format!("{id} a {constraint} ;")),
+            None => self.unrestated.push(id.to_string()),
+        }
+    }
+
+    /// Write out the chunk, starting with `header`.
+    fn finish(self, header: &[String]) -> io::Result<ChunkInfo> {
+        let mut out = BufWriter::new(File::create(&self.info.path)?);
+        for header_line in header {
+            writeln!(out, "{header_line}")?;
+        }
+        if self.index > 0 {
+            writeln!(
+                out,
+                "% pbarber split: chunk {}, from line {}, derived IDs continue from {}",
+                self.index + 1,
+                self.info.first_line,
+                self.info.first_derived_id
+            )?;
+        }
+        if !self.unrestated.is_empty() {
+            writeln!(
+                out,
+                "% pbarber split: uses {} from earlier chunks, which can't be restated",
+                self.unrestated.join(" ")
+            )?;
+        }
+        for line in &self.restated {
+            writeln!(out, "{line}")?;
+        }
+        let mut body = self
+            .body
+            .into_inner()
+            .map_err(io::IntoInnerError::into_error)?;
+        body.rewind()?;
+        io::copy(&mut body, &mut out)?;
+        out.flush()?;
+        Ok(self.info)
+    }
+}
+
+/// Cut a proof into chunks of at most `config.lines` lines or `config.bytes` bytes, each
+/// starting with the proof header and `a` lines restating the constraints it uses from
+/// earlier chunks. Chunks are only cut between top-level steps, so subproofs and the
+/// conclusion are never split. `chunk_path` names the file for each (0-based) chunk.
+pub fn split<R: BufRead>(
+    input: R,
+    config: &SplitConfig,
+    mut chunk_path: impl FnMut(usize) -> PathBuf,
+) -> Result<SplitManifest, PBarberError> {
+    let mut manifest = SplitManifest::default();
+    let mut chunk: Option<Chunk> = None;
+    let mut in_header = true;
+    let mut subproof_depth = 0usize;
+    // Chunk each labelled constraint ID was derived in
+    let mut defined_in = HashMap::<String, usize>::new();
+    // The constraints that can be worked out, by each ID they're known by
+    let mut constraints = HashMap::<String, Constraint>::new();
+    if let Some(opb_path) = &config.opb_path {
+        let model = OpbModel::open(opb_path)
+            .map_err(|err| PBarberError::reading("OPB model", opb_path, err))?;
+        for (i, constraint) in model.constraints().enumerate() {
+            if let Some(constraint) = constraint.parsed() {
+                constraints.insert((i + 1).to_string(), constraint.clone());
+            }
+        }
+    }
+    let mut formula_constraints = 0;
+    let mut derived = 0;
+
+    for (line_index, line) in input.lines().enumerate() {
+        let raw_line = line?;
+        let line_number = line_index as u64 + 1;
+        let line = split_debug_marker(&raw_line).1.trim();
+        let mut tokens = line.split_whitespace();
+        let first = tokens.next().unwrap_or_default();
+
+        if in_header {
+            if first == "f" {
+                formula_constraints = tokens
+                    .next()
+                    .and_then(|n| n.trim_end_matches(';').parse::<u64>().ok())
+                    .unwrap_or(0);
+            }
+            if first == "pseudo-Boolean" || first == "f" || line.is_empty() {
+                manifest.header.push(raw_line);
+                continue;
+            }
+            in_header = false;
+        }
+
+        let can_cut = subproof_depth == 0 && !matches!(first, "output" | "conclusion" | "end");
+        let current = match chunk.take() {
+            Some(current) if !(can_cut && current.is_full(config, &raw_line)) => {
+                chunk.insert(current)
+            }
+            previous => {
+                if let Some(full) = previous {
+                    manifest.chunks.push(full.finish(&manifest.header)?);
+                }
+                let index = manifest.chunks.len();
+                chunk.insert(Chunk::new(
+                    index,
+                    chunk_path(index),
+                    line_number,
+                    formula_constraints + derived + 1,
+                )?)
+            }
+        };
+        writeln!(current.body, "{raw_line}")?;
+        current.lines += 1;
+        current.bytes += raw_line.len() as u64 + 1;
+        current.info.last_line = line_number;
+
+        // Track subproofs so they stay in one chunk
+        if line.ends_with("begin") {
+            subproof_depth += 1;
+        } else if first == "end" && tokens.next() != Some("pseudo-Boolean") {
+            subproof_depth = subproof_depth.saturating_sub(1);
+        }
+
+        let Ok(parsed) = ProofLine::parse(line) else {
+            // Still counted, so the implicit IDs after it are right
+            let rule = if first.starts_with('@') {
+                line.split_whitespace().nth(1).unwrap_or_default()
+            } else {
+                first
+            };
+            if DERIVING_RULES.contains(&rule) {
+                derived += 1;
+            }
+            continue;
+        };
+        // Record the IDs used from earlier chunks
+        let used = match &parsed {
+            ProofLine::Pol { terms, .. } => terms
+                .iter()
+                .filter_map(|token| match token {
+                    PolToken::Constraint(id) => Some(*id),
+                    _ => None,
+                })
+                .collect(),
+            ProofLine::Del(Deletion::Ids(ids)) => ids.clone(),
+            line => antecedents_of(line),
+        };
+        for id in used {
+            let earlier = match defined_in.get(id) {
+                Some(&defined) => defined < current.index,
+                None => id.parse::<u64>().is_ok_and(|number| {
+                    number > formula_constraints && number < current.info.first_derived_id
+                }),
+            };
+            if earlier {
+                current.import(id, constraints.get(id));
+            }
+        }
+        if let ProofLine::Del(Deletion::Ids(ids)) = &parsed {
+            for id in ids {
+                constraints.remove(*id);
+            }
+        }
+        if !parsed.derives() {
+            continue;
+        }
+        derived += 1;
+        let constraint = match &parsed {
+            ProofLine::Pol { terms, .. } => evaluate_pol(terms, |id| constraints.get(id)),
+            ProofLine::Assertion { constraint, .. }
+            | ProofLine::Rup { constraint, .. }
+            | ProofLine::Red { constraint, .. }
+            | ProofLine::Ia { constraint, .. } => Constraint::parse(constraint),
+            _ => None,
+        };
+        let implicit_id = (formula_constraints + derived).to_string();
+        for id in parsed.label().into_iter().chain([implicit_id.as_str()]) {
+            match &constraint {
+                Some(constraint) => constraints.insert(id.to_string(), constraint.clone()),
+                None => constraints.remove(id),
+            };
+        }
+        if let Some(label) = parsed.label() {
+            defined_in.insert(label.to_string(), current.index);
+        }
+    }
+
+    if let Some(last) = chunk {
+        manifest.chunks.push(last.finish(&manifest.header)?);
+    }
+    Ok(manifest)
+}
+
+impl SplitManifest {
+    pub fn write_json<W: Write>(&self, out: W) -> io::Result<()> {
+        serde_json::to_writer_pretty(out, self).map_err(io::Error::other)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn chunks_repeat_the_header_and_import_earlier_ids() {
+        let proof = "\
+pseudo-Boolean proof version 2.0
+f 2 ;
+@c1 pol 1 2 + ;
+@c2 pol @c1 2 + ;
+@c3 pol @c2 @c1 + ;
+output NONE ;
+conclusion UNSAT : @c3 ;
+end pseudo-Boolean proof
+";
+        let dir = tempfile::tempdir().unwrap();
+        let opb_path = dir.path().join("model.opb");
+        fs::write(&opb_path, "1 x1 1 x2 >= 1 ;\n1 x2 >= 1 ;\n").unwrap();
+        let config = SplitConfig {
+            lines: Some(2),
+            opb_path: Some(opb_path),
+            ..SplitConfig::default()
+        };
+        let manifest = split(proof.as_bytes(), &config, |i| {
+            dir.path().join(format!("chunk{i}.pbp"))
+        })
+        .unwrap();
+
+        // The conclusion can't be cut from the rest of the proof
+        assert_eq!(manifest.chunks.len(), 2);
+        let second = &manifest.chunks[1];
+        assert_eq!((second.first_line, second.last_line), (5, 8));
+        assert_eq!(second.first_derived_id, 5);
+        assert_eq!(
+            second.imports,
+            BTreeSet::from(["@c1".to_string(), "@c2".to_string()])
+        );
+        assert_eq!(
+            fs::read_to_string(&second.path).unwrap(),
+            "\
+pseudo-Boolean proof version 2.0
+f 2 ;
+% pbarber split: chunk 2, from line 5, derived IDs continue from 5
+@c2 a 1 x1 3 x2 >= 3 ;
+@c1 a 1 x1 2 x2 >= 2 ;
+@c3 pol @c2 @c1 + ;
+output NONE ;
+conclusion UNSAT : @c3 ;
+end pseudo-Boolean proof
+"
+        );
+    }
+
+    #[test]
+    fn hints_are_imported_even_if_they_cant_be_restated() {
+        let proof = "\
+pseudo-Boolean proof version 2.0
+f 2 ;
+@c1 pol 1 2 + ;
+@c2 rup 1 x1 >= 1 ; @c1 2 ;
+output NONE ;
+conclusion UNSAT : @c2 ;
+end pseudo-Boolean proof
+";
+        let dir = tempfile::tempdir().unwrap();
+        let config = SplitConfig {
+            lines: Some(1),
+            ..SplitConfig::default()
+        };
+        let manifest = split(proof.as_bytes(), &config, |i| {
+            dir.path().join(format!("chunk{i}.pbp"))
+        })
+        .unwrap();
+
+        let second = &manifest.chunks[1];
+        assert_eq!(second.imports, BTreeSet::from(["@c1".to_string()]));
+        assert_eq!(
+            fs::read_to_string(&second.path).unwrap(),
+            "\
+pseudo-Boolean proof version 2.0
+f 2 ;
+% pbarber split: chunk 2, from line 4, derived IDs continue from 4
+% pbarber split: uses @c1 from earlier chunks, which can't be restated
+@c2 rup 1 x1 >= 1 ; @c1 2 ;
+output NONE ;
+conclusion UNSAT : @c2 ;
+end pseudo-Boolean proof
+"
+        );
+    }
+}
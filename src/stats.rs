@@ -0,0 +1,1062 @@
+use crate::{ProofFileStats, StatChange, limits, split_debug_marker};
+use serde::{Deserialize, Serialize, Serializer};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fmt::{self, Write as _},
+    io,
+    time::Duration,
+};
+
+/// Exact counts of the values a quantity took, e.g. the number of antecedents of each
+/// constraint of a proof.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Distribution {
+    counts: BTreeMap<u64, u64>,
+}
+
+impl Distribution {
+    pub fn record(&mut self, value: u64) {
+        *self.counts.entry(value).or_insert(0) += 1;
+    }
+
+    pub fn merge(&mut self, other: &Distribution) {
+        for (value, count) in &other.counts {
+            *self.counts.entry(*value).or_insert(0) += count;
+        }
+    }
+
+    /// The number of values recorded.
+    pub fn count(&self) -> u64 {
+        self.counts.values().sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.counts.is_empty()
+    }
+
+    pub fn sum(&self) -> u64 {
+        self.counts.iter().map(|(value, count)| value * count).sum()
+    }
+
+    /// The mean value, or 0 if there are none.
+    pub fn mean(&self) -> f64 {
+        match self.count() {
+            0 => 0.0,
+            count => self.sum() as f64 / count as f64,
+        }
+    }
+
+    pub fn max(&self) -> u64 {
+        self.counts.keys().next_back().copied().unwrap_or(0)
+    }
+
+    /// The smallest value at least `percent`% of the values are at most, or 0 if there are
+    /// none.
+    pub fn percentile(&self, percent: f64) -> u64 {
+        let rank = (percent / 100.0 * self.count() as f64).ceil().max(1.0) as u64;
+        let mut seen = 0;
+        for (value, count) in &self.counts {
+            seen += count;
+            if seen >= rank {
+                return *value;
+            }
+        }
+        0
+    }
+
+    /// The values recorded here but not in `subset`, which must only have recorded values
+    /// recorded here.
+    pub fn without(&self, subset: &Distribution) -> Distribution {
+        let counts = self
+            .counts
+            .iter()
+            .map(|(value, count)| {
+                let removed = subset.counts.get(value).copied().unwrap_or(0);
+                (*value, count.saturating_sub(removed))
+            })
+            .filter(|(_, count)| *count > 0)
+            .collect();
+        Distribution { counts }
+    }
+
+    /// The values and how many times each was recorded, smallest first.
+    pub fn iter(&self) -> impl Iterator<Item = (u64, u64)> + '_ {
+        self.counts.iter().map(|(value, count)| (*value, *count))
+    }
+}
+
+impl fmt::Display for Distribution {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "mean {:.1}, p50 {}, p90 {}, p99 {}, max {}",
+            self.mean(),
+            self.percentile(50.0),
+            self.percentile(90.0),
+            self.percentile(99.0),
+            self.max()
+        )
+    }
+}
+
+/// Sizes of the lines of a proof, and of the constraints stated by those that state one.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConstraintSizes {
+    /// Literals on the left-hand side
+    pub literals: Distribution,
+    /// Decimal digits of each coefficient and degree, ignoring the sign
+    pub coefficient_digits: Distribution,
+    /// Bytes in each line, without the line break
+    pub line_length: Distribution,
+    /// Operands (constraint IDs, literals and scalars) of each `pol` line
+    #[serde(default)]
+    pub pol_terms: Distribution,
+}
+
+impl ConstraintSizes {
+    pub fn record_line(&mut self, line: &str) {
+        let line = split_debug_marker(line).1;
+        self.line_length.record(line.len() as u64);
+        let mut tokens = line.split_whitespace().peekable();
+        tokens.next_if(|token| token.starts_with('@'));
+        if matches!(tokens.next(), Some("pol" | "p")) {
+            let operators = ["+", "*", "d", "s", "w", ";"];
+            let terms = tokens.filter(|token| !operators.contains(token)).count();
+            self.pol_terms.record(terms as u64);
+            return;
+        }
+        let Some((terms, rest)) = line.split_once(" >= ") else {
+            return;
+        };
+        let mut tokens = terms.split_whitespace().peekable();
+        // Skip the label and rule before the first term
+        tokens.next_if(|token| token.starts_with('@'));
+        tokens.next();
+        let mut literals = 0;
+        for token in tokens {
+            let digits = token.trim_start_matches(['+', '-']);
+            if !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit()) {
+                self.coefficient_digits.record(digits.len() as u64);
+            } else {
+                literals += 1;
+            }
+        }
+        self.literals.record(literals);
+        if let Some(degree) = rest.split_whitespace().next() {
+            let digits = degree.trim_start_matches(['+', '-']);
+            self.coefficient_digits.record(digits.len() as u64);
+        }
+    }
+
+    pub fn merge(&mut self, other: &ConstraintSizes) {
+        self.literals.merge(&other.literals);
+        self.coefficient_digits.merge(&other.coefficient_digits);
+        self.line_length.merge(&other.line_length);
+        self.pol_terms.merge(&other.pol_terms);
+    }
+
+    /// The sizes of the lines recorded here but not in `subset`, which must only have
+    /// recorded lines recorded here.
+    pub fn without(&self, subset: &ConstraintSizes) -> ConstraintSizes {
+        ConstraintSizes {
+            literals: self.literals.without(&subset.literals),
+            coefficient_digits: self.coefficient_digits.without(&subset.coefficient_digits),
+            line_length: self.line_length.without(&subset.line_length),
+            pol_terms: self.pol_terms.without(&subset.pol_terms),
+        }
+    }
+
+    fn distributions(&self) -> [(&'static str, &Distribution); 4] {
+        [
+            ("literals", &self.literals),
+            ("coefficient_digits", &self.coefficient_digits),
+            ("line_length", &self.line_length),
+            ("pol_terms", &self.pol_terms),
+        ]
+    }
+
+    /// The sizes as `<prefix>.<stat> <value>` lines, for `--porcelain` output.
+    pub(crate) fn write_porcelain(&self, f: &mut fmt::Formatter<'_>, prefix: &str) -> fmt::Result {
+        for (name, distribution) in self.distributions() {
+            writeln!(f, "{prefix}.{name}.mean {:.3}", distribution.mean())?;
+            for percentile in [50, 90, 99] {
+                writeln!(
+                    f,
+                    "{prefix}.{name}.p{percentile} {}",
+                    distribution.percentile(f64::from(percentile))
+                )?;
+            }
+            writeln!(f, "{prefix}.{name}.max {}", distribution.max())?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for ConstraintSizes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, " ∟ Literals: {}", self.literals)?;
+        writeln!(f, " ∟ Coefficient digits: {}", self.coefficient_digits)?;
+        writeln!(f, " ∟ Line length: {}", self.line_length)?;
+        if !self.pol_terms.is_empty() {
+            writeln!(f, " ∟ Pol line terms: {}", self.pol_terms)?;
+        }
+        Ok(())
+    }
+}
+
+/// How many examples of the unknown lines with each rule [`UnknownLines`] keeps.
+pub const MAX_UNKNOWN_EXAMPLES: usize = 3;
+
+/// The lines that weren't understood, but were kept or dropped, by their rule.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UnknownLines {
+    pub by_rule: BTreeMap<String, UnknownRule>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UnknownRule {
+    pub kept: u64,
+    pub dropped: u64,
+    /// Up to [`MAX_UNKNOWN_EXAMPLES`] of the lines, in the order they were read
+    pub examples: Vec<String>,
+}
+
+impl UnknownLines {
+    pub fn record(&mut self, rule: &str, line: &str, kept: bool) {
+        let unknown = match self.by_rule.get_mut(rule) {
+            Some(unknown) => unknown,
+            None => self.by_rule.entry(rule.to_string()).or_default(),
+        };
+        if kept {
+            unknown.kept += 1;
+        } else {
+            unknown.dropped += 1;
+        }
+        if unknown.examples.len() < MAX_UNKNOWN_EXAMPLES {
+            unknown.examples.push(line.to_string());
+        }
+    }
+
+    pub fn merge(&mut self, other: &UnknownLines) {
+        for (rule, other_unknown) in &other.by_rule {
+            let unknown = self.by_rule.entry(rule.clone()).or_default();
+            unknown.kept += other_unknown.kept;
+            unknown.dropped += other_unknown.dropped;
+            let room = MAX_UNKNOWN_EXAMPLES.saturating_sub(unknown.examples.len());
+            unknown
+                .examples
+                .extend(other_unknown.examples.iter().take(room).cloned());
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_rule.is_empty()
+    }
+
+    /// The counts as `<prefix>.unknown.<rule>.kept|dropped <value>` lines, for `--porcelain`.
+    pub(crate) fn write_porcelain(&self, f: &mut fmt::Formatter<'_>, prefix: &str) -> fmt::Result {
+        for (rule, unknown) in &self.by_rule {
+            writeln!(f, "{prefix}.unknown.{rule}.kept {}", unknown.kept)?;
+            writeln!(f, "{prefix}.unknown.{rule}.dropped {}", unknown.dropped)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for UnknownLines {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (rule, unknown) in &self.by_rule {
+            writeln!(
+                f,
+                " ∟ `{rule}`: {} kept, {} dropped, e.g.",
+                unknown.kept, unknown.dropped
+            )?;
+            for example in &unknown.examples {
+                writeln!(f, "    {example}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// How many of the heaviest lines [`Heaviest`] keeps.
+pub const HEAVIEST_KEPT: usize = 10;
+
+/// The heaviest lines recorded by some weight, e.g. their number of antecedents, keeping the
+/// [`HEAVIEST_KEPT`] heaviest.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Heaviest {
+    /// Heaviest first
+    lines: Vec<HeavyLine>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HeavyLine {
+    pub id: String,
+    /// The justifier named, for assertions
+    pub name: Option<String>,
+    pub weight: u64,
+}
+
+impl Heaviest {
+    /// Whether a line of `weight` would be kept, so its ID only has to be copied if so.
+    pub fn would_keep(&self, weight: u64) -> bool {
+        weight > 0
+            && (self.lines.len() < HEAVIEST_KEPT
+                || self
+                    .lines
+                    .last()
+                    .is_some_and(|lightest| weight > lightest.weight))
+    }
+
+    pub fn record(&mut self, line: HeavyLine) {
+        if !self.would_keep(line.weight) {
+            return;
+        }
+        let position = self
+            .lines
+            .partition_point(|kept| kept.weight >= line.weight);
+        self.lines.insert(position, line);
+        self.lines.truncate(HEAVIEST_KEPT);
+    }
+
+    pub fn merge(&mut self, other: &Heaviest) {
+        for line in &other.lines {
+            self.record(line.clone());
+        }
+    }
+
+    /// The lines kept, heaviest first.
+    pub fn lines(&self) -> &[HeavyLine] {
+        &self.lines
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty()
+    }
+
+    /// The lines as `<prefix>.<id> <weight>` lines, for `--porcelain` output.
+    pub(crate) fn write_porcelain(&self, f: &mut fmt::Formatter<'_>, prefix: &str) -> fmt::Result {
+        for line in &self.lines {
+            writeln!(f, "{prefix}.{} {}", line.id, line.weight)?;
+        }
+        Ok(())
+    }
+
+    /// The lines as a list under `heading`, each with its weight in `unit`, if there are any.
+    pub(crate) fn write_list(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+        heading: &str,
+        unit: &str,
+    ) -> fmt::Result {
+        if self.lines.is_empty() {
+            return Ok(());
+        }
+        writeln!(f, "{heading}:")?;
+        for line in &self.lines {
+            write!(f, " ∟ `{}`", line.id)?;
+            if let Some(name) = &line.name {
+                write!(f, " ({name})")?;
+            }
+            writeln!(f, ": {} {unit}", line.weight)?;
+        }
+        Ok(())
+    }
+}
+
+/// The shape of the derivation of a trimmed proof's conclusion, over the constraints it needs.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DerivationStats {
+    /// The longest chain of uses from each constraint to the conclusion
+    pub depth: Distribution,
+    /// Antecedents of each constraint, which are 0 for formula constraints and assertions
+    pub in_degree: Distribution,
+    /// Kept derivations using each constraint
+    pub out_degree: Distribution,
+}
+
+impl DerivationStats {
+    pub fn merge(&mut self, other: &DerivationStats) {
+        self.depth.merge(&other.depth);
+        self.in_degree.merge(&other.in_degree);
+        self.out_degree.merge(&other.out_degree);
+    }
+
+    fn distributions(&self) -> [(&'static str, &Distribution); 3] {
+        [
+            ("depth", &self.depth),
+            ("in_degree", &self.in_degree),
+            ("out_degree", &self.out_degree),
+        ]
+    }
+
+    /// The stats as `<prefix>.derivation.<stat> <value>` lines, for `--porcelain` output.
+    pub(crate) fn write_porcelain(&self, f: &mut fmt::Formatter<'_>, prefix: &str) -> fmt::Result {
+        for (name, distribution) in self.distributions() {
+            let key = format!("{prefix}.derivation.{name}");
+            writeln!(f, "{key}.mean {:.3}", distribution.mean())?;
+            writeln!(f, "{key}.max {}", distribution.max())?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for DerivationStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Derivation of the conclusion:")?;
+        writeln!(f, " ∟ Constraints: {}", self.depth.count())?;
+        writeln!(f, " ∟ Depth: {}", self.depth)?;
+        writeln!(f, " ∟ Antecedents: {}", self.in_degree)?;
+        writeln!(f, " ∟ Uses: {}", self.out_degree)
+    }
+}
+
+/// Records [`DerivationStats`] from the derivations of a proof, from the last to the first.
+#[derive(Debug, Default)]
+pub(crate) struct DerivationRecorder {
+    /// Depth and uses so far of the constraints used but not derived yet
+    pending: HashMap<String, (u64, u64)>,
+    stats: DerivationStats,
+}
+
+impl DerivationRecorder {
+    /// Record the derivation of `id` from `antecedents`. The derivations using `id` must all
+    /// have been recorded already.
+    pub(crate) fn record<'a>(&mut self, id: &str, antecedents: impl IntoIterator<Item = &'a str>) {
+        let (depth, uses) = self.pending.remove(id).unwrap_or_default();
+        let mut in_degree = 0;
+        for antecedent in antecedents {
+            in_degree += 1;
+            let (antecedent_depth, antecedent_uses) = match self.pending.get_mut(antecedent) {
+                Some(pending) => pending,
+                None => self.pending.entry(antecedent.to_string()).or_default(),
+            };
+            *antecedent_depth = (*antecedent_depth).max(depth + 1);
+            *antecedent_uses += 1;
+        }
+        self.stats.depth.record(depth);
+        self.stats.in_degree.record(in_degree);
+        self.stats.out_degree.record(uses);
+    }
+
+    /// The stats, with the constraints used but never derived (the formula constraints)
+    /// having no antecedents.
+    pub(crate) fn finish(mut self) -> DerivationStats {
+        for (depth, uses) in self.pending.into_values() {
+            self.stats.depth.record(depth);
+            self.stats.in_degree.record(0);
+            self.stats.out_degree.record(uses);
+        }
+        self.stats
+    }
+}
+
+/// The prefixes of the IDs of the literal (`@lf`/`@lr`) and bounds (`@lb`/`@ub`) definitions
+/// the justifier writes.
+const DEFINITION_PREFIXES: [&str; 4] = ["@lf", "@lr", "@lb", "@ub"];
+
+fn definition_prefix(id: &str) -> Option<&'static str> {
+    DEFINITION_PREFIXES
+        .into_iter()
+        .find(|prefix| id.starts_with(prefix))
+}
+
+/// How the literal and bounds definitions of a proof were used, by ID prefix (e.g. `@lf`).
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DefinitionStats {
+    pub prefixes: BTreeMap<String, DefinitionUses>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DefinitionUses {
+    pub written: u64,
+    /// Of those, the ones no other line refers to
+    pub never_used: u64,
+    /// Lines referring to each definition
+    pub uses: Distribution,
+}
+
+impl DefinitionUses {
+    /// The uses after the first of the definitions that were used, which would each have
+    /// needed the definition written again without the definitions being cached.
+    pub fn reuses(&self) -> u64 {
+        self.uses.sum() - (self.written - self.never_used)
+    }
+}
+
+impl DefinitionStats {
+    pub fn merge(&mut self, other: &DefinitionStats) {
+        for (prefix, other_uses) in &other.prefixes {
+            let uses = self.prefixes.entry(prefix.clone()).or_default();
+            uses.written += other_uses.written;
+            uses.never_used += other_uses.never_used;
+            uses.uses.merge(&other_uses.uses);
+        }
+    }
+
+    /// The stats as `<prefix>.definitions.<id prefix>.<stat> <value>` lines, for `--porcelain`.
+    pub(crate) fn write_porcelain(&self, f: &mut fmt::Formatter<'_>, prefix: &str) -> fmt::Result {
+        for (id_prefix, uses) in &self.prefixes {
+            let key = format!("{prefix}.definitions.{}", id_prefix.trim_start_matches('@'));
+            writeln!(f, "{key}.written {}", uses.written)?;
+            writeln!(f, "{key}.uses {}", uses.uses.sum())?;
+            writeln!(f, "{key}.reuses {}", uses.reuses())?;
+            writeln!(f, "{key}.never_used {}", uses.never_used)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for DefinitionStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Definitions:")?;
+        for (prefix, uses) in &self.prefixes {
+            writeln!(
+                f,
+                " ∟ `{prefix}`: {} written, {} uses ({} reuses), {} never used",
+                uses.written,
+                uses.uses.sum(),
+                uses.reuses(),
+                uses.never_used
+            )?;
+            writeln!(f, "    uses of each: {}", uses.uses)?;
+        }
+        Ok(())
+    }
+}
+
+/// Records [`DefinitionStats`] from the lines of a proof, in either order.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub(crate) struct DefinitionRecorder {
+    /// Whether each definition ID has been seen defined, and the lines referring to it
+    ids: HashMap<String, (bool, u64)>,
+}
+
+impl DefinitionRecorder {
+    /// Record the definitions `line` makes and uses. Deletions aren't uses.
+    pub(crate) fn record(&mut self, line: &str, rule: &str) {
+        if rule == "del"
+            || !DEFINITION_PREFIXES
+                .iter()
+                .any(|prefix| line.contains(prefix))
+        {
+            return;
+        }
+        let mut tokens = line.split_whitespace().peekable();
+        // Only a label can come before the rule
+        if let Some(label) = tokens.next_if(|token| definition_prefix(token).is_some()) {
+            self.update(label, |(defined, _)| *defined = true);
+        }
+        for token in tokens.filter(|token| definition_prefix(token).is_some()) {
+            self.update(token, |(_, uses)| *uses += 1);
+        }
+    }
+
+    fn update(&mut self, id: &str, f: impl FnOnce(&mut (bool, u64))) {
+        match self.ids.get_mut(id) {
+            Some(entry) => f(entry),
+            None => f(self.ids.entry(id.to_string()).or_default()),
+        }
+    }
+
+    /// Add what was recorded from another part of the same proof.
+    pub(crate) fn merge(&mut self, other: &DefinitionRecorder) {
+        for (id, (other_defined, other_uses)) in &other.ids {
+            self.update(id, |(defined, uses)| {
+                *defined |= other_defined;
+                *uses += other_uses;
+            });
+        }
+    }
+
+    /// The stats of the definitions seen, or `None` if there weren't any.
+    pub(crate) fn finish(&self) -> Option<DefinitionStats> {
+        let mut stats = DefinitionStats::default();
+        for (id, &(defined, uses)) in &self.ids {
+            let Some(prefix) = definition_prefix(id).filter(|_| defined) else {
+                continue;
+            };
+            let prefix_uses = stats.prefixes.entry(prefix.to_string()).or_default();
+            prefix_uses.written += 1;
+            prefix_uses.never_used += u64::from(uses == 0);
+            prefix_uses.uses.record(uses);
+        }
+        (!stats.prefixes.is_empty()).then_some(stats)
+    }
+}
+
+/// How the constraints of a FlatZinc model were used by the assertions of a styled proof, by
+/// constraint type (e.g. `int_lin_le`).
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FznCoverage {
+    pub types: BTreeMap<String, ConstraintTypeCoverage>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConstraintTypeCoverage {
+    /// Constraints of the type in the model
+    pub constraints: u64,
+    /// Of those, the ones an assertion names as its antecedent
+    pub referenced: u64,
+    /// Assertions with a constraint of the type as their antecedent
+    pub assertions: u64,
+    /// Of those, the ones naming a justifier that doesn't exist
+    pub unsupported: u64,
+    /// The justifiers the assertions name
+    pub justifiers: BTreeSet<String>,
+}
+
+impl FznCoverage {
+    pub fn merge(&mut self, other: &FznCoverage) {
+        for (name, other_coverage) in &other.types {
+            let coverage = self.types.entry(name.clone()).or_default();
+            coverage.constraints += other_coverage.constraints;
+            coverage.referenced += other_coverage.referenced;
+            coverage.assertions += other_coverage.assertions;
+            coverage.unsupported += other_coverage.unsupported;
+            coverage
+                .justifiers
+                .extend(other_coverage.justifiers.iter().cloned());
+        }
+    }
+
+    /// The constraint types that assertions were made about without a justifier for them.
+    pub fn lacking_justifier(&self) -> impl Iterator<Item = &str> {
+        self.types
+            .iter()
+            .filter(|(_, coverage)| coverage.unsupported > 0)
+            .map(|(name, _)| name.as_str())
+    }
+
+    /// The coverage as `<prefix>.fzn.<type>.<stat> <value>` lines, for `--porcelain` output.
+    pub(crate) fn write_porcelain(&self, f: &mut fmt::Formatter<'_>, prefix: &str) -> fmt::Result {
+        for (name, coverage) in &self.types {
+            let key = format!("{prefix}.fzn.{name}");
+            writeln!(f, "{key}.constraints {}", coverage.constraints)?;
+            writeln!(f, "{key}.referenced {}", coverage.referenced)?;
+            writeln!(f, "{key}.assertions {}", coverage.assertions)?;
+            writeln!(f, "{key}.unsupported {}", coverage.unsupported)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for FznCoverage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "FlatZinc constraints by type:")?;
+        for (name, coverage) in &self.types {
+            write!(f, " ∟ `{name}`: {} in the model", coverage.constraints)?;
+            if coverage.referenced == 0 {
+                writeln!(f, ", never referenced")?;
+                continue;
+            }
+            write!(
+                f,
+                ", {} referenced by {} assertions",
+                coverage.referenced, coverage.assertions
+            )?;
+            let justifiers: Vec<_> = coverage.justifiers.iter().map(String::as_str).collect();
+            if !justifiers.is_empty() {
+                write!(f, " ({})", justifiers.join(", "))?;
+            }
+            if coverage.unsupported > 0 {
+                write!(f, ", {} with no justifier", coverage.unsupported)?;
+            }
+            writeln!(f)?;
+        }
+        let lacking: Vec<_> = self.lacking_justifier().collect();
+        if !lacking.is_empty() {
+            writeln!(
+                f,
+                "Constraint types lacking a justifier: {}",
+                lacking.join(", ")
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Lines written to justify assertions, by what they are.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JustificationLineKinds {
+    /// Bounds of the bit encodings of CP variables, `@lb` and `@ub` lines
+    pub encodings: u64,
+    /// Literal definitions, `@lf` and `@lr` lines
+    pub definitions: u64,
+    pub pol: u64,
+    pub rup: u64,
+    /// Anything else, e.g. the assertion kept when a justifier fails
+    pub other: u64,
+}
+
+impl JustificationLineKinds {
+    /// Count a justification line `line`, whose label is `label` if it has one.
+    #[cfg(feature = "justify")]
+    pub(crate) fn record(&mut self, label: &str, line: &str) {
+        let count = if label.starts_with("@lb") || label.starts_with("@ub") {
+            &mut self.encodings
+        } else if label.starts_with("@lf") || label.starts_with("@lr") {
+            &mut self.definitions
+        } else {
+            let rule = match label {
+                "" => line.split_whitespace().next(),
+                _ => line.split_whitespace().nth(1),
+            };
+            match rule {
+                Some("pol" | "p") => &mut self.pol,
+                Some("rup" | "u") => &mut self.rup,
+                _ => &mut self.other,
+            }
+        };
+        *count += 1;
+    }
+
+    pub fn add(&mut self, other: &JustificationLineKinds) {
+        self.encodings += other.encodings;
+        self.definitions += other.definitions;
+        self.pol += other.pol;
+        self.rup += other.rup;
+        self.other += other.other;
+    }
+
+    pub fn total(&self) -> u64 {
+        self.encodings + self.definitions + self.pol + self.rup + self.other
+    }
+
+    fn kinds(&self) -> [(&'static str, u64); 5] {
+        [
+            ("encodings", self.encodings),
+            ("definitions", self.definitions),
+            ("pol", self.pol),
+            ("rup", self.rup),
+            ("other", self.other),
+        ]
+    }
+}
+
+impl fmt::Display for JustificationLineKinds {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kinds: Vec<_> = self
+            .kinds()
+            .iter()
+            .map(|(kind, count)| format!("{count} {kind}"))
+            .collect();
+        write!(f, "{}", kinds.join(", "))
+    }
+}
+
+/// The lines each justifier wrote for the assertions about each type of FlatZinc constraint,
+/// so the growth of a styled proof can be put down to them. Assertions are counted under the
+/// type of the first constraint they name.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JustificationLines {
+    /// By constraint type, then justifier
+    pub types: BTreeMap<String, BTreeMap<String, JustificationLineKinds>>,
+}
+
+impl JustificationLines {
+    pub fn merge(&mut self, other: &JustificationLines) {
+        for (constraint_type, justifiers) in &other.types {
+            let entry = self.types.entry(constraint_type.clone()).or_default();
+            for (name, kinds) in justifiers {
+                entry.entry(name.clone()).or_default().add(kinds);
+            }
+        }
+    }
+
+    /// The lines over all the types and justifiers.
+    pub fn total(&self) -> JustificationLineKinds {
+        let mut total = JustificationLineKinds::default();
+        for kinds in self.types.values().flat_map(BTreeMap::values) {
+            total.add(kinds);
+        }
+        total
+    }
+
+    /// The counts as `<prefix>.justification_lines.<type>.<justifier>.<kind> <value>` lines,
+    /// for `--porcelain`.
+    pub(crate) fn write_porcelain(&self, f: &mut fmt::Formatter<'_>, prefix: &str) -> fmt::Result {
+        for (constraint_type, justifiers) in &self.types {
+            for (name, kinds) in justifiers {
+                let key = format!("{prefix}.justification_lines.{constraint_type}.{name}");
+                for (kind, count) in kinds.kinds() {
+                    writeln!(f, "{key}.{kind} {count}")?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The lines by type and justifier, with their shares of the justification lines, and the
+    /// total with its share of the `output_lines` of the proof.
+    pub(crate) fn write_shares(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+        output_lines: u64,
+    ) -> fmt::Result {
+        let total = self.total();
+        let share = |lines: u64, of: u64| lines as f64 * 100.0 / of.max(1) as f64;
+        writeln!(f, "Justification lines by FlatZinc constraint type:")?;
+        for (constraint_type, justifiers) in &self.types {
+            for (name, kinds) in justifiers {
+                writeln!(
+                    f,
+                    " ∟ `{constraint_type}` by {name}: {} lines ({:.1}%): {kinds}",
+                    kinds.total(),
+                    share(kinds.total(), total.total())
+                )?;
+            }
+        }
+        writeln!(
+            f,
+            " ∟ Total: {} lines ({:.1}% of the output): {total}",
+            total.total(),
+            share(total.total(), output_lines)
+        )
+    }
+}
+
+/// The counters of several proofs side by side, each compared to the first.
+#[derive(Debug, Clone)]
+pub struct StatsTable {
+    names: Vec<String>,
+    /// Each counter, with its change in each proof from the first
+    rows: Vec<(String, Vec<StatChange>)>,
+}
+
+impl StatsTable {
+    /// The table of the proofs `stats`, each given with a name for its column.
+    pub fn new(stats: &[(String, ProofFileStats)]) -> Self {
+        let names = stats.iter().map(|(name, _)| name.clone()).collect();
+        let mut rows: Vec<(String, Vec<StatChange>)> = Vec::new();
+        let mut row_indices = HashMap::new();
+        let Some((_, first)) = stats.first() else {
+            return Self { names, rows };
+        };
+        for (column, (_, current)) in stats.iter().enumerate() {
+            for (counter, change) in current.compared_to(first).changes().counters() {
+                let row = *row_indices.entry(counter.clone()).or_insert_with(|| {
+                    // Counters missing from the proofs before are 0 in them
+                    let missing = StatChange {
+                        current: 0,
+                        reference: change.reference,
+                    };
+                    rows.push((counter, vec![missing; stats.len()]));
+                    rows.len() - 1
+                });
+                rows[row].1[column] = change;
+            }
+        }
+        Self { names, rows }
+    }
+
+    pub fn write_csv<W: io::Write>(&self, mut out: W) -> io::Result<()> {
+        write!(out, "counter")?;
+        for (column, name) in self.names.iter().enumerate() {
+            write!(out, ",{}", csv_field(name))?;
+            if column > 0 {
+                write!(out, ",{} change %", csv_field(name))?;
+            }
+        }
+        writeln!(out)?;
+        for (counter, changes) in &self.rows {
+            write!(out, "{}", csv_field(counter))?;
+            for (column, change) in changes.iter().enumerate() {
+                write!(out, ",{}", change.current)?;
+                if column > 0 {
+                    match change.percent() {
+                        Some(percent) => write!(out, ",{percent:.1}")?,
+                        None => write!(out, ",")?,
+                    }
+                }
+            }
+            writeln!(out)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for StatsTable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let cells: Vec<Vec<String>> = self
+            .rows
+            .iter()
+            .map(|(_, changes)| {
+                changes
+                    .iter()
+                    .enumerate()
+                    .map(|(column, change)| match change.percent() {
+                        _ if column == 0 => change.current.to_string(),
+                        Some(percent) => format!("{} ({percent:+.1}%)", change.current),
+                        None => change.current.to_string(),
+                    })
+                    .collect()
+            })
+            .collect();
+        let counter_width = self
+            .rows
+            .iter()
+            .map(|(counter, _)| counter.len())
+            .max()
+            .unwrap_or(0)
+            .max("counter".len());
+        let widths: Vec<usize> = self
+            .names
+            .iter()
+            .enumerate()
+            .map(|(column, name)| {
+                cells
+                    .iter()
+                    .map(|row| row[column].len())
+                    .max()
+                    .unwrap_or(0)
+                    .max(name.len())
+            })
+            .collect();
+        write!(f, "{:counter_width$}", "counter")?;
+        for (name, width) in self.names.iter().zip(&widths) {
+            write!(f, "  {name:>width$}")?;
+        }
+        writeln!(f)?;
+        for ((counter, _), row) in self.rows.iter().zip(&cells) {
+            write!(f, "{counter:counter_width$}")?;
+            for (cell, width) in row.iter().zip(&widths) {
+                write!(f, "  {cell:>width$}")?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// A CSV field, quoted if it has to be.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// What running a command cost: how long each phase took and how much it read and wrote,
+/// and the most memory used.
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct RunStats {
+    pub phases: Vec<PhaseStats>,
+    /// Peak resident memory in bytes, where the platform reports it
+    pub peak_memory: Option<u64>,
+}
+
+/// One phase of a command, e.g. trimming or renumbering.
+#[derive(Debug, Clone, Serialize)]
+pub struct PhaseStats {
+    pub name: String,
+    #[serde(rename = "seconds", serialize_with = "as_seconds")]
+    pub elapsed: Duration,
+    pub bytes_read: u64,
+    pub bytes_written: u64,
+    /// Lines of proof read, if they were counted
+    pub lines: Option<u64>,
+}
+
+impl PhaseStats {
+    /// Lines read per second, if they were counted.
+    pub fn lines_per_second(&self) -> Option<f64> {
+        let seconds = self.elapsed.as_secs_f64();
+        self.lines
+            .filter(|_| seconds > 0.0)
+            .map(|lines| lines as f64 / seconds)
+    }
+}
+
+impl RunStats {
+    pub fn record_phase(&mut self, phase: PhaseStats) {
+        self.phases.push(phase);
+    }
+
+    /// Set the lines read by the last phase recorded.
+    pub fn set_lines(&mut self, lines: u64) {
+        if let Some(phase) = self.phases.last_mut() {
+            phase.lines = Some(lines);
+        }
+    }
+
+    /// Note the most memory this process has used so far.
+    pub fn record_peak_memory(&mut self) {
+        self.peak_memory = limits::peak_resident_memory();
+    }
+
+    /// The stats as `timing.<phase>.<stat> <value>` lines, for `--porcelain`.
+    pub fn porcelain(&self) -> String {
+        let mut lines = String::new();
+        // Writing to a String can't fail
+        for phase in &self.phases {
+            let key = format!("timing.{}", phase.name.replace(' ', "_"));
+            let _ = writeln!(lines, "{key}.seconds {:.3}", phase.elapsed.as_secs_f64());
+            let _ = writeln!(lines, "{key}.bytes_read {}", phase.bytes_read);
+            let _ = writeln!(lines, "{key}.bytes_written {}", phase.bytes_written);
+            if let Some(lines_read) = phase.lines {
+                let _ = writeln!(lines, "{key}.lines {lines_read}");
+            }
+            if let Some(rate) = phase.lines_per_second() {
+                let _ = writeln!(lines, "{key}.lines_per_second {rate:.0}");
+            }
+        }
+        if let Some(peak_memory) = self.peak_memory {
+            let _ = writeln!(lines, "timing.peak_memory {peak_memory}");
+        }
+        lines
+    }
+
+    pub fn write_json<W: io::Write>(&self, out: W) -> io::Result<()> {
+        serde_json::to_writer_pretty(out, self).map_err(io::Error::other)
+    }
+}
+
+impl fmt::Display for RunStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for phase in &self.phases {
+            write!(
+                f,
+                " ∟ {}: {:.3}s, read {} bytes, wrote {} bytes",
+                phase.name,
+                phase.elapsed.as_secs_f64(),
+                phase.bytes_read,
+                phase.bytes_written
+            )?;
+            if let Some(rate) = phase.lines_per_second() {
+                write!(f, ", {rate:.0} lines/s")?;
+            }
+            writeln!(f)?;
+        }
+        if let Some(peak_memory) = self.peak_memory {
+            writeln!(f, " ∟ Peak memory: {peak_memory} bytes")?;
+        }
+        Ok(())
+    }
+}
+
+fn as_seconds<S: Serializer>(time: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_f64(time.as_secs_f64())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn percentiles_are_values_recorded() {
+        let mut distribution = Distribution::default();
+        assert_eq!(distribution.percentile(50.0), 0);
+        for value in (1..=10).rev() {
+            distribution.record(value);
+        }
+        distribution.record(10);
+        assert_eq!(distribution.percentile(0.0), 1);
+        assert_eq!(distribution.percentile(50.0), 6);
+        assert_eq!(distribution.percentile(90.0), 10);
+        assert_eq!(distribution.percentile(100.0), 10);
+        assert_eq!((distribution.count(), distribution.max()), (11, 10));
+    }
+}
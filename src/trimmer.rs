@@ -1,28 +1,103 @@
-use rev_buf_reader::RevBufReader;
 use std::{
-    collections::HashSet,
-    io::{self, BufRead, Lines, Read, Seek, Write},
+    collections::{HashMap, HashSet},
+    fs::File,
+    io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write},
 };
 
 use crate::{
-    ALLOWED_RULES, FORWARD_LIT_DEF_PREFIX, PBarberError, ProofFileStats, ProofReader,
-    REVERSE_LIT_DEF_PREFIX, TrimmerConfig,
+    FORWARD_LIT_DEF_PREFIX, LinePosition, PBarberError, ProofFileStats, ProofReader,
+    REVERSE_LIT_DEF_PREFIX, TrimmerConfig, UnknownRulePolicy, WarningKind,
+    diagnostics::Diagnostics,
+    graph::ProofGraph,
+    id_set::{IdSet, RangeSet},
+    limits::ResourceGuard,
+    line_map::LineMap,
+    observer::{Observer, ObserverSlot},
+    opb_model::OpbModel,
+    pol_eval::{Constraint, PolToken, evaluate_pol, is_scalar},
+    proof_line::{Deletion, IdStyle, ProofLine, line_label},
+    reverse_lines::{ReverseLines, set_line},
+    split_debug_marker,
+    stats::{ConstraintSizes, DerivationRecorder},
+    verify::LineOrigin,
 };
+use tracing::debug;
+
+mod parallel;
+
+/// A line of the trimmed proof, recorded during the reverse scan and written out forwards
+/// once the scan is complete.
+enum KeptLine {
+    /// A line copied from the input, starting at this byte offset.
+    Input(u64),
+    /// A line produced by the trimmer itself (e.g. a deletion).
+    Generated(String),
+    /// The ID of a derivation at the depth limit, to be restated as a bare assertion.
+    Frontier(String),
+}
 
 pub struct Trimmer<R: Read + Seek, W> {
-    marked_for_output: HashSet<String>,
-    marked_for_deletion: HashSet<String>,
-    lits_seen: HashSet<String>,
-    lines: Lines<RevBufReader<R>>,
+    marked_for_output: IdSet,
+    marked_for_deletion: IdSet,
+    deleted_ranges: RangeSet,
+    lits_seen: IdSet,
+    core_ids: IdSet,
+    graph: Option<ProofGraph>,
+    /// The OPB model from `config.opb_path`, to check formula references against
+    model: Option<OpbModel>,
+    warnings: Vec<(WarningKind, String)>,
+    unknown_rules_seen: HashSet<String>,
+    /// Numeric IDs referenced by each derivation, checked against the model at the `f` line
+    formula_references: Vec<(String, String)>,
+    formula_constraints: u64,
+    next_implicit_id: u64,
+    depths: HashMap<String, usize>,
+    /// Depths and degrees of the kept constraints, if stats were asked for
+    derivation: Option<DerivationRecorder>,
+    /// Sizes of the input lines kept, if stats were asked for
+    kept_sizes: ConstraintSizes,
+    frontier: HashSet<String>,
+    frontier_lines: HashMap<String, String>,
+    /// IDs whose constraint can't be worked out, if there's a depth limit
+    unrestatable: HashSet<String>,
+    /// Derivations at or past the depth limit that were kept, as they couldn't be restated
+    kept_past_depth_limit: usize,
+    lines: ReverseLines<R>,
+    /// Offsets of the only lines left to scan, if the parallel pre-pass found them
+    selected_offsets: Option<Vec<u64>>,
+    current_offset: u64,
+    /// Lines read by the reverse scan, unless it only reads the lines the pre-pass selected
+    lines_read: Option<u64>,
+    kept: Vec<KeptLine>,
+    /// The input offset of the line each output line was copied from (`true`) or written for,
+    /// in the order they were written, if a line map was asked for
+    line_origins: Option<Vec<(u64, bool)>>,
+    kept_reader: BlockReader,
+    observer: ObserverSlot,
     out: W,
     config: TrimmerConfig,
+    guard: ResourceGuard,
     input_stats: ProofFileStats,
     output_stats: ProofFileStats,
 }
 
 impl<R: Read + Seek, W: Write> ProofReader<W> for Trimmer<R, W> {
     fn lines_next(&mut self) -> Option<Result<String, io::Error>> {
-        self.lines.next()
+        let mut line = String::new();
+        match self.read_line(&mut line) {
+            Ok(true) => Some(Ok(line)),
+            Ok(false) => None,
+            Err(err) => Some(Err(err)),
+        }
+    }
+
+    fn next_line(&mut self) -> Option<Result<String, io::Error>> {
+        let mut line = String::new();
+        match self.next_line_into(&mut line) {
+            Ok(true) => Some(Ok(line)),
+            Ok(false) => None,
+            Err(err) => Some(Err(err)),
+        }
     }
 
     fn has_stats(&self) -> bool {
@@ -40,21 +115,77 @@ impl<R: Read + Seek, W: Write> ProofReader<W> for Trimmer<R, W> {
     fn out_mut(&mut self) -> &mut W {
         &mut self.out
     }
+
+    fn write_line(&mut self, content: &str) -> io::Result<()> {
+        self.record_origin(false);
+        self.write_output(content)
+    }
 }
 
 impl<R: Read + Seek, W: Write> Trimmer<R, W> {
+    /// Panicking form of [`Trimmer::try_new`].
+    #[deprecated(note = "use `Trimmer::try_new`, which returns the error instead")]
     pub fn new(input: R, out: W) -> Self {
-        Self::with_config(input, out, TrimmerConfig::default())
+        Self::try_new(input, out).expect("Failed to set up trimmer.")
+    }
+
+    /// Trim `input` with the default config.
+    pub fn try_new(input: R, out: W) -> Result<Self, PBarberError> {
+        Self::try_with_config(input, out, TrimmerConfig::default())
     }
 
+    /// Panicking form of [`Trimmer::try_with_config`].
+    #[deprecated(note = "use `Trimmer::try_with_config`, which returns the error instead")]
     pub fn with_config(input: R, out: W, config: TrimmerConfig) -> Self {
-        let rev_reader = RevBufReader::new(input);
+        Self::try_with_config(input, out, config).expect("Failed to set up trimmer.")
+    }
+
+    /// Trim `input` with `config`. Fails if the options don't make sense (see
+    /// [`TrimmerConfig::validate`]) or the OPB model can't be read.
+    pub fn try_with_config(input: R, out: W, config: TrimmerConfig) -> Result<Self, PBarberError> {
+        config.validate()?;
+        let model = match &config.opb_path {
+            Some(opb_path) => Some(
+                OpbModel::open(opb_path)
+                    .map_err(|err| PBarberError::reading("OPB model", opb_path, err))?,
+            ),
+            None => None,
+        };
+        Ok(Self::with_model(input, out, config, model))
+    }
+
+    fn with_model(input: R, out: W, config: TrimmerConfig, model: Option<OpbModel>) -> Self {
+        let graph = config.emit_graph.map(|_| ProofGraph::default());
         Self {
-            marked_for_output: HashSet::<String>::new(),
-            marked_for_deletion: HashSet::<String>::new(),
-            lits_seen: HashSet::<String>::new(),
-            lines: rev_reader.lines(),
+            marked_for_output: IdSet::default(),
+            marked_for_deletion: IdSet::default(),
+            deleted_ranges: RangeSet::default(),
+            lits_seen: IdSet::default(),
+            core_ids: IdSet::default(),
+            graph,
+            model,
+            warnings: Vec::new(),
+            unknown_rules_seen: HashSet::<String>::new(),
+            formula_references: Vec::new(),
+            formula_constraints: 0,
+            next_implicit_id: 0,
+            depths: HashMap::<String, usize>::new(),
+            derivation: config.stats.then(DerivationRecorder::default),
+            kept_sizes: ConstraintSizes::default(),
+            frontier: HashSet::<String>::new(),
+            frontier_lines: HashMap::<String, String>::new(),
+            unrestatable: HashSet::new(),
+            kept_past_depth_limit: 0,
+            lines: ReverseLines::new(input).block_size(config.buffers.block_size),
+            selected_offsets: None,
+            current_offset: 0,
+            lines_read: Some(0),
+            kept: Vec::new(),
+            line_origins: config.line_map_path.as_ref().map(|_| Vec::new()),
+            kept_reader: BlockReader::default(),
+            observer: ObserverSlot::default(),
             out,
+            guard: ResourceGuard::new(&config.limits),
             config,
             input_stats: ProofFileStats::default(),
             output_stats: ProofFileStats::default(),
@@ -62,111 +193,1483 @@ impl<R: Read + Seek, W: Write> Trimmer<R, W> {
     }
 
     pub fn trim(&mut self) -> Result<Option<(ProofFileStats, ProofFileStats)>, PBarberError> {
-        let mut current_line = self.next_line().unwrap().unwrap();
+        self.scan()?;
+        let mut line = String::new();
+        while self.next_kept_line(&mut line)? {
+            writeln!(self.out, "{}", line)?;
+        }
+        self.out.flush()?;
+        if let Some(line_map_path) = self.config.line_map_path.clone() {
+            let line_map = self.line_map()?.unwrap_or_default();
+            line_map.write_to_path(&line_map_path)?;
+        }
+        Ok(self.take_stats())
+    }
+
+    /// Where each line of the trimmed proof came from in the input, in the order the lines
+    /// are written out, if a line map was asked for.
+    pub fn line_map(&mut self) -> io::Result<Option<LineMap>> {
+        let Some(line_origins) = &self.line_origins else {
+            return Ok(None);
+        };
+        let mut offsets: Vec<u64> = line_origins.iter().map(|(offset, _)| *offset).collect();
+        offsets.sort_unstable();
+        offsets.dedup();
+        let line_numbers = line_numbers_at(self.lines.get_mut(), &offsets)?;
+        let mut line_map = LineMap::default();
+        for (offset, copied) in line_origins {
+            let line_number = offsets
+                .binary_search(offset)
+                .ok()
+                .and_then(|i| line_numbers.get(i).copied())
+                .unwrap_or_default();
+            line_map.push(match copied {
+                true => LineOrigin::Original(line_number),
+                false => LineOrigin::WrittenFor(line_number),
+            });
+        }
+        // Kept lines are written out in the reverse of the order they were kept
+        Ok(Some(match self.config.reverse_output {
+            true => line_map,
+            false => line_map.reversed(),
+        }))
+    }
+
+    /// Note where the output line about to be written came from.
+    fn record_origin(&mut self, copied: bool) {
+        if let Some(line_origins) = self.line_origins.as_mut() {
+            line_origins.push((self.current_offset, copied));
+        }
+    }
+
+    /// Trim the proof, giving the lines of the trimmed proof instead of writing them to the
+    /// output (which isn't used). The proof is scanned when the first line is taken.
+    pub fn lines(mut self) -> TrimmedLines<R, W> {
+        // Lines are only written out during the scan when writing in reverse, so keep them
+        // all and hand them out in reverse instead
+        let reverse = std::mem::take(&mut self.config.reverse_output);
+        TrimmedLines {
+            trimmer: self,
+            reverse,
+            scanned: false,
+            done: false,
+        }
+    }
+
+    /// Call `observer` as the proof is trimmed.
+    pub fn set_observer(&mut self, observer: impl Observer + 'static) {
+        self.observer.set(observer);
+    }
+
+    /// Input and output stats, if they were requested in the config. Complete once the proof
+    /// has been trimmed, and empty once [`Trimmer::trim`] has returned them.
+    pub fn stats(&self) -> Option<(&ProofFileStats, &ProofFileStats)> {
+        self.config
+            .stats
+            .then_some((&self.input_stats, &self.output_stats))
+    }
+
+    /// Move the stats out, if they were requested, rather than copying them.
+    fn take_stats(&mut self) -> Option<(ProofFileStats, ProofFileStats)> {
+        self.config.stats.then(|| {
+            (
+                std::mem::take(&mut self.input_stats),
+                std::mem::take(&mut self.output_stats),
+            )
+        })
+    }
+
+    /// Read the next line to scan into `line`, returning whether there was one.
+    fn read_line(&mut self, line: &mut String) -> io::Result<bool> {
+        if let Some(offsets) = self.selected_offsets.as_mut() {
+            let Some(offset) = offsets.pop() else {
+                return Ok(false);
+            };
+            self.current_offset = offset;
+            *line = read_line_at(self.lines.get_mut(), offset)?;
+            return Ok(true);
+        }
+        let Some(offset) = self.lines.read_line(line)? else {
+            return Ok(false);
+        };
+        self.current_offset = offset;
+        Ok(true)
+    }
+
+    /// [`ProofReader::next_line`] into a reused `line`, which the scan reads every line with.
+    fn next_line_into(&mut self, line: &mut String) -> io::Result<bool> {
+        if !self.read_line(line)? {
+            return Ok(false);
+        }
+        if self.selected_offsets.is_none() {
+            self.lines_read = self.lines_read.map(|n| n + 1);
+        }
+        self.observer.notify(|o| o.on_input_line(line));
+        // The parallel pre-pass has already recorded the selected lines
+        if self.has_stats() && self.selected_offsets.is_none() {
+            self.input_stats.record_line(line);
+        }
+        Ok(true)
+    }
+
+    /// Whether a line can be dropped on its label alone, without parsing the rest of it: a
+    /// labelled derivation that nothing kept needs. Most lines of a large proof are.
+    fn is_unneeded(&self, line: &str) -> bool {
+        // Implicit IDs have to be counted off every derivation
+        if self.config.implicit_ids {
+            return false;
+        }
+        line_label(line).is_some_and(|label| {
+            !self.marked_for_output.contains(label)
+                && lit_def_literal(label).is_none_or(|lit| !self.lits_seen.contains(lit))
+        })
+    }
 
-        if current_line.starts_with("end pseudo-Boolean") {
+    /// Find the lines to keep with the reverse scan.
+    fn scan(&mut self) -> Result<(), PBarberError> {
+        if self.config.implicit_ids || self.config.faithful_deletions {
+            self.prescan()?;
+        }
+        if self.config.max_depth.is_some() {
+            self.find_unrestatable()?;
+        }
+        let Some(current_line) = self.next_line() else {
+            return Err(PBarberError::MissingConclusion);
+        };
+        let mut current_line = current_line?;
+        // First line of an incomplete proof, still to be trimmed as normal
+        let mut pending_line = None;
+        let mut assume_last_derived = false;
+
+        if matches!(ProofLine::parse(&current_line), Ok(ProofLine::End)) {
             // Write end pseudo-Boolean proof
-            self.write_line(&current_line)?;
+            self.keep_line(&current_line)?;
 
             // Write UNSAT conclusion
-            current_line = self.next_line().unwrap().unwrap();
-            self.assert_starts_with(&current_line, "conclusion UNSAT")?;
-            self.write_line(&current_line)?;
+            current_line = self.expect_line("conclusion UNSAT")?;
 
             // Mark the contradicting constraint ID
-            let contr_id = current_line
-                .split(":")
-                .nth(1)
-                .unwrap()
-                .split(";")
-                .nth(0)
-                .unwrap()
-                .trim()
-                .to_string();
-            self.marked_for_output.insert(contr_id);
+            let contr_id = match self.parse(&current_line)? {
+                ProofLine::Conclusion { id: Some(id), .. } => id.to_string(),
+                _ => return Err(self.malformed(&current_line, "missing the constraint ID")),
+            };
+            if self.config.implicit_ids {
+                self.write_line(&format!(
+                    "conclusion UNSAT : {} ;",
+                    self.output_id(&contr_id)
+                ))?;
+            } else {
+                self.keep_line(&current_line)?;
+            }
+            self.marked_for_output.insert(&contr_id);
 
             // Write output (hopefully NONE)
-            current_line = self.next_line().unwrap().unwrap();
-            self.assert_starts_with(&current_line, "output")?;
-            self.write_line(&current_line)?;
+            current_line = self.expect_line("output")?;
+            self.keep_line(&current_line)?;
+        } else if let Some(goal) = self.config.assume_contradiction.clone() {
+            // Trim an incomplete proof towards the chosen constraint instead
+            self.warn(
+                WarningKind::Recovered,
+                format!("proof has no conclusion, trimming towards `{goal}` instead"),
+            )?;
+            if goal == "last" {
+                assume_last_derived = true;
+            } else {
+                self.marked_for_output.insert(&goal);
+            }
+            pending_line = Some(current_line);
         } else {
-            // Don't trim proofs that don't end (TODO?)
+            // Don't trim proofs that don't end
             return Err(PBarberError::MissingConclusion);
         }
 
-        while let Some(current_line) = self.next_line() {
-            let current_line = current_line.unwrap();
-            if current_line.starts_with("@") {
-                let mut split_line = current_line.split(" ");
-                let id = split_line.next().unwrap();
-                if self.marked_for_output.contains(id) {
-                    let rule = split_line.next().unwrap();
-                    assert!(ALLOWED_RULES.contains(&rule));
-                    if rule == "pol" || rule == "p" {
-                        for term in split_line {
-                            if term == "+" || term == "s" || term == ";" {
+        if self.config.parallel {
+            self.mark_in_parallel(assume_last_derived)?;
+        }
+
+        let mut raw_line = String::new();
+        loop {
+            match pending_line.take() {
+                Some(line) => raw_line = line,
+                None if !self.next_line_into(&mut raw_line)? => break,
+                None => (),
+            }
+            self.guard.tick()?;
+            // Only the first line read from an incomplete proof can be cut short
+            let truncatable = assume_last_derived;
+            // Debug markers are kept on copied lines, but otherwise ignored
+            let (marker, current_line) = split_debug_marker(&raw_line);
+            // Until the goal of an incomplete proof is found, any line may be it
+            if !assume_last_derived && self.is_unneeded(current_line) {
+                self.observer.notify(|o| o.on_dropped(&raw_line));
+                continue;
+            }
+            let line = match ProofLine::parse(current_line) {
+                Ok(line) => line,
+                // Lines we'd drop anyway don't have to make sense
+                Err(_)
+                    if truncatable
+                        || line_label(current_line)
+                            .is_some_and(|label| !self.marked_for_output.contains(label)) =>
+                {
+                    self.observer.notify(|o| o.on_dropped(&raw_line));
+                    continue;
+                }
+                Err(err) => return Err(err.at(self.position())),
+            };
+            let implicit_id = self.take_implicit_id(&line);
+            // A killed solver may leave the last line truncated, so only take complete lines
+            if assume_last_derived && current_line.trim_end().ends_with(';') {
+                let goal = line.label().map(str::to_string).or(implicit_id.clone());
+                if let Some(goal) = goal {
+                    self.marked_for_output.insert(&goal);
+                    assume_last_derived = false;
+                }
+            }
+            let label = line.label();
+            if label.is_some() || implicit_id.is_some() {
+                // Literal definitions are needed by any kept constraint using the literal
+                let defined_lit = label.and_then(lit_def_literal);
+                let needed = label.is_some_and(|l| self.marked_for_output.contains(l))
+                    || implicit_id
+                        .as_ref()
+                        .is_some_and(|n| self.marked_for_output.contains(n))
+                    || defined_lit.is_some_and(|lit| self.lits_seen.contains(lit));
+                if needed {
+                    let Some(id) = label.or(implicit_id.as_deref()) else {
+                        return Err(PBarberError::Internal(
+                            "kept a line with no constraint ID".to_string(),
+                        ));
+                    };
+                    let rule = line.rule();
+                    let is_pol = matches!(line, ProofLine::Pol { .. });
+                    let references = match &line {
+                        ProofLine::Rup { hints, .. } if hints.is_empty() => {
+                            self.warn_untraced(&raw_line, rule)?;
+                            Vec::new()
+                        }
+                        ProofLine::Red { .. } if defined_lit.is_none() => {
+                            self.warn_untraced(&raw_line, rule)?;
+                            Vec::new()
+                        }
+                        ProofLine::Other { .. } => {
+                            // We can't tell what this depends on, so it's up to the policy
+                            if !self.keeps_unknown_line(&raw_line, rule)? {
                                 continue;
-                            } else {
-                                self.assert_starts_with(&term.to_string(), "@")?;
-                                if !self.marked_for_output.contains(term) {
-                                    if self.config.eager_deletion
-                                        || self.marked_for_deletion.contains(term)
-                                    {
-                                        // We haven't marked this yet, so it's the last time
-                                        // this ID is needed in the proof, hence delete it
-                                        let _ = self.write_line(&format!("del id {term} ;"));
-                                    }
-                                    self.marked_for_output.insert(term.to_string());
+                            }
+                            antecedents_of(&line)
+                        }
+                        _ => antecedents_of(&line),
+                    };
+                    if let Some(graph) = self.graph.as_mut() {
+                        graph.add_node(id, rule);
+                    }
+                    // Deletions that belong right after this derivation, emitted as one line
+                    let mut deletions = Vec::<String>::new();
+                    let mut antecedents = Vec::new();
+                    let depth = self.depths.get(id).copied().unwrap_or(0);
+                    let at_limit = is_pol && self.config.max_depth.is_some_and(|max| depth >= max);
+                    // A constraint that can't be restated keeps its derivation instead
+                    let at_frontier = at_limit && !self.unrestatable.contains(id);
+                    if at_limit && !at_frontier {
+                        self.kept_past_depth_limit += 1;
+                    }
+                    if at_frontier {
+                        // Cut the derivation here, its antecedents aren't needed
+                        self.frontier.insert(id.to_string());
+                    } else if let ProofLine::Pol { terms: tokens, .. } = &line {
+                        for (i, token) in tokens.iter().enumerate() {
+                            // Only constraint references are antecedents, not scalars,
+                            // operators or literal axioms
+                            let term = match *token {
+                                PolToken::Constraint(term) => term,
+                                PolToken::Literal(lit)
+                                    if tokens.get(i + 1) != Some(&PolToken::Weaken) =>
+                                {
+                                    self.see_literal(lit, &mut deletions);
+                                    continue;
                                 }
+                                _ => continue,
+                            };
+                            if self.derivation.is_some() {
+                                antecedents.push(term);
                             }
+                            self.mark_antecedent(Some(id), term, depth, &mut deletions);
                         }
-                    } else if self.config.lit_deletion && rule == "a" {
-                        let split_line = current_line.split(" ");
-                        for token in split_line {
+                    } else if let ProofLine::Assertion { constraint, .. } = &line {
+                        for token in constraint.split_whitespace() {
                             if token == ">=" {
                                 break;
                             }
-                            let mut lit = token;
-
-                            if lit.starts_with("~") {
-                                lit = &lit[1..];
-                            }
-                            if !lit.starts_with("x") || self.lits_seen.contains(lit) {
-                                continue;
-                            }
-
-                            self.lits_seen.insert(lit.to_string());
-                            for prefix in [FORWARD_LIT_DEF_PREFIX, REVERSE_LIT_DEF_PREFIX] {
-                                self.write_line(&format!("del id @{}{}", prefix, &lit))?;
-                            }
+                            self.see_literal(token, &mut deletions);
                         }
                     }
+                    for term in references {
+                        if self.derivation.is_some() {
+                            antecedents.push(term);
+                        }
+                        self.mark_antecedent(Some(id), term, depth, &mut deletions);
+                    }
+                    if !deletions.is_empty() {
+                        self.write_deletion(&format!("del id {} ;", deletions.join(" ")))?;
+                    }
+                    if let Some(derivation) = self.derivation.as_mut() {
+                        derivation.record(id, antecedents);
+                    }
                     // Write out the needed constraint
-                    self.write_line(&current_line)?;
+                    if at_frontier {
+                        self.kept_input(&raw_line);
+                        // Restated as an assertion
+                        self.record_origin(false);
+                        self.kept.push(KeptLine::Frontier(id.to_string()));
+                    } else if self.config.implicit_ids {
+                        if let (Some(label), Some(n)) = (label, implicit_id.as_ref())
+                            && self.marked_for_output.contains(n)
+                        {
+                            // Also referenced by number, so alias the number to the label
+                            self.write_line(&format!("{} pol {label} ;", self.output_id(n)))?;
+                        }
+                        let mut explicit_line = self.with_explicit_ids(current_line, &line, id);
+                        if let Some(marker) = marker {
+                            explicit_line = format!("{marker} {explicit_line}");
+                        }
+                        self.kept_input(&raw_line);
+                        self.write_line(&explicit_line)?;
+                    } else {
+                        self.keep_line(&raw_line)?;
+                    }
                 } else {
                     // Not marked, ignore
+                    self.observer.notify(|o| o.on_dropped(&raw_line));
                     continue;
                 }
-            } else if current_line.starts_with("f") || current_line.starts_with("pseudo-Boolean") {
-                self.write_line(&current_line)?;
-            } else if !self.config.eager_deletion && current_line.starts_with("del id") {
-                let mut id = current_line.split(" ").nth(2).unwrap();
-                id = if id.ends_with(";") {
-                    &id[..id.len() - 2]
-                } else {
-                    id
-                };
-                // We will delete this if anyone uses it
-                self.marked_for_deletion.insert(id.to_string());
             } else {
-                // Something else ? Ignore ;-)
-                continue;
+                match &line {
+                    ProofLine::Header { .. } => self.keep_line(&raw_line)?,
+                    ProofLine::Formula { constraints } => {
+                        // Found by the prescan if there was one, but it's only needed from
+                        // here on otherwise, for the formula core
+                        if let Some(constraints) = constraints {
+                            self.formula_constraints = *constraints;
+                        }
+                        self.check_formula_size(*constraints, current_line)?;
+                        self.check_formula_references(*constraints)?;
+                        self.keep_line(&raw_line)?;
+                    }
+                    ProofLine::Blank if marker.is_some() => self.keep_line(&raw_line)?,
+                    ProofLine::Del(deletion)
+                        if self.config.faithful_deletions || !self.config.eager_deletion =>
+                    {
+                        self.handle_deletion(deletion, &raw_line)?;
+                    }
+                    // Comments, unmarked blank lines and deletions we're replacing
+                    ProofLine::Comment(_) | ProofLine::Blank | ProofLine::Del(_) => {
+                        self.observer.notify(|o| o.on_dropped(&raw_line));
+                    }
+                    ProofLine::Other { rule, .. } => {
+                        // We can't tell what this depends on, so it's up to the policy
+                        if self.keeps_unknown_line(&raw_line, rule)? {
+                            let mut deletions = Vec::new();
+                            for term in antecedents_of(&line) {
+                                self.mark_antecedent(None, term, 0, &mut deletions);
+                            }
+                            if !deletions.is_empty() {
+                                self.write_deletion(&format!("del id {} ;", deletions.join(" ")))?;
+                            }
+                            self.keep_line(&raw_line)?;
+                        }
+                    }
+                    // Other lines nothing can refer to, e.g. unlabelled derivations
+                    _ => self.observer.notify(|o| o.on_dropped(&raw_line)),
+                }
             }
         }
+        if !self.frontier.is_empty() {
+            self.restate_frontier()?;
+        }
+        if let Some(graph) = self.graph.as_mut() {
+            graph.add_referenced_nodes();
+        }
         if self.config.stats {
-            Ok(Some((self.input_stats.clone(), self.output_stats.clone())))
+            // Both sets of stats were recorded during the reverse scan, unless the input was
+            // scanned by the parallel pre-pass
+            self.input_stats.finish(self.selected_offsets.is_none());
+            self.output_stats.finish(true);
+            self.output_stats.derivation = self.derivation.take().map(DerivationRecorder::finish);
+            let kept_sizes = std::mem::take(&mut self.kept_sizes);
+            self.output_stats.dropped_sizes = Some(self.input_stats.sizes.without(&kept_sizes));
+            self.output_stats.kept_sizes = Some(kept_sizes);
+        }
+        Ok(())
+    }
+
+    /// Read the next line, which must start with `expected`.
+    fn expect_line(&mut self, expected: &str) -> Result<String, PBarberError> {
+        let Some(line) = self.next_line() else {
+            return Err(PBarberError::UnexpectedLineStart {
+                expected: expected.to_string(),
+                found: "the start of the proof".to_string(),
+                position: None,
+            });
+        };
+        let line = line?;
+        self.assert_starts_with(&line, expected)
+            .map_err(|err| err.at(self.position()))?;
+        Ok(line)
+    }
+
+    /// Parse the line just read.
+    fn parse<'l>(&mut self, line: &'l str) -> Result<ProofLine<'l>, PBarberError> {
+        ProofLine::parse(line).map_err(|err| err.at(self.position()))
+    }
+
+    /// An error for the line just read.
+    fn malformed(&mut self, line: &str, message: &str) -> PBarberError {
+        PBarberError::MalformedLine {
+            position: Some(Box::new(self.position())),
+            line: line.to_string(),
+            message: message.to_string(),
+        }
+    }
+
+    /// The position of the line just read, for errors. Its number from the start of the
+    /// proof is found by counting the lines before it.
+    fn position(&mut self) -> LinePosition {
+        let offset = self.current_offset;
+        LinePosition {
+            line_number: self.lines_read,
+            from_end: true,
+            original_line_number: count_lines_before(self.lines.get_mut(), offset)
+                .ok()
+                .map(|lines| lines + 1),
+            column: None,
+            byte_offset: Some(offset),
+        }
+    }
+
+    /// Mark `term`, an antecedent of the kept constraint `id`, as needed, and delete it after
+    /// this use if it isn't needed again.
+    fn mark_antecedent(
+        &mut self,
+        id: Option<&str>,
+        term: &str,
+        depth: usize,
+        deletions: &mut Vec<String>,
+    ) {
+        if let Some(id) = id
+            && let Some(graph) = self.graph.as_mut()
+        {
+            graph.add_edge(term, id);
+        }
+        if let Some(id) = id
+            && self.model.is_some()
+            && !self.config.implicit_ids
+            && term.parse::<u64>().is_ok()
+        {
+            self.formula_references
+                .push((id.to_string(), term.to_string()));
+        }
+        if self.config.max_depth.is_some() {
+            let term_depth = self.depths.entry(term.to_string()).or_insert(depth + 1);
+            *term_depth = (*term_depth).min(depth + 1);
+        }
+        if !self.marked_for_output.contains(term) {
+            let eager = self.config.eager_deletion
+                && !(self.config.faithful_deletions && self.is_core(term));
+            if eager || self.is_marked_for_deletion(term) {
+                // We haven't marked this yet, so it's the last time
+                // this ID is needed in the proof, hence delete it
+                deletions.push(self.output_id(term));
+            }
+            self.marked_for_output.insert(term);
+        }
+    }
+
+    /// Record a literal used by a kept line, so its definition is kept, and delete the
+    /// definition after this (its last) use if literal deletion is on.
+    fn see_literal(&mut self, lit: &str, deletions: &mut Vec<String>) {
+        let lit = lit.strip_prefix("~").unwrap_or(lit);
+        if !lit.starts_with("x") || self.lits_seen.contains(lit) {
+            return;
+        }
+        self.lits_seen.insert(lit);
+        if self.config.lit_deletion {
+            for prefix in [FORWARD_LIT_DEF_PREFIX, REVERSE_LIT_DEF_PREFIX] {
+                deletions.push(format!("@{}{}", prefix, &lit));
+            }
+        }
+    }
+
+    /// Input deletions are dropped, and the constraints in them deleted after their last use
+    /// instead. With faithful deletions, deletions of core constraints stay where they were,
+    /// as the checker may check them and they change what later steps are checked against.
+    fn handle_deletion(&mut self, deletion: &Deletion, line: &str) -> Result<(), PBarberError> {
+        let faithful = self.config.faithful_deletions;
+        if !(faithful && matches!(deletion, Deletion::Spec { .. })) {
+            self.observer.notify(|o| o.on_dropped(line));
+        }
+        match deletion {
+            Deletion::Ids(ids) => {
+                let mut core_deletions = Vec::new();
+                for id in ids {
+                    if faithful && self.is_core(id) {
+                        core_deletions.push(self.output_id(id));
+                        if !self.is_formula_id(id) {
+                            // The deletion needs the derivation it deletes
+                            self.marked_for_output.insert(id);
+                        }
+                    } else if !self.config.eager_deletion {
+                        // We will delete these if anyone uses them
+                        self.marked_for_deletion.insert(id);
+                    }
+                }
+                if !core_deletions.is_empty() {
+                    self.write_deletion(&format!("del id {} ;", core_deletions.join(" ")))?;
+                }
+            }
+            Deletion::Range(range) => {
+                let core_end = if faithful {
+                    range.end.min(self.formula_constraints + 1).max(range.start)
+                } else {
+                    range.start
+                };
+                if core_end > range.start {
+                    self.write_deletion(&format!("del range {} {} ;", range.start, core_end))?;
+                }
+                if !self.config.eager_deletion {
+                    self.deleted_ranges
+                        .insert(core_end.max(range.start)..range.end);
+                }
+            }
+            Deletion::Spec { .. } if faithful => self.keep_line(line)?,
+            Deletion::Spec { .. } => (),
+        }
+        Ok(())
+    }
+
+    fn is_formula_id(&self, id: &str) -> bool {
+        id.parse::<u64>()
+            .is_ok_and(|n| (1..=self.formula_constraints).contains(&n))
+    }
+
+    /// Whether the constraint is in the checker's core set: a formula constraint, or one
+    /// moved there with `core id`.
+    fn is_core(&self, id: &str) -> bool {
+        self.is_formula_id(id) || self.core_ids.contains(id)
+    }
+
+    /// Find the lines the reverse scan needs with the parallel pre-pass, so the scan can skip
+    /// the rest of the proof.
+    fn mark_in_parallel(&mut self, assume_last_derived: bool) -> Result<(), PBarberError> {
+        if self.config.implicit_ids || assume_last_derived || self.config.faithful_deletions {
+            self.warn(
+                WarningKind::IgnoredOption,
+                "parallel marking doesn't support implicit IDs, an unknown goal or faithful deletions, so the proof was scanned sequentially"
+                    .to_string(),
+            )?;
+            return Ok(());
+        }
+        let stats = self.has_stats();
+        let marks = parallel::mark(
+            self.lines.get_mut(),
+            self.current_offset,
+            &self.marked_for_output,
+            self.config.eager_deletion,
+            stats,
+        )?;
+        self.marked_for_deletion = marks.marked_for_deletion;
+        self.deleted_ranges = marks.deleted_ranges;
+        if self.has_stats() {
+            // The end of the proof was recorded before the pre-pass
+            let mut stats = marks.stats;
+            stats.append(&self.input_stats);
+            self.input_stats = stats;
+        }
+        debug!(
+            "parallel marking selected {} lines for the reverse scan",
+            marks.offsets.len()
+        );
+        self.selected_offsets = Some(marks.offsets);
+        self.lines_read = None;
+        Ok(())
+    }
+
+    fn write_deletion(&mut self, line: &str) -> io::Result<()> {
+        self.observer.notify(|o| o.on_deletion_emitted(line));
+        self.write_line(line)
+    }
+
+    /// Note that an input line is kept, as it is or rewritten.
+    fn kept_input(&mut self, line: &str) {
+        self.observer.notify(|o| o.on_kept(line));
+        if self.has_stats() {
+            self.kept_sizes.record_line(line);
+        }
+    }
+
+    /// Keep the input line that was just read.
+    fn keep_line(&mut self, content: &str) -> io::Result<()> {
+        self.kept_input(content);
+        self.record_origin(true);
+        if self.config.reverse_output {
+            return self.write_output(content);
+        }
+        if self.has_stats() {
+            self.output_stats.record_line(content);
+        }
+        self.kept.push(KeptLine::Input(self.current_offset));
+        Ok(())
+    }
+
+    /// Write a line the trimmer made, or a kept line when writing in reverse.
+    fn write_output(&mut self, content: &str) -> io::Result<()> {
+        if self.has_stats() {
+            self.output_stats.record_line(content);
+        }
+        if self.config.reverse_output {
+            writeln!(self.out, "{}", content)
         } else {
-            Ok(None)
+            self.kept.push(KeptLine::Generated(content.to_string()));
+            Ok(())
+        }
+    }
+
+    /// The next kept line in forwards order, copying input lines back out of the input.
+    fn next_kept_line(&mut self, line: &mut String) -> io::Result<bool> {
+        let Some(kept_line) = self.kept.pop() else {
+            return Ok(false);
+        };
+        match kept_line {
+            KeptLine::Generated(content) => *line = content,
+            KeptLine::Frontier(id) => match self.frontier_lines.get(&id) {
+                Some(restated) => line.clone_from(restated),
+                None => {
+                    return Err(io::Error::other(format!(
+                        "{id} was cut at the depth limit but not restated"
+                    )));
+                }
+            },
+            KeptLine::Input(offset) => {
+                self.kept_reader
+                    .read_line_at(self.lines.get_mut(), offset, line)?;
+            }
         }
+        Ok(true)
+    }
+
+    /// Work out the constraint each line derives with a forwards pass over the input, passing
+    /// each ID it's known by to `visit` with the constraint, or `None` if it can't be worked
+    /// out (e.g. it uses a formula constraint and there's no OPB model). Stops early if
+    /// `visit` returns false.
+    fn visit_derived_constraints(
+        &mut self,
+        mut visit: impl FnMut(&str, Option<&Constraint>) -> bool,
+    ) -> io::Result<()> {
+        let mut constraints = HashMap::<String, Constraint>::new();
+        if let Some(model) = &self.model {
+            for (i, constraint) in model.constraints().enumerate() {
+                if let Some(constraint) = constraint.parsed() {
+                    constraints.insert((i + 1).to_string(), constraint.clone());
+                }
+            }
+        }
+
+        let implicit_ids = self.config.implicit_ids;
+        let mut next_implicit_id = self.formula_constraints + 1;
+        let read_buffer = self.config.buffers.read_buffer;
+        let input = self.lines.get_mut();
+        input.seek(SeekFrom::Start(0))?;
+        for text in BufReader::with_capacity(read_buffer, input).lines() {
+            let text = text?;
+            let Ok(line) = ProofLine::parse(split_debug_marker(&text).1) else {
+                continue;
+            };
+            if let ProofLine::Del(Deletion::Ids(ids)) = &line {
+                for id in ids {
+                    constraints.remove(*id);
+                }
+                continue;
+            }
+            if !line.derives() {
+                continue;
+            }
+            let implicit_id = implicit_ids.then(|| {
+                next_implicit_id += 1;
+                (next_implicit_id - 1).to_string()
+            });
+
+            let constraint = match &line {
+                ProofLine::Pol { terms, .. } => evaluate_pol(terms, |id| constraints.get(id)),
+                ProofLine::Assertion { constraint, .. }
+                | ProofLine::Rup { constraint, .. }
+                | ProofLine::Red { constraint, .. }
+                | ProofLine::Ia { constraint, .. } => Constraint::parse(constraint),
+                _ => None,
+            };
+            for id in line.label().into_iter().chain(implicit_id.as_deref()) {
+                if !visit(id, constraint.as_ref()) {
+                    return Ok(());
+                }
+                match &constraint {
+                    Some(constraint) => constraints.insert(id.to_string(), constraint.clone()),
+                    None => constraints.remove(id),
+                };
+            }
+        }
+        Ok(())
+    }
+
+    /// Find the IDs whose constraint can't be worked out, so the derivation can't be cut there
+    /// at the depth limit.
+    fn find_unrestatable(&mut self) -> io::Result<()> {
+        let mut unrestatable = HashSet::new();
+        self.visit_derived_constraints(|id, constraint| {
+            if constraint.is_none() {
+                unrestatable.insert(id.to_string());
+            }
+            true
+        })?;
+        self.unrestatable = unrestatable;
+        Ok(())
+    }
+
+    /// Work out the constraints derived at the depth limit, so they can be written as bare
+    /// assertions, which the justifier passes through unchanged.
+    fn restate_frontier(&mut self) -> Result<(), PBarberError> {
+        let frontier = std::mem::take(&mut self.frontier);
+        let mut restated = HashMap::<String, Constraint>::new();
+        self.visit_derived_constraints(|id, constraint| {
+            if let Some(constraint) = constraint.filter(|_| frontier.contains(id)) {
+                restated.insert(id.to_string(), constraint.clone());
+            }
+            restated.len() < frontier.len()
+        })?;
+
+        for id in &frontier {
+            // Only IDs that can be restated are cut at the depth limit
+            let Some(constraint) = restated.get(id) else {
+                return Err(PBarberError::Internal(format!(
+                    "couldn't restate {id} at the depth limit"
+                )));
+            };
+            let line = format!("{} a {} ;", self.output_id(id), constraint);
+            self.frontier_lines.insert(id.clone(), line);
+        }
+        self.frontier = frontier;
+        Ok(())
+    }
+
+    /// Find the number of formula constraints and derived constraints, and the IDs moved into
+    /// the core, with a forwards pass before the reverse scan.
+    fn prescan(&mut self) -> Result<(), PBarberError> {
+        let read_buffer = self.config.buffers.read_buffer;
+        let input = self.lines.get_mut();
+        input.seek(SeekFrom::Start(0))?;
+        let mut formula_constraints = 0;
+        let mut derived = 0;
+        for text in BufReader::with_capacity(read_buffer, input).lines() {
+            let text = text?;
+            let Ok(line) = ProofLine::parse(split_debug_marker(&text).1) else {
+                continue;
+            };
+            match line {
+                ProofLine::Formula { constraints } => {
+                    formula_constraints = constraints.unwrap_or(0)
+                }
+                ProofLine::Core { ids } => {
+                    for id in ids {
+                        self.core_ids.insert(id);
+                    }
+                }
+                line if line.derives() => derived += 1,
+                _ => (),
+            }
+        }
+        self.formula_constraints = formula_constraints;
+        self.next_implicit_id = formula_constraints + derived;
+        Ok(())
+    }
+
+    /// The implicit ID of the line just read (counting down from the end), if it derives a
+    /// constraint and implicit IDs are enabled.
+    fn take_implicit_id(&mut self, line: &ProofLine) -> Option<String> {
+        if !self.config.implicit_ids || !line.derives() {
+            return None;
+        }
+        let id = self.next_implicit_id;
+        self.next_implicit_id = self.next_implicit_id.saturating_sub(1);
+        Some(id.to_string())
+    }
+
+    /// Trimming changes the implicit numbering of derived constraints, so numeric references
+    /// to them are written as explicit `@i<n>` labels instead. Formula constraints keep their
+    /// numbers.
+    fn output_id(&self, id: &str) -> String {
+        if !self.config.implicit_ids {
+            return id.to_string();
+        }
+        IdStyle::Symbolic {
+            formula_constraints: self.formula_constraints,
+        }
+        .apply(id)
+        .into_owned()
+    }
+
+    /// Rewrite a kept line so it carries an explicit label and refers to derived constraints
+    /// by label.
+    fn with_explicit_ids(&self, text: &str, line: &ProofLine, id: &str) -> String {
+        let mut tokens = text.split(" ");
+        if line.label().is_some() {
+            tokens.next();
+        }
+        let mut explicit_line = self.output_id(id);
+        let rule = tokens.next().unwrap_or_default();
+        explicit_line.push(' ');
+        explicit_line.push_str(rule);
+        let is_pol = matches!(line, ProofLine::Pol { .. });
+        let is_rup = matches!(line, ProofLine::Rup { .. });
+        let terms: Vec<&str> = tokens.collect();
+        // The hints of a `rup` follow the first `;`
+        let mut in_hints = false;
+        for (i, term) in terms.iter().enumerate() {
+            explicit_line.push(' ');
+            if (is_pol && !is_scalar(&terms, i)) || (in_hints && *term != ";") {
+                explicit_line.push_str(&self.output_id(term));
+            } else {
+                explicit_line.push_str(term);
+                in_hints |= is_rup && *term == ";";
+            }
+        }
+        explicit_line
+    }
+
+    /// Apply the unknown rules policy to `line`, returning whether to keep it.
+    fn keeps_unknown_line(&mut self, line: &str, rule: &str) -> Result<bool, PBarberError> {
+        if self.has_stats() {
+            let kept = self.config.unknown_rules == UnknownRulePolicy::WarnKeep;
+            self.input_stats.record_unknown(rule, line, kept);
+        }
+        match self.config.unknown_rules {
+            UnknownRulePolicy::Error => Err(PBarberError::UnknownRule(line.to_string())),
+            UnknownRulePolicy::WarnKeep => {
+                if self.unknown_rules_seen.insert(rule.to_string()) {
+                    self.warn(
+                        WarningKind::UnknownRule,
+                        format!(
+                            "kept line(s) with unknown rule `{rule}` unchanged, e.g. `{line}`, guessing they only depend on the IDs they mention, so the trimmed proof may be invalid"
+                        ),
+                    )?;
+                }
+                Ok(true)
+            }
+            UnknownRulePolicy::Drop => {
+                self.observer.notify(|o| o.on_dropped(line));
+                Ok(false)
+            }
+        }
+    }
+
+    /// Warn that a kept line may depend on constraints the trimmer can't tell, e.g. a `rup`
+    /// without hints.
+    fn warn_untraced(&mut self, line: &str, rule: &str) -> Result<(), PBarberError> {
+        if self.unknown_rules_seen.insert(rule.to_string()) {
+            self.warn(
+                WarningKind::UnknownRule,
+                format!(
+                    "kept line(s) using `{rule}` without knowing what they depend on, e.g. `{line}`, so the trimmed proof may be invalid"
+                ),
+            )?;
+        }
+        Ok(())
+    }
+
+    fn check_formula_size(&mut self, found: Option<u64>, f_line: &str) -> Result<(), PBarberError> {
+        let Some(expected) = self.model.as_ref().map(OpbModel::len) else {
+            return Ok(());
+        };
+        if let Some(found) = found
+            && found != expected as u64
+        {
+            self.warn(
+                WarningKind::Recovered,
+                format!(
+                    "proof declares {found} formula constraints (`{f_line}`), but the OPB model has {expected}"
+                ),
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Check the numeric IDs referenced by derivations that are within the declared formula
+    /// exist in the model. Higher IDs are derived constraints, and unknown without a count.
+    fn check_formula_references(&mut self, declared: Option<u64>) -> Result<(), PBarberError> {
+        let references = std::mem::take(&mut self.formula_references);
+        let (Some(model), Some(declared)) = (&self.model, declared) else {
+            return Ok(());
+        };
+        let formula_size = model.len();
+        let missing: Vec<_> = references
+            .into_iter()
+            .filter(|(_, formula_id)| {
+                formula_id.parse::<u64>().is_ok_and(|n| n <= declared)
+                    && model.resolve(formula_id).is_none()
+            })
+            .collect();
+        for (id, formula_id) in missing {
+            self.warn(
+                WarningKind::Recovered,
+                format!(
+                    "{id} references formula constraint {formula_id}, but the OPB model only has {formula_size} constraints"
+                ),
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Record a warning, or fail with it if warnings are denied.
+    fn warn(&mut self, kind: WarningKind, message: String) -> Result<(), PBarberError> {
+        if self.config.deny_warnings {
+            return Err(PBarberError::DeniedWarning { kind, message });
+        }
+        self.warnings.push((kind, message));
+        Ok(())
+    }
+
+    /// How many derivations at or past the depth limit were kept, as their constraints
+    /// couldn't be restated (e.g. they use formula constraints and there's no OPB model).
+    pub fn kept_past_depth_limit(&self) -> usize {
+        self.kept_past_depth_limit
+    }
+
+    /// Problems found while trimming that don't stop the proof from being trimmed.
+    pub fn warnings(&self) -> impl Iterator<Item = &str> {
+        self.warnings.iter().map(|(_, message)| message.as_str())
+    }
+
+    /// Pass the warnings on to `diagnostics`, stopping at the first it fails with.
+    pub fn report_warnings(&self, diagnostics: &mut dyn Diagnostics) -> Result<(), PBarberError> {
+        for (kind, message) in &self.warnings {
+            diagnostics.warning(*kind, message)?;
+        }
+        Ok(())
+    }
+
+    fn is_marked_for_deletion(&self, id: &str) -> bool {
+        if self.marked_for_deletion.contains(id) {
+            return true;
+        }
+        match id.parse::<u64>() {
+            Ok(numeric_id) => self.deleted_ranges.contains(numeric_id),
+            Err(_) => false,
+        }
+    }
+
+    /// Antecedent graph of the kept constraints. Only available after `trim` if a graph
+    /// format was requested in the config.
+    pub fn graph(&self) -> Option<&ProofGraph> {
+        self.graph.as_ref()
+    }
+
+    /// IDs of the formula constraints that the kept proof lines reference, i.e. those the
+    /// trimmed proof actually depends on. Only available after `trim` if the core was
+    /// requested in the config.
+    pub fn formula_core(&self) -> Option<Vec<String>> {
+        if !self.config.wants_core() {
+            return None;
+        }
+        let mut core: Vec<String> = self
+            .marked_for_output
+            .iter()
+            .filter(|id| self.is_formula_id(id))
+            .map(|id| id.to_string())
+            .collect();
+        core.sort();
+        Some(core)
+    }
+}
+
+impl<W: Write> Trimmer<File, W> {
+    /// Trim a proof read from a non-seekable stream (e.g. stdin or a pipe from the solver)
+    /// by spooling it to a temporary file first.
+    pub fn from_stream<S: Read>(
+        input: S,
+        out: W,
+        config: TrimmerConfig,
+    ) -> Result<Self, PBarberError> {
+        Self::from_file(spool(input)?, out, config)
+    }
+
+    /// [`Trimmer::try_with_config`] for a proof file, which is read through a memory map with
+    /// the `mmap` feature. The file mustn't change while it's being trimmed.
+    pub fn from_file(input: File, out: W, config: TrimmerConfig) -> Result<Self, PBarberError> {
+        let trimmer = Self::try_with_config(input, out, config)?;
+        Ok(Self {
+            lines: trimmer.lines.mapped()?,
+            ..trimmer
+        })
+    }
+}
+
+/// Copy a stream into an anonymous temporary file so it can be read in reverse.
+pub fn spool<S: Read>(mut input: S) -> io::Result<File> {
+    let mut spooled = tempfile::tempfile()?;
+    io::copy(&mut input, &mut spooled)?;
+    Ok(spooled)
+}
+
+/// The lines of a trimmed proof, from [`Trimmer::lines`].
+pub struct TrimmedLines<R: Read + Seek, W> {
+    trimmer: Trimmer<R, W>,
+    reverse: bool,
+    scanned: bool,
+    done: bool,
+}
+
+impl<R: Read + Seek, W: Write> TrimmedLines<R, W> {
+    /// The trimmer, e.g. for its warnings and stats once all the lines have been taken.
+    pub fn trimmer(&self) -> &Trimmer<R, W> {
+        &self.trimmer
+    }
+}
+
+impl<R: Read + Seek, W: Write> Iterator for TrimmedLines<R, W> {
+    type Item = Result<String, PBarberError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        if !self.scanned {
+            self.scanned = true;
+            if let Err(err) = self.trimmer.scan() {
+                self.done = true;
+                return Some(Err(err));
+            }
+            if self.reverse {
+                self.trimmer.kept.reverse();
+            }
+        }
+        let mut line = String::new();
+        let kept = self.trimmer.next_kept_line(&mut line);
+        self.done = !matches!(kept, Ok(true));
+        match kept {
+            Ok(true) => Some(Ok(line)),
+            Ok(false) => None,
+            Err(err) => Some(Err(err.into())),
+        }
+    }
+}
+
+/// Reads lines back out of the input by their offsets, a block at a time, as the kept lines
+/// are read in order and are often close together.
+#[derive(Default)]
+struct BlockReader {
+    /// Offset in the input of the first byte in `buf`
+    start: u64,
+    buf: Vec<u8>,
+}
+
+impl BlockReader {
+    const BLOCK_SIZE: u64 = 64 * 1024;
+
+    /// Read the line starting at `offset` into `line`.
+    fn read_line_at<R: Read + Seek>(
+        &mut self,
+        input: &mut R,
+        offset: u64,
+        line: &mut String,
+    ) -> io::Result<()> {
+        let cached_line = offset
+            .checked_sub(self.start)
+            .map(|from| from as usize)
+            .filter(|from| *from < self.buf.len())
+            .and_then(|from| {
+                let len = self.buf[from..].iter().position(|b| *b == b'\n')?;
+                Some(&self.buf[from..from + len])
+            });
+        if let Some(cached_line) = cached_line {
+            return set_line(line, cached_line);
+        }
+        input.seek(SeekFrom::Start(offset))?;
+        self.start = offset;
+        self.buf.clear();
+        loop {
+            let read = input
+                .by_ref()
+                .take(Self::BLOCK_SIZE)
+                .read_to_end(&mut self.buf)?;
+            if let Some(len) = self.buf.iter().position(|b| *b == b'\n') {
+                return set_line(line, &self.buf[..len]);
+            }
+            if read == 0 {
+                // The last line, without a newline
+                return set_line(line, &self.buf);
+            }
+        }
+    }
+}
+
+/// Read the line starting at `offset`, without its newline.
+fn read_line_at<R: Read + Seek>(input: &mut R, offset: u64) -> io::Result<String> {
+    input.seek(SeekFrom::Start(offset))?;
+    let mut line = String::new();
+    BufReader::new(input).read_line(&mut line)?;
+    line.truncate(line.trim_end_matches(['\n', '\r']).len());
+    Ok(line)
+}
+
+/// The number of lines that end before `offset` in `input`.
+fn count_lines_before<R: Read + Seek>(input: &mut R, offset: u64) -> io::Result<u64> {
+    input.seek(SeekFrom::Start(0))?;
+    let mut reader = BufReader::new(input.take(offset));
+    let mut lines = 0;
+    loop {
+        let buf = reader.fill_buf()?;
+        if buf.is_empty() {
+            return Ok(lines);
+        }
+        lines += buf.iter().filter(|b| **b == b'\n').count() as u64;
+        let len = buf.len();
+        reader.consume(len);
+    }
+}
+
+/// The numbers (from 1) of the lines of `input` starting at the sorted `offsets`.
+fn line_numbers_at<R: Read + Seek>(input: &mut R, offsets: &[u64]) -> io::Result<Vec<u64>> {
+    input.seek(SeekFrom::Start(0))?;
+    let mut reader = BufReader::new(input);
+    let mut line_numbers = Vec::with_capacity(offsets.len());
+    let mut offsets = offsets.iter().peekable();
+    let (mut offset, mut line_number) = (0, 1);
+    let mut line = Vec::new();
+    while offsets.peek().is_some() {
+        while offsets.next_if(|next| **next == offset).is_some() {
+            line_numbers.push(line_number);
+        }
+        line.clear();
+        let read = reader.read_until(b'\n', &mut line)?;
+        if read == 0 {
+            break;
+        }
+        offset += read as u64;
+        line_number += 1;
+    }
+    Ok(line_numbers)
+}
+
+/// The literal defined by a literal-definition ID (`@lf<lit>` or `@lr<lit>`).
+pub(crate) fn lit_def_literal(id: &str) -> Option<&str> {
+    let id = id.strip_prefix("@")?;
+    id.strip_prefix(FORWARD_LIT_DEF_PREFIX)
+        .or_else(|| id.strip_prefix(REVERSE_LIT_DEF_PREFIX))
+}
+
+/// The constraint IDs a kept line other than a `pol` derivation depends on: the hints of a
+/// `rup`, the antecedent of an `ia`, and for unknown rules any `@` IDs it mentions.
+pub(crate) fn antecedents_of<'a>(line: &ProofLine<'a>) -> Vec<&'a str> {
+    match line {
+        ProofLine::Rup { hints, .. } => hints.clone(),
+        ProofLine::Ia { antecedent, .. } => antecedent.iter().copied().collect(),
+        ProofLine::Other { body, .. } => body
+            .split_whitespace()
+            .filter(|token| token.starts_with('@'))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::graph::GraphFormat;
+
+    fn trim(proof: &str, config: TrimmerConfig) -> String {
+        let mut out = Vec::new();
+        Trimmer::try_with_config(Cursor::new(proof.as_bytes()), &mut out, config)
+            .unwrap()
+            .trim()
+            .unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    const PROOF: &str = "\
+pseudo-Boolean proof version 2.0
+f 2 ;
+@c1 pol 1 2 + ;
+% unused
+@dead pol 1 1 + ;
+
+@c2 pol @c1 2 + ;
+del id @dead ;
+@c3 pol @c2 @c1 + ;
+output NONE ;
+conclusion UNSAT : @c3 ;
+end pseudo-Boolean proof
+";
+
+    const TRIMMED: &str = "\
+pseudo-Boolean proof version 2.0
+f 2 ;
+@c1 pol 1 2 + ;
+@c2 pol @c1 2 + ;
+@c3 pol @c2 @c1 + ;
+output NONE ;
+conclusion UNSAT : @c3 ;
+end pseudo-Boolean proof
+";
+
+    #[test]
+    fn drops_what_the_conclusion_doesnt_need() {
+        assert_eq!(trim(PROOF, TrimmerConfig::default()), TRIMMED);
+    }
+
+    #[test]
+    fn formula_core_leaves_out_derived_constraints() {
+        // The unlabelled line derives constraint 4
+        let proof = "\
+pseudo-Boolean proof version 2.0
+f 3 ;
+pol 1 2 + ;
+@c2 pol 4 1 + ;
+output NONE ;
+conclusion UNSAT : @c2 ;
+end pseudo-Boolean proof
+";
+        let config = TrimmerConfig {
+            core: true,
+            implicit_ids: true,
+            ..TrimmerConfig::default()
+        };
+        let mut trimmer =
+            Trimmer::try_with_config(Cursor::new(proof.as_bytes()), io::sink(), config).unwrap();
+        trimmer.trim().unwrap();
+        assert_eq!(trimmer.formula_core().unwrap(), ["1", "2"]);
+    }
+
+    #[test]
+    fn missing_opb_model_is_an_io_error() {
+        let config = TrimmerConfig {
+            opb_path: Some("missing.opb".into()),
+            ..TrimmerConfig::default()
+        };
+        let result = Trimmer::try_with_config(Cursor::new(PROOF.as_bytes()), io::sink(), config);
+        assert!(matches!(result, Err(PBarberError::Io(_))));
+    }
+
+    #[test]
+    fn parallel_marking_keeps_the_same_lines() {
+        let config = TrimmerConfig {
+            parallel: true,
+            ..TrimmerConfig::default()
+        };
+        assert_eq!(trim(PROOF, config), TRIMMED);
+    }
+
+    #[test]
+    fn rup_hints_and_unknown_rule_ids_are_kept() {
+        let proof = "\
+pseudo-Boolean proof version 2.0
+f 2 ;
+@c1 pol 1 2 + ;
+@dead pol 1 1 + ;
+pol @c1 1 + ;
+@c2 rup 1 x1 >= 1 ; @c1 2 ;
+@c3 frob @c2 ;
+output NONE ;
+conclusion UNSAT : @c3 ;
+end pseudo-Boolean proof
+";
+        let config = TrimmerConfig {
+            emit_graph: Some(GraphFormat::Json),
+            ..TrimmerConfig::default()
+        };
+        let mut out = Vec::new();
+        let mut trimmer =
+            Trimmer::try_with_config(Cursor::new(proof.as_bytes()), &mut out, config).unwrap();
+        trimmer.trim().unwrap();
+        let graph = trimmer.graph().unwrap();
+        let edges: Vec<_> = graph
+            .edges
+            .iter()
+            .map(|edge| (edge.from.as_str(), edge.to.as_str()))
+            .collect();
+        assert!(edges.contains(&("@c1", "@c2")) && edges.contains(&("2", "@c2")));
+        assert!(
+            graph
+                .nodes
+                .iter()
+                .any(|node| node.id == "2" && node.rule == "formula")
+        );
+        let warnings: Vec<_> = trimmer.warnings().map(str::to_string).collect();
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("`frob`") && warnings[0].contains("may be invalid"));
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "\
+pseudo-Boolean proof version 2.0
+f 2 ;
+@c1 pol 1 2 + ;
+@c2 rup 1 x1 >= 1 ; @c1 2 ;
+@c3 frob @c2 ;
+output NONE ;
+conclusion UNSAT : @c3 ;
+end pseudo-Boolean proof
+"
+        );
+    }
+
+    #[test]
+    fn implicit_ids_are_labelled_in_the_output() {
+        let proof = "\
+pseudo-Boolean proof version 2.0
+f 2
+pol 1 2 + ;
+pol 1 1 + ;
+pol 3 2 + ;
+output NONE
+conclusion UNSAT : 5 ;
+end pseudo-Boolean proof
+";
+        let config = TrimmerConfig {
+            implicit_ids: true,
+            ..TrimmerConfig::default()
+        };
+        assert_eq!(
+            trim(proof, config),
+            "\
+pseudo-Boolean proof version 2.0
+f 2
+@i3 pol 1 2 + ;
+@i5 pol @i3 2 + ;
+output NONE
+conclusion UNSAT : @i5 ;
+end pseudo-Boolean proof
+"
+        );
+    }
+
+    #[test]
+    fn ranges_deleted_are_deleted_after_their_last_use() {
+        let proof = "\
+pseudo-Boolean proof version 2.0
+f 2
+pol 1 2 + ;
+pol 3 1 + ;
+pol 4 2 + ;
+del range 3 5 ;
+pol 5 1 + ;
+output NONE
+conclusion UNSAT : 6 ;
+end pseudo-Boolean proof
+";
+        let config = TrimmerConfig {
+            implicit_ids: true,
+            ..TrimmerConfig::default()
+        };
+        assert_eq!(
+            trim(proof, config),
+            "\
+pseudo-Boolean proof version 2.0
+f 2
+@i3 pol 1 2 + ;
+@i4 pol @i3 1 + ;
+del id @i3 ;
+@i5 pol @i4 2 + ;
+del id @i4 ;
+@i6 pol @i5 1 + ;
+output NONE
+conclusion UNSAT : @i6 ;
+end pseudo-Boolean proof
+"
+        );
+    }
+
+    #[test]
+    fn spec_deletions_are_only_kept_faithfully() {
+        let proof = "\
+pseudo-Boolean proof version 2.0
+f 2 ;
+@c1 pol 1 2 + ;
+del spec 1 x1 >= 1 ;
+@c2 pol @c1 2 + ;
+output NONE ;
+conclusion UNSAT : @c2 ;
+end pseudo-Boolean proof
+";
+        assert_eq!(
+            trim(proof, TrimmerConfig::default()),
+            "\
+pseudo-Boolean proof version 2.0
+f 2 ;
+@c1 pol 1 2 + ;
+@c2 pol @c1 2 + ;
+output NONE ;
+conclusion UNSAT : @c2 ;
+end pseudo-Boolean proof
+"
+        );
+        let config = TrimmerConfig {
+            faithful_deletions: true,
+            ..TrimmerConfig::default()
+        };
+        assert_eq!(trim(proof, config), proof);
+    }
+
+    #[test]
+    fn derivations_at_the_depth_limit_are_restated() {
+        let proof = "\
+pseudo-Boolean proof version 2.0
+f 0
+@c1 pol x1 x2 + ;
+@c2 pol @c1 x3 + ;
+@c3 pol @c2 @c2 + ;
+output NONE
+conclusion UNSAT : @c3 ;
+end pseudo-Boolean proof
+";
+        let config = TrimmerConfig {
+            max_depth: Some(1),
+            ..TrimmerConfig::default()
+        };
+        assert_eq!(
+            trim(proof, config),
+            "\
+pseudo-Boolean proof version 2.0
+f 0
+@c2 a 1 x1 1 x2 1 x3 >= 0 ;
+@c3 pol @c2 @c2 + ;
+output NONE
+conclusion UNSAT : @c3 ;
+end pseudo-Boolean proof
+"
+        );
+    }
+
+    #[test]
+    fn derivations_that_cant_be_restated_are_kept_whole() {
+        // Without the OPB model the formula constraints, and so `@c1` and `@c2`, are unknown
+        let proof = "\
+pseudo-Boolean proof version 2.0
+f 3
+@c1 pol 1 2 + ;
+@c2 pol @c1 3 + ;
+@c4 pol @c2 ;
+output NONE
+conclusion UNSAT : @c4 ;
+end pseudo-Boolean proof
+";
+        let config = TrimmerConfig {
+            max_depth: Some(1),
+            ..TrimmerConfig::default()
+        };
+        let mut out = Vec::new();
+        let mut trimmer =
+            Trimmer::try_with_config(Cursor::new(proof.as_bytes()), &mut out, config).unwrap();
+        trimmer.trim().unwrap();
+        assert_eq!(trimmer.kept_past_depth_limit(), 2);
+        drop(trimmer);
+        assert_eq!(String::from_utf8(out).unwrap(), proof);
     }
 }
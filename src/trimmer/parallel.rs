@@ -0,0 +1,181 @@
+use std::{
+    io::{self, Read, Seek, SeekFrom},
+    ops::Range,
+};
+
+use rayon::prelude::*;
+use ustr::{Ustr, UstrMap, UstrSet, ustr};
+
+use crate::{
+    PBarberError, ProofFileStats,
+    id_set::{IdSet, RangeSet},
+    proof_line::{Deletion, PolToken, ProofLine},
+    split_debug_marker,
+};
+
+use super::{antecedents_of, lit_def_literal};
+
+/// Bytes of the proof handed to each worker at a time.
+const CHUNK_SIZE: usize = 16 * 1024 * 1024;
+
+/// What a worker learned from one chunk of the proof.
+#[derive(Default)]
+struct ChunkDeps {
+    /// Labelled derivations with their offset and the IDs they use
+    derivations: Vec<(Ustr, u64, Vec<Ustr>)>,
+    /// Lines the sequential scan has to see whatever is needed (formula/header lines,
+    /// literal definitions and unknown lines)
+    other_offsets: Vec<u64>,
+    deleted_ids: Vec<Ustr>,
+    deleted_ranges: Vec<Range<u64>>,
+    stats: ProofFileStats,
+}
+
+/// The result of the parallel marking pre-pass.
+pub(super) struct ParallelMarks {
+    /// Offsets (ascending) of every line the sequential scan may need to keep
+    pub(super) offsets: Vec<u64>,
+    pub(super) marked_for_deletion: IdSet,
+    pub(super) deleted_ranges: RangeSet,
+    /// Input stats for the scanned part of the proof, recorded forwards
+    pub(super) stats: ProofFileStats,
+}
+
+/// Scan the first `end` bytes of the proof in chunks on the rayon thread pool, building the
+/// dependencies of every labelled derivation, then find everything reachable from `goals`.
+/// The result over-approximates what the sequential scan keeps (e.g. it ignores deletions
+/// and the depth limit), so that scan only has to visit these lines to give the same output.
+pub(super) fn mark<R: Read + Seek>(
+    input: &mut R,
+    end: u64,
+    goals: &IdSet,
+    eager_deletion: bool,
+    stats: bool,
+) -> Result<ParallelMarks, PBarberError> {
+    let batch_size = rayon::current_num_threads().max(1);
+    let mut chunks = Vec::<ChunkDeps>::new();
+    let mut pos = 0;
+    let mut carry = Vec::new();
+    input.seek(SeekFrom::Start(0))?;
+    while pos < end {
+        // Read a batch of chunks sequentially, each ending on a line boundary
+        let mut batch = Vec::with_capacity(batch_size);
+        while batch.len() < batch_size && pos < end {
+            let read_size = (CHUNK_SIZE as u64).min(end - pos);
+            let mut chunk = std::mem::take(&mut carry);
+            let start = pos - chunk.len() as u64;
+            let old_len = chunk.len();
+            chunk.resize(old_len + read_size as usize, 0);
+            input.read_exact(&mut chunk[old_len..])?;
+            pos += read_size;
+            if pos < end {
+                match chunk.iter().rposition(|b| *b == b'\n') {
+                    Some(i) => carry = chunk.split_off(i + 1),
+                    None => {
+                        // A single line longer than a chunk, keep reading
+                        carry = chunk;
+                        continue;
+                    }
+                }
+            }
+            batch.push((start, chunk));
+        }
+        let batch_deps = batch
+            .par_iter()
+            .map(|(start, chunk)| scan_chunk(*start, chunk, eager_deletion, stats))
+            .collect::<Result<Vec<_>, _>>()?;
+        chunks.extend(batch_deps);
+    }
+
+    // Merge the chunks in proof order
+    let mut antecedents = UstrMap::<Vec<(u64, Vec<Ustr>)>>::default();
+    let mut marks = ParallelMarks {
+        offsets: Vec::new(),
+        marked_for_deletion: IdSet::default(),
+        deleted_ranges: RangeSet::default(),
+        stats: ProofFileStats::default(),
+    };
+    for chunk in chunks {
+        for (id, offset, uses) in chunk.derivations {
+            antecedents.entry(id).or_default().push((offset, uses));
+        }
+        marks.offsets.extend(chunk.other_offsets);
+        for id in chunk.deleted_ids {
+            marks.marked_for_deletion.insert(&id);
+        }
+        marks.deleted_ranges.extend(chunk.deleted_ranges);
+        marks.stats.append(&chunk.stats);
+    }
+
+    // Everything reachable from the goals
+    let mut needed = UstrSet::default();
+    let mut queue: Vec<Ustr> = goals.iter().collect();
+    while let Some(id) = queue.pop() {
+        if !needed.insert(id) {
+            continue;
+        }
+        for (offset, uses) in antecedents.get(&id).into_iter().flatten() {
+            marks.offsets.push(*offset);
+            queue.extend(uses.iter().filter(|used| !needed.contains(*used)));
+        }
+    }
+    marks.offsets.sort_unstable();
+    marks.offsets.dedup();
+    Ok(marks)
+}
+
+fn scan_chunk(
+    start: u64,
+    chunk: &[u8],
+    eager_deletion: bool,
+    stats: bool,
+) -> Result<ChunkDeps, PBarberError> {
+    let mut deps = ChunkDeps::default();
+    let mut offset = start;
+    let chunk = chunk.strip_suffix(b"\n").unwrap_or(chunk);
+    for line in chunk.split(|b| *b == b'\n') {
+        let line_offset = offset;
+        offset += line.len() as u64 + 1;
+        let line = std::str::from_utf8(line)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?
+            .trim_end_matches('\r');
+        if stats {
+            deps.stats.record_line(line);
+        }
+        // Lines that don't parse are left to the sequential scan
+        let Ok(line) = ProofLine::parse(split_debug_marker(line).1) else {
+            deps.other_offsets.push(line_offset);
+            continue;
+        };
+
+        if let Some(label) = line.label() {
+            if lit_def_literal(label).is_some() {
+                deps.other_offsets.push(line_offset);
+                continue;
+            }
+            let uses = match &line {
+                ProofLine::Pol { terms, .. } => terms
+                    .iter()
+                    .filter_map(|token| match token {
+                        PolToken::Constraint(id) => Some(ustr(id)),
+                        _ => None,
+                    })
+                    .collect(),
+                line => antecedents_of(line).into_iter().map(ustr).collect(),
+            };
+            deps.derivations.push((ustr(label), line_offset, uses));
+            continue;
+        }
+        match line {
+            ProofLine::Comment(_) => continue,
+            ProofLine::Del(_) if eager_deletion => continue,
+            ProofLine::Del(Deletion::Ids(ids)) => {
+                deps.deleted_ids.extend(ids.into_iter().map(ustr))
+            }
+            ProofLine::Del(Deletion::Range(range)) => deps.deleted_ranges.push(range),
+            ProofLine::Del(Deletion::Spec { .. }) => (),
+            _ => deps.other_offsets.push(line_offset),
+        }
+    }
+    Ok(deps)
+}
@@ -0,0 +1,144 @@
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader, Read},
+    path::Path,
+    process::{Command, Stdio},
+    thread,
+};
+
+use crate::{PBarberError, VerifyConfig};
+use tracing::{info, warn};
+
+/// Where a line of the verified proof came from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LineOrigin {
+    /// Copied from this (1-based) line of the original proof
+    Original(u64),
+    /// Written by PBarber for this line of the original proof, e.g. a deletion after its last
+    /// use or a step justifying the assertion on it
+    WrittenFor(u64),
+    /// Written by PBarber, e.g. a deletion or a justification step
+    Generated,
+}
+
+/// The result of a VeriPB run.
+#[derive(Debug)]
+pub struct VerifyOutcome {
+    pub success: bool,
+    /// Proof line numbers VeriPB reported, in the order it reported them
+    pub reported_lines: Vec<u64>,
+}
+
+/// Run VeriPB on a proof, streaming its output to ours, and collect any proof line numbers it
+/// reports.
+pub fn run_veripb(
+    opb_path: &Path,
+    proof_path: &Path,
+    config: &VerifyConfig,
+) -> Result<VerifyOutcome, PBarberError> {
+    let mut child = Command::new(&config.veripb)
+        .args(&config.veripb_args)
+        .arg(opb_path)
+        .arg(proof_path)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| {
+            PBarberError::VerificationFailed(format!(
+                "couldn't run `{}`: {e}",
+                config.veripb.display()
+            ))
+        })?;
+
+    let stderr = child.stderr.take().expect("VeriPB stderr was piped");
+    let stderr_lines = thread::spawn(move || stream_lines(stderr, true));
+    let mut reported_lines =
+        stream_lines(child.stdout.take().expect("VeriPB stdout was piped"), false)?;
+    reported_lines.extend(
+        stderr_lines
+            .join()
+            .map_err(|_| PBarberError::Internal("VeriPB output thread panicked".to_string()))??,
+    );
+    let status = child.wait()?;
+    Ok(VerifyOutcome {
+        success: status.success(),
+        reported_lines,
+    })
+}
+
+/// Log each line of VeriPB's output and pick out the proof line numbers it mentions.
+fn stream_lines<R: Read>(output: R, to_stderr: bool) -> io::Result<Vec<u64>> {
+    let mut reported_lines = Vec::new();
+    for line in BufReader::new(output).lines() {
+        let line = line?;
+        if to_stderr {
+            warn!(target: "veripb", "{line}");
+        } else {
+            info!(target: "veripb", "{line}");
+        }
+        reported_lines.extend(reported_line_number(&line));
+    }
+    Ok(reported_lines)
+}
+
+/// The proof line numbers mentioned in a saved VeriPB output, in the order it mentions them.
+pub fn reported_lines<R: BufRead>(output: R) -> io::Result<Vec<u64>> {
+    let mut reported_lines = Vec::new();
+    for line in output.lines() {
+        reported_lines.extend(reported_line_number(&line?));
+    }
+    Ok(reported_lines)
+}
+
+/// The proof line number in a VeriPB message such as `Failed in proof file line 12.`
+fn reported_line_number(message: &str) -> Option<u64> {
+    let lower = message.to_lowercase();
+    if !lower.contains("proof") && !lower.contains("failed") {
+        return None;
+    }
+    let (_, after) = lower.split_once("line ")?;
+    let digits: String = after.chars().take_while(|c| c.is_ascii_digit()).collect();
+    digits.parse().ok()
+}
+
+/// Find where a line of a trimmed or styled proof came from in the original proof. Kept lines
+/// are copied verbatim, so a line is matched by its text, falling back to the definition of
+/// the same constraint ID (e.g. an assertion that was rewritten).
+pub fn map_line_to_original(
+    proof_path: &Path,
+    line_number: u64,
+    original_path: &Path,
+) -> Result<Option<(String, LineOrigin)>, PBarberError> {
+    let Some(line) = BufReader::new(File::open(proof_path)?)
+        .lines()
+        .nth(line_number.saturating_sub(1) as usize)
+        .transpose()?
+    else {
+        return Ok(None);
+    };
+    let line = line.trim().to_string();
+    let label = line
+        .split_whitespace()
+        .next()
+        .filter(|token| token.starts_with('@'));
+
+    let mut label_match = None;
+    for (i, original_line) in BufReader::new(File::open(original_path)?)
+        .lines()
+        .enumerate()
+    {
+        let original_line = original_line?;
+        let original_line = original_line.trim();
+        let original_number = i as u64 + 1;
+        if original_line == line {
+            return Ok(Some((line, LineOrigin::Original(original_number))));
+        }
+        if label_match.is_none()
+            && label.is_some_and(|label| original_line.split_whitespace().next() == Some(label))
+        {
+            label_match = Some(original_number);
+        }
+    }
+    let origin = label_match.map_or(LineOrigin::Generated, LineOrigin::Original);
+    Ok(Some((line, origin)))
+}
@@ -0,0 +1,29 @@
+use wasm_bindgen::prelude::*;
+
+#[cfg(feature = "justify")]
+use crate::{JustifierConfig, in_memory::style_bytes};
+use crate::{TrimmerConfig, in_memory::trim_bytes};
+
+/// Trim a proof, returning the trimmed proof. `implicit_ids` is `--implicit-ids`.
+#[wasm_bindgen(js_name = trim)]
+pub fn trim_js(proof: &[u8], implicit_ids: bool) -> Result<Vec<u8>, JsError> {
+    let config = TrimmerConfig {
+        implicit_ids,
+        ..TrimmerConfig::default()
+    };
+    Ok(trim_bytes(proof, config)?.proof)
+}
+
+/// Justify the assertions in a proof against a FlatZinc model (in JSON) and literal mapping,
+/// returning the styled proof. `read_forwards` is `--read-forwards`.
+#[cfg(feature = "justify")]
+#[wasm_bindgen(js_name = style)]
+pub fn style_js(
+    proof: &[u8],
+    fzn: &[u8],
+    lits: &[u8],
+    read_forwards: bool,
+) -> Result<Vec<u8>, JsError> {
+    let config = JustifierConfig::default().read_forwards(read_forwards);
+    Ok(style_bytes(proof, fzn, lits, config)?.proof)
+}
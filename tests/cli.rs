@@ -0,0 +1,131 @@
+//! Runs the `pbarber` binary on proofs in a temporary directory.
+
+use std::{
+    fs,
+    path::Path,
+    process::{Command, Output},
+};
+
+const PROOF: &str = "\
+pseudo-Boolean proof version 2.0
+f 2 ;
+@c1 pol 1 2 + ;
+@dead pol 1 1 + ;
+@c2 pol @c1 2 + ;
+@c3 pol @c2 @c1 + ;
+output NONE ;
+conclusion UNSAT : @c3 ;
+end pseudo-Boolean proof
+";
+
+fn run(dir: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_pbarber"))
+        .current_dir(dir)
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+fn pbarber(dir: &Path, args: &[&str]) {
+    let output = run(dir, args);
+    assert!(
+        output.status.success(),
+        "pbarber {} failed: {}",
+        args.join(" "),
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+/// The names of the files left in `dir`.
+fn files(dir: &Path) -> Vec<String> {
+    let mut names: Vec<String> = fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .collect();
+    names.sort();
+    names
+}
+
+#[test]
+fn trims_and_renumbers_in_place() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("proof.pbp"), PROOF).unwrap();
+
+    pbarber(
+        dir.path(),
+        &["trim", "--in-place", "--renumber", "proof.pbp"],
+    );
+
+    assert_eq!(
+        fs::read_to_string(dir.path().join("proof.pbp")).unwrap(),
+        "\
+pseudo-Boolean proof version 2.0
+f 2 ;
+@1 pol 1 2 + ;
+@2 pol @1 2 + ;
+@3 pol @2 @1 + ;
+output NONE ;
+conclusion UNSAT : @3 ;
+end pseudo-Boolean proof
+"
+    );
+    assert_eq!(files(dir.path()), ["proof.pbp"]);
+}
+
+#[test]
+fn missing_input_is_an_io_error() {
+    let dir = tempfile::tempdir().unwrap();
+
+    let output = run(dir.path(), &["advise", "missing.pbp"]);
+
+    assert_eq!(output.status.code(), Some(10));
+}
+
+#[test]
+fn usage_errors_are_invalid_options() {
+    let dir = tempfile::tempdir().unwrap();
+
+    let output = run(dir.path(), &["trim", "--in-place", "-"]);
+
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn trims_to_the_output_budget() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(
+        dir.path().join("proof.pbp"),
+        "\
+pseudo-Boolean proof version 2.0
+f 0
+@c1 pol x1 x2 + ;
+@c2 pol @c1 x3 + ;
+@c3 pol @c2 @c2 + ;
+@c4 pol @c3 @c3 + ;
+output NONE
+conclusion UNSAT : @c4 ;
+end pseudo-Boolean proof
+",
+    )
+    .unwrap();
+
+    pbarber(
+        dir.path(),
+        &["trim", "--target-lines", "7", "proof.pbp", "trimmed.pbp"],
+    );
+
+    // Cut at depth 1, the deepest that fits
+    assert_eq!(
+        fs::read_to_string(dir.path().join("trimmed.pbp")).unwrap(),
+        "\
+pseudo-Boolean proof version 2.0
+f 0
+@c3 a 2 x1 2 x2 2 x3 >= 0 ;
+@c4 pol @c3 @c3 + ;
+output NONE
+conclusion UNSAT : @c4 ;
+end pseudo-Boolean proof
+"
+    );
+    assert_eq!(files(dir.path()), ["proof.pbp", "trimmed.pbp"]);
+}